@@ -17,6 +17,9 @@ use crate::page::page_match::MatchPage;
 use crate::page::page_1p::GameSoloLobbyPage;
 
 
+/// Final-board previews served by the backend as png.
+pub const THUMBNAIL_URL: &str = "http://localhost:3000/api/thumbnail";
+
 #[component]
 pub fn AppRoot() -> impl IntoView {
     let _style = stylist::style!(
@@ -144,6 +147,12 @@ pub fn AppRoot() -> impl IntoView {
         ready_state_stream: rx.deactivate(),
         ready_signal,
         subscribe_game_callbacks: create_rw_signal(std::collections::HashMap::<_, _>::new()),
+        subscribe_match_callbacks: create_rw_signal(std::collections::HashMap::<_, _>::new()),
+        matchmaking_status: create_rw_signal(None),
+        world_record: create_rw_signal(None),
+        presence: create_rw_signal(std::collections::HashMap::<_, _>::new()),
+        challenges: create_rw_signal(Vec::<_>::new()),
+        challenge_event: create_rw_signal(None),
         error_msgs: create_rw_signal(Vec::<_>::new()),
     };
     provide_context(api.clone());
@@ -205,6 +214,8 @@ pub fn AppRoot() -> impl IntoView {
 
     use crate::page::page_1p::Game1PPage;
     use crate::page::page_2p_lobby::Game2LobbyPage;
+    use crate::page::page_coop::{CoopLocalPage, CoopOnlinePage};
+    use crate::page::page_room::{RoomCreatePage, RoomPage};
     use crate::page::page_user_profile::{MyAccountPage, UserProfilePage};
     use crate::page::page_vs_cpu::GameCPUPage;
 
@@ -244,6 +255,8 @@ pub fn AppRoot() -> impl IntoView {
                 }>
                     <nav>
                         <MainMenu/>
+                        <crate::page::page_leaderboard::WorldRecordBanner/>
+                        <crate::comp::challenge::ChallengeBanner/>
                         <div>
                             <p>"status: " {status}</p>
 
@@ -283,11 +296,19 @@ pub fn AppRoot() -> impl IntoView {
                             <Route path="/play-game-solo/:game_id" view=Game1PPage/>
                             <Route path="/vs_cpu" view=GameCPUPage/>
                             <Route path="/vs_net" view=Game2LobbyPage/>
+                            <Route path="/coop" view=CoopLocalPage/>
+                            <Route path="/coop/:game_id" view=CoopOnlinePage/>
+                            <Route path="/room" view=RoomCreatePage/>
+                            <Route path="/room/:code" view=RoomPage/>
                             <Route
                                 path="/replay"
                                 view=crate::page::page_replay_browser::GameReplayBrowserPage
                             />
                             <Route path="/account" view=MyAccountPage/>
+                            <Route
+                                path="/friends"
+                                view=crate::page::page_friends::FriendsPage
+                            />
                             <Route path="/spectate-game/:game_id" view=SpectateGamePage/>
                             <Route path="/user/:user_id" view=UserProfilePage/>
                             <Route
@@ -295,6 +316,10 @@ pub fn AppRoot() -> impl IntoView {
                                 view=crate::page::page_replay_single::GameReplaySinglePage
                             />
                             <Route path="/match/:match_id" view=MatchPage/>
+                            <Route
+                                path="/leaderboard"
+                                view=crate::page::page_leaderboard::LeaderboardPage
+                            />
                             <Route path="/mspaint" view=MsPaintPage/>
                             <Route path="/edit-custom-game/:save_id" view=MsPaintPage/>
                             <Route
@@ -319,8 +344,12 @@ pub fn MainMenu() -> impl IntoView {
             ("/solo", "solo"),
             ("/vs_cpu", "man vs car"),
             ("/vs_net", "1v1 online"),
+            ("/coop", "co-op"),
+            ("/room", "private room"),
             ("/replay", "replay"),
+            ("/leaderboard", "leaderboard"),
             ("/account", "account"),
+            ("/friends", "friends"),
             ("/mspaint", "mspaint"),
             ("/gamebordflex", "gamebordflex"),
         ]
@@ -0,0 +1,87 @@
+use game::api::challenge::{Challenge, ChallengeEvent};
+use game::api::room::RoomSettings;
+use game::api::websocket::{AnswerChallenge, SendChallenge};
+use leptos::*;
+use leptos_router::{use_navigate, NavigateOptions};
+
+use crate::page::page_room::RoomSettingsEditor;
+use crate::websocket::demo_comp::{call_api_sync_or_error, WebsocketAPI};
+
+/// Challenges waiting for an answer, and what became of the last one.
+/// Both players go to the match once one is accepted.
+#[component]
+pub fn ChallengeBanner() -> impl IntoView {
+    let api = expect_context::<WebsocketAPI>();
+    create_effect(move |_| {
+        if let Some((_, ChallengeEvent::Accepted(match_id))) = api.challenge_event.get() {
+            let navigate = use_navigate();
+            navigate(&format!("/match/{match_id}"), NavigateOptions::default());
+        }
+    });
+    let last_event = move || match api.challenge_event.get() {
+        Some((_, ChallengeEvent::Declined)) => Some("challenge declined"),
+        Some((_, ChallengeEvent::Expired)) => Some("challenge expired"),
+        _ => None,
+    };
+
+    view! {
+        <For
+            each=move || api.challenges.get()
+            key=|(id, _)| *id
+            children=move |(id, c)| view! { <IncomingChallenge id c/> }
+        />
+        <p style="color:darkred">{last_event}</p>
+    }
+}
+
+#[component]
+fn IncomingChallenge(id: uuid::Uuid, c: Challenge) -> impl IntoView {
+    let api = expect_context::<WebsocketAPI>();
+    let error_display = create_rw_signal(String::new());
+    let answer = move |accept: bool| {
+        call_api_sync_or_error::<AnswerChallenge>(
+            (id, accept),
+            move |_| api.challenges.update(|v| v.retain(|(i, _)| *i != id)),
+            move |err| error_display.set(err),
+        );
+    };
+
+    view! {
+        <div>
+            <p>
+                <a href=format!("/user/{}", c.from)>{c.from_name.clone()}</a>
+                {format!(" challenges you to {:?}", c.settings.match_type)}
+            </p>
+            <button on:click=move |_| answer(true)>"accept"</button>
+            <button on:click=move |_| answer(false)>"decline"</button>
+            <p style="color:red">{error_display}</p>
+        </div>
+    }
+}
+
+/// Opens the rules to challenge `user_id` with, then sends the challenge.
+#[component]
+pub fn ChallengeButton(user_id: uuid::Uuid) -> impl IntoView {
+    let open = create_rw_signal(false);
+    let settings = create_rw_signal(RoomSettings::default());
+    let status = create_rw_signal(String::new());
+    let send = move |_| {
+        call_api_sync_or_error::<SendChallenge>(
+            (user_id, settings.get_untracked()),
+            move |_| {
+                open.set(false);
+                status.set("challenge sent".to_string());
+            },
+            move |err| status.set(err),
+        );
+    };
+
+    view! {
+        <button on:click=move |_| open.update(|o| *o = !*o)>"challenge"</button>
+        <Show when=move || open.get() fallback=|| ()>
+            <RoomSettingsEditor settings/>
+            <button on:click=send>"send challenge"</button>
+        </Show>
+        <span>{status}</span>
+    }
+}
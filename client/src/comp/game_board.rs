@@ -2,10 +2,14 @@ use game::random::GameSeed;
 use game::tet::TetAction;
 use game::timestamp::get_timestamp_now_nano;
 use crate::comp::game_board_flex::GameBoardFlex;
+use game::rules::LockedVisibility;
 use game::tet::{self, CellValue, GameState};
 use leptos::*;
 
 const BOARD_HEIGHT: usize = 20;
+/// How locked cells are drawn, and the cells of the falling piece that always
+/// show.
+pub type LockedCells = (LockedVisibility, Vec<(usize, usize)>);
 ///componenta
 
 #[derive(Clone, PartialEq)]
@@ -50,6 +54,9 @@ pub fn BoardTable<const R: usize, const C: usize>(
     #[prop(optional)]
     on_click: Callback<(i8, i8)>,
 
+    #[prop(optional)]
+    locked: Option<Signal<LockedCells>>,
+
 ) -> impl IntoView {
     //
     // log::info!("redraw BoardTable R={} C={}", R, C);
@@ -117,7 +124,7 @@ pub fn BoardTable<const R: usize, const C: usize>(
                                 on_click.call((y, _x));
                             })
                         };
-                        view! { <BoardRow row_vals=r.1 row_idx=r.0 on_click=cb/> }
+                        view! { <BoardRow row_vals=r.1 row_idx=r.0 on_click=cb locked/> }
                     }
                 />
 
@@ -131,6 +138,7 @@ pub fn BoardRow(
     row_vals: Vec<RwSignal<CellValue>>,
     row_idx: usize,
     on_click: Callback<i8>,
+    locked: Option<Signal<LockedCells>>,
 ) -> impl IntoView {
     let iter = move || row_vals.clone().into_iter().enumerate();
     let overflow = row_idx >= BOARD_HEIGHT;
@@ -152,9 +160,21 @@ pub fn BoardRow(
                             on_click.call(x as i8);
                         })
                     };
+                    let x = c.0;
+                    let locked_cls = Signal::derive(move || {
+                        let Some(locked) = locked else { return "" };
+                        locked.with(|(visibility, current)| {
+                            match visibility {
+                                _ if current.contains(&(row_idx, x)) => "",
+                                LockedVisibility::Visible => "",
+                                LockedVisibility::Invisible => "invisible",
+                                LockedVisibility::Fading => "fading",
+                            }
+                        })
+                    });
                     view! {
                         <td>
-                            <BoardCell cell=c.1 overflow=overflow on_click=cb/>
+                            <BoardCell cell=c.1 overflow=overflow on_click=cb locked_cls/>
                         </td>
                     }
                 }
@@ -169,12 +189,17 @@ pub fn BoardCell(
     cell: RwSignal<CellValue>,
     overflow: bool,
     on_click: Callback<()>,
+    /// Extra class for locked cells: invisible and fading modifiers.
+    locked_cls: Signal<&'static str>,
 ) -> impl IntoView {
     let lambda = move || {
         let _cell_cls = match cell.get() {
-            tet::CellValue::Piece(p) => format!("tet {}", p.name()),
+            tet::CellValue::Piece(tet::Tet::Custom(p)) => {
+                format!("tet custom {} {}", p.name(), locked_cls.get())
+            }
+            tet::CellValue::Piece(p) => format!("tet {} {}", p.name(), locked_cls.get()),
             tet::CellValue::Empty => "empty".to_string(),
-            tet::CellValue::Garbage => "garbage".to_string(),
+            tet::CellValue::Garbage => format!("garbage {}", locked_cls.get()),
             tet::CellValue::Ghost => "ghost".to_string(),
         };
         let overflow_txt = if overflow { "overflow_cell" } else { "cell" };
@@ -182,7 +207,16 @@ pub fn BoardCell(
         _cell_cls
     };
 
-    view! { <div class=lambda on:click=move |_| on_click.call(())></div> }
+    // custom pieces carry their own color, see `.tet.custom` in style.rs
+    let piece_color = move || match cell.get() {
+        tet::CellValue::Piece(tet::Tet::Custom(p)) => {
+            let [r, g, b] = p.color;
+            format!("--piece-color: #{r:02X}{g:02X}{b:02X}")
+        }
+        _ => String::new(),
+    };
+
+    view! { <div class=lambda style=piece_color on:click=move |_| on_click.call(())></div> }
 }
 
 
@@ -0,0 +1,68 @@
+use game::coop::CoopState;
+use leptos::*;
+
+use crate::{
+    comp::game_board::BoardTable,
+    style::{flex_gameboard_style, GameBoardTetStyle},
+};
+
+/// The shared wide board, with each player's hold and queue on their side.
+#[component]
+pub fn CoopBoardView(#[prop(into)] state: Signal<CoopState>) -> impl IntoView {
+    let tet_style = GameBoardTetStyle::new();
+    let _style_name = flex_gameboard_style(tet_style).get_class_name().to_owned();
+
+    let main_board = Signal::derive(move || state.with(|s| s.get_display_board()));
+    let locked = Signal::derive(move || {
+        state.with(|s| {
+            (s.replay.rules.modifiers.locked_pieces, s.current_piece_cells())
+        })
+    });
+
+    let side = move |player: usize| {
+        let hold_board = Signal::derive(move || state.with(|s| s.get_hold_board(player)));
+        let next_board = Signal::derive(move || state.with(|s| s.get_next_board(player)));
+        view! {
+            <div style="width:15%;height:100%;flex-direction: column;display: flex;">
+                <div style="width:100%;height:6%; container-type: size;">
+                    <h3 style="font-size:80cqh; text-align: center;">
+                        {format!("P{} HOLD", player + 1)}
+                    </h3>
+                </div>
+                <div style="width:100%;height:12%;">
+                    <BoardTable board=hold_board/>
+                </div>
+                <div style="width:100%;height:6%; container-type: size;">
+                    <h3 style="font-size:80cqh; text-align: center;">NEXT</h3>
+                </div>
+                <div style="width:100%;height:60%;">
+                    <BoardTable board=next_board/>
+                </div>
+            </div>
+        }
+    };
+
+    view! {
+        <div
+            class=_style_name
+            style="height:100%;flex-direction: column;display: flex; container-type: size;"
+        >
+            <div style="height:8%; container-type: size;">
+                <h3 style="font-size:60cqh; text-align: center;">
+                    {move || {
+                        let (score, over) = state.with(|s| (s.score, s.game_over));
+                        if over { format!("{score} - game over") } else { format!("{score}") }
+                    }}
+
+                </h3>
+            </div>
+            <div style="height:92%;flex-direction: row;display: flex;">
+                {side(0)}
+                <div style="width:70%;height:100%;">
+                    <BoardTable board=main_board locked/>
+                </div>
+                {side(1)}
+            </div>
+        </div>
+    }
+}
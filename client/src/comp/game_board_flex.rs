@@ -65,7 +65,11 @@ pub fn GameBoardFlex(
         create_read_slice(game_state, |state: &tet::GameState| state.get_next_board());
 
     let main_board =
-        create_read_slice(game_state, |state: &tet::GameState| state.main_board);
+        create_read_slice(game_state, |state: &tet::GameState| state.get_display_board());
+
+    let locked = create_read_slice(game_state, |state: &tet::GameState| {
+        (state.replay.rules.modifiers.locked_pieces, state.current_piece_cells())
+    });
 
     let gameover = view! {
         <Show when=move || game_state.get().game_over fallback=|| view! {}>
@@ -150,7 +154,7 @@ pub fn GameBoardFlex(
                             </div>
                         </div>
 
-                        <BoardTable board=main_board on_click=on_main_cell_click/>
+                        <BoardTable board=main_board on_click=on_main_cell_click locked/>
                     </div>
 
                     <div style="width:1%;height:100%;flex-direction: column;display: flex;"></div>
@@ -4,6 +4,8 @@ use std::collections::VecDeque;
 
 use game::{
     api::websocket::{GetCustomGame, GetRandomWord, UpdateCustomGame},
+    fumen::{game_state_from_fumen, game_state_to_fumen, FUMEN_VIEWER_URL},
+    history::GameHistory,
     tet::{CellValue, CurrentPcsInfo, GameState, Tet},
 };
 use leptonic::{
@@ -20,6 +22,7 @@ use crate::{
 #[component]
 pub fn MsPaintPlayPage() ->impl IntoView{
     let game_state = create_rw_signal(GameState::empty());
+    let history = store_value(GameHistory::new(&game_state.get_untracked()));
     let params = use_params_map();
     let (save_name, set_save_name) = create_signal("".to_string());
 
@@ -30,7 +33,9 @@ pub fn MsPaintPlayPage() ->impl IntoView{
             if let Some(url_save_name) = p {
                     set_save_name.set(url_save_name.clone());
 
-                    call_api_sync::<GetCustomGame>(url_save_name, move |r| {
+                    call_api_sync::<GetCustomGame>(url_save_name, move |mut r| {
+                        r.enable_undo();
+                        history.set_value(GameHistory::new(&r));
                         game_state.set(r);
                     });
             }
@@ -40,7 +45,11 @@ pub fn MsPaintPlayPage() ->impl IntoView{
         <div class="main_left">
             <PlayerGameBoardSingle
                 state=game_state
-                top_bar=view! { <h1>"play custom     | " {save_name}</h1> }.into_view()
+                history
+                top_bar=view! {
+                    <h1>"play custom     | " {save_name}</h1>
+                    <p>"backspace: undo | Y: redo"</p>
+                }.into_view()
             />
         </div>
     }
@@ -104,6 +113,11 @@ pub fn MsPaintPage() -> impl IntoView {
                     }>Play</a>
                 </Tab>
 
+                <Tab name="ascii-custom-game" label="Text".into_view()>
+                    <AsciiNotationEditor game_state/>
+                    <FumenEditor game_state/>
+                </Tab>
+
                 <Tab name="list-custom-games" label="All Custom Games".into_view()>
 
                     <ListAllCustomGames/>
@@ -115,6 +129,82 @@ pub fn MsPaintPage() -> impl IntoView {
 }
 
 
+#[component]
+pub fn AsciiNotationEditor(game_state: RwSignal<GameState>) -> impl IntoView {
+    let text = create_rw_signal(game_state.get_untracked().to_ascii());
+    let (status, set_status) = create_signal("".to_string());
+
+    let on_show = move |_| {
+        text.set(game_state.get_untracked().to_ascii());
+        set_status.set("".to_string());
+    };
+    let on_load = move |_| {
+        match GameState::from_ascii(&text.get_untracked()) {
+            Ok(new_state) => {
+                game_state.set(new_state);
+                set_status.set("Load ok".to_string());
+            }
+            Err(e) => set_status.set(format!("{e:#}")),
+        }
+    };
+
+    view! {
+        <h1>"board as text"</h1>
+        <textarea
+            rows="24"
+            cols="20"
+            style="font-family: monospace;"
+            prop:value=move || text.get()
+            on:input=move |ev| text.set(event_target_value(&ev))
+        ></textarea>
+        <Button on_click=on_show color=ButtonColor::Secondary>
+            "Show current"
+        </Button>
+        <Button on_click=on_load color=ButtonColor::Info>
+            "Load"
+        </Button>
+        <p>{move || status.get()}</p>
+    }
+}
+
+#[component]
+pub fn FumenEditor(game_state: RwSignal<GameState>) -> impl IntoView {
+    let (text, set_text) = create_signal("".to_string());
+    let (status, set_status) = create_signal("".to_string());
+
+    let on_copy = move |_| {
+        set_text.set(game_state.with_untracked(game_state_to_fumen));
+        set_status.set("".to_string());
+    };
+    let on_import = move |_| {
+        match game_state_from_fumen(&text.get_untracked()) {
+            Ok(new_state) => {
+                game_state.set(new_state);
+                set_status.set("Import ok".to_string());
+            }
+            Err(e) => set_status.set(format!("{e:#}")),
+        }
+    };
+
+    view! {
+        <h1>"fumen"</h1>
+        <TextInput get=text set=set_text/>
+        <Button on_click=on_copy color=ButtonColor::Secondary>
+            "Copy as fumen"
+        </Button>
+        <Button on_click=on_import color=ButtonColor::Info>
+            "Import fumen"
+        </Button>
+        <a
+            href=move || format!("{FUMEN_VIEWER_URL}{}", game_state.with(game_state_to_fumen))
+            target="_blank"
+        >
+            "Open in fumen"
+        </a>
+        <p>{move || status.get()}</p>
+    }
+}
+
 #[component]
 pub fn CurrentPeaceSelector(game_state: RwSignal<GameState>) -> impl IntoView {
     let selected =
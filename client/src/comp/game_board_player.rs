@@ -1,6 +1,7 @@
-use crate::comp::hotkey_reader::create_hotkey_reader;
+use crate::comp::hotkey_reader::{create_history_hotkey_reader, create_hotkey_reader, HistoryAction};
 use crate::{comp::game_board::key_debounce_ms, websocket::demo_comp::call_api_sync};
-use game::api::{game_replay::GameId, websocket::*};
+use game::api::{game_match::GarbageBatch, game_replay::GameId, websocket::*};
+use game::history::GameHistory;
 use game::tet::TetAction;
 use game::timestamp::get_timestamp_now_nano;
 use leptos_use::{use_interval, use_interval_with_options, UseIntervalOptions, UseIntervalReturn};
@@ -12,23 +13,31 @@ use leptos::*;
 pub fn PlayerGameBoardFromId(game_id: GameId,    #[prop(default = Callback::<()>::new(move |_| {}))]
 #[prop(optional)]
 on_reset: Callback<()>,
+/// Garbage the server sent to this board in a match, oldest first.
+#[prop(optional)]
+incoming_garbage: Option<Signal<Vec<GarbageBatch>>>,
 ) -> impl IntoView {
     let on_state_change = Callback::<GameState>::new(move |s| {
         let segment: GameReplaySegment = {
             if s.replay.replay_slices.is_empty() {
                 GameReplaySegment::Init(s.replay)
-            } else if s.game_over {
-                GameReplaySegment::GameOver
             } else {
                 GameReplaySegment::Update(
                     s.replay.replay_slices.last().unwrap().clone(),
                 )
             }
         };
+        let game_over = s.game_over;
 
         let segment_json: String = serde_json::to_string(&segment).unwrap();
         call_api_sync::<AppendGameSegment>((game_id, segment_json), move |_r| {
             // log::info!("append OK: {:?}", _r);
+            // the slice that ended the game goes first, so the server
+            // can check the end of the game too
+            if game_over {
+                let over_json = serde_json::to_string(&GameReplaySegment::GameOver).unwrap();
+                call_api_sync::<AppendGameSegment>((game_id, over_json), move |_r| {});
+            }
         });
     });
 
@@ -59,6 +68,31 @@ on_reset: Callback<()>,
     let state = create_rw_signal(
         tet::GameState::new(&game_id.init_seed, game_id.start_time));
 
+    if let Some(incoming_garbage) = incoming_garbage {
+        create_effect(move |_| {
+            let incoming = incoming_garbage.get();
+            state.update(|state| {
+                let taken = state
+                    .replay
+                    .replay_slices
+                    .iter()
+                    .filter(|s| matches!(s.event.action, TetAction::ReceiveGarbage(_)))
+                    .count();
+                for batch in incoming.iter().skip(taken) {
+                    if state.game_over {
+                        break;
+                    }
+                    if state
+                        .apply_action_if_works(TetAction::ReceiveGarbage(batch.lines), get_timestamp_now_nano())
+                        .is_ok()
+                    {
+                        on_state_change.call(state.clone());
+                    }
+                }
+            });
+        });
+    }
+
     call_api_sync::<GetLastFullGameState>(game_id, move |_state| {
         match _state {
             Some(_state) => {
@@ -66,7 +100,12 @@ on_reset: Callback<()>,
                 resume_pre_123();
             },
             None => {
-                resume_pre_123();
+                // fresh game: start it under the rules it was created with
+                let resume_pre_123 = resume_pre_123.clone();
+                call_api_sync::<GetGameRules>(game_id, move |rules| {
+                    state.set(tet::GameState::new_with_rules(&game_id.init_seed, game_id.start_time, &rules));
+                    resume_pre_123();
+                });
             }
         }
     });
@@ -108,10 +147,31 @@ pub fn PlayerGameBoardSingle(
     #[prop(default = view!{}.into_view())]
     top_bar: View,
 
+    /// Practice boards pass a history to get the undo / redo keys.
+    #[prop(optional)]
+    history: Option<StoredValue<GameHistory>>,
+
 ) -> impl IntoView {
 
     on_state_change.call(state.get_untracked());
 
+    let held_actions = create_rw_signal(Vec::<TetAction>::new());
+    // IRS / IHS: a piece that just spawned takes the rotate and hold keys
+    // still held; each one is its own replay slice.
+    let apply_initial_actions = move |state: &mut GameState, piece_before: Option<u32>| {
+        if state.game_over || state.current_pcs.map(|p| p.id) == piece_before {
+            return;
+        }
+        for action in held_actions.with_untracked(|held| state.initial_actions(held)) {
+            if state
+                .apply_action_if_works(action, get_timestamp_now_nano())
+                .is_ok()
+            {
+                on_state_change.call(state.clone());
+            }
+        }
+    };
+
     let leptos_use::utils::Pausable {
         pause: _timer_pause,
         resume: _timer_resume,
@@ -120,6 +180,7 @@ pub fn PlayerGameBoardSingle(
         move || {
             state.update(move |state| {
                 if !state.game_over {
+                    let piece_before = state.current_pcs.map(|p| p.id);
                     if state
                         .apply_action_if_works(
                             TetAction::SoftDrop,
@@ -128,11 +189,12 @@ pub fn PlayerGameBoardSingle(
                         .is_ok()
                     {
                         on_state_change.call(state.clone());
+                        apply_initial_actions(state, piece_before);
                     }
                 }
             })
         },
-        1000,
+        Signal::derive(move || state.with(|s| s.replay.rules.gravity_ms)),
     );
 
     let reset_timer = move || {
@@ -140,9 +202,11 @@ pub fn PlayerGameBoardSingle(
         _timer_resume();
     };
 
+    let reset_timer2 = reset_timer.clone();
     let (get_ts, set_ts) =
         create_signal(std::collections::HashMap::<TetAction, i64>::new());
-    create_hotkey_reader( move |_action| {
+    let rules = create_memo(move |_| state.with(|s| s.replay.rules.clone())).into();
+    create_hotkey_reader(rules, held_actions, move |_action| {
         let timestamp1 = game::timestamp::get_timestamp_now_ms();
         let timestamp0 = *get_ts.get().get(&_action).unwrap_or(&0);
         if (timestamp1 - timestamp0) > key_debounce_ms(_action) {
@@ -151,17 +215,38 @@ pub fn PlayerGameBoardSingle(
             });
             state.update(|state| {
                 log::info!("press action: {:?}", _action);
+                let piece_before = state.current_pcs.map(|p| p.id);
                 if state
                     .apply_action_if_works(_action, get_timestamp_now_nano())
                     .is_ok()
                 {
                     on_state_change.call(state.clone());
+                    apply_initial_actions(state, piece_before);
                     reset_timer();
                 }
             })
         }
     });
 
+    if let Some(history) = history {
+        create_history_hotkey_reader(move |history_action| {
+            state.update(|state| {
+                let r = history.try_update_value(|history| match history_action {
+                    HistoryAction::Undo => history.undo(state),
+                    HistoryAction::Redo => history.redo(state),
+                });
+                match r {
+                    Some(Ok(())) => {
+                        on_state_change.call(state.clone());
+                        reset_timer2();
+                    }
+                    Some(Err(e)) => log::info!("{history_action:?} failed: {e:#}"),
+                    None => {}
+                }
+            })
+        });
+    }
+
     view! {
         <GameBoardFlex
             game_state=state
@@ -1,3 +1,4 @@
+use game::fumen::{game_states_to_fumen, FUMEN_VIEWER_URL};
 use game::tet::{GameReplaySegment, GameState};
 use game::timestamp::get_timestamp_now_ms;
 use leptonic::prelude::*;
@@ -23,7 +24,7 @@ pub fn ReplayGameBoardFromSegmments(
         status_message.set("simulating...".to_string());
         let mut current_state = match all_segments.get(0) {
             Some(GameReplaySegment::Init(_replay)) => {
-                GameState::new(&_replay.init_seed, _replay.start_time)
+                GameState::new_with_rules(&_replay.init_seed, _replay.start_time, &_replay.rules)
             }
             _ => {
                 log::error!("got no init segment");
@@ -253,6 +254,17 @@ pub fn ReplayGameBoardFromSegmments(
                     />
                 </div>
 
+                <div class="control_icon_container">
+                    <a
+                        href=move || {
+                            all_states.with(|s| format!("{FUMEN_VIEWER_URL}{}", game_states_to_fumen(s)))
+                        }
+                        target="_blank"
+                    >
+                        "fumen"
+                    </a>
+                </div>
+
             </div>
         }.into_view()
     };
@@ -18,7 +18,7 @@ pub fn SpectatorGameBoard(game_id: GameId) -> impl IntoView {
                 log::info!("got surpriuzxe segment: {}",key.segment_id);
                 match _value {
                     GameReplaySegment::Init(init) => {
-                            *state_val = GameState::new(&init.init_seed, init.start_time)
+                            *state_val = GameState::new_with_rules(&init.init_seed, init.start_time, &init.rules)
                     }
                     GameReplaySegment::Update(slice) => {
                             if let Err(e) = state_val.accept_replay_slice(&slice) {
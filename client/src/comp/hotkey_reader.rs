@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use game::rules::GameRules;
 use game::tet::TetAction;
 use leptos::*;
 use leptos_use::{use_interval, use_interval_with_options, UseIntervalOptions, UseIntervalReturn};
@@ -19,11 +20,11 @@ where
     pub stop: StopFn,
 }
 
-fn create_hotkey_repeater(action: TetAction, on_action: impl Fn(TetAction) + Clone + 'static) -> HotkeyRepeaterFunctions<impl Fn() + Clone, impl Fn() + Clone> {
+fn create_hotkey_repeater(action: TetAction, arr_ms: Signal<u64>, on_action: impl Fn(TetAction) + Clone + 'static) -> HotkeyRepeaterFunctions<impl Fn() + Clone, impl Fn() + Clone> {
 
     let UseIntervalReturn{ 
         counter: das_counter, reset: das_reset, is_active: das_is_active, pause: das_pause, resume: das_resume }    =  use_interval_with_options( DAS_MS , UseIntervalOptions::default().immediate(false));
-    let UseIntervalReturn{ counter: arr_counter, reset: arr_reset, is_active: arr_is_active, pause: arr_pause, resume: arr_resume }    = use_interval_with_options(ARR_MS,UseIntervalOptions::default().immediate(false));
+    let UseIntervalReturn{ counter: arr_counter, reset: arr_reset, is_active: arr_is_active, pause: arr_pause, resume: arr_resume }    = use_interval_with_options(arr_ms,UseIntervalOptions::default().immediate(false));
 
     // when das counter changes, reset and pause it and ccall fisrt repeat acction. Also start repeat arr counter
     let das_reset2 = das_reset.clone();
@@ -82,7 +83,12 @@ fn create_hotkey_repeater(action: TetAction, on_action: impl Fn(TetAction) + Clo
 }
 
 
-pub fn create_hotkey_reader(on_action: impl Fn(TetAction) + Clone+'static) {
+/// `held` tracks the actions whose keys are down, for IRS / IHS.
+pub fn create_hotkey_reader(
+    rules: Signal<GameRules>,
+    held: RwSignal<Vec<TetAction>>,
+    on_action: impl Fn(TetAction) + Clone + 'static,
+) {
     let mut control_mapping = HashMap::<String, TetAction>::new();
     control_mapping.insert("arrowup".to_string(),TetAction::RotateRight );
     control_mapping.insert("keyx".to_string(),TetAction::RotateRight );
@@ -91,6 +97,7 @@ pub fn create_hotkey_reader(on_action: impl Fn(TetAction) + Clone+'static) {
 
     control_mapping.insert("arrowdown".to_string(),TetAction::SoftDrop );
     control_mapping.insert("space".to_string(),TetAction::HardDrop );
+    control_mapping.insert("keya".to_string(),TetAction::SonicDrop );
     control_mapping.insert("keyz".to_string(),TetAction::RotateLeft );
     control_mapping.insert("arrowleft".to_string(),TetAction::MoveLeft );
     control_mapping.insert("arrowright".to_string(),TetAction::MoveRight );
@@ -102,20 +109,25 @@ pub fn create_hotkey_reader(on_action: impl Fn(TetAction) + Clone+'static) {
     let hotkey_context = expect_context::<HotkeysContext>();
     let events = hotkey_context.key_events;
 
+    let softdrop_arr_ms = create_memo(move |_| {
+        rules.with(|r| r.soft_drop_speed.repeat_ms(r.gravity_ms).unwrap_or(ARR_MS))
+    })
+    .into();
+
     let HotkeyRepeaterFunctions {
         start: start_left,
         stop: stop_left,
-    } = create_hotkey_repeater(TetAction::MoveLeft, on_action.clone());
+    } = create_hotkey_repeater(TetAction::MoveLeft, Signal::derive(|| ARR_MS), on_action.clone());
     
     let HotkeyRepeaterFunctions {
         start: start_right,
         stop: stop_right,
-    } = create_hotkey_repeater(TetAction::MoveRight, on_action.clone());
+    } = create_hotkey_repeater(TetAction::MoveRight, Signal::derive(|| ARR_MS), on_action.clone());
 
     let HotkeyRepeaterFunctions {
         start: start_softdrop,
         stop: stop_softdrop,
-    } = create_hotkey_repeater(TetAction::SoftDrop, on_action.clone());
+    } = create_hotkey_repeater(TetAction::SoftDrop, softdrop_arr_ms, on_action.clone());
 
     let start_left2 = start_left.clone();
     let start_right2 = start_right.clone();
@@ -141,6 +153,13 @@ pub fn create_hotkey_reader(on_action: impl Fn(TetAction) + Clone+'static) {
                 match event {
                     crate::hotkey_context::KeyPressEvent::KeyDown(key_id) => {
                         if let Some(tet_action) = control_mapping.get(key_id) {
+                            held.update_untracked(|h| h.push(*tet_action));
+                            let sonic = *tet_action == TetAction::SoftDrop
+                                && rules.with_untracked(|r| r.soft_drop_speed.repeat_ms(r.gravity_ms).is_none());
+                            if sonic {
+                                on_action(TetAction::SonicDrop);
+                                continue;
+                            }
                             on_action(*tet_action);
                             if tet_action.is_repeating() {
                                 // magic
@@ -155,6 +174,11 @@ pub fn create_hotkey_reader(on_action: impl Fn(TetAction) + Clone+'static) {
                     },
                     crate::hotkey_context::KeyPressEvent::KeyUp(key_id) => {
                         if let Some(tet_action) = control_mapping.get(key_id) {
+                            held.update_untracked(|h| {
+                                if let Some(i) = h.iter().position(|a| a == tet_action) {
+                                    h.remove(i);
+                                }
+                            });
                             if tet_action.is_repeating() {
                                 // more magic   
                                 match tet_action {
@@ -171,3 +195,79 @@ pub fn create_hotkey_reader(on_action: impl Fn(TetAction) + Clone+'static) {
         })
     });
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryAction {
+    Undo,
+    Redo,
+}
+
+/// Keys for `game::history::GameHistory`, only wired up on practice boards.
+pub fn create_history_hotkey_reader(on_action: impl Fn(HistoryAction) + 'static) {
+    let mut history_mapping = HashMap::<String, HistoryAction>::new();
+    history_mapping.insert("backspace".to_string(), HistoryAction::Undo);
+    history_mapping.insert("keyy".to_string(), HistoryAction::Redo);
+
+    let hotkey_context = expect_context::<HotkeysContext>();
+    let events = hotkey_context.key_events;
+    let last_events_sig = create_rw_signal(vec![]);
+
+    create_effect(move |_| {
+        let current_events = events.get();
+        if last_events_sig.get_untracked() == current_events {
+            return;
+        }
+        last_events_sig.set_untracked(current_events);
+        events.with(|events| {
+            for event in events {
+                if let crate::hotkey_context::KeyPressEvent::KeyDown(key_id) = event {
+                    if let Some(history_action) = history_mapping.get(key_id) {
+                        on_action(*history_action);
+                    }
+                }
+            }
+        })
+    });
+}
+
+/// Two players on one keyboard: WASD side for player 0, arrows for player 1.
+/// Keys do not repeat here; hold them and the piece falls with gravity.
+pub fn create_coop_hotkey_reader(on_action: impl Fn(usize, TetAction) + 'static) {
+    let mut coop_mapping = HashMap::<String, (usize, TetAction)>::new();
+    coop_mapping.insert("keya".to_string(), (0, TetAction::MoveLeft));
+    coop_mapping.insert("keyd".to_string(), (0, TetAction::MoveRight));
+    coop_mapping.insert("keys".to_string(), (0, TetAction::SoftDrop));
+    coop_mapping.insert("keyw".to_string(), (0, TetAction::RotateRight));
+    coop_mapping.insert("keyq".to_string(), (0, TetAction::RotateLeft));
+    coop_mapping.insert("space".to_string(), (0, TetAction::HardDrop));
+    coop_mapping.insert("shiftleft".to_string(), (0, TetAction::Hold));
+
+    coop_mapping.insert("arrowleft".to_string(), (1, TetAction::MoveLeft));
+    coop_mapping.insert("arrowright".to_string(), (1, TetAction::MoveRight));
+    coop_mapping.insert("arrowdown".to_string(), (1, TetAction::SoftDrop));
+    coop_mapping.insert("arrowup".to_string(), (1, TetAction::RotateRight));
+    coop_mapping.insert("period".to_string(), (1, TetAction::RotateLeft));
+    coop_mapping.insert("enter".to_string(), (1, TetAction::HardDrop));
+    coop_mapping.insert("shiftright".to_string(), (1, TetAction::Hold));
+
+    let hotkey_context = expect_context::<HotkeysContext>();
+    let events = hotkey_context.key_events;
+    let last_events_sig = create_rw_signal(vec![]);
+
+    create_effect(move |_| {
+        let current_events = events.get();
+        if last_events_sig.get_untracked() == current_events {
+            return;
+        }
+        last_events_sig.set_untracked(current_events);
+        events.with(|events| {
+            for event in events {
+                if let crate::hotkey_context::KeyPressEvent::KeyDown(key_id) = event {
+                    if let Some((player, tet_action)) = coop_mapping.get(key_id) {
+                        on_action(*player, *tet_action);
+                    }
+                }
+            }
+        })
+    });
+}
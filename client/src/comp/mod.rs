@@ -10,4 +10,10 @@ pub mod multiselect_repeat;
 pub mod game_board_player;
 pub mod game_board_flex;
 pub mod table_match;
-pub mod menu_grid_view;
\ No newline at end of file
+pub mod menu_grid_view;
+pub mod game_board_coop;
+pub mod rating_graph;
+pub mod stats_dashboard;
+pub mod challenge;
+
+pub mod pager;
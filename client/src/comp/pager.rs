@@ -0,0 +1,77 @@
+use game::api::page::{PageCursor, PageRequest, DEFAULT_PAGE_SIZE};
+use leptos::*;
+
+/// Where a cursor-paged table is. The server only hands out cursors going
+/// forward, so the ones we came from are kept to go back.
+#[derive(Clone, Copy)]
+pub struct Pager {
+    current: RwSignal<Option<PageCursor>>,
+    previous: RwSignal<Vec<Option<PageCursor>>>,
+    next: RwSignal<Option<PageCursor>>,
+}
+
+impl Pager {
+    pub fn new() -> Self {
+        Self {
+            current: create_rw_signal(None),
+            previous: create_rw_signal(vec![]),
+            next: create_rw_signal(None),
+        }
+    }
+
+    /// Request for the page on display; tracked.
+    pub fn request(&self) -> PageRequest {
+        PageRequest {
+            cursor: self.current.get(),
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+
+    /// Call with the `next` of every page that comes back.
+    pub fn loaded(&self, next: Option<PageCursor>) {
+        self.next.set(next);
+    }
+
+    fn go_next(&self) {
+        if let Some(next) = self.next.get_untracked() {
+            let current = self.current.get_untracked();
+            self.previous.update(|p| p.push(current));
+            self.next.set(None);
+            self.current.set(Some(next));
+        }
+    }
+
+    fn go_back(&self) {
+        if let Some(previous) = self.previous.try_update(|p| p.pop()).flatten() {
+            self.next.set(None);
+            self.current.set(previous);
+        }
+    }
+}
+
+impl Default for Pager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[component]
+pub fn PagerButtons(pager: Pager) -> impl IntoView {
+    view! {
+        <div>
+            <button
+                on:click=move |_| pager.go_back()
+                disabled=move || pager.previous.with(|p| p.is_empty())
+            >
+                "<"
+            </button>
+            {move || format!(" page {} ", pager.previous.with(|p| p.len()) + 1)}
+            <button
+                on:click=move |_| pager.go_next()
+                disabled=move || pager.next.with(|n| n.is_none())
+            >
+                ">"
+            </button>
+        </div>
+    }
+}
@@ -0,0 +1,67 @@
+use game::api::{
+    game_match::GameMatchType,
+    websocket::{GetRating, GetRatingHistory},
+};
+use leptos::*;
+
+use crate::websocket::demo_comp::call_api_sync;
+
+const GRAPH_W: f64 = 600.0;
+const GRAPH_H: f64 = 200.0;
+
+/// Current rating of a player, with the rating after each match plotted.
+#[component]
+pub fn RatingGraph(user_id: uuid::Uuid, match_type: GameMatchType) -> impl IntoView {
+    let rating = create_rw_signal(None);
+    call_api_sync::<GetRating>((user_id, match_type.clone()), move |r| {
+        rating.set(Some(r));
+    });
+    let history = create_rw_signal(vec![]);
+    call_api_sync::<GetRatingHistory>((user_id, match_type.clone()), move |r| {
+        history.set(r);
+    });
+
+    let rating_text = move || match rating.get() {
+        Some(r) if r.is_provisional() => format!("{:.0}? (provisional)", r.rating),
+        Some(r) => format!("{:.0} ± {:.0}", r.rating, 2.0 * r.deviation),
+        None => "-".to_string(),
+    };
+
+    let points = move || {
+        history.with(|h| {
+            let values: Vec<f64> = h
+                .first()
+                .map(|first| first.1.before.rating)
+                .into_iter()
+                .chain(h.iter().map(|x| x.1.after.rating))
+                .collect();
+            let min = values.iter().cloned().fold(f64::INFINITY, f64::min) - 10.0;
+            let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max) + 10.0;
+            let step = GRAPH_W / (values.len().max(2) - 1) as f64;
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let y = GRAPH_H - (v - min) / (max - min) * GRAPH_H;
+                    format!("{:.1},{:.1}", i as f64 * step, y)
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    };
+
+    view! {
+        <h3>{format!("{match_type:?} rating: ")} {rating_text}</h3>
+        <Show
+            when=move || history.with(|h| !h.is_empty())
+            fallback=|| view! { <p>no rated matches yet</p> }
+        >
+            <svg
+                viewBox=format!("0 0 {GRAPH_W} {GRAPH_H}")
+                style="width:100%;max-width:600px;border:1px solid gray"
+            >
+                <polyline points=points fill="none" stroke="#21B6F8" stroke-width="2"></polyline>
+            </svg>
+        </Show>
+    }
+}
@@ -0,0 +1,175 @@
+use game::api::stats::{PaceStats, UserStats};
+use game::api::websocket::GetUserStats;
+use leptos::*;
+
+use crate::page::page_leaderboard::format_result;
+use crate::websocket::demo_comp::call_api_sync;
+
+const CHART_W: f64 = 600.0;
+const CHART_H: f64 = 150.0;
+
+fn format_duration(time_ns: i64) -> String {
+    let s = time_ns / 1_000_000_000;
+    format!("{}h {:02}m {:02}s", s / 3600, s / 60 % 60, s % 60)
+}
+
+fn format_rate(x: Option<f64>) -> String {
+    x.map(|x| format!("{x:.2}")).unwrap_or("-".to_string())
+}
+
+/// Totals of a player, games per mode and their pace on each day.
+#[component]
+pub fn StatsDashboard(user_id: uuid::Uuid) -> impl IntoView {
+    let stats = create_rw_signal(None);
+    call_api_sync::<GetUserStats>(user_id, move |r| stats.set(Some(r)));
+
+    move || {
+        stats.get().map(|(totals, days)| {
+            view! {
+                <StatsTotals totals=totals.clone()/>
+                <h4>"games per mode"</h4>
+                <ModeBars totals/>
+                <h4>"pieces per second, by day"</h4>
+                <DayChart days=days.clone() value=PaceStats::pps color="#21B6F8"/>
+                <h4>"attack per minute, by day"</h4>
+                <DayChart days value=PaceStats::apm color="#E66956"/>
+            }
+        })
+    }
+}
+
+#[component]
+fn StatsTotals(totals: UserStats) -> impl IntoView {
+    let time_ns: i64 = totals.modes.values().map(|m| m.time_ns).sum();
+    let pieces: u64 = totals.modes.values().map(|m| m.pieces).sum();
+    let rows = totals
+        .modes
+        .iter()
+        .map(|(mode, m)| {
+            view! {
+                <tr>
+                    <td>{mode.name()}</td>
+                    <td>{m.games}</td>
+                    <td>{m.lines}</td>
+                    <td>{format_duration(m.time_ns)}</td>
+                    <td>
+                        {m
+                            .best
+                            .as_ref()
+                            .map(|best| {
+                                view! {
+                                    <a href=format!(
+                                        "/view-game/{}",
+                                        best.game_id.to_url(),
+                                    )>{format_result(best)}</a>
+                                }
+                            })}
+                    </td>
+                </tr>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <p>
+            {format!(
+                "{} games, {} lines, {} pieces, played {}",
+                totals.games(),
+                totals.lines(),
+                pieces,
+                format_duration(time_ns),
+            )}
+        </p>
+        <p>
+            {format!(
+                "{} matches, win rate {}, {} pps, {} apm",
+                totals.matches,
+                totals
+                    .win_rate()
+                    .map(|r| format!("{:.0}%", r * 100.0))
+                    .unwrap_or("-".to_string()),
+                format_rate(totals.pace.pps()),
+                format_rate(totals.pace.apm()),
+            )}
+        </p>
+        <table class="table">
+            <thead>
+                <tr>
+                    <th>"mode"</th>
+                    <th>"games"</th>
+                    <th>"lines"</th>
+                    <th>"time"</th>
+                    <th>"best"</th>
+                </tr>
+            </thead>
+            <tbody>{rows}</tbody>
+        </table>
+    }
+}
+
+#[component]
+fn ModeBars(totals: UserStats) -> impl IntoView {
+    let most = totals.modes.values().map(|m| m.games).max().unwrap_or(0).max(1);
+    let bar_h = CHART_H / totals.modes.len().max(1) as f64;
+    let bars = totals
+        .modes
+        .iter()
+        .enumerate()
+        .map(|(i, (mode, m))| {
+            let y = i as f64 * bar_h;
+            let w = m.games as f64 / most as f64 * (CHART_W - 100.0);
+            view! {
+                <text x="0" y=y + bar_h * 0.7 font-size="14">{mode.name()}</text>
+                <rect x="100" y=y + 2.0 width=w height=bar_h - 4.0 fill="#21B6F8"></rect>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <svg
+            viewBox=format!("0 0 {CHART_W} {CHART_H}")
+            style="width:100%;max-width:600px;border:1px solid gray"
+        >
+            {bars}
+        </svg>
+    }
+}
+
+/// `value` of each day that has one, as a line from the first day to the last.
+#[component]
+fn DayChart(
+    days: Vec<(i64, PaceStats)>,
+    value: fn(&PaceStats) -> Option<f64>,
+    color: &'static str,
+) -> impl IntoView {
+    let points: Vec<(i64, f64)> = days
+        .iter()
+        .filter_map(|(day, pace)| value(pace).map(|v| (*day, v)))
+        .collect();
+    if points.is_empty() {
+        return view! { <p>"not enough games yet"</p> }.into_view();
+    }
+    let first = points[0].0;
+    let span = (points[points.len() - 1].0 - first).max(1) as f64;
+    let max = points.iter().map(|p| p.1).fold(0.0, f64::max).max(0.01);
+    let line = points
+        .iter()
+        .map(|(day, v)| {
+            let x = (day - first) as f64 / span * CHART_W;
+            let y = CHART_H - v / max * (CHART_H - 10.0);
+            format!("{x:.1},{y:.1}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    view! {
+        <svg
+            viewBox=format!("0 0 {CHART_W} {CHART_H}")
+            style="width:100%;max-width:600px;border:1px solid gray"
+        >
+            <text x="4" y="14" font-size="14">{format!("max {max:.2}")}</text>
+            <polyline points=line fill="none" stroke=color stroke-width="2"></polyline>
+        </svg>
+    }
+    .into_view()
+}
@@ -1,20 +1,26 @@
 use game::{
     api::{
-        game_match::GameMatch, websocket::{GetMatchList, GetMatchListArg}
+        game_match::{GameMatch, UserAndMatchResult},
+        websocket::{GetMatchHistory, GetMatchList, GetMatchListArg},
     },
     random::GameSeed,
     timestamp::get_human_readable_nano,
 };
 
-use crate::websocket::demo_comp::call_api_sync;
+use crate::comp::pager::{Pager, PagerButtons};
+use crate::{app_root::THUMBNAIL_URL, websocket::demo_comp::call_api_sync};
 use leptos::*;
 use leptos_struct_table::*;
 
 #[component]
 pub fn AllMatchTable(list_type: GetMatchListArg) -> impl IntoView {
     let all_games = create_rw_signal(vec![]);
-    call_api_sync::<GetMatchList>(list_type, move |_r| {
-        all_games.set(_r);
+    let pager = Pager::new();
+    create_effect(move |_| {
+        call_api_sync::<GetMatchList>((list_type, pager.request()), move |_r| {
+            pager.loaded(_r.next);
+            all_games.set(_r.items);
+        });
     });
 
     let table_from_rows = move || {
@@ -34,7 +40,44 @@ pub fn AllMatchTable(list_type: GetMatchListArg) -> impl IntoView {
             .into_view()
     };
 
-    view! { {table_from_rows} }
+    view! {
+        {table_from_rows}
+        <PagerButtons pager/>
+    }
+}
+
+/// Finished matches of a player, with a win/loss count on top.
+#[component]
+pub fn MatchHistoryTable(user_id: uuid::Uuid) -> impl IntoView {
+    let history = create_rw_signal(vec![]);
+    call_api_sync::<GetMatchHistory>(user_id, move |_r| {
+        history.set(_r);
+    });
+
+    let summary = move || {
+        history.with(|h| {
+            let wins = h.iter().filter(|r| r.2.is_win).count();
+            format!("{wins} wins, {} losses", h.len() - wins)
+        })
+    };
+    let table_from_rows = move || {
+        let rows = history
+            .get()
+            .into_iter()
+            .map(MatchHistoryTableRow::new)
+            .collect::<Vec<_>>();
+        view! {
+            <table id=format!("match-history-{user_id}")>
+                <TableContent rows/>
+            </table>
+        }
+        .into_view()
+    };
+
+    view! {
+        <h3>{summary}</h3>
+        {table_from_rows}
+    }
 }
 
 // #[allow(unused_variables, non_snake_case)]
@@ -93,6 +136,8 @@ pub struct GameMatchTableRow {
     pub start_time: i64,
     pub users: String,
     pub title: String,
+    #[table(renderer = "ThumbnailRenderer")]
+    pub thumbnail: String,
 }
 
 impl GameMatchTableRow {
@@ -103,6 +148,7 @@ impl GameMatchTableRow {
             start_time: db_row.1.time,
             users: format!("{:?}",db_row.1.users),
             title : db_row.1.title,
+            thumbnail: format!("{THUMBNAIL_URL}/match/{}", db_row.0),
         }
     }
 
@@ -116,6 +162,38 @@ impl GameMatchTableRow {
     // }
 }
 
+#[derive(TableRow, Clone, Debug)]
+#[table( 
+    classes_provider = "BootstrapClassesPreset", impl_vec_data_provider)]
+pub struct MatchHistoryTableRow {
+    #[table(renderer = "WeedRenderer")]
+    pub match_id: uuid::Uuid,
+    pub result: String,
+    #[table(renderer = "TimeRenderer")]
+    pub end_time: i64,
+    pub title: String,
+    pub lines_sent: u32,
+    pub lines_received: u32,
+    pub pieces: u32,
+    pub score: i64,
+}
+
+impl MatchHistoryTableRow {
+    pub fn new(db_row: (uuid::Uuid, GameMatch, UserAndMatchResult)) -> Self {
+        let (match_id, _match, result) = db_row;
+        Self {
+            match_id,
+            result: if result.is_win { "win" } else { "loss" }.to_string(),
+            end_time: result.end_time,
+            title: _match.title,
+            lines_sent: result.lines_sent,
+            lines_received: result.lines_received,
+            pieces: result.pieces,
+            score: result.score,
+        }
+    }
+}
+
 #[allow(unused_variables)]
 #[component]
 fn TimeRenderer<F>(
@@ -173,3 +251,21 @@ where
         </td>
     }
 }
+
+#[allow(unused_variables)]
+#[component]
+fn ThumbnailRenderer<F>(
+    class: String,
+    #[prop(into)] value: MaybeSignal<String>,
+    on_change: F,
+    index: usize,
+) -> impl IntoView
+where
+    F: Fn(String) + 'static,
+{
+    view! {
+        <td class=class>
+            <img src=move || value.get() loading="lazy" style="height: 6em"/>
+        </td>
+    }
+}
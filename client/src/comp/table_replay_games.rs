@@ -7,7 +7,8 @@ use game::{
     timestamp::get_human_readable_nano,
 };
 
-use crate::websocket::demo_comp::call_api_sync;
+use crate::comp::pager::{Pager, PagerButtons};
+use crate::{app_root::THUMBNAIL_URL, websocket::demo_comp::call_api_sync};
 use game::api::websocket::GetAllGamesArg;
 use leptos::*;
 use leptos_struct_table::*;
@@ -16,8 +17,12 @@ use leptos_struct_table::*;
 #[component]
 pub fn AllGamesTable(list_type: GetAllGamesArg) -> impl IntoView {
     let all_games = create_rw_signal(vec![]);
-    call_api_sync::<GetAllGames>(list_type, move |r| {
-        all_games.set(r);
+    let pager = Pager::new();
+    create_effect(move |_| {
+        call_api_sync::<GetAllGames>((list_type, pager.request()), move |r| {
+            pager.loaded(r.next);
+            all_games.set(r.items);
+        });
     });
     let table_from_rows = move || {
         let rows = all_games.get();
@@ -35,7 +40,10 @@ pub fn AllGamesTable(list_type: GetAllGamesArg) -> impl IntoView {
             .into_view()
     };
 
-    view! { {table_from_rows} }
+    view! {
+        {table_from_rows}
+        <PagerButtons pager/>
+    }
 }
 
 #[allow(unused_variables, non_snake_case)]
@@ -94,6 +102,8 @@ pub struct FullGameReplayTableRow {
     pub start_time: i64,
     pub num_segments: usize,
     pub is_in_progress: bool,
+    #[table(renderer = "ThumbnailRenderer")]
+    pub thumbnail: String,
 }
 
 impl FullGameReplayTableRow {
@@ -104,6 +114,7 @@ impl FullGameReplayTableRow {
             start_time: db_row.0.start_time,
             num_segments: db_row.1.segment_count as usize,
             is_in_progress: db_row.1.is_in_progress,
+            thumbnail: format!("{THUMBNAIL_URL}/game/{}", db_row.0.to_url()),
         }
     }
 
@@ -174,3 +185,21 @@ where
         </td>
     }
 }
+
+#[allow(unused_variables)]
+#[component]
+fn ThumbnailRenderer<F>(
+    class: String,
+    #[prop(into)] value: MaybeSignal<String>,
+    on_change: F,
+    index: usize,
+) -> impl IntoView
+where
+    F: Fn(String) + 'static,
+{
+    view! {
+        <td class=class>
+            <img src=move || value.get() loading="lazy" style="height: 6em"/>
+        </td>
+    }
+}
@@ -6,4 +6,8 @@ pub mod page_user_profile;
 pub mod page_vs_cpu;
 pub mod page_spectate;
 pub mod homepage;
-pub mod page_match;
\ No newline at end of file
+pub mod page_match;
+pub mod page_coop;
+pub mod page_room;
+pub mod page_leaderboard;
+pub mod page_friends;
@@ -1,5 +1,8 @@
 use crate::{comp::menu_grid_view::MenuGridView, websocket::demo_comp::call_api_sync};
-use game::api::{game_replay::GameId, websocket::CreateNewGameId};
+use crate::page::page_leaderboard::WorldRecord;
+use game::api::{game_replay::GameId, leaderboard::GameMode, websocket::CreateNewGameId};
+use game::pieces::PieceSet;
+use game::rules::{GameRules, LockedVisibility};
 use leptos::*;
 use leptos_router::{use_navigate, use_params_map, NavigateOptions};
 use crate::comp::game_board_player::PlayerGameBoardFromId;
@@ -38,9 +41,10 @@ pub fn Game1PPage() -> impl IntoView {
 #[component]
 pub fn GameSoloLobbyPage() -> impl IntoView {
     
+    let rules = create_rw_signal(GameRules::standard());
     let redirect_to_new_game = Callback::new(move |_|{
         let navigate = use_navigate();
-         call_api_sync::<CreateNewGameId>((), move |r:GameId| {
+         call_api_sync::<CreateNewGameId>(rules.get_untracked(), move |r:GameId| {
             let new_url = format!("/play-game-solo/{}", r.to_url());
             navigate(&new_url, NavigateOptions::default());
          });        
@@ -52,6 +56,8 @@ pub fn GameSoloLobbyPage() -> impl IntoView {
     let views:Vec<_> = {0..20}.into_iter().map(move |x|{
         match x{
             0 => play_button.clone(),
+            6 => view! { <ModePicker rules/> }.into_view(),
+            8 => view! { <ModifierPicker rules/> }.into_view(),
             _ => view!{            }.into_view()
             
         }
@@ -61,3 +67,78 @@ pub fn GameSoloLobbyPage() -> impl IntoView {
 }
 
 
+
+/// Ranked modes for the next solo game; picking one resets the modifiers.
+#[component]
+pub fn ModePicker(rules: RwSignal<GameRules>) -> impl IntoView {
+    let buttons = GameMode::RANKED.into_iter().map(|mode| {
+        let mode_rules = mode.rules().unwrap();
+        let selected_rules = mode_rules.clone();
+        view! {
+            <label style="display:block">
+                <input
+                    type="radio"
+                    name="solo_mode"
+                    prop:checked=move || rules.with(|r| *r == selected_rules)
+                    on:change=move |_| rules.set(mode_rules.clone())
+                />
+                {mode.name()}
+            </label>
+        }
+    }).collect_view();
+    let record = move || {
+        let mode = rules.with(|r| GameMode::of_game(r, false));
+        GameMode::RANKED.contains(&mode).then(|| view! { <WorldRecord mode/> })
+    };
+    view! {
+        <h3>Mode</h3>
+        {buttons}
+        {record}
+    }
+}
+
+/// Challenge modifiers for the next solo game.
+#[component]
+pub fn ModifierPicker(rules: RwSignal<GameRules>) -> impl IntoView {
+    let toggle = move |label: &'static str,
+                       get: fn(&GameRules) -> bool,
+                       set: fn(&mut GameRules, bool)| {
+        view! {
+            <label style="display:block">
+                <input
+                    type="checkbox"
+                    prop:checked=move || rules.with(get)
+                    on:change=move |ev| {
+                        let on = event_target_checked(&ev);
+                        rules.update(|r| set(r, on));
+                    }
+                />
+                {label}
+            </label>
+        }
+    };
+    let standard = GameRules::standard();
+    view! {
+        <h3>Modifiers</h3>
+        {toggle("20G", |r| r.modifiers.instant_gravity, |r, on| r.modifiers.instant_gravity = on)}
+        {toggle("invisible", |r| r.modifiers.locked_pieces == LockedVisibility::Invisible, |r, on| {
+            r.modifiers.locked_pieces = if on { LockedVisibility::Invisible } else { LockedVisibility::Visible };
+        })}
+        {toggle("fading", |r| r.modifiers.locked_pieces == LockedVisibility::Fading, |r, on| {
+            r.modifiers.locked_pieces = if on { LockedVisibility::Fading } else { LockedVisibility::Visible };
+        })}
+        {toggle("mirror", |r| r.modifiers.mirror, |r, on| r.modifiers.mirror = on)}
+        {toggle("big", |r| r.modifiers.big, |r, on| r.modifiers.big = on)}
+        {toggle("no hold", |r| !r.hold_enabled, |r, on| r.hold_enabled = !on)}
+        {toggle("no preview", |r| r.next_preview == 0, |r, on| {
+            r.next_preview = if on { 0 } else { GameRules::standard().next_preview };
+        })}
+        {toggle("trominoes", |r| r.pieces == PieceSet::trominoes(), |r, on| {
+            r.pieces = if on { PieceSet::trominoes() } else { PieceSet::standard() };
+        })}
+        {toggle("pentominoes", |r| r.pieces == PieceSet::pentominoes(), |r, on| {
+            r.pieces = if on { PieceSet::pentominoes() } else { PieceSet::standard() };
+        })}
+        <p>{move || if rules.get() == standard { "standard rules" } else { "custom rules" }}</p>
+    }
+}
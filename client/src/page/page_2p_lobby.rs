@@ -1,5 +1,5 @@
-use crate::{comp::table_match::AllMatchTable, websocket::demo_comp::{call_api_sync, call_api_sync_or_error}};
-use game::api::{game_match::GameMatchType, websocket::{GetMatchListArg, StartMatch}};
+use crate::{comp::table_match::AllMatchTable, websocket::demo_comp::{call_api_sync, call_api_sync_or_error, WebsocketAPI}};
+use game::api::{game_match::{GameMatchType, MatchmakingStatus}, websocket::{CancelMatchmaking, GetMatchListArg, StartMatch}};
 use leptos::*;
 use leptos_router::{use_navigate, NavigateOptions};
 #[component]
@@ -21,19 +21,52 @@ pub fn Lobby2P() -> impl IntoView {
     let waiting_for_game = create_rw_signal(false);
     let error_display = create_rw_signal("".to_string());
 
+    let api: WebsocketAPI = expect_context();
+
     let obtain_new_match_id: Callback<()> = Callback::new(move |_| {
+        api.matchmaking_status.set(None);
         waiting_for_game.set(true);
+        error_display.set("".to_string());
         log::info!("waiting for game...");
 
-        call_api_sync_or_error::<StartMatch>(GameMatchType::_1v1, move |r| {
-            waiting_for_game.set(false);
-            match_id_signal.set(Some(r));
-        }, move |err_str| {
+        call_api_sync_or_error::<StartMatch>(GameMatchType::_1v1, move |_| {}, move |err_str| {
             waiting_for_game.set(false);
             error_display.set(err_str);
         });
 
     });
+    let cancel_matchmaking = move || {
+        call_api_sync::<CancelMatchmaking>((), move |_| {});
+        waiting_for_game.set(false);
+    };
+    on_cleanup(move || {
+        if waiting_for_game.get_untracked() {
+            cancel_matchmaking();
+        }
+    });
+
+    create_effect(move |_| {
+        let status = api.matchmaking_status.get();
+        if !waiting_for_game.get_untracked() {
+            return;
+        }
+        match status {
+            Some(MatchmakingStatus::Found(match_id, match_info)) => {
+                waiting_for_game.set(false);
+                match_id_signal.set(Some((match_id, match_info)));
+            }
+            Some(MatchmakingStatus::Cancelled) => {
+                waiting_for_game.set(false);
+            }
+            _ => {}
+        }
+    });
+    let queue_status = move || match api.matchmaking_status.get() {
+        Some(MatchmakingStatus::Waiting { waited_secs, players_waiting, rating_band, .. }) => {
+            format!("{waited_secs}s, {players_waiting} in queue, rating ±{rating_band}")
+        }
+        _ => "".to_string(),
+    };
 
     create_effect(move |_| {
         if let Some(newgame) = match_id_signal.get() {
@@ -51,6 +84,10 @@ pub fn Lobby2P() -> impl IntoView {
         >
 
             <h1>WAITING FOR GAME</h1>
+            <p>{queue_status}</p>
+            <Button on_click=move |_| cancel_matchmaking() color=ButtonColor::Secondary>
+                "CANCEL"
+            </Button>
         </Show>
 
         <Show
@@ -0,0 +1,168 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+use game::api::websocket::{AppendCoopAction, CreateCoopGame, GetCoopSlices, JoinCoopGame};
+use game::coop::{CoopState, COOP_PLAYERS};
+use game::rules::GameRules;
+use game::tet::TetAction;
+use game::timestamp::get_timestamp_now_nano;
+use leptos::*;
+use leptos_router::{use_navigate, use_params_map, NavigateOptions};
+
+use crate::comp::game_board_coop::CoopBoardView;
+use crate::comp::hotkey_reader::{create_coop_hotkey_reader, create_hotkey_reader};
+use crate::websocket::demo_comp::{call_api_sync, call_api_sync_or_error};
+
+/// How often the online page asks for the other player's moves.
+const COOP_POLL_MS: u64 = 100;
+
+/// Both players on this keyboard.
+#[component]
+pub fn CoopLocalPage() -> impl IntoView {
+    let rules = GameRules::standard();
+    let new_state = move || {
+        let seed = rand::random();
+        CoopState::new(&seed, get_timestamp_now_nano(), &GameRules::standard())
+    };
+    let state = create_rw_signal(new_state());
+
+    create_coop_hotkey_reader(move |player, action| {
+        state.update(|s| {
+            let _ = s.apply_action_if_works(player, action, get_timestamp_now_nano());
+        });
+    });
+
+    let _ = leptos_use::use_interval_fn(
+        move || {
+            state.update(|s| {
+                for player in 0..COOP_PLAYERS {
+                    let _ = s.apply_action_if_works(
+                        player,
+                        TetAction::SoftDrop,
+                        get_timestamp_now_nano(),
+                    );
+                }
+            })
+        },
+        rules.gravity_ms,
+    );
+
+    let create_online = move |_| {
+        call_api_sync::<CreateCoopGame>(GameRules::standard(), move |game_id| {
+            let navigate = use_navigate();
+            navigate(&format!("/coop/{game_id}"), NavigateOptions::default());
+        });
+    };
+
+    view! {
+        <div class="main_left" style="width:142.85vmin">
+            <CoopBoardView state/>
+        </div>
+        <div style="position:absolute;top:1vmin;left:2vmin;">
+            <button on:click=move |_| state.set(new_state())>"restart"</button>
+            <button on:click=create_online>"play online"</button>
+            <p>"P1: A D S W Q, space, left shift. P2: arrows, period, enter, right shift."</p>
+        </div>
+    }
+}
+
+/// One seat each; share the link with the other player.
+#[component]
+pub fn CoopOnlinePage() -> impl IntoView {
+    let params = use_params_map();
+    let game_id = move || -> anyhow::Result<uuid::Uuid> {
+        let x = params.with(|params| params.get("game_id").cloned());
+        let x = x.context("no uuid given for game_id")?;
+        Ok(uuid::Uuid::from_str(&x)?)
+    };
+
+    let state = create_rw_signal(None::<CoopState>);
+    let seat = create_rw_signal(None::<u8>);
+    let error_display = create_rw_signal("".to_string());
+
+    create_effect(move |_| {
+        if let Ok(game_id) = game_id() {
+            call_api_sync_or_error::<JoinCoopGame>(
+                game_id,
+                move |(my_seat, info)| {
+                    seat.set(my_seat);
+                    // replay from the start so every client builds the state itself
+                    let init = &info.state.replay;
+                    let mut s = CoopState::new(&init.init_seed, init.start_time, &init.rules);
+                    for slice in &init.replay_slices {
+                        if let Err(e) = s.accept_replay_slice(slice) {
+                            log::warn!("bad co-op slice: {e:?}");
+                        }
+                    }
+                    state.set(Some(s));
+                },
+                move |err| error_display.set(err),
+            );
+        }
+    });
+
+    let fetch_slices = move || {
+        let Ok(game_id) = game_id() else { return };
+        let Some(from) = state.with_untracked(|s| {
+            s.as_ref().map(|s| s.replay.replay_slices.len() as u32)
+        }) else {
+            return;
+        };
+        call_api_sync::<GetCoopSlices>((game_id, from), move |slices| {
+            state.update(|s| {
+                let Some(s) = s else { return };
+                for slice in &slices {
+                    if slice.idx as usize != s.replay.replay_slices.len() {
+                        continue;
+                    }
+                    if let Err(e) = s.accept_replay_slice(slice) {
+                        log::warn!("bad co-op slice: {e:?}");
+                    }
+                }
+            });
+        });
+    };
+    let _ = leptos_use::use_interval_fn(fetch_slices, COOP_POLL_MS);
+
+    let send_action = move |action: TetAction| {
+        let (Ok(game_id), Some(_)) = (game_id(), seat.get_untracked()) else {
+            return;
+        };
+        if state.with_untracked(|s| s.as_ref().map_or(true, |s| s.game_over)) {
+            return;
+        }
+        call_api_sync::<AppendCoopAction>(
+            (game_id, action, get_timestamp_now_nano()),
+            move |_| fetch_slices(),
+        );
+    };
+
+    let rules = create_memo(move |_| {
+        state.with(|s| s.as_ref().map(|s| s.replay.rules.clone()).unwrap_or_default())
+    });
+    create_hotkey_reader(rules.into(), create_rw_signal(vec![]), send_action);
+    let _ = leptos_use::use_interval_fn(
+        move || send_action(TetAction::SoftDrop),
+        Signal::derive(move || rules.with(|r| r.gravity_ms)),
+    );
+
+    let board_state = Signal::derive(move || {
+        state.get().unwrap_or_else(|| CoopState::new(&[0; 32], 0, &GameRules::standard()))
+    });
+
+    view! {
+        <div class="main_left" style="width:142.85vmin">
+            <CoopBoardView state=board_state/>
+        </div>
+        <div style="position:absolute;top:1vmin;left:2vmin;">
+            <h3 style="color:red">{error_display}</h3>
+            <p>
+                {move || match seat.get() {
+                    Some(s) => format!("you are P{}", s + 1),
+                    None => "watching".to_string(),
+                }}
+
+            </p>
+        </div>
+    }
+}
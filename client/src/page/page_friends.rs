@@ -0,0 +1,103 @@
+use game::api::presence::{FollowInfo, Presence};
+use game::api::websocket::{Follow, GetFollowing, Unfollow};
+use leptos::*;
+
+use crate::comp::challenge::ChallengeButton;
+use crate::websocket::demo_comp::{call_api_sync, WebsocketAPI};
+
+#[component]
+pub fn FriendsPage() -> impl IntoView {
+    let following = create_rw_signal(vec![]);
+    let reload = move || call_api_sync::<GetFollowing>((), move |r| following.set(r));
+    reload();
+
+    view! {
+        <div class="main_left">
+            <h1>friends</h1>
+            <p>"people who follow you back are friends, and see when you are online"</p>
+            <table class="table">
+                <tbody>
+                    {move || {
+                        following
+                            .get()
+                            .into_iter()
+                            .map(|f| view! { <FollowRow f on_unfollow=reload/> })
+                            .collect_view()
+                    }}
+                </tbody>
+            </table>
+        </div>
+    }
+}
+
+#[component]
+fn FollowRow(f: FollowInfo, on_unfollow: impl Fn() + Copy + 'static) -> impl IntoView {
+    let api = expect_context::<WebsocketAPI>();
+    let user_id = f.user_id;
+    // pushed updates are newer than the list
+    let presence = move || {
+        let pushed = api.presence.with(|p| p.get(&user_id).copied());
+        pushed.or(f.presence)
+    };
+    let unfollow = move |_| call_api_sync::<Unfollow>(user_id, move |_| on_unfollow());
+
+    view! {
+        <tr>
+            <td>
+                <a href=format!("/user/{user_id}")>{f.display_name.clone()}</a>
+            </td>
+            <td>{if f.follows_back { "friend" } else { "following" }}</td>
+            <td>
+                {move || match presence() {
+                    None => view! { "-" }.into_view(),
+                    Some(Presence::Offline) => view! { "offline" }.into_view(),
+                    Some(Presence::Online) => view! { "online" }.into_view(),
+                    Some(Presence::Playing(game_id)) => {
+                        view! {
+                            "playing - "
+                            <a href=format!(
+                                "/spectate-game/{}",
+                                game_id.to_url(),
+                            )>"spectate"</a>
+                        }
+                            .into_view()
+                    }
+                }}
+            </td>
+            <td>
+                {move || {
+                    (presence() == Some(Presence::Online))
+                        .then(|| view! { <ChallengeButton user_id/> })
+                }}
+            </td>
+            <td>
+                <button on:click=unfollow>"unfollow"</button>
+            </td>
+        </tr>
+    }
+}
+
+/// Follows or unfollows `user_id`, whichever the current user isn't doing.
+#[component]
+pub fn FollowButton(user_id: uuid::Uuid) -> impl IntoView {
+    let following = create_rw_signal(None);
+    call_api_sync::<GetFollowing>((), move |r| {
+        following.set(Some(r.iter().any(|f| f.user_id == user_id)));
+    });
+    let toggle = move |_| match following.get_untracked() {
+        Some(true) => {
+            call_api_sync::<Unfollow>(user_id, move |_| following.set(Some(false)))
+        }
+        Some(false) => call_api_sync::<Follow>(user_id, move |_| following.set(Some(true))),
+        None => {}
+    };
+
+    view! {
+        <button on:click=toggle disabled=move || following.get().is_none()>
+            {move || match following.get() {
+                Some(true) => "unfollow",
+                _ => "follow",
+            }}
+        </button>
+    }
+}
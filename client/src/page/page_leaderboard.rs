@@ -0,0 +1,167 @@
+use game::api::leaderboard::{GameMode, GameSummary, LeaderboardView};
+use game::api::websocket::{GetLeaderboard, GetWorldRecord, WhoAmI};
+use leptonic::prelude::*;
+use leptos::*;
+
+use crate::websocket::demo_comp::{call_api_sync, WebsocketAPI};
+
+/// Time for sprints, score for everything else.
+pub fn format_result(summary: &GameSummary) -> String {
+    match summary.mode {
+        GameMode::Sprint40 => format!("{:.3}s", summary.duration_ns as f64 / 1e9),
+        _ => summary.score.to_string(),
+    }
+}
+
+#[component]
+pub fn LeaderboardPage() -> impl IntoView {
+    let me = create_rw_signal(None);
+    call_api_sync::<WhoAmI>((), move |r| me.set(Some(r.user_id)));
+
+    let mode_tabs = GameMode::RANKED
+        .into_iter()
+        .map(|mode| {
+            view! {
+                <Tab name=format!("tab-{mode:?}") label=mode.name().into_view()>
+                    <WorldRecord mode/>
+                    <Tabs mount=Mount::WhenShown>
+                        <Tab name=format!("tab-{mode:?}-all") label="Global".into_view()>
+                            <LeaderboardTable mode view=LeaderboardView::AllGames/>
+                        </Tab>
+                        <Tab name=format!("tab-{mode:?}-pb") label="Personal Bests".into_view()>
+                            <LeaderboardTable mode view=LeaderboardView::PersonalBests/>
+                        </Tab>
+                        <Tab name=format!("tab-{mode:?}-mine") label="Mine".into_view()>
+                            {move || match me.get() {
+                                Some(user_id) => {
+                                    view! {
+                                        <LeaderboardTable
+                                            mode
+                                            view=LeaderboardView::Player(user_id)
+                                        />
+                                    }
+                                        .into_view()
+                                }
+                                None => view! { <p>"..."</p> }.into_view(),
+                            }}
+                        </Tab>
+                    </Tabs>
+                </Tab>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <div class="main_left">
+            <Tabs mount=Mount::WhenShown>{mode_tabs}</Tabs>
+        </div>
+    }
+}
+
+#[component]
+pub fn LeaderboardTable(mode: GameMode, view: LeaderboardView) -> impl IntoView {
+    let rows = create_rw_signal(vec![]);
+    call_api_sync::<GetLeaderboard>((mode, view), move |r| rows.set(r));
+
+    let result_header = match mode {
+        GameMode::Sprint40 => "time",
+        _ => "score",
+    };
+    view! {
+        <table class="table">
+            <thead>
+                <tr>
+                    <th>"#"</th>
+                    <th>"player"</th>
+                    <th>{result_header}</th>
+                    <th>"lines"</th>
+                    <th>"pps"</th>
+                    <th>"verified"</th>
+                    <th></th>
+                </tr>
+            </thead>
+            <tbody>
+                {move || {
+                    rows.get()
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, s)| {
+                            let user_id = s.game_id.user_id;
+                            view! {
+                                <tr>
+                                    <td>{i + 1}</td>
+                                    <td>
+                                        <a href=format!(
+                                            "/user/{user_id}",
+                                        )>{user_id.to_string()[..8].to_string()}</a>
+                                    </td>
+                                    <td>{format_result(&s)}</td>
+                                    <td>{s.lines}</td>
+                                    <td>{format!("{:.2}", s.pps)}</td>
+                                    <td>{if s.verified { "yes" } else { "no" }}</td>
+                                    <td>
+                                        <a href=format!(
+                                            "/view-game/{}",
+                                            s.game_id.to_url(),
+                                        )>"Replay"</a>
+                                    </td>
+                                </tr>
+                            }
+                        })
+                        .collect_view()
+                }}
+            </tbody>
+        </table>
+    }
+}
+
+/// Current record for `mode`, kept fresh by the world record notification.
+#[component]
+pub fn WorldRecord(mode: GameMode) -> impl IntoView {
+    let record = create_rw_signal(None);
+    call_api_sync::<GetWorldRecord>(mode, move |r| record.set(r));
+    let api = expect_context::<WebsocketAPI>();
+    create_effect(move |_| {
+        if let Some(new_record) = api.world_record.get() {
+            if new_record.mode == mode {
+                record.set(Some(new_record));
+            }
+        }
+    });
+
+    view! {
+        <p>
+            {move || match record.get() {
+                Some(s) => {
+                    view! {
+                        "world record: "
+                        <a href=format!(
+                            "/view-game/{}",
+                            s.game_id.to_url(),
+                        )>{format_result(&s)}</a>
+                    }
+                        .into_view()
+                }
+                None => view! { "no world record yet" }.into_view(),
+            }}
+
+        </p>
+    }
+}
+
+/// Shown in the nav when someone sets a new world record.
+#[component]
+pub fn WorldRecordBanner() -> impl IntoView {
+    let api = expect_context::<WebsocketAPI>();
+    move || {
+        api.world_record.get().map(|s| {
+            view! {
+                <a href="/leaderboard">
+                    <p style="color:darkred">
+                        {format!("new {} record: {}", s.mode.name(), format_result(&s))}
+                    </p>
+                </a>
+            }
+        })
+    }
+}
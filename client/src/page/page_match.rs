@@ -1,11 +1,19 @@
 use std::str::FromStr;
 
 use anyhow::Context;
-use game::api::{game_match::GameMatch, game_replay::GameId, websocket::{GetMatchInfo, GetSegmentCount, WhoAmI}};
+use game::api::{game_match::{GameMatch, GameMatchResult, GameSeries, GarbageBatch, GarbageTargeting, MatchPlayerState}, game_replay::GameId, websocket::{AcceptRematch, GetMatchInfo, GetMatchResult, GetMatchSeries, GetSegmentCount, SetGarbageTargeting, WhoAmI}};
 use leptos::*;
-use leptos_router::use_params_map;
+use leptos_router::{use_navigate, use_params_map, NavigateOptions};
 
-use crate::{comp::{game_board_player::PlayerGameBoardFromId, game_board_spectator::SpectatorGameBoard}, websocket::demo_comp::call_api_sync};
+use crate::{comp::{game_board_player::PlayerGameBoardFromId, game_board_spectator::SpectatorGameBoard, menu_grid_view::MenuGridView}, websocket::demo_comp::{call_api_sync, call_api_sync_or_error, WebsocketAPI}};
+
+/// How often a finished match asks whether the rematch started.
+const SERIES_POLL_MS: u64 = 1000;
+/// Mini boards that fit around our own board in `MenuGridView`; the rest go
+/// to the right side.
+const GRID_MINI_BOARDS: usize = 16;
+/// The biggest cell of `MenuGridView`.
+const GRID_MAIN_SLOT: usize = 8;
 
 #[component]
 pub fn MatchPage() -> impl IntoView {
@@ -18,9 +26,6 @@ pub fn MatchPage() -> impl IntoView {
         Ok(x)
     };
 
-    let ginfo_0 = create_rw_signal(None);
-    let ginfo_1  = create_rw_signal(None);
-    
     let match_info = create_rw_signal(None); 
     create_effect(move |_|{
         if let Ok(match_uuid) = url() {
@@ -30,92 +35,317 @@ pub fn MatchPage() -> impl IntoView {
         }
     });
 
+    // one entry per player of the match, filled in as the replies come
+    let ginfos = create_rw_signal(vec![]);
     create_effect(move |_| {
         if let Some((_, match_info)) = match_info.get() {
-            let gameinfo_0 = GameId {
-                user_id: match_info.users[0],
-                init_seed: match_info.seed,
-                start_time: match_info.time,
-            };
-            let gameinfo_1 = GameId {
-                user_id: match_info.users[1],
-                init_seed: match_info.seed,
-                start_time: match_info.time,
-            };
-
-            call_api_sync::<GetSegmentCount>(gameinfo_0, move |r| {
-                ginfo_0.set(Some((gameinfo_0, r)));
-            });
-
-
-            call_api_sync::<GetSegmentCount>(gameinfo_1, move |r| {
-                ginfo_1.set(Some((gameinfo_1, r)));
-            });
-
+            ginfos.set(vec![None; match_info.users.len()]);
+            for (i, user_id) in match_info.users.iter().enumerate() {
+                let game_id = GameId {
+                    user_id: *user_id,
+                    init_seed: match_info.seed,
+                    start_time: match_info.time,
+                };
+                call_api_sync::<GetSegmentCount>(game_id, move |r| {
+                    ginfos.update(|g| {
+                        if let Some(slot) = g.get_mut(i) {
+                            *slot = Some((game_id, r));
+                        }
+                    });
+                });
+            }
         }
     });
+    let all_ginfo = move || {
+        let g = ginfos.get();
+        if g.is_empty() {
+            return None;
+        }
+        g.into_iter().collect::<Option<Vec<_>>>()
+    };
 
     let guest_id = create_rw_signal(None);
     call_api_sync::<WhoAmI>((), move |r| {
         guest_id.set(Some(r));
     });
 
-   let left_view = create_rw_signal(view!{}.into_view());
+    let match_result = create_rw_signal(None::<GameMatchResult>);
+    create_effect(move |_| {
+        if let Some((match_uuid, _)) = match_info.get() {
+            call_api_sync::<GetMatchResult>(match_uuid, move |r| {
+                if r.is_some() {
+                    match_result.set(r);
+                }
+            });
+        }
+    });
+
+    // garbage for our own board, and the end of the match, come from the server
+    let my_match_state = create_rw_signal(None::<MatchPlayerState>);
+    let api: WebsocketAPI = expect_context();
+    create_effect(move |_| {
+        let (Some(ginfo), Some(whoami)) = (all_ginfo(), guest_id.get()) else {
+            return;
+        };
+        let Some(mine) = ginfo.into_iter().map(|g| g.0).find(|g| g.user_id.eq(&whoami.user_id)) else {
+            return;
+        };
+        api.subscribe_to_match(&mine, Callback::new(move |update: MatchPlayerState| {
+            if update.result.is_some() {
+                match_result.set(update.result.clone());
+            }
+            my_match_state.set(Some(update));
+        }));
+        let api = api.clone();
+        on_cleanup(move || api.stop_subscribe_to_match(&mine));
+    });
+    let incoming_garbage: Signal<Vec<GarbageBatch>> = Signal::derive(move || {
+        my_match_state.with(|s| s.as_ref().map(|s| s.incoming.clone()).unwrap_or_default())
+    });
+
+    let left_view = create_rw_signal(view!{}.into_view());
     let right_view = create_rw_signal(view!{}.into_view()); 
 
     let title_sig = create_rw_signal("".to_string());
     create_effect(move |_| {
         if let (
-            Some(g0), 
-            Some(g1), 
+            Some(ginfo),
             Some(whoami), 
             Some(match_info)
-        ) = (ginfo_0.get(), ginfo_1.get(), guest_id.get(), match_info.get()) {
+        ) = (all_ginfo(), guest_id.get(), match_info.get()) {
             log::info!("===> got final effect");
 
             title_sig.set(match_info.1.title);
-            let v0 = view! {
-                <MatchGameBoard
-                    game_id=g0.0
-                    is_in_progress=g0.1.is_in_progress
-                    is_mine=g0.0.user_id.eq(&whoami.user_id)
-                />
-            }.into_view();
-             let v1 = view! {
-                 <MatchGameBoard
-                     game_id=g1.0
-                     is_in_progress=g1.1.is_in_progress
-                     is_mine=g1.0.user_id.eq(&whoami.user_id)
-                 />
-             }.into_view();
-
-            if g1.0.user_id.eq(&whoami.user_id) {
-                left_view.set(v1);
-                right_view.set(v0);
+            let is_over = match_result.with(|r| r.is_some());
+            let mut boards: Vec<_> = ginfo
+                .iter()
+                .map(|(game_id, count)| {
+                    view! {
+                        <MatchGameBoard
+                            game_id=*game_id
+                            is_in_progress=count.is_in_progress && !is_over
+                            is_mine=game_id.user_id.eq(&whoami.user_id)
+                            incoming_garbage
+                        />
+                    }.into_view()
+                })
+                .collect();
+            // our own board goes first
+            let mine = ginfo.iter().position(|g| g.0.user_id.eq(&whoami.user_id)).unwrap_or(0);
+            let my_board = boards.remove(mine);
+
+            if boards.len() == 1 {
+                left_view.set(view! { <div class="main_left">{my_board}</div> }.into_view());
+                right_view.set(boards.remove(0));
             } else {
-                left_view.set(v0);
-                right_view.set(v1);
+                let overflow = boards.split_off(boards.len().min(GRID_MINI_BOARDS));
+                let mut views = boards;
+                views.insert(GRID_MAIN_SLOT.min(views.len()), my_board);
+                left_view.set(view! { <MenuGridView views/> }.into_view());
+                right_view.set(view! {
+                    <div style="display:flex;flex-wrap:wrap;height:100%;">
+                        {overflow
+                            .into_iter()
+                            .map(|v| view! { <div style="width:25%;height:25%;">{v}</div> })
+                            .collect_view()}
+                    </div>
+                }.into_view());
             }
         };
     });
     
+    let result_text = move || {
+        let result = match_result.get()?;
+        let me = guest_id.get().map(|g| g.user_id);
+        Some(match me.and_then(|me| result.position_of(&me)) {
+            Some(1) => "you win".to_string(),
+            Some(_) if result.podium.len() == 2 => "you lose".to_string(),
+            Some(position) => format!("you placed #{position}"),
+            None => {
+                let winners: Vec<_> = result.winners.iter().map(|w| w.to_string()).collect();
+                format!("winner: {}", winners.join(", "))
+            }
+        })
+    };
+
+    // who our attack goes to, for matches with more than one opponent
+    let many_opponents = move || match_info.with(|m| m.as_ref().is_some_and(|m| m.1.users.len() > 2));
+    let set_targeting = move |targeting: GarbageTargeting| {
+        if let Ok(match_uuid) = url() {
+            call_api_sync::<SetGarbageTargeting>((match_uuid, targeting), move |_| {});
+        }
+    };
+    let targeting_view = move || {
+        let state = my_match_state.get()?;
+        if !many_opponents() || state.result.is_some() {
+            return None;
+        }
+        if let Some(placement) = state.placement.filter(|_| state.out) {
+            return Some(view! { <p>{format!("knocked out, #{placement}")}</p> }.into_view());
+        }
+        let buttons = [
+            (GarbageTargeting::Random, "random"),
+            (GarbageTargeting::Attackers, "attackers"),
+            (GarbageTargeting::KOs, "KOs"),
+            (GarbageTargeting::Badges, "badges"),
+        ]
+        .into_iter()
+        .map(|(targeting, label)| {
+            let style = if state.targeting == targeting { "font-weight:bold" } else { "" };
+            view! {
+                <button style=style on:click=move |_| set_targeting(targeting)>
+                    {label}
+                </button>
+            }
+        })
+        .collect_view();
+        Some(view! {
+            <p>{format!("KOs: {}", state.kos)} " target: " {buttons}</p>
+        }.into_view())
+    };
+
+    let series = create_rw_signal(None::<(uuid::Uuid, GameSeries)>);
+    // the next game that was already there when the page loaded
+    let known_next = create_rw_signal(None::<Option<uuid::Uuid>>);
+    let series_error = create_rw_signal("".to_string());
+
+    // the router keeps this page when going to the next match of the series
+    create_effect(move |_| {
+        let _ = url();
+        match_result.set(None);
+        my_match_state.set(None);
+        series.set(None);
+        known_next.set(None);
+        series_error.set("".to_string());
+    });
+
+    let next_match = move |s: &GameSeries| {
+        let current = url().ok()?;
+        let i = s.matches.iter().position(|m| *m == current)?;
+        s.matches.get(i + 1).cloned().or(s.rematch_match)
+    };
+    let on_series = move |r: Option<(uuid::Uuid, GameSeries)>| {
+        if known_next.get_untracked().is_none() {
+            known_next.set(Some(r.as_ref().and_then(|(_, s)| next_match(s))));
+        }
+        series.set(r);
+    };
+    let load_series = move || {
+        if let Ok(match_uuid) = url() {
+            call_api_sync::<GetMatchSeries>(match_uuid, on_series);
+        }
+    };
+    create_effect(move |_| {
+        let _ = match_result.with(|r| r.is_some());
+        load_series();
+    });
+    let _ = leptos_use::use_interval_fn(
+        move || {
+            if match_result.with_untracked(|r| r.is_some()) {
+                load_series();
+            }
+        },
+        SERIES_POLL_MS,
+    );
+    create_effect(move |_| {
+        // only follow games that start while we are here
+        if known_next.get() != Some(None) {
+            return;
+        }
+        if let Some(next) = series.with(|s| s.as_ref().and_then(|(_, s)| next_match(s))) {
+            let navigate = use_navigate();
+            navigate(&format!("/match/{next}"), NavigateOptions::default());
+        }
+    });
+
+    let series_text = move || {
+        let (_, s) = series.get()?;
+        let me = guest_id.get().map(|g| g.user_id);
+        let name = |u: &uuid::Uuid| {
+            if Some(*u) == me { "you".to_string() } else { u.to_string() }
+        };
+        let score = s
+            .users
+            .iter()
+            .map(|u| format!("{} {}", name(u), s.wins_of(u)))
+            .collect::<Vec<_>>()
+            .join(" - ");
+        let status = match s.winner() {
+            Some(w) => format!("{} won the series", name(&w)),
+            None => format!("first to {}", s.first_to),
+        };
+        Some(format!("{score} ({status})"))
+    };
+
+    let rematch = move |_| {
+        if let Ok(match_uuid) = url() {
+            call_api_sync_or_error::<AcceptRematch>(
+                match_uuid,
+                move |s| on_series(series.get_untracked().map(|(id, _)| (id, s))),
+                move |err| series_error.set(err),
+            );
+        }
+    };
+    let can_rematch = move || {
+        let me = guest_id.get().map(|g| g.user_id);
+        match_result.with(|r| r.is_some())
+            && series.with(|s| {
+                s.as_ref().is_some_and(|(_, s)| {
+                    me.is_some_and(|me| s.users.contains(&me)) && next_match(s).is_none()
+                })
+            })
+    };
+    let rematch_text = move || {
+        let me = guest_id.get().map(|g| g.user_id);
+        series.with(|s| {
+            let Some((_, s)) = s else { return "" };
+            if me.is_some_and(|me| s.rematch.contains(&me)) {
+                "waiting for opponent"
+            } else if !s.rematch.is_empty() {
+                "opponent wants a rematch - accept"
+            } else if s.winner().is_some() {
+                "rematch"
+            } else {
+                "next game"
+            }
+        })
+    };
+    let old_next = move || {
+        known_next.get().flatten().map(|next| {
+            view! { <a href=format!("/match/{next}")>"next game"</a> }
+        })
+    };
+
     view! {
         <h1>{title_sig}</h1>
-        <div class="main_left">{move || left_view.get()}</div>
+        <h2>{result_text}</h2>
+        <h3>{series_text}</h3>
+        <Show when=can_rematch fallback=|| view! {}>
+            <button on:click=rematch>{rematch_text}</button>
+        </Show>
+        {old_next}
+        <h3 style="color:red">{series_error}</h3>
+        {targeting_view}
+        {move || left_view.get()}
         <div class="main_right">{move || right_view.get()}</div>
     }
 }
 
 
 #[component]
-pub fn MatchGameBoard(game_id: GameId, is_in_progress: bool, is_mine: bool) -> impl IntoView {
+pub fn MatchGameBoard(
+    game_id: GameId,
+    is_in_progress: bool,
+    is_mine: bool,
+    incoming_garbage: Signal<Vec<GarbageBatch>>,
+) -> impl IntoView {
 
     match (is_in_progress, is_mine) {
         (false, _) => {
             view! { <SpectatorGameBoard game_id/> }.into_view()
         },
         (true, true) => {
-            view! { <PlayerGameBoardFromId game_id=game_id/> }.into_view()
+            view! { <PlayerGameBoardFromId game_id=game_id incoming_garbage/> }.into_view()
         },
 
         (true, false) => {
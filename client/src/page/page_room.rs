@@ -0,0 +1,329 @@
+use game::api::game_match::GameMatchType;
+use game::api::room::{RoomInfo, RoomSettings};
+use game::api::websocket::{CreateRoom, GetRoom, JoinRoom, LeaveRoom, SetRoomReady, SetRoomSettings, WhoAmI};
+use game::timestamp::get_timestamp_now_nano;
+use leptos::*;
+use leptos_router::{use_navigate, use_params_map, NavigateOptions};
+
+use crate::page::page_1p::ModifierPicker;
+use crate::websocket::demo_comp::{call_api_sync, call_api_sync_or_error};
+
+/// How often the room page asks who is in the room.
+const ROOM_POLL_MS: u64 = 1000;
+
+const ROOM_MATCH_TYPES: [(GameMatchType, &str); 4] = [
+    (GameMatchType::_1v1, "1v1"),
+    (GameMatchType::_4v4, "4v4"),
+    (GameMatchType::_10v10, "10v10"),
+    (GameMatchType::BattleRoyale, "battle royale"),
+];
+
+/// Pick the rules, then share the room link.
+#[component]
+pub fn RoomCreatePage() -> impl IntoView {
+    let settings = create_rw_signal(RoomSettings::default());
+    let error_display = create_rw_signal("".to_string());
+    let join_code = create_rw_signal("".to_string());
+
+    let create_room = move |_| {
+        call_api_sync_or_error::<CreateRoom>(
+            settings.get_untracked(),
+            move |room| {
+                let navigate = use_navigate();
+                navigate(&format!("/room/{}", room.code), NavigateOptions::default());
+            },
+            move |err| error_display.set(err),
+        );
+    };
+    let join_room = move |_| {
+        let code = join_code.get_untracked().trim().to_uppercase();
+        let navigate = use_navigate();
+        navigate(&format!("/room/{code}"), NavigateOptions::default());
+    };
+
+    view! {
+        <div class="main_left">
+            <h1>private room</h1>
+            <RoomSettingsEditor settings/>
+            <button on:click=create_room>"create room"</button>
+            <h3 style="color:red">{error_display}</h3>
+            <h2>join a room</h2>
+            <input
+                type="text"
+                placeholder="code"
+                prop:value=join_code
+                on:input=move |ev| join_code.set(event_target_value(&ev))
+            />
+            <button on:click=join_room>"join"</button>
+        </div>
+    }
+}
+
+#[component]
+pub fn RoomSettingsEditor(settings: RwSignal<RoomSettings>) -> impl IntoView {
+    let rules = create_rw_signal(settings.get_untracked().rules);
+    create_effect(move |_| {
+        let r = settings.with(|s| s.rules.clone());
+        if r != rules.get_untracked() {
+            rules.set(r);
+        }
+    });
+    create_effect(move |_| {
+        let r = rules.get();
+        if settings.with_untracked(|s| s.rules != r) {
+            settings.update(|s| s.rules = r);
+        }
+    });
+
+    view! {
+        <label style="display:block">
+            "mode "
+            <select on:change=move |ev| {
+                let i = event_target_value(&ev).parse::<usize>().unwrap_or(0);
+                if let Some((match_type, _)) = ROOM_MATCH_TYPES.get(i) {
+                    settings.update(|s| s.match_type = match_type.clone());
+                }
+            }>
+                {ROOM_MATCH_TYPES
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (match_type, label))| {
+                        let match_type = match_type.clone();
+                        view! {
+                            <option
+                                value=i.to_string()
+                                selected=move || settings.with(|s| s.match_type == match_type)
+                            >
+                                {*label}
+                            </option>
+                        }
+                    })
+                    .collect_view()}
+            </select>
+        </label>
+        <ModifierPicker rules/>
+        <label style="display:block">
+            "gravity (ms) "
+            <input
+                type="number"
+                min="1"
+                prop:value=move || rules.with(|r| r.gravity_ms)
+                on:change=move |ev| {
+                    if let Ok(ms) = event_target_value(&ev).parse::<u64>() {
+                        rules.update(|r| r.gravity_ms = ms.max(1));
+                    }
+                }
+            />
+        </label>
+        <label style="display:block">
+            <input
+                type="checkbox"
+                prop:checked=move || settings.with(|s| s.garbage.enabled)
+                on:change=move |ev| {
+                    let on = event_target_checked(&ev);
+                    settings.update(|s| s.garbage.enabled = on);
+                }
+            />
+            "garbage"
+        </label>
+        <label style="display:block">
+            "garbage multiplier (%) "
+            <input
+                type="number"
+                min="0"
+                prop:value=move || settings.with(|s| s.garbage.multiplier_percent)
+                on:change=move |ev| {
+                    if let Ok(p) = event_target_value(&ev).parse::<u32>() {
+                        settings.update(|s| s.garbage.multiplier_percent = p);
+                    }
+                }
+            />
+        </label>
+        <label style="display:block">
+            "first to (wins) "
+            <input
+                type="number"
+                min="1"
+                prop:value=move || settings.with(|s| s.first_to)
+                on:change=move |ev| {
+                    if let Ok(n) = event_target_value(&ev).parse::<u32>() {
+                        settings.update(|s| s.first_to = n.max(1));
+                    }
+                }
+            />
+        </label>
+    }
+}
+
+/// Anyone with the link lands here; players ready up and the room sends
+/// everyone to the match page when it starts.
+#[component]
+pub fn RoomPage() -> impl IntoView {
+    let params = use_params_map();
+    let code = move || params.with(|p| p.get("code").cloned().unwrap_or_default());
+
+    let room = create_rw_signal(None::<RoomInfo>);
+    let error_display = create_rw_signal("".to_string());
+    let me = create_rw_signal(None);
+    call_api_sync::<WhoAmI>((), move |r| me.set(Some(r.user_id)));
+
+    // matches that were over before we got here
+    let seen_matches = create_rw_signal(None::<usize>);
+    let on_room = move |r: RoomInfo| {
+        if seen_matches.get_untracked().is_none() {
+            seen_matches.set(Some(r.matches.len()));
+        }
+        room.set(Some(r));
+    };
+
+    create_effect(move |_| {
+        call_api_sync_or_error::<JoinRoom>((code(), true), on_room, move |err| {
+            error_display.set(err)
+        });
+    });
+    let _ = leptos_use::use_interval_fn(
+        move || {
+            if room.with_untracked(|r| r.is_some()) {
+                call_api_sync::<GetRoom>(code(), on_room);
+            }
+        },
+        ROOM_POLL_MS,
+    );
+
+    create_effect(move |_| {
+        let Some(seen) = seen_matches.get() else { return };
+        let new_match = room.with(|r| {
+            r.as_ref().and_then(|r| r.matches.get(seen..).and_then(|m| m.last().cloned()))
+        });
+        if let Some(match_id) = new_match {
+            let navigate = use_navigate();
+            navigate(&format!("/match/{match_id}"), NavigateOptions::default());
+        }
+    });
+
+    let is_player = move || {
+        room.with(|r| me.with(|me| matches!((r, me), (Some(r), Some(me)) if r.is_player(me))))
+    };
+    let is_host = move || {
+        room.with(|r| me.with(|me| matches!((r, me), (Some(r), Some(me)) if r.host == *me)))
+    };
+    let my_ready = move || {
+        room.with(|r| {
+            me.with(|me| {
+                r.as_ref()
+                    .zip(me.as_ref())
+                    .and_then(|(r, me)| r.players.iter().find(|p| p.user_id == *me))
+                    .is_some_and(|p| p.ready)
+            })
+        })
+    };
+
+    let toggle_ready = move |_| {
+        call_api_sync_or_error::<SetRoomReady>((code(), !my_ready()), on_room, move |err| {
+            error_display.set(err)
+        });
+    };
+    let spectate = move |_| {
+        call_api_sync::<LeaveRoom>(code(), move |_| {
+            call_api_sync::<JoinRoom>((code(), false), on_room);
+        });
+    };
+    let take_seat = move |_| {
+        call_api_sync_or_error::<JoinRoom>((code(), true), on_room, move |err| {
+            error_display.set(err)
+        });
+    };
+
+    // the host's edits stay until the room's rules change
+    let settings = create_rw_signal(RoomSettings::default());
+    let room_settings = create_memo(move |_| room.with(|r| r.as_ref().map(|r| r.settings.clone())));
+    create_effect(move |_| {
+        if let Some(s) = room_settings.get() {
+            settings.set(s);
+        }
+    });
+    let apply_settings = move |_| {
+        call_api_sync_or_error::<SetRoomSettings>(
+            (code(), settings.get_untracked()),
+            on_room,
+            move |err| error_display.set(err),
+        );
+    };
+
+    let invite_link = move || {
+        let origin = window().location().origin().unwrap_or_default();
+        format!("{origin}/room/{}", code())
+    };
+
+    view! {
+        <div class="main_left">
+            <h1>"room " {code}</h1>
+            <p>"invite link: " <code>{invite_link}</code></p>
+            <h3 style="color:red">{error_display}</h3>
+            {move || {
+                room.get()
+                    .map(|r| {
+                        view! {
+                            <h3>{format!("players {}/{}", r.players.len(), r.seats())}</h3>
+                            {r
+                                .starts_at
+                                .map(|t| {
+                                    let left = (t - get_timestamp_now_nano()).max(0);
+                                    let secs = (left + 999_999_999) / 1_000_000_000;
+                                    view! { <h3>{format!("starting in {secs}s")}</h3> }
+                                })}
+                            <ul>
+                                {r
+                                    .players
+                                    .iter()
+                                    .map(|p| {
+                                        view! {
+                                            <li>
+                                                <a href=format!(
+                                                    "/user/{}",
+                                                    p.user_id,
+                                                )>{format!("{}", p.user_id)}</a>
+                                                {if p.user_id == r.host { " (host)" } else { "" }}
+                                                {if p.ready { " - ready" } else { "" }}
+                                            </li>
+                                        }
+                                    })
+                                    .collect_view()}
+                            </ul>
+                            <p>{format!("{} watching", r.spectators.len())}</p>
+                            <ul>
+                                {r
+                                    .matches
+                                    .iter()
+                                    .map(|m| {
+                                        view! {
+                                            <li>
+                                                <a href=format!("/match/{m}")>{format!("match {m}")}</a>
+                                            </li>
+                                        }
+                                    })
+                                    .collect_view()}
+                            </ul>
+                        }
+                    })
+            }}
+
+            <Show
+                when=is_player
+                fallback=move || view! { <button on:click=take_seat>"play"</button> }
+            >
+                <button on:click=toggle_ready>
+                    {move || if my_ready() { "not ready" } else { "ready" }}
+                </button>
+                <button on:click=spectate>"spectate"</button>
+            </Show>
+        </div>
+        <div class="main_right">
+            <Show when=is_host fallback=|| view! {}>
+                <h2>rules</h2>
+                <RoomSettingsEditor settings/>
+                <button on:click=apply_settings>"apply"</button>
+            </Show>
+        </div>
+    }
+}
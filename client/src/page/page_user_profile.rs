@@ -1,9 +1,19 @@
 use leptos::*;
 
+use crate::comp::rating_graph::RatingGraph;
+use crate::comp::stats_dashboard::StatsDashboard;
+use crate::comp::challenge::ChallengeButton;
+use crate::page::page_friends::FollowButton;
+use crate::comp::table_match::{AllMatchTable, MatchHistoryTable};
 use crate::comp::table_replay_games::AllGamesTable;
-use crate::websocket::demo_comp::call_api_sync;
+use crate::websocket::demo_comp::{call_api_sync, call_api_sync_or_error};
+use game::api::game_match::GameMatchType;
 use game::api::user;
-use game::api::websocket::{GetAllGamesArg, GetProfile, WhoAmI};
+use game::api::websocket::{
+    GetAllGamesArg, GetMatchListArg, GetProfile, Login, Logout, Register, UpdateProfile,
+    WhoAmI,
+};
+use game::timestamp::get_human_readable_nano;
 use leptonic::prelude::*;
 
 #[component]
@@ -41,13 +51,152 @@ pub fn MyAccountPage() -> impl IntoView {
     view! {
         <h2>account</h2>
         <pre>{{ move || format!("guest_info: {:?}", guest_id.get()) }}</pre>
+        <AccountForm guest_id/>
 
         <h2>profile</h2>
         <pre>{{ move || format!("user_profile: {:?}", user_profile.get()) }}</pre>
+        {move || user_profile.get().map(|p| view! { <ProfileEditor p user_profile/> })}
         <h3>{{ user_link }}</h3>
     }
 }
 
+#[component]
+fn ProfileEditor(
+    p: user::UserProfile,
+    user_profile: RwSignal<Option<user::UserProfile>>,
+) -> impl IntoView {
+    let update = create_rw_signal(user::ProfileUpdate::of(&p));
+    let error_display = create_rw_signal(String::new());
+    let save = move |_| {
+        call_api_sync_or_error::<UpdateProfile>(
+            update.get_untracked(),
+            move |r| {
+                error_display.set(String::new());
+                user_profile.set(Some(r));
+            },
+            move |err| error_display.set(err),
+        );
+    };
+
+    view! {
+        <label style="display:block">
+            "display name "
+            <input
+                type="text"
+                prop:value=move || update.with(|u| u.display_name.clone())
+                on:input=move |ev| update.update(|u| u.display_name = event_target_value(&ev))
+            />
+        </label>
+        <label style="display:block">
+            "avatar "
+            <select on:change=move |ev| {
+                let i = event_target_value(&ev).parse::<usize>().unwrap_or(0);
+                if let Some(avatar) = user::Avatar::ALL.get(i) {
+                    update.update(|u| u.avatar = *avatar);
+                }
+            }>
+                {user::Avatar::ALL
+                    .iter()
+                    .enumerate()
+                    .map(|(i, avatar)| {
+                        let avatar = *avatar;
+                        view! {
+                            <option
+                                value=i.to_string()
+                                selected=move || update.with(|u| u.avatar == avatar)
+                            >
+                                {avatar.tet().name()}
+                            </option>
+                        }
+                    })
+                    .collect_view()}
+            </select>
+        </label>
+        <label style="display:block">
+            "country "
+            <input
+                type="text"
+                placeholder="DE"
+                maxlength="2"
+                prop:value=move || update.with(|u| u.country.clone().unwrap_or_default())
+                on:input=move |ev| {
+                    let country = event_target_value(&ev).trim().to_uppercase();
+                    update.update(|u| u.country = Some(country).filter(|c| !c.is_empty()))
+                }
+            />
+        </label>
+        <label style="display:block">
+            "bio "
+            <textarea
+                maxlength=user::BIO_MAX_LEN.to_string()
+                prop:value=move || update.with(|u| u.bio.clone())
+                on:input=move |ev| update.update(|u| u.bio = event_target_value(&ev))
+            ></textarea>
+        </label>
+        <button on:click=save>"save profile"</button>
+        <h3 style="color:red">{error_display}</h3>
+    }
+}
+
+/// Register or log in while a guest, log out while logged in.
+#[component]
+fn AccountForm(guest_id: RwSignal<Option<user::GuestInfo>>) -> impl IntoView {
+    let username = create_rw_signal(String::new());
+    let password = create_rw_signal(String::new());
+    let error_display = create_rw_signal(String::new());
+
+    let credentials = move || user::Credentials {
+        username: username.get_untracked().trim().to_string(),
+        password: password.get_untracked(),
+    };
+    let on_identity = move |r: user::GuestInfo| {
+        password.set(String::new());
+        error_display.set(String::new());
+        guest_id.set(Some(r));
+    };
+    let on_error = move |err| error_display.set(err);
+    let register = move |_| {
+        call_api_sync_or_error::<Register>(credentials(), on_identity, on_error);
+    };
+    let login = move |_| {
+        call_api_sync_or_error::<Login>(credentials(), on_identity, on_error);
+    };
+    let logout = move |_| {
+        call_api_sync_or_error::<Logout>((), on_identity, on_error);
+    };
+
+    let form = move || match guest_id.get().and_then(|g| g.username) {
+        Some(name) => view! {
+            <p>"logged in as " {name}</p>
+            <button on:click=logout>"log out"</button>
+        }
+        .into_view(),
+        None => view! {
+            <p>"playing as guest - register to keep your games on any device"</p>
+            <input
+                type="text"
+                placeholder="username"
+                prop:value=username
+                on:input=move |ev| username.set(event_target_value(&ev))
+            />
+            <input
+                type="password"
+                placeholder="password"
+                prop:value=password
+                on:input=move |ev| password.set(event_target_value(&ev))
+            />
+            <button on:click=login>"log in"</button>
+            <button on:click=register>"register"</button>
+        }
+        .into_view(),
+    };
+
+    view! {
+        {form}
+        <h3 style="color:red">{error_display}</h3>
+    }
+}
+
 #[component]
 pub fn UserProfilePage() -> impl IntoView {
     let params = leptos_router::use_params_map();
@@ -84,8 +233,23 @@ pub fn UserProfilePage() -> impl IntoView {
 pub fn UserProfileView(_user_id: uuid::Uuid, p: user::UserProfile) -> impl IntoView {
     view! {
         <div class="profile_view_container">
-            <h1>{{ &p.display_name }}</h1>
+            <h1>
+                <span class=format!("tet {}", p.avatar.tet().name())>
+                    {p.avatar.tet().name()}
+                </span>
+                " "
+                {{ &p.display_name }}
+            </h1>
             <h3>user_id: {{ format!("{:?}", _user_id) }}</h3>
+            <FollowButton user_id=_user_id/>
+            <ChallengeButton user_id=_user_id/>
+            <p>
+                {p.country.clone().map(|c| format!("{c} - "))}
+                "joined " {get_human_readable_nano(p.joined)}
+            </p>
+            <p>{p.bio.clone()}</p>
+            <RatingGraph user_id=_user_id match_type=GameMatchType::_1v1/>
+            <StatsDashboard user_id=_user_id/>
 
             <Tabs mount=Mount::WhenShown>
                 <Tab
@@ -105,6 +269,22 @@ pub fn UserProfileView(_user_id: uuid::Uuid, p: user::UserProfile) -> impl IntoV
                         _user_id,
                     )/>
                 </Tab>
+
+                <Tab
+                    name="tab-user-match-history"
+                    label="Match History".into_view()
+                >
+                    <MatchHistoryTable user_id=_user_id/>
+                </Tab>
+
+                <Tab
+                    name="tab-best-user-matches"
+                    label="Best Matches from $User".into_view()
+                >
+                    <AllMatchTable list_type=GetMatchListArg::BestGamesForPlayer(
+                        _user_id,
+                    )/>
+                </Tab>
             </Tabs>
 
             <code>
@@ -6,6 +6,7 @@ pub struct GameBoardTetStyle {
     pub i: String,
     pub j: String,
     pub l: String,
+    pub garbage: String,
 }
 
 impl GameBoardTetStyle {
@@ -18,6 +19,7 @@ impl GameBoardTetStyle {
             i: "#21B6F8".to_string(),
             j: "#4169E7".to_string(),
             l: "#FF8720".to_string(),
+            garbage: "#8C8C8C".to_string(),
         }
     }
 }
@@ -109,6 +111,16 @@ pub fn flex_gameboard_style(
     .tet.L.cell {            background-color: ${tet_style.l};     }
     .tet.O.cell {            background-color: ${tet_style.o};     }
     .tet.Z.cell {            background-color: ${tet_style.z};     }
+    .tet.custom.cell {       background-color: var(--piece-color);     }
+    .garbage.cell {          background-color: ${tet_style.garbage};     }
+
+    .cell.tet.invisible, .cell.garbage.invisible {
+        background-color: black;
+    }
+    .cell.tet.fading, .cell.garbage.fading {
+        background-color: black;
+        transition: background-color 2s ease-in;
+    }
 
 
     .game_over_display {
@@ -1,5 +1,5 @@
-use game::{api::{game_replay::{GameId, GameSegmentId}, websocket::{
-    APIMethod, SubscribeGamePlz, SubscribeGamePlzArgument, WebsocketAPIMessageRaw, WebsocketAPIMessageType
+use game::{api::{challenge::{Challenge, ChallengeEvent}, game_match::{MatchPlayerState, MatchmakingStatus}, leaderboard::GameSummary, presence::Presence, game_replay::{GameId, GameSegmentId}, websocket::{
+    APIMethod, SubscribeGamePlz, SubscribeGamePlzArgument, SubscribeMatchPlz, WebsocketAPIMessageRaw, WebsocketAPIMessageType
 }}, tet::GameReplaySegment};
 use leptos::*;
 use leptos_use::core::ConnectionReadyState;
@@ -27,6 +27,17 @@ pub struct WebsocketAPI {
     pub ready_signal: RwSignal<bool>,
 
     pub subscribe_game_callbacks: RwSignal<HashMap<GameId, SubscribeSegmentCallback>>,
+    pub subscribe_match_callbacks: RwSignal<HashMap<GameId, Callback<MatchPlayerState>>>,
+    /// Last word from the matchmaking queue on this connection.
+    pub matchmaking_status: RwSignal<Option<MatchmakingStatus>>,
+    /// Latest world record broken while connected.
+    pub world_record: RwSignal<Option<GameSummary>>,
+    /// Presence of friends as pushed since connecting.
+    pub presence: RwSignal<HashMap<uuid::Uuid, Presence>>,
+    /// Open challenges sent to this user.
+    pub challenges: RwSignal<Vec<(uuid::Uuid, Challenge)>>,
+    /// Last word on any challenge this user sent or answered.
+    pub challenge_event: RwSignal<Option<(uuid::Uuid, ChallengeEvent)>>,
     pub error_msgs: RwSignal<Vec<String>>,
 }
 
@@ -50,6 +61,28 @@ impl WebsocketAPI {
         });
     }
 
+    /// Garbage and result updates for a game that is part of a match.
+    pub fn subscribe_to_match(&self, game_id: &GameId, cb: Callback<MatchPlayerState>) {
+        self.subscribe_match_callbacks.update_untracked(|map| {
+            map.insert(*game_id, cb);
+        });
+        let game_id = *game_id;
+        let api = self.clone();
+        spawn_local(async move {
+            if let Ok(fut) = _call_websocket_api::<SubscribeMatchPlz>(api, game_id) {
+                if let Err(e) = fut.await {
+                    log::warn!("subscribe to match failed: {e}");
+                }
+            }
+        });
+    }
+
+    pub fn stop_subscribe_to_match(&self, game_id: &GameId) {
+        self.subscribe_match_callbacks.update_untracked(|map| {
+            map.remove(game_id);
+        })
+    }
+
    pub fn stop_subscribe_to_game(&self, game_id: &GameId) {
     self.subscribe_game_callbacks.update_untracked(|map| {
         map.remove(game_id);
@@ -158,6 +191,37 @@ pub async fn accept_subscribe_notification(_api: &WebsocketAPI, msg: WebsocketAP
             })};
             
         },
+        WebsocketAPIMessageType::MatchPlayerUpdateNotification => {
+            let (game_id, update) = bincode::deserialize::<<game::api::websocket::MatchPlayerUpdateNotification as game::api::websocket::APIMethod>::Req>(&msg.data)?;
+            _api.subscribe_match_callbacks.with_untracked(move |map| {
+                if let Some(cb) = map.get(&game_id) {
+                    cb.call(update);
+                }
+            });
+        },
+        WebsocketAPIMessageType::MatchmakingStatusNotification => {
+            let status = bincode::deserialize::<<game::api::websocket::MatchmakingStatusNotification as game::api::websocket::APIMethod>::Req>(&msg.data)?;
+            _api.matchmaking_status.set(Some(status));
+        },
+        WebsocketAPIMessageType::WorldRecordNotification => {
+            let record = bincode::deserialize::<<game::api::websocket::WorldRecordNotification as game::api::websocket::APIMethod>::Req>(&msg.data)?;
+            _api.world_record.set(Some(record));
+        },
+        WebsocketAPIMessageType::PresenceNotification => {
+            let (user_id, presence) = bincode::deserialize::<<game::api::websocket::PresenceNotification as game::api::websocket::APIMethod>::Req>(&msg.data)?;
+            _api.presence.update(|map| {
+                map.insert(user_id, presence);
+            });
+        },
+        WebsocketAPIMessageType::ChallengeNotification => {
+            let (challenge_id, event) = bincode::deserialize::<<game::api::websocket::ChallengeNotification as game::api::websocket::APIMethod>::Req>(&msg.data)?;
+            if let ChallengeEvent::Received(challenge) = event {
+                _api.challenges.update(|v| v.push((challenge_id, *challenge)));
+            } else {
+                _api.challenges.update(|v| v.retain(|(id, _)| *id != challenge_id));
+                _api.challenge_event.set(Some((challenge_id, event)));
+            }
+        },
         _x => {
             anyhow::bail!("unsupported message type for subscribe nmmotification:L {:?}", msg._type);
         }
@@ -0,0 +1,41 @@
+//! One player asking another for a match, outside of matchmaking.
+
+use serde::{Deserialize, Serialize};
+
+use super::room::RoomSettings;
+
+/// How long the target has to answer.
+pub const CHALLENGE_TTL_NS: i64 = 60_000_000_000;
+/// Time between two challenges to the same player.
+pub const CHALLENGE_COOLDOWN_NS: i64 = 30_000_000_000;
+/// Unanswered challenges one player can have out at once.
+pub const MAX_OPEN_CHALLENGES: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Challenge {
+    pub from: uuid::Uuid,
+    pub from_name: String,
+    pub to: uuid::Uuid,
+    /// Chosen by the challenger.
+    pub settings: RoomSettings,
+    pub created: i64,
+}
+
+impl Challenge {
+    pub fn expires(&self) -> i64 {
+        self.created + CHALLENGE_TTL_NS
+    }
+}
+
+/// What happened to a challenge, told to whoever it concerns.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ChallengeEvent {
+    /// To the target.
+    Received(Box<Challenge>),
+    /// To both players, with the new match.
+    Accepted(uuid::Uuid),
+    /// To the challenger.
+    Declined,
+    /// To both players.
+    Expired,
+}
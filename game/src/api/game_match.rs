@@ -10,6 +10,14 @@ pub struct GameMatch {
     pub time: i64,
     pub users: Vec<uuid::Uuid>,
     pub title: String,
+    pub match_type: GameMatchType,
+}
+
+impl GameMatch {
+    /// Team of the player at `index` in `users`: teams sit next to each other.
+    pub fn team_of(&self, index: usize) -> u32 {
+        (index / self.match_type.team_size().unwrap_or(1).max(1)) as u32
+    }
 }
 
 #[derive(
@@ -43,7 +51,14 @@ impl UserAndMatchId {
 )]
 pub struct UserAndMatchResult {
     pub is_win: bool,
+    /// 1 for the winner.
     pub podium_position: u32,
+    pub score: i64,
+    pub lines_sent: u32,
+    pub lines_received: u32,
+    pub pieces: u32,
+    /// Server time the match ended, in nanoseconds.
+    pub end_time: i64,
 }
 
 #[derive(
@@ -55,4 +70,236 @@ pub enum GameMatchType {
     _40lines,
     _10v10,
     _4v4,
+    /// Free-for-all, the last one standing wins.
+    BattleRoyale,
+}
+
+pub const BATTLE_ROYALE_MAX_PLAYERS: usize = 99;
+
+impl GameMatchType {
+    /// Players per team, or `None` if this is not a versus match.
+    pub fn team_size(&self) -> Option<usize> {
+        match self {
+            GameMatchType::_1v1 | GameMatchType::BattleRoyale => Some(1),
+            GameMatchType::_4v4 => Some(4),
+            GameMatchType::_10v10 => Some(10),
+            _ => None,
+        }
+    }
+
+    pub fn max_players(&self) -> usize {
+        match self {
+            GameMatchType::_1v1 => 2,
+            GameMatchType::_4v4 => 8,
+            GameMatchType::_10v10 => 20,
+            GameMatchType::BattleRoyale => BATTLE_ROYALE_MAX_PLAYERS,
+            _ => 1,
+        }
+    }
+
+    pub fn min_players(&self) -> usize {
+        match self {
+            GameMatchType::BattleRoyale => 2,
+            _ => self.max_players(),
+        }
+    }
+}
+
+/// A co-op game on the server: who plays which cursor, and the shared state.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct CoopGameInfo {
+    pub players: Vec<uuid::Uuid>,
+    pub state: crate::coop::CoopState,
+}
+
+/// Lines the server routed to a player, in the order they must be taken in.
+#[derive(
+    Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct GarbageBatch {
+    pub lines: u8,
+    /// Server time the batch was sent, in nanoseconds.
+    pub sent_at: i64,
+}
+
+#[derive(
+    Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct GameMatchResult {
+    /// Everyone on the team left standing.
+    pub winners: Vec<uuid::Uuid>,
+    /// Podium position of every player, 1 for the winners. Teammates share
+    /// the position of their team.
+    pub podium: Vec<(uuid::Uuid, u32)>,
+    pub end_time: i64,
+}
+
+impl GameMatchResult {
+    pub fn position_of(&self, user_id: &uuid::Uuid) -> Option<u32> {
+        self.podium
+            .iter()
+            .find(|(u, _)| u == user_id)
+            .map(|(_, p)| *p)
+    }
+}
+
+/// Who a player's attack goes to when there is more than one opponent.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+)]
+pub enum GarbageTargeting {
+    #[default]
+    Random,
+    /// Everyone that targets this player.
+    Attackers,
+    /// The opponent with the most garbage waiting, closest to a KO.
+    KOs,
+    /// The opponent with the most KOs.
+    Badges,
+}
+
+/// How attack turns into garbage in a versus match.
+#[derive(
+    Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct GarbageSettings {
+    pub enabled: bool,
+    /// Share of the attack that is sent, in percent.
+    pub multiplier_percent: u32,
+}
+
+impl Default for GarbageSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            multiplier_percent: 100,
+        }
+    }
+}
+
+impl GarbageSettings {
+    /// Lines sent for a board that has attacked with `attack` lines so far.
+    pub fn lines_sent(&self, attack: u32) -> u32 {
+        if self.enabled {
+            attack.saturating_mul(self.multiplier_percent) / 100
+        } else {
+            0
+        }
+    }
+}
+
+/// One player's side of a versus match, kept by the server's coordinator.
+#[derive(
+    Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct MatchPlayerState {
+    pub match_id: uuid::Uuid,
+    pub team: u32,
+    pub targeting: GarbageTargeting,
+    /// Who the player's last attack went to.
+    pub target: Option<uuid::Uuid>,
+    /// Who sent the player's last garbage, gets the KO.
+    pub last_attacker: Option<uuid::Uuid>,
+    pub kos: u32,
+    /// Every batch sent to this player so far.
+    pub incoming: Vec<GarbageBatch>,
+    /// How many of `incoming` the player's replay has taken in.
+    pub applied: u32,
+    /// Attack lines of this player already added to the team's pool.
+    pub routed: u32,
+    /// Topped out or forfeited; the match may go on without the player.
+    pub out: bool,
+    /// Podium position, set once the player's team is out or has won.
+    pub placement: Option<u32>,
+    pub result: Option<GameMatchResult>,
+}
+
+impl MatchPlayerState {
+    /// Garbage lines sent to the player and not taken in yet.
+    pub fn pending_lines(&self) -> u32 {
+        self.incoming
+            .iter()
+            .skip(self.applied as usize)
+            .map(|b| b.lines as u32)
+            .sum()
+    }
+}
+
+/// What the server tells a player in the matchmaking queue.
+#[derive(
+    Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum MatchmakingStatus {
+    Waiting {
+        match_type: GameMatchType,
+        waited_secs: u32,
+        players_waiting: u32,
+        /// Opponents can be this far from the player's rating.
+        rating_band: u32,
+    },
+    Found(uuid::Uuid, GameMatch),
+    Cancelled,
+}
+
+/// Matches between the same players until one of them has won `first_to`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct GameSeries {
+    pub title: String,
+    pub users: Vec<uuid::Uuid>,
+    pub first_to: u32,
+    /// Rules for every match of the series.
+    pub settings: super::room::RoomSettings,
+    /// The last one is the current one.
+    pub matches: Vec<uuid::Uuid>,
+    /// Wins of each of `users`, in the same order.
+    pub wins: Vec<u32>,
+    /// Players that accepted a rematch after the last match.
+    pub rematch: Vec<uuid::Uuid>,
+    /// First match of the series that a rematch started once this one was
+    /// decided.
+    pub rematch_match: Option<uuid::Uuid>,
+}
+
+impl GameSeries {
+    pub fn new(
+        title: String,
+        users: Vec<uuid::Uuid>,
+        settings: super::room::RoomSettings,
+    ) -> Self {
+        Self {
+            title,
+            wins: vec![0; users.len()],
+            users,
+            first_to: settings.first_to.max(1),
+            settings,
+            matches: vec![],
+            rematch: vec![],
+            rematch_match: None,
+        }
+    }
+
+    pub fn winner(&self) -> Option<uuid::Uuid> {
+        self.users
+            .iter()
+            .zip(self.wins.iter())
+            .find(|(_, w)| **w >= self.first_to)
+            .map(|(u, _)| *u)
+    }
+
+    pub fn wins_of(&self, user_id: &uuid::Uuid) -> u32 {
+        self.users
+            .iter()
+            .position(|u| u == user_id)
+            .map_or(0, |i| self.wins[i])
+    }
 }
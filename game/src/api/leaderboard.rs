@@ -0,0 +1,229 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use super::game_replay::GameId;
+use crate::rules::{GameGoal, GameRules};
+use crate::tet::GameState;
+
+pub const SPRINT_LINES: u32 = 40;
+pub const BLITZ_MS: u64 = 120_000;
+
+/// What a game is ranked as. Only standard rules with one of the ranked
+/// goals make it to a leaderboard.
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum GameMode {
+    /// Clear `SPRINT_LINES` as fast as possible.
+    Sprint40,
+    /// Best score in `BLITZ_MS`.
+    Blitz,
+    /// Best score until topping out.
+    Marathon,
+    Custom,
+    Versus,
+}
+
+impl GameMode {
+    pub const RANKED: [GameMode; 3] =
+        [GameMode::Sprint40, GameMode::Blitz, GameMode::Marathon];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Sprint40 => "40 lines",
+            GameMode::Blitz => "blitz",
+            GameMode::Marathon => "marathon",
+            GameMode::Custom => "custom",
+            GameMode::Versus => "versus",
+        }
+    }
+
+    /// Rules for a new game of this mode.
+    pub fn rules(&self) -> Option<GameRules> {
+        let goal = match self {
+            GameMode::Sprint40 => GameGoal::Lines(SPRINT_LINES),
+            GameMode::Blitz => GameGoal::TimeLimitMs(BLITZ_MS),
+            GameMode::Marathon => GameGoal::Endless,
+            GameMode::Custom | GameMode::Versus => return None,
+        };
+        Some(GameRules {
+            goal,
+            ..GameRules::standard()
+        })
+    }
+
+    pub fn of_game(rules: &GameRules, versus: bool) -> Self {
+        if versus {
+            return GameMode::Versus;
+        }
+        Self::RANKED
+            .into_iter()
+            .find(|mode| mode.rules().as_ref() == Some(rules))
+            .unwrap_or(GameMode::Custom)
+    }
+
+    /// Whether `summary` can be on this mode's leaderboard.
+    pub fn is_ranked(&self, summary: &GameSummary) -> bool {
+        match self {
+            GameMode::Sprint40 => summary.verified && summary.goal_reached,
+            GameMode::Blitz | GameMode::Marathon => summary.verified,
+            GameMode::Custom | GameMode::Versus => false,
+        }
+    }
+
+    /// Better games first: sprints by time, the others by score. Ties go
+    /// to the game that was done first.
+    pub fn compare(&self, a: &GameSummary, b: &GameSummary) -> Ordering {
+        let by_mode = match self {
+            GameMode::Sprint40 => a.duration_ns.cmp(&b.duration_ns),
+            _ => b.score.cmp(&a.score),
+        };
+        by_mode.then(a.end_time.cmp(&b.end_time))
+    }
+
+    /// Where a ranked `summary` goes on this mode's leaderboard: the higher,
+    /// the better, in the order of [compare][Self::compare]. `None` if it
+    /// is not ranked.
+    pub fn rank(&self, summary: &GameSummary) -> Option<(i64, i64)> {
+        if summary.mode != *self || !self.is_ranked(summary) {
+            return None;
+        }
+        let by_mode = match self {
+            GameMode::Sprint40 => summary.duration_ns.saturating_neg(),
+            _ => summary.score,
+        };
+        Some((by_mode, summary.end_time.saturating_neg()))
+    }
+}
+
+/// Written by the server when a game is over.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GameSummary {
+    pub game_id: GameId,
+    pub mode: GameMode,
+    pub score: i64,
+    pub lines: u32,
+    pub pieces: u32,
+    pub duration_ns: i64,
+    /// Pieces per second.
+    pub pps: f64,
+    /// The server replayed the game from its first segment and got the same
+    /// result.
+    pub verified: bool,
+    /// The game ended on its goal, not by topping out.
+    pub goal_reached: bool,
+    pub end_time: i64,
+}
+
+impl GameSummary {
+    pub fn new(
+        game_id: GameId,
+        mode: GameMode,
+        state: &GameState,
+        verified: bool,
+        end_time: i64,
+    ) -> Self {
+        let duration_ns = state.duration_ns();
+        let pps = if duration_ns > 0 {
+            state.current_id as f64 / (duration_ns as f64 / 1e9)
+        } else {
+            0.0
+        };
+        let last_time = state.start_time + duration_ns;
+        Self {
+            game_id,
+            mode,
+            score: state.score,
+            lines: state.lines,
+            pieces: state.current_id,
+            duration_ns,
+            pps,
+            verified,
+            goal_reached: state.game_over && state.goal_reached(last_time),
+            end_time,
+        }
+    }
+}
+
+/// Which games of a mode a leaderboard lists.
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum LeaderboardView {
+    AllGames,
+    PersonalBests,
+    Player(uuid::Uuid),
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    fn summary(user: u8, mode: GameMode, score: i64, duration_ns: i64) -> GameSummary {
+        GameSummary {
+            game_id: GameId {
+                user_id: uuid::Uuid::from_bytes([user; 16]),
+                init_seed: [0; 32],
+                start_time: score,
+            },
+            mode,
+            score,
+            lines: SPRINT_LINES,
+            pieces: 100,
+            duration_ns,
+            pps: 1.0,
+            verified: true,
+            goal_reached: true,
+            end_time: 0,
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn modes_come_from_rules() {
+        for mode in GameMode::RANKED {
+            assert_eq!(GameMode::of_game(&mode.rules().unwrap(), false), mode);
+            assert_eq!(
+                GameMode::of_game(&mode.rules().unwrap(), true),
+                GameMode::Versus
+            );
+        }
+        let mut rules = GameMode::Sprint40.rules().unwrap();
+        rules.hold_enabled = false;
+        assert_eq!(GameMode::of_game(&rules, false), GameMode::Custom);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn leaderboards_rank_by_mode() {
+        let games = vec![
+            summary(1, GameMode::Sprint40, 0, 50),
+            summary(2, GameMode::Sprint40, 1, 30),
+            summary(1, GameMode::Sprint40, 2, 40),
+            summary(3, GameMode::Blitz, 900, 0),
+        ];
+        let mut ranked: Vec<_> = games
+            .iter()
+            .filter_map(|s| Some((GameMode::Sprint40.rank(s)?, s.duration_ns)))
+            .collect();
+        ranked.sort();
+        let times: Vec<_> = ranked.iter().rev().map(|x| x.1).collect();
+        assert_eq!(times, vec![30, 40, 50]);
+        for (a, b) in [(&games[1], &games[2]), (&games[2], &games[0])] {
+            assert!(GameMode::Sprint40.compare(a, b).is_lt());
+            assert!(GameMode::Sprint40.rank(a) > GameMode::Sprint40.rank(b));
+        }
+
+        let mut topped_out = summary(4, GameMode::Sprint40, 3, 1);
+        topped_out.goal_reached = false;
+        let mut unverified = summary(5, GameMode::Blitz, 1000, 0);
+        unverified.verified = false;
+        assert_eq!(GameMode::Sprint40.rank(&topped_out), None);
+        assert_eq!(GameMode::Blitz.rank(&unverified), None);
+        assert_eq!(GameMode::Sprint40.rank(&games[3]), None);
+        let blitz = summary(6, GameMode::Blitz, 100, 0);
+        assert!(GameMode::Blitz.rank(&games[3]) > GameMode::Blitz.rank(&blitz));
+    }
+}
@@ -1,4 +1,10 @@
+pub mod challenge;
 pub mod game_match;
 pub mod game_replay;
+pub mod leaderboard;
+pub mod page;
+pub mod presence;
+pub mod room;
+pub mod stats;
 pub mod user;
 pub mod websocket;
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PAGE_SIZE: u32 = 9;
+pub const MAX_PAGE_SIZE: u32 = 50;
+
+/// Where a listing continues. Only the server knows what is inside.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct PageCursor(pub Vec<u8>);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct PageRequest {
+    /// `None` for the first page.
+    pub cursor: Option<PageCursor>,
+    pub limit: u32,
+}
+
+impl PageRequest {
+    pub fn first(limit: u32) -> Self {
+        Self {
+            cursor: None,
+            limit,
+        }
+    }
+
+    pub fn after(cursor: PageCursor, limit: u32) -> Self {
+        Self {
+            cursor: Some(cursor),
+            limit,
+        }
+    }
+
+    /// What the server actually returns at most.
+    pub fn clamped_limit(&self) -> usize {
+        self.limit.clamp(1, MAX_PAGE_SIZE) as usize
+    }
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self::first(DEFAULT_PAGE_SIZE)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Set if there is more after this page.
+    pub next: Option<PageCursor>,
+}
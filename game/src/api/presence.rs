@@ -0,0 +1,25 @@
+//! Who is online, shared between friends: users who follow each other.
+
+use serde::{Deserialize, Serialize};
+
+use super::game_replay::GameId;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum Presence {
+    #[default]
+    Offline,
+    Online,
+    /// In a game that can be spectated.
+    Playing(GameId),
+}
+
+/// Someone the user follows.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FollowInfo {
+    pub user_id: uuid::Uuid,
+    pub display_name: String,
+    /// Friends follow each other.
+    pub follows_back: bool,
+    /// Only friends see each other's presence.
+    pub presence: Option<Presence>,
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use super::game_match::{GameMatchType, GarbageSettings};
+use crate::rules::GameRules;
+
+pub const ROOM_CODE_LEN: usize = 6;
+/// Room codes leave out letters that are easy to mix up.
+pub const ROOM_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+/// Wins needed to take a series, best of three.
+pub const DEFAULT_FIRST_TO: u32 = 2;
+pub const MAX_FIRST_TO: u32 = 10;
+pub const MAX_GARBAGE_MULTIPLIER_PERCENT: u32 = 1000;
+/// From everyone being ready to the match starting.
+pub const ROOM_COUNTDOWN_NS: i64 = 3_000_000_000;
+
+/// What the host of a private room decides.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct RoomSettings {
+    pub match_type: GameMatchType,
+    pub rules: GameRules,
+    pub garbage: GarbageSettings,
+    /// Wins needed to take the series.
+    pub first_to: u32,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        Self {
+            match_type: GameMatchType::_1v1,
+            rules: GameRules::default(),
+            garbage: GarbageSettings::default(),
+            first_to: DEFAULT_FIRST_TO,
+        }
+    }
+}
+
+impl RoomSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.rules.validate()?;
+        if self.match_type.team_size().is_none() {
+            anyhow::bail!("{:?} is not a versus match", self.match_type);
+        }
+        if self.garbage.multiplier_percent > MAX_GARBAGE_MULTIPLIER_PERCENT {
+            anyhow::bail!(
+                "garbage multiplier can be at most {MAX_GARBAGE_MULTIPLIER_PERCENT}%"
+            );
+        }
+        if !(1..=MAX_FIRST_TO).contains(&self.first_to) {
+            anyhow::bail!("series are first to 1 to {MAX_FIRST_TO} wins");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct RoomPlayer {
+    pub user_id: uuid::Uuid,
+    pub ready: bool,
+}
+
+/// A private room, found by its code. Members are kept by user, so a player
+/// that reconnects is still in it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct RoomInfo {
+    pub code: String,
+    pub host: uuid::Uuid,
+    pub settings: RoomSettings,
+    pub players: Vec<RoomPlayer>,
+    pub spectators: Vec<uuid::Uuid>,
+    /// Matches played in this room, the last one is the current one.
+    pub matches: Vec<uuid::Uuid>,
+    pub created: i64,
+    /// When the next match starts, while everyone is ready.
+    pub starts_at: Option<i64>,
+}
+
+impl RoomInfo {
+    pub fn is_player(&self, user_id: &uuid::Uuid) -> bool {
+        self.players.iter().any(|p| p.user_id == *user_id)
+    }
+
+    /// Player seats for the room's match type.
+    pub fn seats(&self) -> usize {
+        self.settings.match_type.max_players()
+    }
+
+    /// The match starts once enough players are seated and all are ready.
+    pub fn all_ready(&self) -> bool {
+        self.players.len() >= self.settings.match_type.min_players()
+            && self.players.len() <= self.seats()
+            && self.players.iter().all(|p| p.ready)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn room_settings_are_for_versus_series() {
+        RoomSettings::default().validate().unwrap();
+        for bad in [
+            RoomSettings {
+                match_type: GameMatchType::_40lines,
+                ..Default::default()
+            },
+            RoomSettings {
+                first_to: 0,
+                ..Default::default()
+            },
+            RoomSettings {
+                first_to: MAX_FIRST_TO + 1,
+                ..Default::default()
+            },
+            RoomSettings {
+                garbage: GarbageSettings {
+                    enabled: true,
+                    multiplier_percent: u32::MAX,
+                },
+                ..Default::default()
+            },
+        ] {
+            assert!(bad.validate().is_err(), "{bad:?}");
+        }
+    }
+}
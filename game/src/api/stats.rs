@@ -0,0 +1,194 @@
+//! Per-user totals, kept up to date by the server as games and matches end.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::game_match::UserAndMatchResult;
+use super::leaderboard::{GameMode, GameSummary};
+
+pub const NANOS_PER_DAY: i64 = 86_400 * 1_000_000_000;
+
+/// Days since the epoch, for bucketing stats over time.
+pub fn day_of(time_ns: i64) -> i64 {
+    time_ns.div_euclid(NANOS_PER_DAY)
+}
+
+fn per_second(count: u64, time_ns: i64) -> Option<f64> {
+    (time_ns > 0).then(|| count as f64 / (time_ns as f64 / 1e9))
+}
+
+/// Finished games of one mode.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ModeStats {
+    pub games: u32,
+    pub lines: u64,
+    pub pieces: u64,
+    pub time_ns: i64,
+    /// Best ranked game, for the modes that have a leaderboard.
+    pub best: Option<GameSummary>,
+}
+
+impl ModeStats {
+    fn add_game(&mut self, summary: &GameSummary) {
+        self.games += 1;
+        self.lines += summary.lines as u64;
+        self.pieces += summary.pieces as u64;
+        self.time_ns += summary.duration_ns;
+        let mode = summary.mode;
+        if mode.is_ranked(summary)
+            && self
+                .best
+                .as_ref()
+                .is_none_or(|best| mode.compare(summary, best).is_lt())
+        {
+            self.best = Some(summary.clone());
+        }
+    }
+}
+
+/// Pieces and attack of one stretch of time, to get PPS and APM from.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct PaceStats {
+    pub pieces: u64,
+    pub time_ns: i64,
+    pub lines_sent: u64,
+    pub versus_time_ns: i64,
+}
+
+impl PaceStats {
+    pub fn add_game(&mut self, summary: &GameSummary) {
+        self.pieces += summary.pieces as u64;
+        self.time_ns += summary.duration_ns;
+    }
+
+    pub fn add_match(&mut self, result: &UserAndMatchResult, duration_ns: i64) {
+        self.lines_sent += result.lines_sent as u64;
+        self.versus_time_ns += duration_ns;
+    }
+
+    pub fn pps(&self) -> Option<f64> {
+        per_second(self.pieces, self.time_ns)
+    }
+
+    /// Lines sent per minute of versus.
+    pub fn apm(&self) -> Option<f64> {
+        per_second(self.lines_sent, self.versus_time_ns).map(|x| x * 60.0)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct UserStats {
+    pub modes: BTreeMap<GameMode, ModeStats>,
+    pub matches: u32,
+    pub wins: u32,
+    pub pace: PaceStats,
+}
+
+impl UserStats {
+    pub fn add_game(&mut self, summary: &GameSummary) {
+        self.modes
+            .entry(summary.mode)
+            .or_default()
+            .add_game(summary);
+        self.pace.add_game(summary);
+    }
+
+    /// `duration_ns` is from the start of the match to its result.
+    pub fn add_match(&mut self, result: &UserAndMatchResult, duration_ns: i64) {
+        self.matches += 1;
+        if result.is_win {
+            self.wins += 1;
+        }
+        self.pace.add_match(result, duration_ns);
+    }
+
+    pub fn games(&self) -> u32 {
+        self.modes.values().map(|m| m.games).sum()
+    }
+
+    pub fn lines(&self) -> u64 {
+        self.modes.values().map(|m| m.lines).sum()
+    }
+
+    pub fn win_rate(&self) -> Option<f64> {
+        (self.matches > 0).then(|| self.wins as f64 / self.matches as f64)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::api::game_replay::GameId;
+    use wasm_bindgen_test::*;
+
+    fn summary(
+        mode: GameMode,
+        score: i64,
+        pieces: u32,
+        duration_s: i64,
+    ) -> GameSummary {
+        GameSummary {
+            game_id: GameId {
+                user_id: uuid::Uuid::nil(),
+                init_seed: [0; 32],
+                start_time: 0,
+            },
+            mode,
+            score,
+            lines: pieces / 2,
+            pieces,
+            duration_ns: duration_s * 1_000_000_000,
+            pps: 0.0,
+            verified: true,
+            goal_reached: true,
+            end_time: 0,
+        }
+    }
+
+    fn result(is_win: bool, lines_sent: u32) -> UserAndMatchResult {
+        UserAndMatchResult {
+            is_win,
+            podium_position: if is_win { 1 } else { 2 },
+            score: 0,
+            lines_sent,
+            lines_received: 0,
+            pieces: 0,
+            end_time: 0,
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn stats_add_up() {
+        let mut stats = UserStats::default();
+        assert_eq!(stats.win_rate(), None);
+        assert_eq!(stats.pace.pps(), None);
+
+        stats.add_game(&summary(GameMode::Blitz, 500, 100, 50));
+        stats.add_game(&summary(GameMode::Blitz, 900, 100, 50));
+        stats.add_game(&summary(GameMode::Blitz, 700, 100, 50));
+        stats.add_game(&summary(GameMode::Custom, 10, 20, 10));
+        let blitz = &stats.modes[&GameMode::Blitz];
+        assert_eq!(blitz.games, 3);
+        assert_eq!(blitz.best.as_ref().map(|b| b.score), Some(900));
+        assert_eq!(stats.modes[&GameMode::Custom].best, None);
+        assert_eq!(stats.games(), 4);
+        assert_eq!(stats.lines(), 160);
+        assert_eq!(stats.pace.pps(), Some(2.0));
+
+        stats.add_match(&result(true, 30), 60 * 1_000_000_000);
+        stats.add_match(&result(false, 10), 60 * 1_000_000_000);
+        assert_eq!(stats.win_rate(), Some(0.5));
+        assert_eq!(stats.pace.apm(), Some(20.0));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn days_round_down() {
+        assert_eq!(day_of(0), 0);
+        assert_eq!(day_of(NANOS_PER_DAY - 1), 0);
+        assert_eq!(day_of(NANOS_PER_DAY), 1);
+        assert_eq!(day_of(-1), -1);
+    }
+}
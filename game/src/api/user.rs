@@ -1,12 +1,17 @@
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
+use crate::tet::Tet;
+
 #[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
 pub struct GuestInfo {
     pub user_id: uuid::Uuid,
     pub pageviews: usize,
     pub first_seen: OffsetDateTime,
     pub last_seen: OffsetDateTime,
+    /// Set while logged in to the account that owns `user_id`.
+    #[serde(default)]
+    pub username: Option<String>,
 }
 
 impl Default for GuestInfo {
@@ -16,6 +21,7 @@ impl Default for GuestInfo {
             pageviews: 0,
             first_seen: OffsetDateTime::now_utc(),
             last_seen: OffsetDateTime::now_utc(),
+            username: None,
         }
     }
 }
@@ -23,4 +29,274 @@ impl Default for GuestInfo {
 #[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
 pub struct UserProfile {
     pub display_name: String,
+    pub avatar: Avatar,
+    /// ISO 3166 alpha-2 code.
+    pub country: Option<String>,
+    pub bio: String,
+    pub joined: i64,
+}
+
+/// Built-in avatars, one per tetromino.
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq, Default)]
+pub enum Avatar {
+    #[default]
+    T,
+    I,
+    O,
+    L,
+    J,
+    S,
+    Z,
+}
+
+impl Avatar {
+    pub const ALL: [Avatar; 7] = [
+        Avatar::T,
+        Avatar::I,
+        Avatar::O,
+        Avatar::L,
+        Avatar::J,
+        Avatar::S,
+        Avatar::Z,
+    ];
+
+    pub fn tet(&self) -> Tet {
+        match self {
+            Avatar::T => Tet::T,
+            Avatar::I => Tet::I,
+            Avatar::O => Tet::O,
+            Avatar::L => Tet::L,
+            Avatar::J => Tet::J,
+            Avatar::S => Tet::S,
+            Avatar::Z => Tet::Z,
+        }
+    }
+}
+
+pub const DISPLAY_NAME_LEN: std::ops::RangeInclusive<usize> = 3..=24;
+pub const BIO_MAX_LEN: usize = 280;
+
+/// Not allowed as a word of a display name or bio, see `words`.
+const BLOCKED_WORDS: &[&str] = &[
+    "fuck",
+    "shit",
+    "cunt",
+    "bitch",
+    "asshole",
+    "wanker",
+    "nazi",
+    "hitler",
+    "scheisse",
+    "arschloch",
+    "fotze",
+    "wichser",
+];
+
+/// Display names are unique regardless of case.
+pub fn display_name_key(display_name: &str) -> String {
+    display_name.to_lowercase()
+}
+
+/// Lowercase words of `text`. Words end at anything but a letter or digit
+/// and where a capital follows a small letter, so `ShitHead` is two words.
+/// Runs of single letters are one word, so `f_u_c_k` is too.
+fn words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = vec![];
+    let mut word = String::new();
+    let mut last = None;
+    for c in text.chars() {
+        let camel = last.is_some_and(char::is_lowercase) && c.is_uppercase();
+        if !c.is_alphanumeric() || camel {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+        }
+        if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        }
+        last = Some(c);
+    }
+    words.extend((!word.is_empty()).then_some(word));
+
+    let mut joined: Vec<String> = vec![];
+    let mut letters = String::new();
+    for w in words {
+        if w.chars().count() == 1 {
+            letters.push_str(&w);
+            continue;
+        }
+        joined.extend((!letters.is_empty()).then(|| std::mem::take(&mut letters)));
+        joined.push(w);
+    }
+    joined.extend((!letters.is_empty()).then_some(letters));
+    joined
+}
+
+fn has_blocked_word(text: &str) -> bool {
+    words(text)
+        .iter()
+        .any(|w| BLOCKED_WORDS.contains(&w.as_str()))
+}
+
+/// Everything of a profile its owner can change.
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+pub struct ProfileUpdate {
+    pub display_name: String,
+    pub avatar: Avatar,
+    pub country: Option<String>,
+    pub bio: String,
+}
+
+impl ProfileUpdate {
+    pub fn of(profile: &UserProfile) -> Self {
+        Self {
+            display_name: profile.display_name.clone(),
+            avatar: profile.avatar,
+            country: profile.country.clone(),
+            bio: profile.bio.clone(),
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let name = &self.display_name;
+        if !DISPLAY_NAME_LEN.contains(&name.chars().count()) {
+            anyhow::bail!(
+                "display name needs {} to {} characters",
+                DISPLAY_NAME_LEN.start(),
+                DISPLAY_NAME_LEN.end()
+            );
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '_' || c == '-')
+        {
+            anyhow::bail!(
+                "display name can only have letters, digits, spaces, _ and -"
+            );
+        }
+        if name.trim() != name || name.contains("  ") {
+            anyhow::bail!("display name has stray spaces");
+        }
+        if has_blocked_word(name) {
+            anyhow::bail!("display name is not allowed");
+        }
+        if let Some(country) = &self.country {
+            if country.len() != 2 || !country.chars().all(|c| c.is_ascii_uppercase()) {
+                anyhow::bail!("country needs a two letter code");
+            }
+        }
+        if self.bio.chars().count() > BIO_MAX_LEN {
+            anyhow::bail!("bio can have at most {BIO_MAX_LEN} characters");
+        }
+        if has_blocked_word(&self.bio) {
+            anyhow::bail!("bio is not allowed");
+        }
+        Ok(())
+    }
+}
+
+pub const USERNAME_LEN: std::ops::RangeInclusive<usize> = 3..=24;
+pub const PASSWORD_LEN: std::ops::RangeInclusive<usize> = 8..=128;
+
+/// Username and password, for registering and logging in.
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !USERNAME_LEN.contains(&self.username.chars().count()) {
+            anyhow::bail!(
+                "username needs {} to {} characters",
+                USERNAME_LEN.start(),
+                USERNAME_LEN.end()
+            );
+        }
+        if !self
+            .username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            anyhow::bail!("username can only have letters, digits, _ and -");
+        }
+        if !PASSWORD_LEN.contains(&self.password.chars().count()) {
+            anyhow::bail!(
+                "password needs {} to {} characters",
+                PASSWORD_LEN.start(),
+                PASSWORD_LEN.end()
+            );
+        }
+        Ok(())
+    }
+
+    /// Usernames are unique regardless of case.
+    pub fn username_key(&self) -> String {
+        self.username.to_ascii_lowercase()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    fn creds(username: &str, password: &str) -> Credentials {
+        Credentials {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn credentials_are_validated() {
+        assert!(creds("Some_User-1", "hunter22").validate().is_ok());
+        assert!(creds("ab", "hunter22").validate().is_err());
+        assert!(creds("no spaces", "hunter22").validate().is_err());
+        assert!(creds("dëtte", "hunter22").validate().is_err());
+        assert!(creds("someone", "short").validate().is_err());
+        assert_eq!(creds("Some_User", "x").username_key(), "some_user");
+    }
+
+    fn update(display_name: &str, country: Option<&str>, bio: &str) -> ProfileUpdate {
+        ProfileUpdate {
+            display_name: display_name.to_owned(),
+            avatar: Avatar::default(),
+            country: country.map(|c| c.to_owned()),
+            bio: bio.to_owned(),
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn profile_updates_are_validated() {
+        assert!(update("Grüne Wiese-2", Some("DE"), "hi").validate().is_ok());
+        assert!(update("ab", None, "").validate().is_err());
+        assert!(update("tab\there", None, "").validate().is_err());
+        assert!(update(" padded", None, "").validate().is_err());
+        assert!(update("two  spaces", None, "").validate().is_err());
+        assert!(update("ShitHead", None, "").validate().is_err());
+        assert!(update("someone", Some("de"), "").validate().is_err());
+        assert!(update("someone", Some("DEU"), "").validate().is_err());
+        assert!(update("someone", None, &"x".repeat(BIO_MAX_LEN + 1))
+            .validate()
+            .is_err());
+        assert_eq!(display_name_key("Grüne Wiese"), "grüne wiese");
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn blocked_words_match_whole_words() {
+        for name in ["Scunthorpe", "Shitake", "Cocktail", "Bass-Ass", "Hitlerite"] {
+            assert!(update(name, None, "").validate().is_ok(), "{name}");
+        }
+        assert!(update("someone", None, "from Scunthorpe")
+            .validate()
+            .is_ok());
+        for name in ["FUCK", "Shit_Head", "big-shit", "f-u-c-k", "the shit 2"] {
+            assert!(update(name, None, "").validate().is_err(), "{name}");
+        }
+        assert!(update("someone", None, "oh, s h i t!").validate().is_err());
+        assert_eq!(words("ShitHead 2_go"), vec!["shit", "head", "2", "go"]);
+    }
 }
@@ -1,13 +1,34 @@
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::coop::CoopReplaySlice;
+use crate::rating::Rating;
+use crate::rating::RatingChange;
+use crate::rules::GameRules;
 use crate::tet::GameReplaySegment;
 use crate::tet::GameState;
 
+use super::challenge::ChallengeEvent;
+use super::game_match::CoopGameInfo;
 use super::game_match::GameMatch;
+use super::game_match::GameMatchResult;
 use super::game_match::GameMatchType;
+use super::game_match::GameSeries;
+use super::game_match::GarbageTargeting;
+use super::game_match::MatchPlayerState;
+use super::game_match::MatchmakingStatus;
+use super::game_match::UserAndMatchResult;
 use super::game_replay::GameId;
 use super::game_replay::GameSegmentId;
+use super::leaderboard::GameMode;
+use super::leaderboard::GameSummary;
+use super::leaderboard::LeaderboardView;
+use super::page::Page;
+use super::page::PageRequest;
+use super::presence::FollowInfo;
+use super::presence::Presence;
+use super::room::RoomInfo;
+use super::room::RoomSettings;
 
 #[derive(
     Copy, Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd, Eq, Ord, Hash,
@@ -35,6 +56,56 @@ pub enum WebsocketAPIMessageType {
     GetMatchList,
 
     GetMatchInfo,
+    GetGameRules,
+
+    CreateCoopGame,
+    JoinCoopGame,
+    AppendCoopAction,
+    GetCoopSlices,
+
+    SubscribeMatchPlz,
+    MatchPlayerUpdateNotification,
+    GetMatchResult,
+    GetMatchHistory,
+
+    GetRating,
+    GetRatingHistory,
+
+    CancelMatchmaking,
+    MatchmakingStatusNotification,
+
+    CreateRoom,
+    GetRoom,
+    JoinRoom,
+    LeaveRoom,
+    SetRoomSettings,
+    SetRoomReady,
+
+    GetMatchSeries,
+    AcceptRematch,
+
+    SetGarbageTargeting,
+
+    GetLeaderboard,
+    GetWorldRecord,
+    WorldRecordNotification,
+
+    Register,
+    Login,
+    Logout,
+
+    UpdateProfile,
+
+    GetUserStats,
+
+    Follow,
+    Unfollow,
+    GetFollowing,
+    PresenceNotification,
+
+    SendChallenge,
+    AnswerChallenge,
+    ChallengeNotification,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
@@ -81,6 +152,50 @@ impl APIMethod for GetProfile {
     type Resp = crate::api::user::UserProfile;
 }
 
+/// Makes an account out of the current identity, so the games played so far
+/// stay with it. The session is logged in to it right away.
+pub struct Register {}
+impl APIMethod for Register {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::Register;
+    type Req = crate::api::user::Credentials;
+    type Resp = crate::api::user::GuestInfo;
+}
+
+/// Switches the session to the identity of an account, from any device.
+pub struct Login {}
+impl APIMethod for Login {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::Login;
+    type Req = crate::api::user::Credentials;
+    type Resp = crate::api::user::GuestInfo;
+}
+
+/// Leaves the account for a fresh guest identity.
+pub struct Logout {}
+impl APIMethod for Logout {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::Logout;
+    type Req = ();
+    type Resp = crate::api::user::GuestInfo;
+}
+
+/// Changes the profile of the current user. Display names are unique.
+pub struct UpdateProfile {}
+impl APIMethod for UpdateProfile {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::UpdateProfile;
+    type Req = crate::api::user::ProfileUpdate;
+    type Resp = crate::api::user::UserProfile;
+}
+
+/// Totals of a user, and their pace on each day they played, oldest first.
+pub struct GetUserStats {}
+impl APIMethod for GetUserStats {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetUserStats;
+    type Req = uuid::Uuid;
+    type Resp = (
+        crate::api::stats::UserStats,
+        Vec<(i64, crate::api::stats::PaceStats)>,
+    );
+}
+
 pub struct WhoAmI {}
 impl APIMethod for WhoAmI {
     const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::WhoAmI;
@@ -98,10 +213,17 @@ impl APIMethod for GitVersion {
 pub struct CreateNewGameId {}
 impl APIMethod for CreateNewGameId {
     const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::CreateNewGameId;
-    type Req = ();
+    type Req = GameRules;
     type Resp = GameId;
 }
 
+pub struct GetGameRules {}
+impl APIMethod for GetGameRules {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetGameRules;
+    type Req = GameId;
+    type Resp = GameRules;
+}
+
 pub struct AppendGameSegment {}
 impl APIMethod for AppendGameSegment {
     const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::AppendGameSegment;
@@ -151,8 +273,8 @@ pub enum GetAllGamesArg {
 pub struct GetAllGames {}
 impl APIMethod for GetAllGames {
     const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetAllGames;
-    type Req = GetAllGamesArg;
-    type Resp = Vec<(GameId, GameSegmentCountReply)>;
+    type Req = (GetAllGamesArg, PageRequest);
+    type Resp = Page<(GameId, GameSegmentCountReply)>;
 }
 
 pub struct GetAllCustomGames {}
@@ -213,11 +335,29 @@ impl APIMethod for SubscribedGameUpdateNotification {
     type Resp = ();
 }
 
+/// Joins the matchmaking queue for a match type. The match itself comes as a
+/// `MatchmakingStatusNotification`.
 pub struct StartMatch {}
 impl APIMethod for StartMatch {
     const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::StartMatch;
     type Req = GameMatchType;
-    type Resp = (uuid::Uuid, GameMatch);
+    type Resp = ();
+}
+
+/// Leaves the matchmaking queue. Returns false if this connection was not in it.
+pub struct CancelMatchmaking {}
+impl APIMethod for CancelMatchmaking {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::CancelMatchmaking;
+    type Req = ();
+    type Resp = bool;
+}
+
+pub struct MatchmakingStatusNotification {}
+impl APIMethod for MatchmakingStatusNotification {
+    const TYPE: WebsocketAPIMessageType =
+        WebsocketAPIMessageType::MatchmakingStatusNotification;
+    type Req = MatchmakingStatus;
+    type Resp = ();
 }
 
 #[derive(
@@ -235,8 +375,8 @@ pub enum GetMatchListArg {
 pub struct GetMatchList {}
 impl APIMethod for GetMatchList {
     const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetMatchList;
-    type Req = GetMatchListArg;
-    type Resp = Vec<(uuid::Uuid, GameMatch)>;
+    type Req = (GetMatchListArg, PageRequest);
+    type Resp = Page<(uuid::Uuid, GameMatch)>;
 }
 
 pub struct GetMatchInfo {}
@@ -245,3 +385,232 @@ impl APIMethod for GetMatchInfo {
     type Req = uuid::Uuid;
     type Resp = GameMatch;
 }
+
+pub struct CreateCoopGame {}
+impl APIMethod for CreateCoopGame {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::CreateCoopGame;
+    type Req = GameRules;
+    type Resp = uuid::Uuid;
+}
+
+/// Takes the first free seat, or the seat you already have. No seat left
+/// means you watch.
+pub struct JoinCoopGame {}
+impl APIMethod for JoinCoopGame {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::JoinCoopGame;
+    type Req = uuid::Uuid;
+    type Resp = (Option<u8>, CoopGameInfo);
+}
+
+pub struct AppendCoopAction {}
+impl APIMethod for AppendCoopAction {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::AppendCoopAction;
+    type Req = (uuid::Uuid, crate::tet::TetAction, i64);
+    type Resp = CoopReplaySlice;
+}
+
+/// Slices of a co-op game starting at the given index.
+pub struct GetCoopSlices {}
+impl APIMethod for GetCoopSlices {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetCoopSlices;
+    type Req = (uuid::Uuid, u32);
+    type Resp = Vec<CoopReplaySlice>;
+}
+
+/// Streams `MatchPlayerState` updates for one of your own match games.
+pub struct SubscribeMatchPlz {}
+impl APIMethod for SubscribeMatchPlz {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::SubscribeMatchPlz;
+    type Req = GameId;
+    type Resp = ();
+}
+
+pub struct MatchPlayerUpdateNotification {}
+impl APIMethod for MatchPlayerUpdateNotification {
+    const TYPE: WebsocketAPIMessageType =
+        WebsocketAPIMessageType::MatchPlayerUpdateNotification;
+    type Req = (GameId, MatchPlayerState);
+    type Resp = ();
+}
+
+pub struct GetMatchResult {}
+impl APIMethod for GetMatchResult {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetMatchResult;
+    type Req = uuid::Uuid;
+    type Resp = Option<GameMatchResult>;
+}
+
+/// Finished matches of a player, most recent first.
+pub struct GetMatchHistory {}
+impl APIMethod for GetMatchHistory {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetMatchHistory;
+    type Req = uuid::Uuid;
+    type Resp = Vec<(uuid::Uuid, GameMatch, UserAndMatchResult)>;
+}
+
+/// Current rating of a user in a match type, with inactivity decay applied.
+pub struct GetRating {}
+impl APIMethod for GetRating {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetRating;
+    type Req = (uuid::Uuid, GameMatchType);
+    type Resp = Rating;
+}
+
+/// Rating changes of a user in a match type, oldest first, by match id.
+pub struct GetRatingHistory {}
+impl APIMethod for GetRatingHistory {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetRatingHistory;
+    type Req = (uuid::Uuid, GameMatchType);
+    type Resp = Vec<(uuid::Uuid, RatingChange)>;
+}
+
+pub struct CreateRoom {}
+impl APIMethod for CreateRoom {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::CreateRoom;
+    type Req = RoomSettings;
+    type Resp = RoomInfo;
+}
+
+pub struct GetRoom {}
+impl APIMethod for GetRoom {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetRoom;
+    type Req = String;
+    type Resp = RoomInfo;
+}
+
+/// Room code, and whether to take a player seat.
+pub struct JoinRoom {}
+impl APIMethod for JoinRoom {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::JoinRoom;
+    type Req = (String, bool);
+    type Resp = RoomInfo;
+}
+
+pub struct LeaveRoom {}
+impl APIMethod for LeaveRoom {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::LeaveRoom;
+    type Req = String;
+    type Resp = RoomInfo;
+}
+
+/// Host only; makes everyone ready up again.
+pub struct SetRoomSettings {}
+impl APIMethod for SetRoomSettings {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::SetRoomSettings;
+    type Req = (String, RoomSettings);
+    type Resp = RoomInfo;
+}
+
+/// The match starts when all players are ready.
+pub struct SetRoomReady {}
+impl APIMethod for SetRoomReady {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::SetRoomReady;
+    type Req = (String, bool);
+    type Resp = RoomInfo;
+}
+
+/// The series a match is part of, with its id.
+pub struct GetMatchSeries {}
+impl APIMethod for GetMatchSeries {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetMatchSeries;
+    type Req = uuid::Uuid;
+    type Resp = Option<(uuid::Uuid, GameSeries)>;
+}
+
+/// Asks for another game after a finished match. Once every player of the
+/// series asked, the next match starts.
+pub struct AcceptRematch {}
+impl APIMethod for AcceptRematch {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::AcceptRematch;
+    type Req = uuid::Uuid;
+    type Resp = GameSeries;
+}
+
+/// Changes who the player's attack goes to in a match.
+pub struct SetGarbageTargeting {}
+impl APIMethod for SetGarbageTargeting {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::SetGarbageTargeting;
+    type Req = (uuid::Uuid, GarbageTargeting);
+    type Resp = ();
+}
+
+/// Ranked games of a mode, best first.
+pub struct GetLeaderboard {}
+impl APIMethod for GetLeaderboard {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetLeaderboard;
+    type Req = (GameMode, LeaderboardView);
+    type Resp = Vec<GameSummary>;
+}
+
+pub struct GetWorldRecord {}
+impl APIMethod for GetWorldRecord {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetWorldRecord;
+    type Req = GameMode;
+    type Resp = Option<GameSummary>;
+}
+
+/// Sent to everyone connected when a world record is broken.
+pub struct WorldRecordNotification {}
+impl APIMethod for WorldRecordNotification {
+    const TYPE: WebsocketAPIMessageType =
+        WebsocketAPIMessageType::WorldRecordNotification;
+    type Req = GameSummary;
+    type Resp = ();
+}
+
+pub struct Follow {}
+impl APIMethod for Follow {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::Follow;
+    type Req = uuid::Uuid;
+    type Resp = ();
+}
+
+pub struct Unfollow {}
+impl APIMethod for Unfollow {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::Unfollow;
+    type Req = uuid::Uuid;
+    type Resp = ();
+}
+
+/// Everyone the current user follows, friends first.
+pub struct GetFollowing {}
+impl APIMethod for GetFollowing {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::GetFollowing;
+    type Req = ();
+    type Resp = Vec<FollowInfo>;
+}
+
+/// Sent to the friends of a user when their presence changes.
+pub struct PresenceNotification {}
+impl APIMethod for PresenceNotification {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::PresenceNotification;
+    type Req = (uuid::Uuid, Presence);
+    type Resp = ();
+}
+
+/// Challenges a player who is online, with the challenger's settings.
+/// Replies with the id of the challenge.
+pub struct SendChallenge {}
+impl APIMethod for SendChallenge {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::SendChallenge;
+    type Req = (uuid::Uuid, RoomSettings);
+    type Resp = uuid::Uuid;
+}
+
+/// Accepts or declines a challenge by its id. Accepting starts the match
+/// and replies with its id.
+pub struct AnswerChallenge {}
+impl APIMethod for AnswerChallenge {
+    const TYPE: WebsocketAPIMessageType = WebsocketAPIMessageType::AnswerChallenge;
+    type Req = (uuid::Uuid, bool);
+    type Resp = Option<uuid::Uuid>;
+}
+
+/// Sent to the players of a challenge as it changes, with its id.
+pub struct ChallengeNotification {}
+impl APIMethod for ChallengeNotification {
+    const TYPE: WebsocketAPIMessageType =
+        WebsocketAPIMessageType::ChallengeNotification;
+    type Req = (uuid::Uuid, ChallengeEvent);
+    type Resp = ();
+}
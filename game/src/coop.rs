@@ -0,0 +1,548 @@
+//! Co-op: two players on one wide board, each with their own falling piece,
+//! queue and hold.
+//!
+//! Falling pieces sit on the board like in `GameState`, so they block each
+//! other. Both players' actions go into one replay, in the order they were
+//! applied; replaying the slices in `idx` order rebuilds the same game.
+
+use std::collections::VecDeque;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::random::{accept_event, shuffle_tets, GameSeed};
+use super::rot::{RotDirection, RotState};
+use super::rules::{GameRules, Randomizer, RotationSystem};
+use super::tet::{
+    hold_board, BoardMatrix, BoardMatrixHold, BoardMatrixNext, CellValue,
+    CurrentPcsInfo, GameReplayEvent, HoldPcsInfo, TetAction,
+};
+
+pub const COOP_BOARD_COLS: usize = 20;
+pub const COOP_PLAYERS: usize = 2;
+pub type CoopBoard = BoardMatrix<40, COOP_BOARD_COLS>;
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoopPlayer {
+    pub next_pcs: VecDeque<crate::tet::Tet>,
+    pub current_pcs: Option<CurrentPcsInfo>,
+    pub hold_pcps: Option<HoldPcsInfo>,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoopReplay {
+    pub init_seed: GameSeed,
+    pub start_time: i64,
+    pub rules: GameRules,
+    pub replay_slices: Vec<CoopReplaySlice>,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoopReplaySlice {
+    pub idx: u32,
+    pub player: u8,
+    pub event: GameReplayEvent,
+    pub event_timestamp: i64,
+    pub new_seed: GameSeed,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoopState {
+    pub score: i64,
+    pub board: CoopBoard,
+    pub players: Vec<CoopPlayer>,
+    pub current_id: u32,
+    pub game_over: bool,
+    pub seed: GameSeed,
+    pub replay: CoopReplay,
+}
+
+impl CoopState {
+    pub fn new(seed: &GameSeed, start_time: i64, rules: &GameRules) -> Self {
+        let mut state = Self {
+            score: 0,
+            board: CoopBoard::empty(),
+            players: vec![
+                CoopPlayer {
+                    next_pcs: VecDeque::new(),
+                    current_pcs: None,
+                    hold_pcps: None,
+                };
+                COOP_PLAYERS
+            ],
+            current_id: 0,
+            game_over: false,
+            seed: *seed,
+            replay: CoopReplay {
+                init_seed: *seed,
+                start_time,
+                rules: rules.clone(),
+                replay_slices: vec![],
+            },
+        };
+        for player in 0..COOP_PLAYERS {
+            state.refill_nextpcs(player, start_time);
+            state.put_next_piece(player);
+        }
+        state
+    }
+
+    /// Index the next slice gets. Only the last slice counts, so a state
+    /// that keeps just that one goes on the same.
+    pub fn next_slice_idx(&self) -> u32 {
+        self.replay.replay_slices.last().map_or(0, |s| s.idx + 1)
+    }
+
+    /// Drops every slice but the last one, for a state whose replay is kept
+    /// elsewhere.
+    pub fn forget_old_slices(&mut self) {
+        let slices = &mut self.replay.replay_slices;
+        slices.drain(..slices.len().saturating_sub(1));
+    }
+
+    /// Rules the co-op board cannot play.
+    pub fn check_rules(rules: &GameRules) -> anyhow::Result<()> {
+        rules.validate()?;
+        if rules.modifiers.big {
+            anyhow::bail!("big mode is not available in co-op");
+        }
+        if rules.initial_rotation || rules.initial_hold {
+            anyhow::bail!("IRS / IHS are not available in co-op");
+        }
+        Ok(())
+    }
+
+    pub fn apply_action_if_works(
+        &mut self,
+        player: usize,
+        action: TetAction,
+        event_time: i64,
+    ) -> anyhow::Result<()> {
+        *self = self.try_action(player, action, event_time)?;
+        Ok(())
+    }
+
+    pub fn accept_replay_slice(
+        &mut self,
+        slice: &CoopReplaySlice,
+    ) -> anyhow::Result<()> {
+        let expected = self.next_slice_idx();
+        if slice.idx != expected {
+            anyhow::bail!("got slice {} expected slice {expected}", slice.idx);
+        }
+        *self = self.try_action(
+            slice.player as usize,
+            slice.event.action,
+            slice.event_timestamp,
+        )?;
+        if self.replay.replay_slices.last() != Some(slice) {
+            log::warn!("co-op slice {} rebuilt differently", slice.idx);
+        }
+        Ok(())
+    }
+
+    /// The board with each player's ghost drawn in.
+    pub fn get_display_board(&self) -> CoopBoard {
+        let mut b = self.board;
+        for p in &self.players {
+            let Some(info) = p.current_pcs else { continue };
+            let mut floor = b;
+            let _ = floor.delete_piece(&info);
+            let mut ghost = info;
+            while ghost.pos.0 > -4 {
+                ghost.pos.0 -= 1;
+                if floor.spawn_piece(&ghost).is_err() {
+                    ghost.pos.0 += 1;
+                    break;
+                }
+                let _ = floor.delete_piece(&ghost);
+            }
+            if ghost.pos != info.pos {
+                let _ = b.spawn_ghost(&ghost);
+            }
+        }
+        b
+    }
+
+    /// `(y, x)` of both falling pieces.
+    pub fn current_piece_cells(&self) -> Vec<(usize, usize)> {
+        let mut cells = vec![];
+        for info in self.players.iter().filter_map(|p| p.current_pcs) {
+            for (j, row) in info.tet.shape(info.rs).iter().enumerate() {
+                for (i, cell) in row.iter().enumerate() {
+                    let (y, x) = (info.pos.0 + j as i8, info.pos.1 + i as i8);
+                    if *cell && y >= 0 && x >= 0 {
+                        cells.push((y as usize, x as usize));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    pub fn get_next_board(&self, player: usize) -> BoardMatrixNext {
+        let mut b = BoardMatrixNext::empty();
+        b.spawn_nextpcs(
+            &self.players[player].next_pcs,
+            self.replay.rules.next_preview,
+        );
+        b
+    }
+
+    pub fn get_hold_board(&self, player: usize) -> BoardMatrixHold {
+        hold_board(&self.players[player].hold_pcps)
+    }
+
+    fn try_action(
+        &self,
+        player: usize,
+        action: TetAction,
+        event_time: i64,
+    ) -> anyhow::Result<Self> {
+        if self.game_over {
+            anyhow::bail!("game over");
+        }
+        if player >= self.players.len() {
+            anyhow::bail!("no player {player}");
+        }
+        let mut new = self.clone();
+        new.refill_nextpcs(player, event_time);
+
+        let input = action;
+        let action = if new.replay.rules.modifiers.mirror {
+            action.mirrored()
+        } else {
+            action
+        };
+        match action {
+            TetAction::HardDrop => new.try_harddrop(player, event_time)?,
+            TetAction::SoftDrop => new.try_softdrop(player, event_time)?,
+            TetAction::MoveLeft => new.try_move(player, -1)?,
+            TetAction::MoveRight => new.try_move(player, 1)?,
+            TetAction::Hold => new.try_hold(player, event_time)?,
+            TetAction::RotateLeft => new.try_rotate(player, RotDirection::Left)?,
+            TetAction::RotateRight => new.try_rotate(player, RotDirection::Right)?,
+            TetAction::Nothing => {}
+            TetAction::SonicDrop => {
+                let rows = new.drop_to_floor(player)?;
+                if rows == 0 {
+                    anyhow::bail!("already on the floor");
+                }
+                new.score += rows as i64 * new.replay.rules.scoring.soft_drop;
+            }
+            TetAction::InitialRotateLeft
+            | TetAction::InitialRotateRight
+            | TetAction::InitialHold
+            | TetAction::ReceiveGarbage(_) => {
+                anyhow::bail!("{action:?} is not available in co-op")
+            }
+        }
+        if new.replay.rules.modifiers.instant_gravity && !new.game_over {
+            let _ = new.drop_to_floor(player);
+        }
+
+        let event = GameReplayEvent { action: input };
+        let idx = new.next_slice_idx();
+        let new_seed = accept_event(&new.seed, &event, event_time, idx);
+        new.seed = new_seed;
+        new.replay.replay_slices.push(CoopReplaySlice {
+            idx,
+            player: player as u8,
+            event,
+            event_timestamp: event_time,
+            new_seed,
+        });
+        Ok(new)
+    }
+
+    fn refill_nextpcs(&mut self, player: usize, event_time: i64) {
+        while self.players[player].next_pcs.len() < self.replay.rules.next_queue_len {
+            let (new_pcs, new_seed) = match self.replay.rules.randomizer {
+                Randomizer::SevenBag => shuffle_tets(
+                    &self.seed,
+                    event_time,
+                    &self.replay.rules.pieces.tets(),
+                ),
+            };
+            self.players[player].next_pcs.extend(new_pcs);
+            self.seed = new_seed;
+        }
+    }
+
+    /// Each player spawns over their half of the board.
+    fn put_next_piece(&mut self, player: usize) {
+        let tet = self.players[player]
+            .next_pcs
+            .pop_front()
+            .expect("queue refilled before every action");
+        let (y, x) = self.replay.rules.pieces.spawn_pos(tet);
+        let half = (COOP_BOARD_COLS / COOP_PLAYERS) as i8;
+        let info = CurrentPcsInfo {
+            pos: (y, x + half * player as i8),
+            tet,
+            rs: RotState::R0,
+            id: self.current_id,
+        };
+        self.current_id += 1;
+        self.players[player].current_pcs = Some(info);
+        if self.board.spawn_piece(&info).is_err() {
+            log::info!("co-op game over");
+            self.game_over = true;
+        } else if let Some(ref mut h) = self.players[player].hold_pcps {
+            h.can_use = true;
+        }
+    }
+
+    fn current(&self, player: usize) -> anyhow::Result<CurrentPcsInfo> {
+        self.players[player].current_pcs.context("no current pcs")
+    }
+
+    /// Moves the falling piece to `new_info` if it fits, else leaves it.
+    fn try_place(
+        &mut self,
+        player: usize,
+        old_info: CurrentPcsInfo,
+        new_info: CurrentPcsInfo,
+    ) -> anyhow::Result<()> {
+        let _ = self.board.delete_piece(&old_info);
+        if let Err(e) = self.board.spawn_piece(&new_info) {
+            self.board.spawn_piece(&old_info)?;
+            return Err(e);
+        }
+        self.players[player].current_pcs = Some(new_info);
+        Ok(())
+    }
+
+    fn try_move(&mut self, player: usize, dx: i8) -> anyhow::Result<()> {
+        let info = self.current(player)?;
+        let mut new_info = info;
+        new_info.pos.1 += dx;
+        self.try_place(player, info, new_info)
+    }
+
+    fn try_rotate(&mut self, player: usize, rot: RotDirection) -> anyhow::Result<()> {
+        let info = self.current(player)?;
+        let after = info.rs.rotate(rot);
+        let offsets = match self.replay.rules.rotation_system {
+            RotationSystem::Srs => {
+                self.replay.rules.pieces.kicks(info.tet, info.rs, after)
+            }
+            RotationSystem::NoKicks => vec![(0, 0)],
+        };
+        for (x, y) in offsets {
+            let mut new_info = info;
+            new_info.rs = after;
+            new_info.pos.0 += y;
+            new_info.pos.1 += x;
+            if self.try_place(player, info, new_info).is_ok() {
+                return Ok(());
+            }
+        }
+        anyhow::bail!("all offsets are blocked")
+    }
+
+    fn drop_to_floor(&mut self, player: usize) -> anyhow::Result<i8> {
+        let mut rows = 0;
+        loop {
+            let info = self.current(player)?;
+            let mut new_info = info;
+            new_info.pos.0 -= 1;
+            if self.try_place(player, info, new_info).is_err() {
+                return Ok(rows);
+            }
+            rows += 1;
+        }
+    }
+
+    fn try_softdrop(&mut self, player: usize, event_time: i64) -> anyhow::Result<()> {
+        let info = self.current(player)?;
+        let mut new_info = info;
+        new_info.pos.0 -= 1;
+        if self.try_place(player, info, new_info).is_ok() {
+            self.score += self.replay.rules.scoring.soft_drop;
+        } else {
+            self.lock(player, event_time);
+        }
+        Ok(())
+    }
+
+    fn try_harddrop(&mut self, player: usize, event_time: i64) -> anyhow::Result<()> {
+        self.drop_to_floor(player)?;
+        self.score += self.replay.rules.scoring.hard_drop;
+        self.lock(player, event_time);
+        Ok(())
+    }
+
+    fn try_hold(&mut self, player: usize, event_time: i64) -> anyhow::Result<()> {
+        if !self.replay.rules.hold_enabled {
+            anyhow::bail!("hold is disabled");
+        }
+        let info = self.current(player)?;
+        if let Some(ref h) = self.players[player].hold_pcps {
+            if !h.can_use {
+                anyhow::bail!("can_use=false for hold");
+            }
+        }
+        let _ = self.board.delete_piece(&info);
+        let p = &mut self.players[player];
+        if let Some(old) = p.hold_pcps.take() {
+            p.next_pcs.push_front(old.tet);
+        }
+        p.current_pcs = None;
+        self.refill_nextpcs(player, event_time);
+        self.put_next_piece(player);
+        self.players[player].hold_pcps = Some(HoldPcsInfo {
+            tet: info.tet,
+            can_use: false,
+        });
+        Ok(())
+    }
+
+    /// Leaves the piece where it is, clears lines and spawns the next one.
+    /// The other falling piece is lifted off while rows shift under it.
+    fn lock(&mut self, player: usize, event_time: i64) {
+        self.players[player].current_pcs = None;
+        let others: Vec<_> = (0..self.players.len())
+            .filter_map(|q| Some((q, self.players[q].current_pcs?)))
+            .collect();
+        for (_, info) in &others {
+            let _ = self.board.delete_piece(info);
+        }
+
+        let lines = self.clear_lines();
+        let scoring = &self.replay.rules.scoring;
+        self.score += scoring.line_clear[lines.min(4)];
+
+        for (q, mut info) in others {
+            while self.board.spawn_piece(&info).is_err() {
+                info.pos.0 += 1;
+                if info.pos.0 as usize >= self.board.get_num_rows() {
+                    self.game_over = true;
+                    return;
+                }
+            }
+            self.players[q].current_pcs = Some(info);
+        }
+        self.refill_nextpcs(player, event_time);
+        self.put_next_piece(player);
+    }
+
+    fn clear_lines(&mut self) -> usize {
+        let rows = self.board.get_num_rows();
+        let mut lines = 0;
+        let mut y = 0;
+        while y < rows {
+            let full = self.board.v[y]
+                .iter()
+                .all(|c| matches!(c, CellValue::Piece(_) | CellValue::Garbage));
+            if full {
+                for i in y..rows - 1 {
+                    self.board.v[i] = self.board.v[i + 1];
+                }
+                self.board.v[rows - 1] = [CellValue::Empty; COOP_BOARD_COLS];
+                lines += 1;
+            } else {
+                y += 1;
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    fn cols(state: &CoopState, player: usize) -> (i8, i8) {
+        let info = state.players[player].current_pcs.unwrap();
+        let mut xs = vec![];
+        for row in info.tet.shape(info.rs) {
+            for (i, cell) in row.iter().enumerate() {
+                if *cell {
+                    xs.push(info.pos.1 + i as i8);
+                }
+            }
+        }
+        (*xs.iter().min().unwrap(), *xs.iter().max().unwrap())
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn falling_pieces_block_each_other() {
+        let mut state = CoopState::new(&[4; 32], 0, &GameRules::standard());
+        assert!(cols(&state, 0).1 < COOP_BOARD_COLS as i8 / 2);
+        assert!(cols(&state, 1).0 >= COOP_BOARD_COLS as i8 / 2);
+
+        // pull both pieces up to the same height, then push them together
+        while state
+            .apply_action_if_works(1, TetAction::MoveLeft, 1)
+            .is_ok()
+        {}
+        assert!(cols(&state, 1).0 > 0, "player 1 must stop at player 0");
+        while state
+            .apply_action_if_works(0, TetAction::MoveRight, 2)
+            .is_ok()
+        {}
+        assert!(cols(&state, 0).1 < cols(&state, 1).0);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn interleaved_replay_is_deterministic() {
+        let rules = GameRules::standard();
+        let mut state = CoopState::new(&[8; 32], 10, &rules);
+        let moves = [
+            (0, TetAction::MoveLeft),
+            (1, TetAction::RotateRight),
+            (0, TetAction::HardDrop),
+            (1, TetAction::Hold),
+            (1, TetAction::MoveRight),
+            (0, TetAction::SoftDrop),
+            (1, TetAction::HardDrop),
+            (0, TetAction::HardDrop),
+        ];
+        for (i, (player, action)) in moves.iter().enumerate() {
+            state
+                .apply_action_if_works(*player, *action, 100 + i as i64)
+                .unwrap();
+        }
+        assert_eq!(state.replay.replay_slices.len(), moves.len());
+        assert!(state.players[1].hold_pcps.as_ref().unwrap().can_use);
+
+        let mut rebuilt = CoopState::new(&[8; 32], 10, &rules);
+        for slice in &state.replay.replay_slices {
+            rebuilt.accept_replay_slice(slice).unwrap();
+        }
+        assert_eq!(rebuilt, state);
+        assert!(rebuilt
+            .accept_replay_slice(&state.replay.replay_slices[0])
+            .is_err());
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn only_the_last_slice_is_needed_to_go_on() {
+        let rules = GameRules::standard();
+        let mut full = CoopState::new(&[9; 32], 10, &rules);
+        for i in 0..3 {
+            full.apply_action_if_works(i % 2, TetAction::MoveLeft, 100 + i as i64)
+                .unwrap();
+        }
+        let mut short = full.clone();
+        short.forget_old_slices();
+        assert_eq!(short.replay.replay_slices.len(), 1);
+        assert_eq!(short.next_slice_idx(), 3);
+
+        full.apply_action_if_works(1, TetAction::HardDrop, 200)
+            .unwrap();
+        short
+            .apply_action_if_works(1, TetAction::HardDrop, 200)
+            .unwrap();
+        assert_eq!(
+            full.replay.replay_slices.last(),
+            short.replay.replay_slices.last()
+        );
+        assert_eq!(full.board, short.board);
+    }
+}
@@ -0,0 +1,822 @@
+//! Encoder and decoder for fumen (v115) diagrams.
+//!
+//! A fumen string is a list of pages. Every page has a 10x23 field (plus the
+//! hidden garbage row below the floor), an optional piece and an optional
+//! comment. Hold and next queue travel in the comment using the quiz syntax
+//! `#Q=[hold](current)next`.
+//!
+//! Only the visible part of our board (the bottom 23 rows) fits in a page.
+
+use std::collections::VecDeque;
+
+use anyhow::Context;
+
+use super::rot::RotState;
+use super::tet::{BoardMatrix, CellValue, CurrentPcsInfo, GameState, HoldPcsInfo, Tet};
+
+const ENCODE_TABLE: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_CHAR_COUNT: u32 = COMMENT_TABLE.len() as u32 + 1;
+const MAX_COMMENT_LEN: usize = 4095;
+
+const FIELD_WIDTH: usize = 10;
+const FIELD_TOP: usize = 23;
+const FIELD_HEIGHT: usize = FIELD_TOP + 1;
+const FIELD_BLOCKS: u32 = (FIELD_HEIGHT * FIELD_WIDTH) as u32;
+const GRAY: i8 = 8;
+
+pub const FUMEN_PREFIX: &str = "v115@";
+pub const FUMEN_VIEWER_URL: &str = "https://fumen.zui.jp/?";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FumenPiece {
+    pub tet: Tet,
+    pub rs: RotState,
+    /// Same `(y, x)` convention as `CurrentPcsInfo::pos`.
+    pub pos: (i8, i8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FumenPage {
+    pub board: BoardMatrix,
+    pub piece: Option<FumenPiece>,
+    pub lock: bool,
+    pub comment: String,
+}
+
+impl FumenPage {
+    /// The current piece becomes the page piece, hold and next go in a quiz comment.
+    pub fn from_game_state(state: &GameState) -> Self {
+        let mut board = state.main_board;
+        for row in board.v.iter_mut() {
+            for cell in row.iter_mut() {
+                if *cell == CellValue::Ghost {
+                    *cell = CellValue::Empty;
+                }
+            }
+        }
+        // fumen only knows tetrominoes: custom pieces stay on the board as
+        // garbage and are left out of the queue
+        let mut piece = None;
+        if let Some(info) = &state.current_pcs {
+            if !state.game_over && !matches!(info.tet, Tet::Custom(_)) {
+                let _ = board.delete_piece(info);
+                piece = Some(FumenPiece {
+                    tet: info.tet,
+                    rs: info.rs,
+                    pos: info.pos,
+                });
+            }
+        }
+
+        let name = |t: &Tet| if let Tet::Custom(_) = t { "" } else { t.name() };
+        let hold: String = state.hold_pcps.iter().map(|h| name(&h.tet)).collect();
+        let current: String = state.current_pcs.iter().map(|c| name(&c.tet)).collect();
+        let next: String = state.next_pcs.iter().map(name).collect();
+        Self {
+            board,
+            piece,
+            lock: false,
+            comment: format!("#Q=[{hold}]({current}){next}"),
+        }
+    }
+
+    pub fn to_game_state(&self) -> anyhow::Result<GameState> {
+        let mut state = GameState::empty();
+        state.main_board = self.board;
+        state.hold_pcps = None;
+        state.next_pcs = VecDeque::new();
+
+        let mut quiz_current = None;
+        if let Some(quiz) = self.comment.strip_prefix("#Q=") {
+            let (hold, rest) = quiz
+                .strip_prefix('[')
+                .and_then(|q| q.split_once(']'))
+                .context("bad quiz hold")?;
+            let (current, next) = rest
+                .strip_prefix('(')
+                .and_then(|q| q.split_once(')'))
+                .context("bad quiz current")?;
+            if let Some(c) = hold.chars().next() {
+                state.hold_pcps = Some(HoldPcsInfo {
+                    can_use: true,
+                    tet: Tet::from_char(c)?,
+                });
+            }
+            if let Some(c) = current.chars().next() {
+                quiz_current = Some(Tet::from_char(c)?);
+            }
+            for c in next.chars().filter(|c| !c.is_whitespace()) {
+                state.next_pcs.push_back(Tet::from_char(c)?);
+            }
+        }
+
+        let (tet, rs, pos) = match (self.piece, quiz_current) {
+            (Some(p), _) => (p.tet, p.rs, p.pos),
+            (None, Some(tet)) => (tet, RotState::R0, tet.spawn_pos()),
+            (None, None) => {
+                let tet = state
+                    .next_pcs
+                    .pop_front()
+                    .context("fumen page has no piece and no queue")?;
+                (tet, RotState::R0, tet.spawn_pos())
+            }
+        };
+        let info = CurrentPcsInfo {
+            pos,
+            tet,
+            rs,
+            id: state.current_id,
+        };
+        state.current_id += 1;
+        state
+            .main_board
+            .spawn_piece(&info)
+            .context("fumen piece does not fit on board")?;
+        state.current_pcs = Some(info);
+        state.clear_ghost();
+        state.put_ghost();
+        Ok(state)
+    }
+}
+
+pub fn game_state_to_fumen(state: &GameState) -> String {
+    encode_fumen(&[FumenPage::from_game_state(state)])
+}
+
+pub fn game_state_from_fumen(fumen: &str) -> anyhow::Result<GameState> {
+    let pages = decode_fumen(fumen)?;
+    pages.first().context("fumen has no pages")?.to_game_state()
+}
+
+/// One page per state, e.g. for every frame of a replay.
+pub fn game_states_to_fumen(states: &[GameState]) -> String {
+    let pages: Vec<_> = states.iter().map(FumenPage::from_game_state).collect();
+    encode_fumen(&pages)
+}
+
+// fumen numbers pieces 1..=7 as I L O Z T J S and uses 8 for garbage
+fn tet_to_fumen(tet: Tet) -> i8 {
+    match tet {
+        Tet::Custom(_) => GRAY,
+        Tet::I => 1,
+        Tet::L => 2,
+        Tet::O => 3,
+        Tet::Z => 4,
+        Tet::T => 5,
+        Tet::J => 6,
+        Tet::S => 7,
+    }
+}
+
+fn tet_from_fumen(n: u32) -> anyhow::Result<Option<Tet>> {
+    Ok(match n {
+        0 => None,
+        1 => Some(Tet::I),
+        2 => Some(Tet::L),
+        3 => Some(Tet::O),
+        4 => Some(Tet::Z),
+        5 => Some(Tet::T),
+        6 => Some(Tet::J),
+        7 => Some(Tet::S),
+        _ => anyhow::bail!("bad fumen piece {n}"),
+    })
+}
+
+fn cell_to_fumen(cell: CellValue) -> i8 {
+    match cell {
+        CellValue::Piece(tet) => tet_to_fumen(tet),
+        CellValue::Garbage => GRAY,
+        CellValue::Empty | CellValue::Ghost => 0,
+    }
+}
+
+fn cell_from_fumen(n: i8) -> anyhow::Result<CellValue> {
+    Ok(match n {
+        GRAY => CellValue::Garbage,
+        n if n >= 0 => match tet_from_fumen(n as u32)? {
+            Some(tet) => CellValue::Piece(tet),
+            None => CellValue::Empty,
+        },
+        _ => anyhow::bail!("bad fumen cell {n}"),
+    })
+}
+
+// our R1 points the T nub right, which fumen calls "Right"
+fn rot_to_fumen(rs: RotState) -> u32 {
+    match rs {
+        RotState::R2 => 0,
+        RotState::R1 => 1,
+        RotState::R0 => 2,
+        RotState::R3 => 3,
+    }
+}
+
+fn rot_from_fumen(n: u32) -> RotState {
+    match n {
+        0 => RotState::R2,
+        1 => RotState::R1,
+        2 => RotState::R0,
+        _ => RotState::R3,
+    }
+}
+
+/// Cells of the piece around the fumen rotation center, `(x, y)` with y up.
+fn fumen_blocks(tet: Tet, rs: RotState) -> Vec<(i8, i8)> {
+    let spawn: [(i8, i8); 4] = match tet {
+        Tet::Custom(_) => return shape_blocks(tet, rs),
+        Tet::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        Tet::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        Tet::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        Tet::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        Tet::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        Tet::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        Tet::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+    };
+    spawn
+        .iter()
+        .map(|&(x, y)| match rs {
+            RotState::R0 => (x, y),
+            RotState::R1 => (y, -x),
+            RotState::R2 => (-x, -y),
+            RotState::R3 => (-y, x),
+        })
+        .collect()
+}
+
+/// Cells of the piece relative to `CurrentPcsInfo::pos`, `(x, y)` with y up.
+fn shape_blocks(tet: Tet, rs: RotState) -> Vec<(i8, i8)> {
+    let mut v = vec![];
+    for (j, row) in tet.shape(rs).iter().enumerate() {
+        for (i, cell) in row.iter().enumerate() {
+            if *cell {
+                v.push((i as i8, j as i8));
+            }
+        }
+    }
+    v
+}
+
+fn min_corner(blocks: &[(i8, i8)]) -> (i8, i8) {
+    let x = blocks.iter().map(|b| b.0).min().unwrap_or(0);
+    let y = blocks.iter().map(|b| b.1).min().unwrap_or(0);
+    (x, y)
+}
+
+/// Offset from our piece position to the fumen rotation center.
+fn center_offset(tet: Tet, rs: RotState) -> (i8, i8) {
+    let ours = min_corner(&shape_blocks(tet, rs));
+    let theirs = min_corner(&fumen_blocks(tet, rs));
+    (ours.0 - theirs.0, ours.1 - theirs.1)
+}
+
+/// The field including the garbage row below the floor; `rows[0]` is that
+/// hidden row and `rows[y + 1]` is row `y` of the board.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Field {
+    rows: [[i8; FIELD_WIDTH]; FIELD_HEIGHT],
+}
+
+impl Field {
+    fn empty() -> Self {
+        Self {
+            rows: [[0; FIELD_WIDTH]; FIELD_HEIGHT],
+        }
+    }
+
+    fn from_board(board: &BoardMatrix) -> Self {
+        let mut field = Self::empty();
+        for y in 0..FIELD_TOP {
+            for x in 0..FIELD_WIDTH {
+                field.rows[y + 1][x] = cell_to_fumen(board.v[y][x]);
+            }
+        }
+        field
+    }
+
+    fn to_board(self) -> anyhow::Result<BoardMatrix> {
+        let mut board = BoardMatrix::empty();
+        for y in 0..FIELD_TOP {
+            for x in 0..FIELD_WIDTH {
+                board.v[y][x] = cell_from_fumen(self.rows[y + 1][x])?;
+            }
+        }
+        Ok(board)
+    }
+
+    /// Block `index` of the encoded field, counted from the top-left corner.
+    fn at_index(&mut self, index: usize) -> &mut i8 {
+        // the last row of the encoding is the garbage row, `rows[0]`
+        let row = FIELD_TOP - index / FIELD_WIDTH;
+        &mut self.rows[row][index % FIELD_WIDTH]
+    }
+
+    fn put_piece(&mut self, piece: &FumenPiece) {
+        let (dx, dy) = center_offset(piece.tet, piece.rs);
+        let (cx, cy) = (piece.pos.1 + dx, piece.pos.0 + dy);
+        for (bx, by) in fumen_blocks(piece.tet, piece.rs) {
+            let (x, y) = (cx + bx, cy + by);
+            if (0..FIELD_WIDTH as i8).contains(&x) && (0..FIELD_TOP as i8).contains(&y)
+            {
+                self.rows[y as usize + 1][x as usize] = tet_to_fumen(piece.tet);
+            }
+        }
+    }
+
+    fn clear_lines(&mut self) {
+        let mut kept: Vec<[i8; FIELD_WIDTH]> = self.rows[1..]
+            .iter()
+            .filter(|row| row.contains(&0))
+            .cloned()
+            .collect();
+        kept.resize(FIELD_TOP, [0; FIELD_WIDTH]);
+        self.rows[1..].copy_from_slice(&kept);
+    }
+
+    fn rise_garbage(&mut self) {
+        for y in (1..FIELD_HEIGHT).rev() {
+            self.rows[y] = self.rows[y - 1];
+        }
+        self.rows[0] = [0; FIELD_WIDTH];
+    }
+
+    fn mirror(&mut self) {
+        for row in self.rows[1..].iter_mut() {
+            row.reverse();
+        }
+    }
+}
+
+fn encode_chars(out: &mut Vec<u8>, mut value: u32, count: usize) {
+    for _ in 0..count {
+        out.push(ENCODE_TABLE[(value % 64) as usize]);
+        value /= 64;
+    }
+}
+
+struct Reader {
+    values: VecDeque<u32>,
+}
+
+impl Reader {
+    fn new(data: &str) -> anyhow::Result<Self> {
+        let values = data
+            .bytes()
+            .filter(|c| *c != b'?')
+            .map(|c| {
+                ENCODE_TABLE
+                    .iter()
+                    .position(|e| *e == c)
+                    .map(|p| p as u32)
+                    .with_context(|| format!("bad fumen character {:?}", c as char))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { values })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn poll(&mut self, count: usize) -> anyhow::Result<u32> {
+        let mut value = 0;
+        let mut scale = 1;
+        for _ in 0..count {
+            value += self.values.pop_front().context("fumen data too short")? * scale;
+            scale *= 64;
+        }
+        Ok(value)
+    }
+}
+
+/// Same as javascript `escape`, which fumen applies to comments.
+fn escape_comment(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+            out.push(c);
+        } else if (c as u32) < 256 {
+            out.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                out.push_str(&format!("%u{:04X}", unit));
+            }
+        }
+    }
+    out
+}
+
+fn unescape_comment(text: &str) -> String {
+    let mut units = vec![];
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let hex = |from: usize, len: usize| -> Option<u16> {
+            let s: String = chars.get(from..from + len)?.iter().collect();
+            u16::from_str_radix(&s, 16).ok()
+        };
+        if chars[i] == '%' {
+            if chars.get(i + 1) == Some(&'u') {
+                if let Some(u) = hex(i + 2, 4) {
+                    units.push(u);
+                    i += 6;
+                    continue;
+                }
+            } else if let Some(u) = hex(i + 1, 2) {
+                units.push(u);
+                i += 3;
+                continue;
+            }
+        }
+        units.push(chars[i] as u16);
+        i += 1;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+pub fn encode_fumen(pages: &[FumenPage]) -> String {
+    let mut out: Vec<u8> = vec![];
+    let mut prev_field = Field::empty();
+    let mut prev_comment = String::new();
+    let mut last_repeat_index: Option<usize> = None;
+
+    for (page_idx, page) in pages.iter().enumerate() {
+        let mut field = Field::from_board(&page.board);
+
+        let mut field_values = vec![];
+        let mut changed = false;
+        let mut prev_diff = None;
+        let mut counter = 0;
+        for index in 0..FIELD_BLOCKS as usize {
+            let diff =
+                (*field.at_index(index) - *prev_field.at_index(index) + 8) as u32;
+            match prev_diff {
+                Some(p) if p == diff => counter += 1,
+                Some(p) => {
+                    encode_chars(&mut field_values, p * FIELD_BLOCKS + counter, 2);
+                    changed = true;
+                    prev_diff = Some(diff);
+                    counter = 0;
+                }
+                None => {
+                    prev_diff = Some(diff);
+                    counter = 0;
+                }
+            }
+        }
+        let prev_diff = prev_diff.unwrap_or(8);
+        encode_chars(&mut field_values, prev_diff * FIELD_BLOCKS + counter, 2);
+        changed |= prev_diff != 8;
+
+        if changed {
+            out.extend(field_values);
+            last_repeat_index = None;
+        } else {
+            match last_repeat_index {
+                Some(idx) if out[idx] != ENCODE_TABLE[63] => {
+                    let count =
+                        ENCODE_TABLE.iter().position(|e| *e == out[idx]).unwrap();
+                    out[idx] = ENCODE_TABLE[count + 1];
+                }
+                _ => {
+                    out.extend(field_values);
+                    out.push(ENCODE_TABLE[0]);
+                    last_repeat_index = Some(out.len() - 1);
+                }
+            }
+        }
+
+        let has_comment = page.comment != prev_comment;
+        let (piece_type, rotation, position) = match &page.piece {
+            Some(p) => {
+                let (dx, dy) = center_offset(p.tet, p.rs);
+                let (cx, cy) = (p.pos.1 + dx, p.pos.0 + dy);
+                let position =
+                    (FIELD_TOP as i32 - cy as i32 - 1) * FIELD_WIDTH as i32 + cx as i32;
+                (
+                    tet_to_fumen(p.tet) as u32,
+                    rot_to_fumen(p.rs),
+                    position.clamp(0, FIELD_BLOCKS as i32 - 1) as u32,
+                )
+            }
+            None => (0, 0, 0),
+        };
+        let mut action = (!page.lock) as u32;
+        action = action * 2 + has_comment as u32;
+        action = action * 2 + (page_idx == 0) as u32; // guideline colors
+        action *= 2; // mirror
+        action *= 2; // rise
+        action = action * FIELD_BLOCKS + position;
+        action = action * 4 + rotation;
+        action = action * 8 + piece_type;
+        encode_chars(&mut out, action, 3);
+
+        if has_comment {
+            let escaped: Vec<u8> = escape_comment(&page.comment)
+                .bytes()
+                .take(MAX_COMMENT_LEN)
+                .collect();
+            encode_chars(&mut out, escaped.len() as u32, 2);
+            for chunk in escaped.chunks(4) {
+                let mut value = 0;
+                for (i, c) in chunk.iter().enumerate() {
+                    let idx = COMMENT_TABLE.iter().position(|e| e == c).unwrap_or(0);
+                    value += idx as u32 * COMMENT_CHAR_COUNT.pow(i as u32);
+                }
+                encode_chars(&mut out, value, 5);
+            }
+            prev_comment = page.comment.clone();
+        }
+
+        if page.lock {
+            if let Some(piece) = &page.piece {
+                field.put_piece(piece);
+            }
+            field.clear_lines();
+        }
+        prev_field = field;
+    }
+
+    let data = String::from_utf8(out).expect("fumen table is ascii");
+    let mut result = String::from(FUMEN_PREFIX);
+    result.push_str(&break_data(&data));
+    result
+}
+
+/// The reference encoder breaks the data with '?' for old browsers: 42 chars,
+/// then every 47.
+fn break_data(data: &str) -> String {
+    let (head, tail) = data.split_at(data.len().min(42));
+    let mut result = String::from(head);
+    for chunk in tail.as_bytes().chunks(47) {
+        result.push('?');
+        result.push_str(std::str::from_utf8(chunk).unwrap());
+    }
+    result
+}
+
+/// Accepts a bare `v115@...` string or a viewer link that contains one.
+pub fn decode_fumen(fumen: &str) -> anyhow::Result<Vec<FumenPage>> {
+    let start = fumen
+        .find(FUMEN_PREFIX)
+        .context("only fumen v115 is supported")?;
+    let data: String = fumen[start + FUMEN_PREFIX.len()..]
+        .chars()
+        .take_while(|c| !c.is_whitespace() && *c != '&' && *c != '#')
+        .collect();
+    let mut reader = Reader::new(&data)?;
+
+    let mut pages = vec![];
+    let mut prev_field = Field::empty();
+    let mut comment = String::new();
+    let mut repeat_count = 0;
+
+    while !reader.is_empty() || pages.is_empty() {
+        let mut field = prev_field;
+        if repeat_count > 0 {
+            repeat_count -= 1;
+        } else {
+            let mut index = 0;
+            let mut changed = true;
+            while index < FIELD_BLOCKS as usize {
+                let block = reader.poll(2)?;
+                let diff = (block / FIELD_BLOCKS) as i8;
+                let count = block % FIELD_BLOCKS;
+                if diff == 8 && count == FIELD_BLOCKS - 1 {
+                    changed = false;
+                }
+                for _ in 0..=count {
+                    if index >= FIELD_BLOCKS as usize {
+                        anyhow::bail!("fumen field overflow");
+                    }
+                    *field.at_index(index) += diff - 8;
+                    index += 1;
+                }
+            }
+            if !changed {
+                repeat_count = reader.poll(1)?;
+            }
+        }
+
+        let mut action = reader.poll(3)?;
+        let piece_type = action % 8;
+        action /= 8;
+        let rotation = action % 4;
+        action /= 4;
+        let position = (action % FIELD_BLOCKS) as i32;
+        action /= FIELD_BLOCKS;
+        let rise = action % 2 != 0;
+        action /= 2;
+        let mirror = action % 2 != 0;
+        action /= 2;
+        action /= 2; // guideline colors
+        let has_comment = action % 2 != 0;
+        action /= 2;
+        let lock = action % 2 == 0;
+
+        if has_comment {
+            let len = reader.poll(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..len.div_ceil(4) {
+                let mut value = reader.poll(5)?;
+                for _ in 0..4 {
+                    let idx = (value % COMMENT_CHAR_COUNT) as usize;
+                    escaped.push(*COMMENT_TABLE.get(idx).unwrap_or(&b' ') as char);
+                    value /= COMMENT_CHAR_COUNT;
+                }
+            }
+            escaped.truncate(len);
+            comment = unescape_comment(&escaped);
+        }
+
+        let piece = match tet_from_fumen(piece_type)? {
+            Some(tet) => {
+                let rs = rot_from_fumen(rotation);
+                let cx = (position % FIELD_WIDTH as i32) as i8;
+                let cy = (FIELD_TOP as i32 - position / FIELD_WIDTH as i32 - 1) as i8;
+                let (dx, dy) = center_offset(tet, rs);
+                Some(FumenPiece {
+                    tet,
+                    rs,
+                    pos: (cy - dy, cx - dx),
+                })
+            }
+            None => None,
+        };
+
+        pages.push(FumenPage {
+            board: field.to_board()?,
+            piece,
+            lock,
+            comment: comment.clone(),
+        });
+
+        if lock {
+            if let Some(piece) = &piece {
+                field.put_piece(piece);
+            }
+            field.clear_lines();
+            if rise {
+                field.rise_garbage();
+            }
+            if mirror {
+                field.mirror();
+            }
+        }
+        prev_field = field;
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tet::TetAction;
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn fumen_empty_page() {
+        let page = FumenPage {
+            board: BoardMatrix::empty(),
+            piece: None,
+            lock: true,
+            comment: "".to_string(),
+        };
+        assert_eq!(encode_fumen(std::slice::from_ref(&page)), "v115@vhAAgH");
+        assert_eq!(decode_fumen("v115@vhAAgH").unwrap(), vec![page]);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn fumen_single_garbage_cell() {
+        let pages = decode_fumen("https://fumen.zui.jp/?v115@bhA8SeAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].board.to_ascii(), "#.........");
+        assert_eq!(encode_fumen(&pages), "v115@bhA8SeAgH");
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn fumen_data_breaks() {
+        for len in [40, 41, 42] {
+            let data = "A".repeat(len);
+            assert_eq!(break_data(&data), data);
+        }
+        let data = "A".repeat(42 + 47 + 1);
+        let broken = break_data(&data);
+        assert_eq!(broken.matches('?').count(), 2);
+        assert_eq!(broken.find('?'), Some(42));
+        assert_eq!(broken.replace('?', ""), data);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn fumen_scattered_garbage_roundtrip() {
+        // every other cell of the bottom rows makes long data
+        for cells in 1..40 {
+            let mut board = BoardMatrix::empty();
+            for i in 0..cells {
+                let (y, x) = (i / 5, (i % 5) * 2 + (i / 5) % 2);
+                board.v[y][x] = CellValue::Garbage;
+            }
+            let page = FumenPage {
+                board,
+                piece: None,
+                lock: false,
+                comment: "".to_string(),
+            };
+            let fumen = encode_fumen(std::slice::from_ref(&page));
+            assert_eq!(decode_fumen(&fumen).unwrap(), vec![page]);
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn fumen_piece_positions_roundtrip() {
+        for tet in Tet::all() {
+            for rs in [RotState::R0, RotState::R1, RotState::R2, RotState::R3] {
+                let page = FumenPage {
+                    board: BoardMatrix::from_ascii("##.#######").unwrap(),
+                    piece: Some(FumenPiece {
+                        tet,
+                        rs,
+                        pos: (5, 3),
+                    }),
+                    lock: false,
+                    comment: "".to_string(),
+                };
+                let decoded =
+                    decode_fumen(&encode_fumen(std::slice::from_ref(&page))).unwrap();
+                assert_eq!(decoded, vec![page]);
+            }
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn fumen_locked_piece_feeds_next_page() {
+        let t_piece = FumenPiece {
+            tet: Tet::T,
+            rs: RotState::R2,
+            pos: (-1, 1),
+        };
+        let pages = vec![
+            FumenPage {
+                board: BoardMatrix::from_ascii("##...#####\n###.######").unwrap(),
+                piece: Some(t_piece),
+                lock: true,
+                comment: "tsd".to_string(),
+            },
+            FumenPage {
+                board: BoardMatrix::empty(),
+                piece: None,
+                lock: true,
+                comment: "tsd".to_string(),
+            },
+        ];
+        let decoded = decode_fumen(&encode_fumen(&pages)).unwrap();
+        assert_eq!(decoded, pages);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn fumen_comment_escaping() {
+        let text = "#Q=[T](I)SZO ünïcode";
+        assert_eq!(unescape_comment(&escape_comment(text)), text);
+        assert_eq!(escape_comment("#Q=[](T)"), "%23Q%3D%5B%5D%28T%29");
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn fumen_game_state_roundtrip() {
+        let mut state = GameState::from_ascii(
+            "
+            hold: O
+            next: SZLJI
+            current: T
+            ####..####
+            ",
+        )
+        .unwrap();
+        let fumen = game_state_to_fumen(&state);
+        let again = game_state_from_fumen(&fumen).unwrap();
+        assert_eq!(state.to_ascii(), again.to_ascii());
+
+        let mut states = vec![state.clone()];
+        for action in [TetAction::MoveRight, TetAction::HardDrop, TetAction::Hold] {
+            state.apply_action_if_works(action, 0).unwrap();
+            states.push(state.clone());
+        }
+        let pages = decode_fumen(&game_states_to_fumen(&states)).unwrap();
+        assert_eq!(pages.len(), states.len());
+        for (page, state) in pages.iter().zip(states.iter()) {
+            // fumen has no place for the score or a used hold
+            let mut decoded = page.to_game_state().unwrap();
+            decoded.score = state.score;
+            decoded.hold_pcps = state.hold_pcps.clone();
+            assert_eq!(decoded.to_ascii(), state.to_ascii());
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Attack and garbage for versus games.
+//!
+//! A board sends `attack` lines for each clear it makes; the server routes
+//! them to the opponent, whose replay takes them in as
+//! `TetAction::ReceiveGarbage`. The hole column comes from the receiving
+//! game's seed, so replays rebuild the same rows.
+
+use rand::Rng;
+
+use super::random::{get_rng, GameSeed};
+
+/// Lines sent for 0..=4 cleared lines.
+pub const LINE_CLEAR_ATTACK: [u32; 5] = [0, 0, 1, 2, 4];
+/// Lines sent for a T-spin clearing 0..=4 lines.
+pub const T_SPIN_ATTACK: [u32; 5] = [0, 2, 4, 6, 6];
+pub const PERFECT_CLEAR_ATTACK: u32 = 10;
+
+pub fn attack(lines: usize, t_spin: bool, perfect_clear: bool) -> u32 {
+    let lines = lines.min(4);
+    if lines == 0 {
+        return 0;
+    }
+    let base = if t_spin {
+        T_SPIN_ATTACK[lines]
+    } else {
+        LINE_CLEAR_ATTACK[lines]
+    };
+    if perfect_clear {
+        base + PERFECT_CLEAR_ATTACK
+    } else {
+        base
+    }
+}
+
+/// Column left open in an incoming batch.
+pub fn hole_column(seed: &GameSeed, cols: usize) -> usize {
+    get_rng(seed).gen_range(0..cols)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tet::{CellValue, GameState, TetAction};
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn attack_table() {
+        assert_eq!(attack(0, true, false), 0);
+        assert_eq!(attack(1, false, false), 0);
+        assert_eq!(attack(4, false, false), 4);
+        assert_eq!(attack(2, true, false), 4);
+        assert_eq!(attack(1, false, true), 10);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn garbage_rows_replay_exactly() {
+        let mut state = GameState::new(&[3; 32], 0);
+        state
+            .apply_action_if_works(TetAction::ReceiveGarbage(3), 5)
+            .unwrap();
+        assert_eq!(state.garbage_received, 3);
+        let holes: Vec<_> = (0..3)
+            .map(|y| {
+                let row = &state.main_board.v[y];
+                assert_eq!(row.iter().filter(|c| **c == CellValue::Garbage).count(), 9);
+                row.iter().position(|c| *c != CellValue::Garbage).unwrap()
+            })
+            .collect();
+        assert!(holes.iter().all(|h| *h == holes[0]));
+        assert!(state.apply_action_if_works(TetAction::HardDrop, 6).is_ok());
+
+        let mut rebuilt = GameState::new(&[3; 32], 0);
+        for slice in &state.replay.replay_slices {
+            rebuilt.accept_replay_slice(slice).unwrap();
+        }
+        assert_eq!(rebuilt, state);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn garbage_tops_out() {
+        let mut state = GameState::new(&[3; 32], 0);
+        for i in 0..20 {
+            if state.game_over {
+                break;
+            }
+            let _ = state.apply_action_if_works(TetAction::ReceiveGarbage(4), i);
+        }
+        assert!(state.game_over);
+    }
+}
@@ -0,0 +1,193 @@
+//! Undo, redo and restore for practice play.
+//!
+//! Nothing but the replay log is kept: rewinding replays the slices recorded
+//! since `base` into a fresh copy of it, so board, queue and randomizer seed
+//! come out exactly as they were. Undone slices are kept for redo until the
+//! player makes a new move.
+
+use anyhow::Context;
+
+use super::tet::{GameReplaySlice, GameState, TetAction, UndoMode};
+
+#[derive(Debug, Clone)]
+pub struct GameHistory {
+    base: GameState,
+    redo: Vec<GameReplaySlice>,
+    /// Replay length right after the last rewind; any other length means the
+    /// player moved on and `redo` is stale.
+    redo_from: usize,
+}
+
+impl GameHistory {
+    /// `base` is the earliest state that can be restored.
+    pub fn new(base: &GameState) -> Self {
+        Self {
+            base: base.clone(),
+            redo: vec![],
+            redo_from: 0,
+        }
+    }
+
+    /// Goes back to just before the last piece locked.
+    pub fn undo(&mut self, state: &mut GameState) -> anyhow::Result<()> {
+        let (timeline, locks) = self.timeline(state)?;
+        let pos = self.position(state);
+        let last = locks
+            .iter()
+            .rev()
+            .find(|k| **k <= pos)
+            .cloned()
+            .unwrap_or(0);
+        let target = *locks
+            .iter()
+            .rev()
+            .find(|k| **k < last)
+            .context("nothing to undo")?;
+        self.rewind(state, &timeline, target)
+    }
+
+    /// Replays the undone moves up to the next lock.
+    pub fn redo(&mut self, state: &mut GameState) -> anyhow::Result<()> {
+        let (timeline, locks) = self.timeline(state)?;
+        let pos = self.position(state);
+        let target = *locks
+            .iter()
+            .find(|k| **k > pos)
+            .context("nothing to redo")?;
+        self.rewind(state, &timeline, target)
+    }
+
+    /// Restores the state after `piece` locks since the base, `0` being the base itself.
+    pub fn restore_to_piece(
+        &mut self,
+        state: &mut GameState,
+        piece: usize,
+    ) -> anyhow::Result<()> {
+        let (timeline, locks) = self.timeline(state)?;
+        let target = *locks
+            .get(piece)
+            .with_context(|| format!("only {} pieces locked", locks.len() - 1))?;
+        self.rewind(state, &timeline, target)
+    }
+
+    fn position(&self, state: &GameState) -> usize {
+        state.replay.replay_slices.len() - self.base.replay.replay_slices.len()
+    }
+
+    /// All slices after the base, including the ones that can be redone, and
+    /// the timeline positions right after each lock (starting with the base).
+    fn timeline(
+        &self,
+        state: &GameState,
+    ) -> anyhow::Result<(Vec<GameReplaySlice>, Vec<usize>)> {
+        if state.replay.undo_mode == UndoMode::Disabled {
+            anyhow::bail!("undo is disabled for this game");
+        }
+        let base_slices = &self.base.replay.replay_slices;
+        if state.replay.init_seed != self.base.replay.init_seed
+            || !state.replay.replay_slices.starts_with(base_slices)
+        {
+            anyhow::bail!("game does not continue from the history base");
+        }
+
+        let mut timeline = state.replay.replay_slices[base_slices.len()..].to_vec();
+        if state.replay.replay_slices.len() == self.redo_from {
+            timeline.extend(self.redo.iter().cloned());
+        }
+
+        let mut locks = vec![0];
+        let mut sim = self.base.clone();
+        for (i, slice) in timeline.iter().enumerate() {
+            let piece_before = sim.current_id;
+            sim.accept_replay_slice(slice)?;
+            let is_hold = matches!(
+                slice.event.action,
+                TetAction::Hold | TetAction::InitialHold
+            );
+            if !is_hold && sim.current_id != piece_before {
+                locks.push(i + 1);
+            }
+        }
+        Ok((timeline, locks))
+    }
+
+    fn rewind(
+        &mut self,
+        state: &mut GameState,
+        timeline: &[GameReplaySlice],
+        target: usize,
+    ) -> anyhow::Result<()> {
+        let mut new_state = self.base.clone();
+        for slice in &timeline[..target] {
+            new_state.accept_replay_slice(slice)?;
+        }
+        new_state.replay.undo_mode = UndoMode::Used;
+        self.redo = timeline[target..].to_vec();
+        self.redo_from = new_state.replay.replay_slices.len();
+        *state = new_state;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    fn play(state: &mut GameState, actions: &[TetAction]) {
+        for (i, action) in actions.iter().enumerate() {
+            state.apply_action_if_works(*action, i as i64).unwrap();
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn undo_redo_is_exact() {
+        let mut state = GameState::new(&[7; 32], 0);
+        state.enable_undo();
+        let mut history = GameHistory::new(&state);
+
+        play(&mut state, &[TetAction::MoveLeft, TetAction::HardDrop]);
+        let after_first = state.clone();
+        play(
+            &mut state,
+            &[TetAction::Hold, TetAction::RotateRight, TetAction::HardDrop],
+        );
+        let after_second = state.clone();
+        play(&mut state, &[TetAction::MoveRight]);
+
+        history.undo(&mut state).unwrap();
+        assert_eq!(state.main_board, after_first.main_board);
+        assert_eq!(state.seed, after_first.seed);
+        assert_eq!(state.next_pcs, after_first.next_pcs);
+        assert_eq!(state.replay.undo_mode, UndoMode::Used);
+
+        history.redo(&mut state).unwrap();
+        assert_eq!(state.main_board, after_second.main_board);
+        assert_eq!(
+            state.replay.replay_slices,
+            after_second.replay.replay_slices
+        );
+        assert!(history.redo(&mut state).is_err());
+
+        history.restore_to_piece(&mut state, 0).unwrap();
+        assert!(state.replay.replay_slices.is_empty());
+        assert!(history.undo(&mut state).is_err());
+        history.restore_to_piece(&mut state, 1).unwrap();
+        assert_eq!(state.main_board, after_first.main_board);
+
+        // a new move drops the redo stack
+        play(&mut state, &[TetAction::HardDrop]);
+        assert!(history.redo(&mut state).is_err());
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn undo_refused_for_recorded_games() {
+        let mut state = GameState::new(&[7; 32], 0);
+        let mut history = GameHistory::new(&state);
+        play(&mut state, &[TetAction::HardDrop, TetAction::HardDrop]);
+        assert!(history.undo(&mut state).is_err());
+        assert!(history.restore_to_piece(&mut state, 0).is_err());
+    }
+}
@@ -4,8 +4,16 @@ use getrandom as _;
 use wasm_bindgen_test as _;
 
 pub mod api;
+pub mod coop;
+pub mod fumen;
+pub mod garbage;
+pub mod history;
+pub mod notation;
+pub mod pieces;
 pub mod random;
+pub mod rating;
 pub mod rot;
+pub mod rules;
 pub mod tet;
 pub mod timestamp;
 
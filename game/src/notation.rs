@@ -0,0 +1,464 @@
+//! Plain-text notation for boards and game states.
+//!
+//! A board is written top row first, one line per row, one character per cell:
+//! `.` empty, `#` garbage, `*` ghost and `I L J T S Z O` for locked pieces.
+//! Custom pieces are written as their lowercase letter; reading them back
+//! needs the piece set, see `GameState::from_ascii_with_rules`. Rows missing
+//! from the top are empty, so fixtures only need to spell out the interesting
+//! part of the stack.
+//!
+//! A game state adds `key: value` header lines before the board:
+//!
+//! ```text
+//! score: 0
+//! hold: I
+//! next: SZOJL
+//! current: T R0 18 3
+//! ..........
+//! ##.#######
+//! ```
+//!
+//! `current` takes the piece, its rotation and its `(y, x)` position; with only
+//! the piece given it spawns at the usual spawn position. When `current` is
+//! missing, the first piece of `next` is taken. `hold: I used` marks a hold that
+//! cannot be swapped until the next piece locks.
+
+use std::collections::VecDeque;
+
+use anyhow::Context;
+
+use super::pieces::PieceSet;
+use super::rot::RotState;
+use super::rules::GameRules;
+use super::tet::{BoardMatrix, CellValue, CurrentPcsInfo, GameState, HoldPcsInfo, Tet};
+
+impl Tet {
+    pub fn from_char(c: char) -> anyhow::Result<Self> {
+        Ok(match c.to_ascii_uppercase() {
+            'I' => Self::I,
+            'L' => Self::L,
+            'J' => Self::J,
+            'T' => Self::T,
+            'S' => Self::S,
+            'Z' => Self::Z,
+            'O' => Self::O,
+            _ => anyhow::bail!("unknown piece {c:?}"),
+        })
+    }
+}
+
+impl PieceSet {
+    /// A custom piece of this set by its letter, or else a standard piece.
+    pub fn tet_from_char(&self, c: char) -> anyhow::Result<Tet> {
+        let custom = self
+            .tets()
+            .into_iter()
+            .find(|t| matches!(t, Tet::Custom(_)) && t.name().starts_with(c));
+        match custom {
+            Some(tet) => Ok(tet),
+            None => Tet::from_char(c),
+        }
+    }
+}
+
+impl CellValue {
+    pub fn to_char(&self) -> char {
+        match self {
+            CellValue::Piece(tet) => tet.name().chars().next().unwrap(),
+            CellValue::Garbage => '#',
+            CellValue::Empty => '.',
+            CellValue::Ghost => '*',
+        }
+    }
+
+    pub fn from_char(c: char, pieces: &PieceSet) -> anyhow::Result<Self> {
+        Ok(match c {
+            '.' => CellValue::Empty,
+            '#' => CellValue::Garbage,
+            '*' => CellValue::Ghost,
+            _ => CellValue::Piece(pieces.tet_from_char(c).context("bad board cell")?),
+        })
+    }
+}
+
+impl RotState {
+    pub fn name(&self) -> &str {
+        match self {
+            RotState::R0 => "R0",
+            RotState::R1 => "R1",
+            RotState::R2 => "R2",
+            RotState::R3 => "R3",
+        }
+    }
+
+    pub fn from_name(name: &str) -> anyhow::Result<Self> {
+        Ok(match name {
+            "R0" => RotState::R0,
+            "R1" => RotState::R1,
+            "R2" => RotState::R2,
+            "R3" => RotState::R3,
+            _ => anyhow::bail!("unknown rotation {name:?}"),
+        })
+    }
+}
+
+impl<const R: usize, const C: usize> BoardMatrix<R, C> {
+    pub fn from_ascii(text: &str) -> anyhow::Result<Self> {
+        Self::from_ascii_with_pieces(text, &PieceSet::standard())
+    }
+
+    pub fn from_ascii_with_pieces(
+        text: &str,
+        pieces: &PieceSet,
+    ) -> anyhow::Result<Self> {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .collect();
+        if lines.len() > R {
+            anyhow::bail!("got {} rows but board only has {R}", lines.len());
+        }
+
+        let mut board = Self::empty();
+        for (i, line) in lines.iter().rev().enumerate() {
+            let cells: Vec<char> = line.chars().collect();
+            if cells.len() != C {
+                anyhow::bail!("row {line:?} has {} cells, expected {C}", cells.len());
+            }
+            for (j, c) in cells.into_iter().enumerate() {
+                board.v[i][j] = CellValue::from_char(c, pieces)?;
+            }
+        }
+        Ok(board)
+    }
+
+    /// Rows above the highest non-empty row are left out.
+    pub fn to_ascii(&self) -> String {
+        let top = self
+            .v
+            .iter()
+            .rposition(|row| row.iter().any(|c| !c.eq(&CellValue::Empty)))
+            .unwrap_or(0);
+        let mut lines = vec![];
+        for row in self.v[..=top].iter().rev() {
+            lines.push(row.iter().map(|c| c.to_char()).collect::<String>());
+        }
+        lines.join("\n")
+    }
+}
+
+impl GameState {
+    pub fn from_ascii(text: &str) -> anyhow::Result<Self> {
+        Self::from_ascii_with_rules(text, &GameRules::standard())
+    }
+
+    /// Custom piece letters are looked up in `rules.pieces`, and the state
+    /// plays by `rules` from there on.
+    pub fn from_ascii_with_rules(
+        text: &str,
+        rules: &GameRules,
+    ) -> anyhow::Result<Self> {
+        let pieces = &rules.pieces;
+        let mut board_lines = vec![];
+        let mut score = 0;
+        let mut hold = None;
+        let mut next = VecDeque::new();
+        let mut current = None;
+
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let Some((key, value)) = line.split_once(':') else {
+                board_lines.push(line);
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "score" => {
+                    score = value.parse().context("bad score")?;
+                }
+                "hold" => {
+                    let mut words = value.split_whitespace();
+                    let tet = words.next().context("hold needs a piece")?;
+                    let tet = pieces.tet_from_char(single_char(tet)?)?;
+                    let can_use = match words.next() {
+                        None => true,
+                        Some("used") => false,
+                        Some(x) => anyhow::bail!("bad hold flag {x:?}"),
+                    };
+                    hold = Some(HoldPcsInfo { can_use, tet });
+                }
+                "next" => {
+                    for c in value.chars().filter(|c| !c.is_whitespace()) {
+                        next.push_back(pieces.tet_from_char(c)?);
+                    }
+                }
+                "current" => {
+                    let words: Vec<&str> = value.split_whitespace().collect();
+                    let tet = words.first().context("current needs a piece")?;
+                    let tet = pieces.tet_from_char(single_char(tet)?)?;
+                    let (rs, pos) = match words.len() {
+                        1 => (RotState::R0, pieces.spawn_pos(tet)),
+                        4 => (
+                            RotState::from_name(words[1])?,
+                            (
+                                words[2].parse().context("bad current y")?,
+                                words[3].parse().context("bad current x")?,
+                            ),
+                        ),
+                        _ => anyhow::bail!("expected `current: T [R0 y x]`"),
+                    };
+                    current = Some((tet, rs, pos));
+                }
+                other => anyhow::bail!("unknown header {other:?}"),
+            }
+        }
+
+        let mut state = GameState::new_with_rules(&[0; 32], 0, rules);
+        state.main_board =
+            BoardMatrix::from_ascii_with_pieces(&board_lines.join("\n"), pieces)?;
+        state.score = score;
+        state.hold_pcps = hold;
+        state.next_pcs = next;
+
+        let (tet, rs, pos) = match current {
+            Some(c) => c,
+            None => {
+                let tet = state
+                    .next_pcs
+                    .pop_front()
+                    .context("need `current` or `next` to pick a piece")?;
+                (tet, RotState::R0, pieces.spawn_pos(tet))
+            }
+        };
+        let info = CurrentPcsInfo {
+            pos,
+            tet,
+            rs,
+            id: state.current_id,
+        };
+        state.current_id += 1;
+        state
+            .main_board
+            .spawn_piece(&info)
+            .context("current piece does not fit on board")?;
+        state.current_pcs = Some(info);
+        state.clear_ghost();
+        state.put_ghost();
+        Ok(state)
+    }
+
+    /// The board is written without the current piece and its ghost; the piece
+    /// goes in the `current` header instead.
+    pub fn to_ascii(&self) -> String {
+        let mut board = self.main_board;
+        for row in board.v.iter_mut() {
+            for cell in row.iter_mut() {
+                if *cell == CellValue::Ghost {
+                    *cell = CellValue::Empty;
+                }
+            }
+        }
+        let mut lines = vec![format!("score: {}", self.score)];
+        if let Some(hold) = &self.hold_pcps {
+            let flag = if hold.can_use { "" } else { " used" };
+            lines.push(format!("hold: {}{flag}", hold.tet.name()));
+        }
+        if !self.next_pcs.is_empty() {
+            let next: String = self.next_pcs.iter().map(|t| t.name()).collect();
+            lines.push(format!("next: {next}"));
+        }
+        if let Some(info) = &self.current_pcs {
+            if !self.game_over {
+                let _ = board.delete_piece(info);
+            }
+            lines.push(format!(
+                "current: {} {} {} {}",
+                info.tet.name(),
+                info.rs.name(),
+                info.pos.0,
+                info.pos.1
+            ));
+        }
+        lines.push(board.to_ascii());
+        lines.join("\n")
+    }
+}
+
+fn single_char(word: &str) -> anyhow::Result<char> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => anyhow::bail!("expected a single piece letter, got {word:?}"),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tet::TetAction;
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn board_ascii_roundtrip() {
+        let text = "
+            ....T.....
+            ...TTT....
+            ##.#######
+        ";
+        let board = BoardMatrix::<40, 10>::from_ascii(text).unwrap();
+        assert_eq!(board.v[0][0], CellValue::Garbage);
+        assert_eq!(board.v[0][2], CellValue::Empty);
+        assert_eq!(board.v[2][4], CellValue::Piece(Tet::T));
+        assert_eq!(board.to_ascii(), "....T.....\n...TTT....\n##.#######");
+        let again = BoardMatrix::<40, 10>::from_ascii(&board.to_ascii()).unwrap();
+        assert_eq!(board, again);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn board_ascii_rejects_bad_rows() {
+        assert!(BoardMatrix::<40, 10>::from_ascii("...").is_err());
+        assert!(BoardMatrix::<40, 10>::from_ascii("....x.....").is_err());
+        assert!(BoardMatrix::<2, 2>::from_ascii("..\n..\n..").is_err());
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn game_state_ascii_roundtrip() {
+        let text = "
+            score: 40
+            hold: I used
+            next: SZOJL
+            current: T R1 5 4
+            ##.#######
+        ";
+        let state = GameState::from_ascii(text).unwrap();
+        assert_eq!(state.score, 40);
+        assert_eq!(state.current_pcs.unwrap().rs, RotState::R1);
+        assert!(!state.hold_pcps.as_ref().unwrap().can_use);
+        let again = GameState::from_ascii(&state.to_ascii()).unwrap();
+        assert_eq!(state.main_board, again.main_board);
+        assert_eq!(state.to_ascii(), again.to_ascii());
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn custom_pieces_ascii_roundtrip() {
+        let mut rules = GameRules::standard();
+        rules.pieces = PieceSet::pentominoes();
+        let mut state = GameState::new_with_rules(&[5; 32], 0, &rules);
+        for i in 0..4 {
+            state.apply_action_if_works(TetAction::HardDrop, i).unwrap();
+        }
+        let text = state.to_ascii();
+        assert!(text.chars().any(|c| c.is_ascii_lowercase()));
+        assert!(!text.contains('C'));
+
+        let again = GameState::from_ascii_with_rules(&text, &rules).unwrap();
+        assert_eq!(state.main_board, again.main_board);
+        assert_eq!(state.next_pcs, again.next_pcs);
+        assert_eq!(
+            state.current_pcs.unwrap().tet,
+            again.current_pcs.unwrap().tet
+        );
+        assert_eq!(text, again.to_ascii());
+
+        // without the set, letters that are no standard piece are refused
+        assert!(PieceSet::pentominoes()
+            .tets()
+            .iter()
+            .any(|t| GameState::from_ascii(&format!("next: {}", t.name())).is_err()));
+        assert_eq!(PieceSet::standard().tet_from_char('t').unwrap(), Tet::T);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn line_clear_scoring() {
+        let mut state = GameState::from_ascii(
+            "
+            next: TTTTTT
+            current: O R0 18 4
+            ####..####
+            ####..####
+            ",
+        )
+        .unwrap();
+        state.apply_action_if_works(TetAction::HardDrop, 0).unwrap();
+        // hard drop (10) + double (80) + combo (50) + perfect clear (400)
+        assert_eq!(
+            state.to_ascii(),
+            "score: 540\nnext: TTTTT\ncurrent: T R0 18 3\n.........."
+        );
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn srs_wall_kick() {
+        let mut state = GameState::from_ascii(
+            "
+            next: IIIIII
+            current: T R1 3 -1
+            ###.......
+            #...######
+            ##.#######
+            ",
+        )
+        .unwrap();
+        state
+            .apply_action_if_works(TetAction::RotateRight, 0)
+            .unwrap();
+        let info = state.current_pcs.unwrap();
+        assert_eq!(info.rs, RotState::R2);
+        assert_eq!(info.pos, (3, 0));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn t_spin_double() {
+        // the overhang keeps a T pointing down from dropping straight in
+        let mut state = GameState::from_ascii(
+            "
+            next: IIIIII
+            current: T R3 0 2
+            ....#.....
+            ##...#####
+            ###.######
+            ",
+        )
+        .unwrap();
+        state
+            .apply_action_if_works(TetAction::RotateLeft, 0)
+            .unwrap();
+        assert!(state.is_t_spin);
+        state.apply_action_if_works(TetAction::HardDrop, 0).unwrap();
+        // hard drop (10) + double (80) + combo (50) + t-spin double (2000)
+        assert_eq!(
+            state.to_ascii(),
+            "score: 2140\nnext: IIIII\ncurrent: I R0 17 3\n....#....."
+        );
+        assert_eq!(state.garbage_sent, crate::garbage::attack(2, true, false));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn dropped_t_is_no_t_spin() {
+        let mut state = GameState::from_ascii(
+            "
+            next: IIIIII
+            current: T R2 10 2
+            #.........
+            ##...#####
+            ###.######
+            ",
+        )
+        .unwrap();
+        state.apply_action_if_works(TetAction::HardDrop, 0).unwrap();
+        // hard drop (10) + double (80) + combo (50)
+        assert_eq!(
+            state.to_ascii(),
+            "score: 140\nnext: IIIII\ncurrent: I R0 17 3\n#........."
+        );
+        assert_eq!(state.garbage_sent, crate::garbage::attack(2, false, false));
+    }
+}
@@ -0,0 +1,386 @@
+//! Piece sets: which pieces a game deals, where they spawn and how they kick.
+//!
+//! The 7 tetrominoes stay the `Tet` variants they always were, so standard
+//! games keep their boards, randomizer order and serialization. Any other
+//! piece is a `Tet::Custom(Polyomino)`: its shape travels with it onto every
+//! board cell, and the set in the rules only adds spawn position and kicks.
+
+use serde::{Deserialize, Serialize};
+
+use super::rot::{srs_offsets, transition_index, RotState, Shape};
+use super::tet::Tet;
+
+/// Largest box a custom piece can rotate in; 5 fits every pentomino.
+pub const MAX_POLYOMINO_SIZE: u8 = 5;
+/// Farthest a custom kick moves a piece, either way on either axis.
+pub const MAX_KICK_OFFSET: i8 = 5;
+/// Most offsets a custom kick table tries for one rotation.
+pub const MAX_KICKS_PER_ROTATION: usize = 16;
+
+/// Custom pieces are named by one lowercase letter, so they never clash with
+/// the standard `I L J T S Z O` in notation or in css classes.
+const CUSTOM_NAMES: [&str; 26] = [
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p",
+    "q", "r", "s", "t", "u", "v", "w", "x", "y", "z",
+];
+
+#[derive(
+    Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct Polyomino {
+    /// Side of the square box the piece rotates in.
+    pub size: u8,
+    /// Bit `j * size + i` is the cell in box row `j`, counted from the
+    /// bottom like board rows, and column `i`.
+    pub cells: u32,
+    /// Lowercase ASCII letter, unique within its piece set.
+    pub id: u8,
+    /// RGB.
+    pub color: [u8; 3],
+}
+
+impl Polyomino {
+    /// `rows` are drawn top row first, `#` for a cell. The piece sits at the
+    /// top of its box, like the standard pieces do.
+    pub fn from_rows(rows: &[&str], id: char, color: [u8; 3]) -> anyhow::Result<Self> {
+        if !id.is_ascii_lowercase() {
+            anyhow::bail!("piece id {id:?} is not a lowercase letter");
+        }
+        let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        let size = width.max(rows.len());
+        if size == 0 || size > MAX_POLYOMINO_SIZE as usize {
+            anyhow::bail!(
+                "piece must fit a {MAX_POLYOMINO_SIZE}x{MAX_POLYOMINO_SIZE} box"
+            );
+        }
+        let mut cells = 0;
+        for (r, row) in rows.iter().enumerate() {
+            let j = size - 1 - r;
+            for (i, c) in row.chars().enumerate() {
+                match c {
+                    '#' => cells |= 1 << (j * size + i),
+                    '.' => {}
+                    _ => anyhow::bail!("bad piece cell {c:?}"),
+                }
+            }
+        }
+        let p = Self {
+            size: size as u8,
+            cells,
+            id: id as u8,
+            color,
+        };
+        p.validate()?;
+        Ok(p)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.size == 0 || self.size > MAX_POLYOMINO_SIZE {
+            anyhow::bail!("piece box size {} out of range", self.size);
+        }
+        let box_cells = self.size as u32 * self.size as u32;
+        if self.cells == 0 || self.cells >> box_cells != 0 {
+            anyhow::bail!("piece cells do not fit its box");
+        }
+        if !self.id.is_ascii_lowercase() {
+            anyhow::bail!("piece id {} is not a lowercase letter", self.id);
+        }
+        Ok(())
+    }
+
+    /// Same layout as `Tet::orig_shape`. A box too big for `validate` is
+    /// cut down to the largest one.
+    pub fn shape(&self) -> Shape {
+        let size = self.size.min(MAX_POLYOMINO_SIZE) as usize;
+        (0..size)
+            .map(|j| {
+                (0..size)
+                    .map(|i| self.cells & (1 << (j * size + i)) != 0)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// `"?"` for an id that `validate` refuses.
+    pub fn name(&self) -> &'static str {
+        self.id
+            .checked_sub(b'a')
+            .and_then(|i| CUSTOM_NAMES.get(i as usize))
+            .copied()
+            .unwrap_or("?")
+    }
+
+    /// Top filled row at the hidden row right above the visible 20, centered.
+    pub fn spawn_pos(&self) -> (i8, i8) {
+        let shape = self.shape();
+        let top = shape
+            .iter()
+            .rposition(|row| row.contains(&true))
+            .unwrap_or(0);
+        (20 - top as i8, (10 - shape.len() as i8) / 2)
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KickTable {
+    /// The SRS tables: the I table for `Tet::I`, the JLSTZ one for the rest.
+    Srs,
+    /// `(x, y)` offsets to try for each of the 8 rotations, in
+    /// `rot::transition_index` order.
+    Custom(Vec<Vec<(i8, i8)>>),
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PieceDef {
+    pub tet: Tet,
+    /// `(y, x)` like `CurrentPcsInfo::pos`.
+    pub spawn_pos: (i8, i8),
+    pub kicks: KickTable,
+}
+
+impl PieceDef {
+    pub fn new(tet: Tet) -> Self {
+        Self {
+            tet,
+            spawn_pos: tet.spawn_pos(),
+            kicks: KickTable::Srs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PieceSet {
+    pub name: String,
+    /// The randomizer deals these in this order before shuffling.
+    pub pieces: Vec<PieceDef>,
+}
+
+impl PieceSet {
+    pub fn standard() -> Self {
+        Self {
+            name: "tetrominoes".to_string(),
+            pieces: Tet::all().into_iter().map(PieceDef::new).collect(),
+        }
+    }
+
+    pub fn trominoes() -> Self {
+        Self::from_rows(
+            "trominoes",
+            &[
+                ('i', [0x21, 0xB6, 0xF8], &["###"]),
+                ('l', [0xFF, 0x87, 0x20], &["#.", "##"]),
+            ],
+        )
+    }
+
+    /// The 12 free pentominoes, F I L N P T U V W X Y Z.
+    pub fn pentominoes() -> Self {
+        Self::from_rows(
+            "pentominoes",
+            &[
+                ('f', [0xE0, 0x60, 0x20], &[".##", "##.", ".#."]),
+                ('i', [0x21, 0xB6, 0xF8], &["#####"]),
+                ('l', [0xFF, 0x87, 0x20], &["...#", "####"]),
+                ('n', [0x20, 0xA0, 0x90], &["##..", ".###"]),
+                ('p', [0xF0, 0x70, 0xA0], &["##", "##", "#."]),
+                ('t', [0xDA, 0x5D, 0xB2], &["###", ".#.", ".#."]),
+                ('u', [0xFF, 0xC1, 0x25], &["#.#", "###"]),
+                ('v', [0x41, 0x69, 0xE7], &["#..", "#..", "###"]),
+                ('w', [0x90, 0xC0, 0x50], &["#..", "##.", ".##"]),
+                ('x', [0xE0, 0xE0, 0xE0], &[".#.", "###", ".#."]),
+                ('y', [0xB0, 0x40, 0xE0], &[".#..", "####"]),
+                ('z', [0xFF, 0x4A, 0x58], &["##.", ".#.", ".##"]),
+            ],
+        )
+    }
+
+    /// Built-in sets have valid shapes, so this only panics on a typo here.
+    fn from_rows(name: &str, shapes: &[(char, [u8; 3], &[&str])]) -> Self {
+        let pieces = shapes
+            .iter()
+            .map(|&(id, color, rows)| {
+                let p =
+                    Polyomino::from_rows(rows, id, color).expect("bad built-in piece");
+                PieceDef::new(Tet::Custom(p))
+            })
+            .collect();
+        Self {
+            name: name.to_string(),
+            pieces,
+        }
+    }
+
+    pub fn tets(&self) -> Vec<Tet> {
+        self.pieces.iter().map(|p| p.tet).collect()
+    }
+
+    pub fn get(&self, tet: Tet) -> Option<&PieceDef> {
+        self.pieces.iter().find(|p| p.tet == tet)
+    }
+
+    pub fn spawn_pos(&self, tet: Tet) -> (i8, i8) {
+        self.get(tet)
+            .map(|p| p.spawn_pos)
+            .unwrap_or_else(|| tet.spawn_pos())
+    }
+
+    /// Offsets to try, `(x, y)`, when `tet` rotates from `before` to `after`.
+    pub fn kicks(&self, tet: Tet, before: RotState, after: RotState) -> Vec<(i8, i8)> {
+        match self.get(tet).map(|p| &p.kicks) {
+            Some(KickTable::Custom(table)) => table
+                .get(transition_index(before, after))
+                .cloned()
+                .unwrap_or_else(|| vec![(0, 0)]),
+            Some(KickTable::Srs) | None => srs_offsets(before, after, tet),
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.pieces.is_empty() {
+            anyhow::bail!("piece set {:?} is empty", self.name);
+        }
+        for (i, p) in self.pieces.iter().enumerate() {
+            if self.pieces[..i]
+                .iter()
+                .any(|q| q.tet.name() == p.tet.name())
+            {
+                anyhow::bail!("piece {} is in the set twice", p.tet.name());
+            }
+            if let Tet::Custom(poly) = p.tet {
+                poly.validate()?;
+            }
+            if let KickTable::Custom(table) = &p.kicks {
+                if table.len() != 8 || table.iter().any(|t| t.is_empty()) {
+                    anyhow::bail!("kick table needs 8 non-empty rotations");
+                }
+                if table.iter().any(|t| t.len() > MAX_KICKS_PER_ROTATION) {
+                    anyhow::bail!(
+                        "kick table has more than {MAX_KICKS_PER_ROTATION} offsets \
+                         for a rotation"
+                    );
+                }
+                let too_far =
+                    |x: i8| !(-MAX_KICK_OFFSET..=MAX_KICK_OFFSET).contains(&x);
+                if table
+                    .iter()
+                    .flatten()
+                    .any(|(x, y)| too_far(*x) || too_far(*y))
+                {
+                    anyhow::bail!("kicks move a piece at most {MAX_KICK_OFFSET} cells");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for PieceSet {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::rules::GameRules;
+    use crate::tet::{GameState, TetAction};
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn polyomino_shapes() {
+        let l3 = Polyomino::from_rows(&["#.", "##"], 'l', [0; 3]).unwrap();
+        assert_eq!(l3.shape(), vec![vec![true, true], vec![true, false]]);
+        assert_eq!(l3.spawn_pos(), (19, 4));
+        assert_eq!(l3.name(), "l");
+        assert!(Polyomino::from_rows(&["######"], 'a', [0; 3]).is_err());
+        assert!(Polyomino::from_rows(&["#x"], 'a', [0; 3]).is_err());
+        assert!(Polyomino::from_rows(&["#"], 'A', [0; 3]).is_err());
+
+        let pentominoes = PieceSet::pentominoes();
+        pentominoes.validate().unwrap();
+        for p in &pentominoes.pieces {
+            let Tet::Custom(poly) = p.tet else { panic!() };
+            assert_eq!(poly.cells.count_ones(), 5);
+        }
+        let names: String = pentominoes.tets().iter().map(|t| t.name()).collect();
+        assert_eq!(names, "filnptuvwxyz");
+        let mut colors: Vec<_> = pentominoes
+            .tets()
+            .iter()
+            .map(|t| match t {
+                Tet::Custom(p) => p.color,
+                _ => panic!(),
+            })
+            .collect();
+        colors.sort();
+        colors.dedup();
+        assert_eq!(colors.len(), 12);
+
+        // same name as the F, another shape
+        let mut twice = pentominoes.clone();
+        let other = Polyomino::from_rows(&["#####"], 'f', [0; 3]).unwrap();
+        twice.pieces.push(PieceDef::new(Tet::Custom(other)));
+        assert!(twice.validate().is_err());
+        assert_eq!(PieceSet::standard().tets(), Tet::all());
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn unchecked_pieces_do_not_panic() {
+        let bad = Polyomino {
+            size: 200,
+            cells: u32::MAX,
+            id: 0,
+            color: [0; 3],
+        };
+        assert!(bad.validate().is_err());
+        assert_eq!(bad.name(), "?");
+        assert_eq!(bad.shape().len(), MAX_POLYOMINO_SIZE as usize);
+        bad.spawn_pos();
+
+        let mut set = PieceSet::standard();
+        set.pieces[0].kicks = KickTable::Custom(vec![vec![(0, 0)]]);
+        assert!(set.validate().is_err());
+        assert_eq!(set.kicks(Tet::I, RotState::R0, RotState::R1), vec![(0, 0)]);
+        set.pieces[0].kicks = KickTable::Custom(vec![vec![(0, 0), (i8::MAX, 0)]; 8]);
+        assert!(set.validate().is_err());
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn pentomino_game_replays() {
+        let mut rules = GameRules::standard();
+        rules.pieces = PieceSet::pentominoes();
+        rules.validate().unwrap();
+        let mut state = GameState::new_with_rules(&[9; 32], 0, &rules);
+        let actions = [
+            TetAction::RotateRight,
+            TetAction::MoveLeft,
+            TetAction::HardDrop,
+            TetAction::Hold,
+            TetAction::MoveRight,
+            TetAction::HardDrop,
+            TetAction::HardDrop,
+        ];
+        for (i, action) in actions.iter().enumerate() {
+            let _ = state.apply_action_if_works(*action, i as i64);
+        }
+        assert!(state.next_pcs.iter().all(|t| matches!(t, Tet::Custom(_))));
+        let filled = state
+            .main_board
+            .v
+            .iter()
+            .flatten()
+            .filter(|c| matches!(c, crate::tet::CellValue::Piece(Tet::Custom(_))))
+            .count();
+        assert_eq!(filled % 5, 0);
+
+        let mut rebuilt = GameState::new_with_rules(&[9; 32], 0, &rules);
+        for slice in &state.replay.replay_slices {
+            rebuilt.accept_replay_slice(slice).unwrap();
+        }
+        assert_eq!(rebuilt, state);
+    }
+}
@@ -4,20 +4,25 @@ use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
 pub type GameSeed = <ChaCha20Rng as SeedableRng>::Seed;
 
-fn get_rng(seed: &GameSeed) -> ChaCha20Rng {
+pub(crate) fn get_rng(seed: &GameSeed) -> ChaCha20Rng {
     // let mut seed = seed.clone();
     // rand:    :thread_rng().fill(&mut seed);
     ChaCha20Rng::from_seed(*seed)
 }
 
-pub fn shuffle_tets(seed: &GameSeed, event_time: i64) -> (Vec<Tet>, GameSeed) {
+/// One bag: every piece of `pieces` once, shuffled.
+pub fn shuffle_tets(
+    seed: &GameSeed,
+    event_time: i64,
+    pieces: &[Tet],
+) -> (Vec<Tet>, GameSeed) {
     let event_time = event_time.to_le_bytes();
     let mut seed = *seed;
     for i in 0..8 {
         seed[i] ^= event_time[i];
     }
 
-    let mut v = Tet::all();
+    let mut v = pieces.to_vec();
     use rand::prelude::SliceRandom;
     let mut rng = get_rng(&seed);
     v.shuffle(&mut rng);
@@ -31,8 +36,11 @@ pub fn accept_event(
     event_ts: i64,
     event_idx: u32,
 ) -> GameSeed {
-    let event_hash = bincode::serialize(event).unwrap(); // 4 bytes
-    assert!(event_hash.len() == 4);
+    // 4 bytes for plain actions; longer ones are folded into 4
+    let mut event_hash = [0u8; 4];
+    for (i, b) in bincode::serialize(event).unwrap().iter().enumerate() {
+        event_hash[i % 4] ^= b;
+    }
     let ts = event_ts.to_le_bytes();
     let event_idx = event_idx.to_le_bytes();
 
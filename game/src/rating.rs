@@ -0,0 +1,218 @@
+//! Glicko-2 skill ratings, see <http://www.glicko.net/glicko/glicko2.pdf>.
+//!
+//! Every finished match is its own rating period. Time without games counts
+//! in `RATING_PERIOD_NS` steps and only grows the deviation, so a player
+//! coming back moves faster until the rating settles again.
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_RATING: f64 = 1500.0;
+pub const DEFAULT_DEVIATION: f64 = 350.0;
+pub const DEFAULT_VOLATILITY: f64 = 0.06;
+/// Ratings with a deviation above this are shown as provisional.
+pub const PROVISIONAL_DEVIATION: f64 = 110.0;
+/// Inactivity is counted in days.
+pub const RATING_PERIOD_NS: i64 = 24 * 3600 * 1_000_000_000;
+
+/// How fast the volatility may change.
+const TAU: f64 = 0.5;
+const SCALE: f64 = 173.7178;
+const EPSILON: f64 = 0.000001;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    /// Time of the last rated match, in nanoseconds.
+    pub last_played: i64,
+    pub games: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+            last_played: 0,
+            games: 0,
+        }
+    }
+}
+
+/// A rating before and after one match.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RatingChange {
+    pub match_type: crate::api::game_match::GameMatchType,
+    pub time: i64,
+    pub before: Rating,
+    pub after: Rating,
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (std::f64::consts::PI * std::f64::consts::PI)).sqrt()
+}
+
+fn expected(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+    1.0 / (1.0 + (-g(phi_j) * (mu - mu_j)).exp())
+}
+
+impl Rating {
+    pub fn is_provisional(&self) -> bool {
+        self.deviation > PROVISIONAL_DEVIATION
+    }
+
+    /// The rating with the deviation grown for the periods since it was last
+    /// played. New ratings are already at the maximum.
+    pub fn decayed(&self, now: i64) -> Rating {
+        let periods = (now - self.last_played) / RATING_PERIOD_NS;
+        if self.games == 0 || periods <= 0 {
+            return *self;
+        }
+        let periods = periods as f64;
+        let phi = self.deviation / SCALE;
+        let phi = (phi * phi + periods * self.volatility * self.volatility).sqrt();
+        Rating {
+            deviation: (phi * SCALE).min(DEFAULT_DEVIATION),
+            ..*self
+        }
+    }
+
+    /// Rates one period of games. `results` holds each opponent with the
+    /// score against them: 1 for a win, 0 for a loss, 0.5 for a draw.
+    pub fn update(&self, results: &[(Rating, f64)], now: i64) -> Rating {
+        let me = self.decayed(now);
+        let mu = (me.rating - DEFAULT_RATING) / SCALE;
+        let phi = me.deviation / SCALE;
+        if results.is_empty() {
+            return me;
+        }
+
+        let mut v_inv = 0.0;
+        let mut delta_sum = 0.0;
+        for (opponent, score) in results {
+            let mu_j = (opponent.rating - DEFAULT_RATING) / SCALE;
+            let phi_j = opponent.deviation / SCALE;
+            let e = expected(mu, mu_j, phi_j);
+            v_inv += g(phi_j) * g(phi_j) * e * (1.0 - e);
+            delta_sum += g(phi_j) * (score - e);
+        }
+        let v = 1.0 / v_inv;
+        let delta = v * delta_sum;
+        let volatility = new_volatility(delta, phi, v, me.volatility);
+
+        let phi_star = (phi * phi + volatility * volatility).sqrt();
+        let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let mu = mu + phi * phi * delta_sum;
+        Rating {
+            rating: mu * SCALE + DEFAULT_RATING,
+            deviation: (phi * SCALE).min(DEFAULT_DEVIATION),
+            volatility,
+            last_played: now,
+            games: me.games + results.len() as u32,
+        }
+    }
+}
+
+/// Step 5 of the paper, the Illinois method.
+fn new_volatility(delta: f64, phi: f64, v: f64, sigma: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let d = phi * phi + v + ex;
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * d * d)
+            - (x - a) / (TAU * TAU)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > EPSILON {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+    (big_a / 2.0).exp()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn paper_example() {
+        let me = rating(1500.0, 200.0);
+        let results = [
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ];
+        let new = me.update(&results, 0);
+        assert!((new.rating - 1464.06).abs() < 0.01, "{new:?}");
+        assert!((new.deviation - 151.52).abs() < 0.01, "{new:?}");
+        assert!((new.volatility - 0.05999).abs() < 0.00001, "{new:?}");
+        assert_eq!(new.games, 3);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn new_players_are_provisional() {
+        let mut a = Rating::default();
+        let mut b = Rating::default();
+        assert!(a.is_provisional());
+        // a wins two of every three
+        for i in 0..30 {
+            let score = if i % 3 == 2 { 0.0 } else { 1.0 };
+            let new_a = a.update(&[(b, score)], i);
+            let new_b = b.update(&[(a, 1.0 - score)], i);
+            a = new_a;
+            b = new_b;
+        }
+        assert!(a.rating > b.rating);
+        assert!(!a.is_provisional(), "{a:?}");
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn inactive_ratings_decay() {
+        let played = Rating {
+            games: 10,
+            last_played: 0,
+            ..rating(1800.0, 60.0)
+        };
+        assert_eq!(played.decayed(RATING_PERIOD_NS - 1), played);
+        let later = played.decayed(365 * RATING_PERIOD_NS);
+        assert_eq!(later.rating, 1800.0);
+        assert!(later.deviation > played.deviation);
+        assert!(
+            played.decayed(100_000 * RATING_PERIOD_NS).deviation <= DEFAULT_DEVIATION
+        );
+    }
+}
@@ -2,6 +2,21 @@ use serde::{Deserialize, Serialize};
 
 use super::tet::Tet;
 
+/// Position of a rotation in the kick tables; same order as `srs_offsets`.
+pub fn transition_index(before: RotState, after: RotState) -> usize {
+    match (before, after) {
+        (RotState::R0, RotState::R1) => 0,
+        (RotState::R1, RotState::R0) => 1,
+        (RotState::R1, RotState::R2) => 2,
+        (RotState::R2, RotState::R1) => 3,
+        (RotState::R2, RotState::R3) => 4,
+        (RotState::R3, RotState::R2) => 5,
+        (RotState::R3, RotState::R0) => 6,
+        (RotState::R0, RotState::R3) => 7,
+        _ => panic!("180 or 0 rot is bad"),
+    }
+}
+
 pub fn srs_offsets(before: RotState, after: RotState, tet: Tet) -> Vec<(i8, i8)> {
     match tet {
         Tet::I => match (before, after) {
@@ -123,41 +138,96 @@ pub fn rotate_shape(shape: Shape, rot: RotDirection) -> Shape {
     new_shape
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     #[test]
-//     fn rot_i_left() {
-//         let result = rotate_shape(super::super::tet::Tet::I.orig_shape(), RotDirection::Left);
-//         let expected = vec![vec![true], vec![true], vec![true], vec![true]];
-//         assert_eq!(result, expected);
-//     }
-
-//     #[test]
-//     fn rot_l_right() {
-//         let result = rotate_shape(super::super::tet::Tet::L.orig_shape(), RotDirection::Right);
-//         let expected = vec![vec![true, true], vec![true, false], vec![true, false]];
-//         assert_eq!(result, expected);
-//     }
-
-//     #[test]
-//     fn rot_l_left() {
-//         let result = rotate_shape(super::super::tet::Tet::L.orig_shape(), RotDirection::Left);
-//         let expected = vec![vec![false, true], vec![false, true], vec![true, true]];
-//         assert_eq!(result, expected);
-//     }
-
-//     #[test]
-//     fn rot_j_right() {
-//         let result = rotate_shape(super::super::tet::Tet::J.orig_shape(), RotDirection::Right);
-//         let expected = vec![vec![true, false], vec![true, false], vec![true, true]];
-//         assert_eq!(result, expected);
-//     }
-
-//     // #[test]
-//     // fn rot_j_left() {
-//     //     let result = rotate_shape(super::super::tet::Tet::J.orig_shape(), RotDirection::Right);
-//     //     let expected = vec![vec![true, true], vec![false, true], vec![false, true]];
-//     //     assert_eq!(result, expected);
-//     // }
-// }
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    // shapes are listed bottom row first
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn rot_i_left() {
+        let result = rotate_shape(Tet::I.orig_shape(), RotDirection::Left);
+        let expected = vec![vec![false, true, false, false]; 4];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn rot_i_right() {
+        let result = rotate_shape(Tet::I.orig_shape(), RotDirection::Right);
+        let expected = vec![vec![false, false, true, false]; 4];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn rot_l_right() {
+        let result = rotate_shape(Tet::L.orig_shape(), RotDirection::Right);
+        let expected = vec![
+            vec![false, true, true],
+            vec![false, true, false],
+            vec![false, true, false],
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn rot_l_left() {
+        let result = rotate_shape(Tet::L.orig_shape(), RotDirection::Left);
+        let expected = vec![
+            vec![false, true, false],
+            vec![false, true, false],
+            vec![true, true, false],
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn rot_j_right() {
+        let result = rotate_shape(Tet::J.orig_shape(), RotDirection::Right);
+        let expected = vec![
+            vec![false, true, false],
+            vec![false, true, false],
+            vec![false, true, true],
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn rot_j_left() {
+        let result = rotate_shape(Tet::J.orig_shape(), RotDirection::Left);
+        let expected = vec![
+            vec![true, true, false],
+            vec![false, true, false],
+            vec![false, true, false],
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn rotations_undo_each_other() {
+        for tet in Tet::all() {
+            let shape = tet.orig_shape();
+            let back = rotate_shape(
+                rotate_shape(shape.clone(), RotDirection::Right),
+                RotDirection::Left,
+            );
+            assert_eq!(back, shape);
+            assert_eq!(
+                tet.shape(RotState::R0.rotate(RotDirection::Left)),
+                tet.shape(RotState::R3)
+            );
+            let mut full = shape.clone();
+            for _ in 0..4 {
+                full = rotate_shape(full, RotDirection::Right);
+            }
+            assert_eq!(full, shape);
+        }
+    }
+}
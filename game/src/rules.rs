@@ -0,0 +1,366 @@
+use serde::{Deserialize, Serialize};
+
+use crate::pieces::PieceSet;
+
+/// Bump whenever the engine changes behavior for existing rules, and keep the
+/// old behavior reachable for replays recorded with older versions.
+///
+/// 1. rules recorded in the `Init` segment
+/// 2. sonic drop, IRS / IHS and the soft drop speed
+/// 3. modifiers
+/// 4. piece sets
+/// 5. goals
+pub const GAME_RULES_VERSION: u32 = 5;
+
+/// Everything the engine decides that a replay needs to reproduce. Recorded
+/// in the `Init` segment, so replays always run under the rules they were
+/// played with. Fields newer than version 1 default to how the engine played
+/// before them, so json replays from older versions still load.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameRules {
+    pub version: u32,
+    /// The queue is refilled whenever it gets shorter than this.
+    pub next_queue_len: usize,
+    /// How many next pieces are shown; `0` plays without preview.
+    pub next_preview: usize,
+    pub hold_enabled: bool,
+    pub randomizer: Randomizer,
+    #[serde(default)]
+    pub pieces: PieceSet,
+    pub rotation_system: RotationSystem,
+    /// Time between automatic soft drops.
+    pub gravity_ms: u64,
+    #[serde(default = "SoftDropSpeed::standard")]
+    pub soft_drop_speed: SoftDropSpeed,
+    /// IRS: rotate the next piece as it spawns while a rotate key is held.
+    #[serde(default)]
+    pub initial_rotation: bool,
+    /// IHS: same for hold.
+    #[serde(default)]
+    pub initial_hold: bool,
+    pub scoring: ScoringRules,
+    #[serde(default)]
+    pub modifiers: Modifiers,
+    #[serde(default)]
+    pub goal: GameGoal,
+}
+
+/// How a game ends, besides topping out.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum GameGoal {
+    #[default]
+    Endless,
+    /// Over once this many lines are cleared.
+    Lines(u32),
+    /// Over at the first action this long after the start.
+    TimeLimitMs(u64),
+}
+
+/// Challenge modes on top of the base rules. No-hold and no-preview are
+/// plain rules: `hold_enabled` and `next_preview`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct Modifiers {
+    /// 20G: pieces fall to the floor as soon as they spawn or move.
+    pub instant_gravity: bool,
+    pub locked_pieces: LockedVisibility,
+    /// Left and right input are swapped.
+    pub mirror: bool,
+    /// Every cell is drawn 2x2, on a logical board of `BIG_BOARD_COLS` by
+    /// `BIG_BOARD_ROWS`.
+    pub big: bool,
+}
+
+pub const BIG_BOARD_ROWS: usize = 20;
+pub const BIG_BOARD_COLS: usize = 5;
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum LockedVisibility {
+    #[default]
+    Visible,
+    Invisible,
+    /// Shown when they lock, then faded out by the renderer.
+    Fading,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SoftDropSpeed {
+    /// Held soft drop moves this many times faster than gravity.
+    Factor(u32),
+    /// Held soft drop is a sonic drop.
+    Infinite,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Randomizer {
+    /// Every batch holds each piece of the set once, 7 for tetrominoes.
+    SevenBag,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RotationSystem {
+    Srs,
+    /// Rotate in place or not at all.
+    NoKicks,
+}
+
+/// Tables are indexed by the number of cleared lines.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScoringRules {
+    pub line_clear: [i64; 5],
+    pub perfect_clear: [i64; 5],
+    pub t_spin: [i64; 5],
+    pub combo: i64,
+    pub soft_drop: i64,
+    pub hard_drop: i64,
+}
+
+impl GameRules {
+    pub fn standard() -> Self {
+        Self {
+            version: GAME_RULES_VERSION,
+            next_queue_len: 6,
+            next_preview: 5,
+            hold_enabled: true,
+            randomizer: Randomizer::SevenBag,
+            pieces: PieceSet::standard(),
+            rotation_system: RotationSystem::Srs,
+            gravity_ms: 1000,
+            soft_drop_speed: SoftDropSpeed::standard(),
+            initial_rotation: false,
+            initial_hold: false,
+            scoring: ScoringRules {
+                line_clear: [0, 40, 80, 160, 320],
+                perfect_clear: [0, 200, 400, 800, 1600],
+                t_spin: [0, 1000, 2000, 3000, 0],
+                combo: 50,
+                soft_drop: 2,
+                hard_drop: 10,
+            },
+            modifiers: Modifiers::default(),
+            goal: GameGoal::Endless,
+        }
+    }
+
+    /// Oldest version whose engine knows every rule set here.
+    pub fn min_version(&self) -> u32 {
+        let standard = Self::standard();
+        if self.goal != standard.goal {
+            5
+        } else if self.pieces != standard.pieces {
+            4
+        } else if self.modifiers != standard.modifiers {
+            3
+        } else if self.soft_drop_speed != standard.soft_drop_speed
+            || self.initial_rotation
+            || self.initial_hold
+        {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Rejects rules the engine cannot play, e.g. from a newer client.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.version == 0 || self.version > GAME_RULES_VERSION {
+            anyhow::bail!(
+                "rules version {} not supported (have {GAME_RULES_VERSION})",
+                self.version
+            );
+        }
+        let min_version = self.min_version();
+        if min_version > self.version {
+            anyhow::bail!(
+                "rules need version {min_version}, but say {}",
+                self.version
+            );
+        }
+        if self.next_preview > self.next_queue_len {
+            anyhow::bail!("cannot preview more pieces than the queue holds");
+        }
+        if self.next_queue_len == 0 {
+            anyhow::bail!("next queue cannot be empty");
+        }
+        if self.gravity_ms == 0 {
+            anyhow::bail!("gravity must be positive");
+        }
+        self.pieces.validate()?;
+        if self.soft_drop_speed == SoftDropSpeed::Factor(0) {
+            anyhow::bail!("soft drop factor must be positive");
+        }
+        if matches!(self.goal, GameGoal::Lines(0) | GameGoal::TimeLimitMs(0)) {
+            anyhow::bail!("game goal must be positive");
+        }
+        Ok(())
+    }
+}
+
+impl SoftDropSpeed {
+    pub fn standard() -> Self {
+        SoftDropSpeed::Factor(30)
+    }
+
+    /// Repeat interval for a held soft drop key, `None` when it sonic drops.
+    pub fn repeat_ms(&self, gravity_ms: u64) -> Option<u64> {
+        match self {
+            SoftDropSpeed::Factor(f) => Some((gravity_ms / *f as u64).max(1)),
+            SoftDropSpeed::Infinite => None,
+        }
+    }
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::tet::{GameReplaySegment, GameState, TetAction};
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn replay_follows_recorded_rules() {
+        let mut rules = GameRules::standard();
+        rules.hold_enabled = false;
+        rules.next_queue_len = 3;
+        rules.next_preview = 3;
+        rules.scoring.hard_drop = 100;
+        rules.validate().unwrap();
+
+        let mut state = GameState::new_with_rules(&[3; 32], 0, &rules);
+        assert!(state.apply_action_if_works(TetAction::Hold, 1).is_err());
+        state.apply_action_if_works(TetAction::HardDrop, 2).unwrap();
+        assert_eq!(state.score, 100);
+        let preview_cells = state
+            .get_next_board()
+            .v
+            .iter()
+            .flatten()
+            .filter(|c| !c.eq(&&crate::tet::CellValue::Empty))
+            .count();
+        assert_eq!(preview_cells, 3 * 4);
+
+        // a replay rebuilt from its init segment uses the same rules
+        let init = GameReplaySegment::Init(
+            GameState::new_with_rules(&[3; 32], 0, &rules).replay,
+        );
+        let GameReplaySegment::Init(replay) = init else {
+            unreachable!()
+        };
+        let mut rebuilt = GameState::new_with_rules(
+            &replay.init_seed,
+            replay.start_time,
+            &replay.rules,
+        );
+        for slice in &state.replay.replay_slices {
+            rebuilt.accept_replay_slice(slice).unwrap();
+        }
+        assert_eq!(rebuilt, state);
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn unknown_rules_version_is_rejected() {
+        let mut rules = GameRules::standard();
+        rules.version += 1;
+        assert!(rules.validate().is_err());
+        rules.version = 0;
+        assert!(rules.validate().is_err());
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn older_rules_versions_still_play() {
+        let old = GameRules {
+            version: 1,
+            ..GameRules::standard()
+        };
+        old.validate().unwrap();
+        let mut state = GameState::new_with_rules(&[3; 32], 0, &old);
+        state.apply_action_if_works(TetAction::HardDrop, 1).unwrap();
+        assert!(state
+            .apply_action_if_works(TetAction::SonicDrop, 2)
+            .is_err());
+
+        let mut rules = old.clone();
+        rules.modifiers.mirror = true;
+        assert_eq!(rules.min_version(), 3);
+        assert!(rules.validate().is_err());
+        rules.version = 3;
+        rules.validate().unwrap();
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn sonic_drop_and_initial_actions() {
+        let mut rules = GameRules::standard();
+        rules.initial_rotation = true;
+        let mut state = GameState::new_with_rules(&[5; 32], 0, &rules);
+
+        let before = state.current_pcs.unwrap();
+        state
+            .apply_action_if_works(TetAction::SonicDrop, 1)
+            .unwrap();
+        let after = state.current_pcs.unwrap();
+        assert_eq!(after.id, before.id, "sonic drop must not lock");
+        assert!(after.pos.0 < before.pos.0);
+        assert!(state
+            .apply_action_if_works(TetAction::SonicDrop, 2)
+            .is_err());
+        assert!(state
+            .apply_action_if_works(TetAction::InitialRotateRight, 3)
+            .is_err());
+
+        state.apply_action_if_works(TetAction::HardDrop, 4).unwrap();
+        let held = [TetAction::RotateRight, TetAction::Hold];
+        assert_eq!(
+            state.initial_actions(&held),
+            vec![TetAction::InitialRotateRight]
+        );
+        state
+            .apply_action_if_works(TetAction::InitialRotateRight, 5)
+            .unwrap();
+        assert!(state
+            .apply_action_if_works(TetAction::InitialHold, 6)
+            .is_err());
+        assert_eq!(SoftDropSpeed::Factor(30).repeat_ms(1000), Some(33));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    pub fn modifiers_change_play() {
+        let mut rules = GameRules::standard();
+        rules.modifiers.mirror = true;
+        rules.modifiers.instant_gravity = true;
+        let mut state = GameState::new_with_rules(&[5; 32], 0, &rules);
+        let spawned = state.current_pcs.unwrap();
+        assert!(
+            spawned.pos.0 < state.spawn_pos(spawned.tet).0,
+            "20G on spawn"
+        );
+        state.apply_action_if_works(TetAction::MoveLeft, 1).unwrap();
+        assert_eq!(state.current_pcs.unwrap().pos.1, spawned.pos.1 + 1);
+        assert_eq!(
+            state.replay.replay_slices[0].event.action,
+            TetAction::MoveLeft
+        );
+
+        let mut rules = GameRules::standard();
+        rules.modifiers.big = true;
+        let mut state = GameState::new_with_rules(&[5; 32], 0, &rules);
+        for i in 0..BIG_BOARD_COLS as i64 {
+            let _ = state.apply_action_if_works(TetAction::MoveRight, i);
+        }
+        let cells = state.current_piece_cells();
+        assert!(cells.iter().all(|(_, x)| *x < 2 * BIG_BOARD_COLS));
+        assert!(cells.iter().any(|(_, x)| *x == 2 * BIG_BOARD_COLS - 1));
+        let display = state.get_display_board();
+        for (y, x) in cells {
+            assert!(matches!(display.v[y][x], crate::tet::CellValue::Piece(_)));
+        }
+    }
+}
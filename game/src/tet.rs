@@ -2,8 +2,13 @@ use anyhow::Context;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
+use super::pieces::Polyomino;
 use super::rot::{RotDirection, RotState, Shape};
+use super::rules::{
+    GameGoal, GameRules, Randomizer, RotationSystem, BIG_BOARD_COLS, BIG_BOARD_ROWS,
+};
 
+use super::garbage;
 use super::random::*;
 
 use std::collections::VecDeque;
@@ -18,6 +23,8 @@ pub enum Tet {
     S,
     Z,
     O,
+    /// Any other piece, from a `pieces::PieceSet`.
+    Custom(Polyomino),
 }
 
 impl Tet {
@@ -27,10 +34,11 @@ impl Tet {
         match self {
             &Self::I => I_SPAWN_POS,
             &Self::O => O_SPAWN_POS,
+            Self::Custom(p) => p.spawn_pos(),
             _ => SPAWN_POS,
         }
     }
-    pub fn name(&self) -> &str {
+    pub fn name(&self) -> &'static str {
         match self {
             &Self::I => "I",
             &Self::L => "L",
@@ -39,6 +47,7 @@ impl Tet {
             &Self::S => "S",
             &Self::Z => "Z",
             &Self::O => "O",
+            Self::Custom(p) => p.name(),
         }
     }
 
@@ -99,6 +108,7 @@ impl Tet {
                 vec![true, true, false],
             ],
             &Self::O => vec![vec![true, true], vec![true, true]],
+            Self::Custom(p) => p.shape(),
         }
     }
     pub fn random() -> Self {
@@ -256,23 +266,40 @@ impl<const R: usize, const C: usize> BoardMatrix<R, C> {
         }
         Ok(())
     }
-    pub fn spawn_nextpcs(&mut self, next_pcs: &VecDeque<Tet>) {
+    pub fn spawn_nextpcs(&mut self, next_pcs: &VecDeque<Tet>, count: usize) {
         let col: i8 = 0;
         let mut row: i8 = R as i8 - 4;
         for (i, piece) in next_pcs.iter().enumerate() {
-            if i >= 5 {
+            if i >= count {
                 break;
             }
+            let dy = match piece {
+                Tet::O => 1,
+                Tet::Custom(p) => p.spawn_pos().0 - SPAWN_POS.0,
+                _ => 0,
+            };
             let info = CurrentPcsInfo {
                 id: 0,
-                pos: (row + if (*piece).eq(&Tet::O) { 1 } else { 0 }, col),
+                pos: (row + dy, col),
                 tet: *piece,
                 rs: RotState::R0,
             };
-            let r = self.spawn_piece(&info);
+            if self.spawn_piece(&info).is_err() {
+                self.spawn_piece_clipped(&info);
+            }
             row -= 3;
-            if r.is_err() {
-                log::info!("{r:?}");
+        }
+    }
+    /// Draws whatever part of the piece fits, for previews of pieces wider
+    /// than the side boards.
+    pub fn spawn_piece_clipped(&mut self, info: &CurrentPcsInfo) {
+        let (y, x) = info.pos;
+        for (j, row) in info.tet.shape(info.rs).iter().enumerate() {
+            for (i, cell) in row.iter().enumerate() {
+                let (cx, cy) = (x + i as i8, y + j as i8);
+                if *cell && (0..C as i8).contains(&cx) && (0..R as i8).contains(&cy) {
+                    self.v[cy as usize][cx as usize] = CellValue::Piece(info.tet);
+                }
             }
         }
     }
@@ -290,6 +317,14 @@ pub enum TetAction {
     RotateLeft,
     RotateRight,
     Nothing,
+    /// Fall to the floor without locking.
+    SonicDrop,
+    /// Only right after a piece spawned, when the rules allow IRS / IHS.
+    InitialRotateLeft,
+    InitialRotateRight,
+    InitialHold,
+    /// Garbage lines sent by the opponent in a versus match.
+    ReceiveGarbage(u8),
 }
 
 impl TetAction {
@@ -299,6 +334,22 @@ impl TetAction {
             _ => false,
         }
     }
+    /// Mirror mode swaps left and right input.
+    pub fn mirrored(&self) -> Self {
+        match self {
+            TetAction::MoveLeft => TetAction::MoveRight,
+            TetAction::MoveRight => TetAction::MoveLeft,
+            other => *other,
+        }
+    }
+    pub fn is_initial(&self) -> bool {
+        matches!(
+            self,
+            TetAction::InitialRotateLeft
+                | TetAction::InitialRotateRight
+                | TetAction::InitialHold
+        )
+    }
     pub fn random() -> Self {
         use rand::seq::SliceRandom;
         use rand::thread_rng;
@@ -321,8 +372,8 @@ impl TetAction {
 }
 
 pub const SIDE_BOARD_WIDTH: usize = 4;
-type BoardMatrixHold = BoardMatrix<3, SIDE_BOARD_WIDTH>;
-type BoardMatrixNext = BoardMatrix<16, SIDE_BOARD_WIDTH>;
+pub type BoardMatrixHold = BoardMatrix<3, SIDE_BOARD_WIDTH>;
+pub type BoardMatrixNext = BoardMatrix<16, SIDE_BOARD_WIDTH>;
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GameState {
     pub score: i64,
@@ -338,6 +389,11 @@ pub struct GameState {
 
     pub hold_pcps: Option<HoldPcsInfo>,
     pub game_over: bool,
+    /// Attack lines this game produced, see `crate::garbage`.
+    pub garbage_sent: u32,
+    pub garbage_received: u32,
+    /// Lines cleared so far.
+    pub lines: u32,
 
     pub replay: GameReplay,
     pub seed: GameSeed,
@@ -350,19 +406,34 @@ pub struct GameReplay {
     pub init_seed: GameSeed,
     pub start_time: i64,
     pub replay_slices: Vec<GameReplaySlice>,
+    pub undo_mode: UndoMode,
+    pub rules: GameRules,
 }
 
 impl GameReplay {
-    pub fn empty(seed: &GameSeed, start_time: i64) -> Self {
+    pub fn empty(seed: &GameSeed, start_time: i64, rules: &GameRules) -> Self {
         Self {
             init_seed: *seed,
             start_time,
             replay_slices: vec![],
+            undo_mode: UndoMode::Disabled,
+            rules: rules.clone(),
         }
     }
 }
 
+/// Whether `GameHistory` may rewind this game. Recorded games stay `Disabled`;
+/// once a rewind happened the replay is marked `Used` for good.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UndoMode {
+    Disabled,
+    Enabled,
+    Used,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+// the init segment is sent once per game, not worth boxing
+#[allow(clippy::large_enum_variant)]
 pub enum GameReplaySegment {
     Init(GameReplay),
     Update(GameReplaySlice),
@@ -396,8 +467,8 @@ const SPAWN_POS: (i8, i8) = (18, 3);
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HoldPcsInfo {
-    can_use: bool,
-    tet: Tet,
+    pub can_use: bool,
+    pub tet: Tet,
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
@@ -408,8 +479,34 @@ pub struct CurrentPcsInfo {
     pub id: u32,
 }
 
+/// The hold box, shared by every board that has a hold.
+pub fn hold_board(hold: &Option<HoldPcsInfo>) -> BoardMatrixHold {
+    let mut b = BoardMatrixHold::empty();
+    if let Some(HoldPcsInfo { can_use: _, tet }) = *hold {
+        let y = match tet {
+            Tet::I => -1,
+            Tet::Custom(p) => p.spawn_pos().0 - SPAWN_POS.0,
+            _ => 0,
+        };
+        let info = CurrentPcsInfo {
+            tet,
+            pos: (y, 0),
+            rs: RotState::R0,
+            id: 0,
+        };
+        if b.spawn_piece(&info).is_err() {
+            b.spawn_piece_clipped(&info);
+        }
+    }
+    b
+}
+
 impl GameState {
     pub fn new(seed: &GameSeed, start_time: i64) -> Self {
+        Self::new_with_rules(seed, start_time, &GameRules::standard())
+    }
+
+    pub fn new_with_rules(seed: &GameSeed, start_time: i64, rules: &GameRules) -> Self {
         let mut new_state = Self {
             score: 0,
             have_combo: false,
@@ -421,15 +518,19 @@ impl GameState {
             next_pcs: VecDeque::new(),
             current_pcs: None,
             game_over: false,
+            garbage_sent: 0,
+            garbage_received: 0,
+            lines: 0,
             hold_pcps: None,
             current_id: 0,
             seed: *seed,
             init_seed: *seed,
-            replay: GameReplay::empty(seed, start_time),
+            replay: GameReplay::empty(seed, start_time, rules),
             start_time,
         };
         new_state.refill_nextpcs(start_time);
         let _ = new_state.put_next_piece(start_time);
+        new_state.apply_instant_gravity();
         new_state.put_ghost();
         new_state
     }
@@ -439,6 +540,13 @@ impl GameState {
         let start_time = 0;
         Self::new(&seed, start_time)
     }
+    /// Practice and custom games only; see `crate::history::GameHistory`.
+    pub fn enable_undo(&mut self) {
+        if self.replay.undo_mode == UndoMode::Disabled {
+            self.replay.undo_mode = UndoMode::Enabled;
+        }
+    }
+
     pub fn get_debug_info(&self) -> String {
         format!(
             "last_acction: {:?} \n next_pcs: {:?} \n current_pcs: {:?} \n hold_psc: {:?} \n is_game_over: {:?}",
@@ -452,6 +560,7 @@ impl GameState {
         let mut score2 = 0;
         let mut score3 = 0;
 
+        let scoring = self.replay.rules.scoring.clone();
         while let Some(line) = self.can_clear_line() {
             for i in line..39 {
                 for j in 0..10 {
@@ -463,41 +572,29 @@ impl GameState {
             self.have_combo = true;
         }
         if self.have_combo {
-            self.score += 50;
+            self.score += scoring.combo;
             self.have_combo = false;
         }
-        score += match lines {
-            1 => 40,
-            2 => 80,
-            3 => 160,
-            4 => 320,
-            _ => 0,
-        };
-        if self.is_gameboard_empty() {
-            score2 += match lines {
-                1 => 200,
-                2 => 400,
-                3 => 800,
-                4 => 1600,
-                _ => 0,
-            };
+        self.lines += lines as u32;
+        let lines = lines.min(4);
+        score += scoring.line_clear[lines];
+        let perfect_clear = self.is_gameboard_empty();
+        if perfect_clear {
+            score2 += scoring.perfect_clear[lines];
         }
+        self.garbage_sent += garbage::attack(lines, self.is_t_spin, perfect_clear);
         if self.is_t_spin {
-            score3 += match lines {
-                1 => 1000,
-                2 => 2000,
-                3 => 3000,
-                _ => 0,
-            };
+            score3 += scoring.t_spin[lines];
             self.is_t_spin = false;
         }
-        self.score += (score + score2 + score3) as i64;
+        self.score += score + score2 + score3;
     }
 
     fn can_clear_line(&self) -> Option<i8> {
+        let (_, cols) = self.playfield_size();
         for i in 0..40 {
             let row = self.main_board.v[i];
-            let is_full = row
+            let is_full = row[..cols as usize]
                 .iter()
                 .map(|cell| match cell {
                     CellValue::Piece(_) => true,
@@ -530,9 +627,15 @@ impl GameState {
     }
 
     fn refill_nextpcs(&mut self, event_time: i64) {
-        while self.next_pcs.len() < 6 {
+        while self.next_pcs.len() < self.replay.rules.next_queue_len {
             log::info!("next refill");
-            let (new_pcs2, new_seed) = shuffle_tets(&self.seed, event_time);
+            let (new_pcs2, new_seed) = match self.replay.rules.randomizer {
+                Randomizer::SevenBag => shuffle_tets(
+                    &self.seed,
+                    event_time,
+                    &self.replay.rules.pieces.tets(),
+                ),
+            };
             for n in new_pcs2 {
                 self.next_pcs.push_back(n);
             }
@@ -551,17 +654,21 @@ impl GameState {
         }
 
         self.clear_line();
+        if self.goal_reached(_event_time) {
+            self.game_over = true;
+            return Ok(());
+        }
         let next_tet = self.next_pcs.pop_front().unwrap();
 
         self.current_pcs = Some(CurrentPcsInfo {
-            pos: next_tet.spawn_pos(),
+            pos: self.spawn_pos(next_tet),
             tet: next_tet,
             id: self.current_id,
             rs: RotState::R0,
         });
         self.current_id += 1;
 
-        if let Err(_) = self.main_board.spawn_piece(&self.current_pcs.unwrap()) {
+        if self.spawn_current(&self.current_pcs.unwrap()).is_err() {
             log::info!("tet game over");
             self.game_over = true;
         } else if let Some(ref mut h) = self.hold_pcps {
@@ -599,29 +706,39 @@ impl GameState {
         }
         Ok(())
     }
+    /// Whether the rules' goal is met at `event_time`.
+    pub fn goal_reached(&self, event_time: i64) -> bool {
+        match self.replay.rules.goal {
+            GameGoal::Endless => false,
+            GameGoal::Lines(lines) => self.lines >= lines,
+            GameGoal::TimeLimitMs(ms) => {
+                event_time - self.start_time >= ms as i64 * 1_000_000
+            }
+        }
+    }
+
+    /// Time between the start and the last action.
+    pub fn duration_ns(&self) -> i64 {
+        self.replay
+            .replay_slices
+            .last()
+            .map_or(0, |s| s.event_timestamp - self.start_time)
+    }
+
     pub fn get_next_board(&self) -> BoardMatrixNext {
         let mut b = BoardMatrixNext::empty();
-        b.spawn_nextpcs(&self.next_pcs);
+        b.spawn_nextpcs(&self.next_pcs, self.replay.rules.next_preview);
         b
     }
 
     pub fn get_hold_board(&self) -> BoardMatrixHold {
-        let mut b = BoardMatrixHold::empty();
-        if let Some(HoldPcsInfo { can_use: _, tet }) = self.hold_pcps {
-            let info = CurrentPcsInfo {
-                tet,
-                pos: (if tet.eq(&Tet::I) { -1 } else { 0 }, 0),
-                rs: RotState::R0,
-                id: 0,
-            };
-            if let Err(e) = b.spawn_piece(&info) {
-                log::warn!("hold board cannot spawn piece WTF: {:?}", e);
-            }
-        }
-        b
+        hold_board(&self.hold_pcps)
     }
 
     fn try_hold(&mut self, event_time: i64) -> anyhow::Result<()> {
+        if !self.replay.rules.hold_enabled {
+            anyhow::bail!("hold is disabled");
+        }
         let current_pcs = self.current_pcs.context("no current pcs")?;
 
         let old_hold = self.hold_pcps.clone();
@@ -657,12 +774,13 @@ impl GameState {
         let mut soft_drops: i16 = 0;
         let current_pcs = self.current_pcs.context("no current pcs")?;
         let mut r = self.try_softdrop(event_time);
-        while r.is_ok() && current_pcs.id == self.current_pcs.unwrap().id {
+        while r.is_ok() && self.current_pcs.map(|p| p.id) == Some(current_pcs.id) {
             r = self.try_softdrop(event_time);
             soft_drops += 1;
         }
-        self.score += 10;
-        self.score -= (soft_drops * 2) as i64;
+        let scoring = &self.replay.rules.scoring;
+        self.score += scoring.hard_drop;
+        self.score -= soft_drops as i64 * scoring.soft_drop;
         Ok(())
     }
 
@@ -674,8 +792,8 @@ impl GameState {
         }
         let mut new_current_pcs = current_pcs;
         new_current_pcs.pos.0 -= 1;
-        if self.main_board.spawn_piece(&new_current_pcs).is_ok() {
-            self.score += 2;
+        if self.spawn_current(&new_current_pcs).is_ok() {
+            self.score += self.replay.rules.scoring.soft_drop;
             self.current_pcs = Some(new_current_pcs);
             self.is_t_spin = false;
         } else {
@@ -686,6 +804,227 @@ impl GameState {
         Ok(())
     }
 
+    fn try_sonicdrop(&mut self) -> anyhow::Result<()> {
+        let rows = self.drop_to_floor()?;
+        if rows == 0 {
+            anyhow::bail!("already on the floor");
+        }
+        self.score += rows as i64 * self.replay.rules.scoring.soft_drop;
+        Ok(())
+    }
+
+    /// Moves the current piece down as far as it goes, without locking.
+    /// Returns how many rows it fell.
+    fn drop_to_floor(&mut self) -> anyhow::Result<i8> {
+        let current_pcs = self.current_pcs.context("no current pcs")?;
+
+        if let Err(e) = self.main_board.delete_piece(&current_pcs) {
+            log::warn!("ccannot delete picei from main board plz: {:?}", e)
+        }
+        let mut new_current_pcs = current_pcs;
+        loop {
+            new_current_pcs.pos.0 -= 1;
+            if self.main_board.spawn_piece(&new_current_pcs).is_err() {
+                new_current_pcs.pos.0 += 1;
+                break;
+            }
+            self.main_board.delete_piece(&new_current_pcs)?;
+        }
+        self.main_board.spawn_piece(&new_current_pcs)?;
+        let rows = current_pcs.pos.0 - new_current_pcs.pos.0;
+        if rows > 0 {
+            self.current_pcs = Some(new_current_pcs);
+            self.is_t_spin = false;
+        }
+        Ok(rows)
+    }
+
+    /// Pushes `lines` garbage rows in from the bottom, all with the hole in
+    /// the same column. The falling piece rides up with the stack.
+    fn receive_garbage(&mut self, lines: u8) -> anyhow::Result<()> {
+        if lines == 0 {
+            anyhow::bail!("no garbage lines");
+        }
+        let (rows, cols) = self.playfield_size();
+        let (rows, cols) = (rows as usize, cols as usize);
+        let current_pcs = self.current_pcs;
+        if let Some(info) = current_pcs {
+            let _ = self.main_board.delete_piece(&info);
+        }
+        self.clear_ghost();
+
+        let n = (lines as usize).min(rows);
+        let hole = garbage::hole_column(&self.seed, cols);
+        let pushed_out = self.main_board.v[rows - n..rows].iter().any(|row| {
+            row[..cols]
+                .iter()
+                .any(|c| matches!(c, CellValue::Piece(_) | CellValue::Garbage))
+        });
+        for y in (n..rows).rev() {
+            self.main_board.v[y] = self.main_board.v[y - n];
+        }
+        for y in 0..n {
+            for x in 0..cols {
+                self.main_board.v[y][x] = if x == hole {
+                    CellValue::Empty
+                } else {
+                    CellValue::Garbage
+                };
+            }
+        }
+        self.garbage_received += n as u32;
+        if pushed_out {
+            log::info!("tet game over: garbage pushed blocks out");
+            self.game_over = true;
+            return Ok(());
+        }
+
+        if let Some(mut info) = current_pcs {
+            while self.spawn_current(&info).is_err() {
+                info.pos.0 += 1;
+                if info.pos.0 as usize >= rows {
+                    log::info!("tet game over: no room for the piece");
+                    self.game_over = true;
+                    return Ok(());
+                }
+            }
+            self.current_pcs = Some(info);
+        }
+        Ok(())
+    }
+
+    /// 20G: whatever the piece did, it ends up on the floor.
+    fn apply_instant_gravity(&mut self) {
+        if self.replay.rules.modifiers.instant_gravity
+            && !self.game_over
+            && self.current_pcs.is_some()
+        {
+            let _ = self.drop_to_floor();
+        }
+    }
+
+    /// Rows and columns pieces can use; big mode plays on the lower left
+    /// corner of the board.
+    fn playfield_size(&self) -> (i8, i8) {
+        if self.replay.rules.modifiers.big {
+            (BIG_BOARD_ROWS as i8, BIG_BOARD_COLS as i8)
+        } else {
+            (
+                self.main_board.get_num_rows() as i8,
+                self.main_board.get_num_cols() as i8,
+            )
+        }
+    }
+
+    pub fn spawn_pos(&self, tet: Tet) -> (i8, i8) {
+        let (y, x) = self.replay.rules.pieces.spawn_pos(tet);
+        if self.replay.rules.modifiers.big {
+            // same height over the visible rows, centered on half the width
+            (y - BIG_BOARD_ROWS as i8 / 2, x - 2)
+        } else {
+            (y, x)
+        }
+    }
+
+    /// Puts the current piece on the board, if it fits the playfield.
+    fn spawn_current(&mut self, info: &CurrentPcsInfo) -> anyhow::Result<()> {
+        let (rows, cols) = self.playfield_size();
+        let shape = info.tet.shape(info.rs);
+        for (j, row) in shape.iter().enumerate() {
+            for (i, cell) in row.iter().enumerate() {
+                if *cell
+                    && (info.pos.1 + i as i8 >= cols || info.pos.0 + j as i8 >= rows)
+                {
+                    anyhow::bail!("piece leaves the playfield");
+                }
+            }
+        }
+        self.main_board.spawn_piece(info)
+    }
+
+    /// The main board as players see it: big mode cells are drawn 2x2.
+    pub fn get_display_board(&self) -> BoardMatrix {
+        if !self.replay.rules.modifiers.big {
+            return self.main_board;
+        }
+        let mut b = BoardMatrix::empty();
+        for y in 0..BIG_BOARD_ROWS {
+            for x in 0..BIG_BOARD_COLS {
+                for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                    b.v[2 * y + dy][2 * x + dx] = self.main_board.v[y][x];
+                }
+            }
+        }
+        b
+    }
+
+    /// `(y, x)` of the falling piece on the display board, so renderers can
+    /// tell it apart from locked cells.
+    pub fn current_piece_cells(&self) -> Vec<(usize, usize)> {
+        let Some(info) = self.current_pcs else {
+            return vec![];
+        };
+        let scale = if self.replay.rules.modifiers.big {
+            2
+        } else {
+            1
+        };
+        let mut cells = vec![];
+        for (j, row) in info.tet.shape(info.rs).iter().enumerate() {
+            for (i, cell) in row.iter().enumerate() {
+                let (y, x) = (info.pos.0 + j as i8, info.pos.1 + i as i8);
+                if !*cell || y < 0 || x < 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        cells.push((y as usize * scale + dy, x as usize * scale + dx));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    /// IRS / IHS only apply to a piece that has not moved since it spawned.
+    fn check_initial_action(&self, action: TetAction) -> anyhow::Result<()> {
+        let rules = &self.replay.rules;
+        let allowed = match action {
+            TetAction::InitialHold => rules.initial_hold,
+            _ => rules.initial_rotation,
+        };
+        if !allowed {
+            anyhow::bail!("{action:?} is disabled");
+        }
+        let current_pcs = self.current_pcs.context("no current pcs")?;
+        let mut spawn_pos = self.spawn_pos(current_pcs.tet);
+        if rules.modifiers.instant_gravity {
+            // already fell, only the height differs
+            spawn_pos.0 = current_pcs.pos.0;
+        }
+        if current_pcs.pos != spawn_pos || current_pcs.rs != RotState::R0 {
+            anyhow::bail!("{action:?} only works on a freshly spawned piece");
+        }
+        Ok(())
+    }
+
+    /// What to apply to a freshly spawned piece while these actions are held.
+    pub fn initial_actions(&self, held: &[TetAction]) -> Vec<TetAction> {
+        let rules = &self.replay.rules;
+        let mut v = vec![];
+        if rules.initial_hold && held.contains(&TetAction::Hold) {
+            v.push(TetAction::InitialHold);
+        }
+        if rules.initial_rotation {
+            if held.contains(&TetAction::RotateLeft) {
+                v.push(TetAction::InitialRotateLeft);
+            } else if held.contains(&TetAction::RotateRight) {
+                v.push(TetAction::InitialRotateRight);
+            }
+        }
+        v
+    }
+
     fn try_moveleft(&mut self) -> anyhow::Result<()> {
         let current_pcs = self.current_pcs.context("no current pcs")?;
 
@@ -696,7 +1035,7 @@ impl GameState {
         let mut new_current_pcs = current_pcs;
         new_current_pcs.pos.1 -= 1;
 
-        self.main_board.spawn_piece(&new_current_pcs)?;
+        self.spawn_current(&new_current_pcs)?;
         self.current_pcs = Some(new_current_pcs);
         Ok(())
     }
@@ -711,7 +1050,7 @@ impl GameState {
         let mut new_current_pcs = current_pcs;
         new_current_pcs.pos.1 += 1;
 
-        self.main_board.spawn_piece(&new_current_pcs)?;
+        self.spawn_current(&new_current_pcs)?;
         self.current_pcs = Some(new_current_pcs);
         Ok(())
     }
@@ -725,17 +1064,27 @@ impl GameState {
         let before = &current_pcs.rs;
         let after = &current_pcs.rs.rotate(rot);
 
-        for (_try_idx, (x, y)) in
-            super::rot::srs_offsets(*before, *after, *(&current_pcs.tet))
-                .iter()
-                .enumerate()
-        {
+        let offsets = match self.replay.rules.rotation_system {
+            RotationSystem::Srs => {
+                self.replay
+                    .rules
+                    .pieces
+                    .kicks(current_pcs.tet, *before, *after)
+            }
+            RotationSystem::NoKicks => vec![(0, 0)],
+        };
+        for (x, y) in offsets.iter() {
             let mut new_current_pcs: CurrentPcsInfo = current_pcs;
             new_current_pcs.rs = *after;
             // warning! table above in (x, y) but our repr in (y, x)
-            new_current_pcs.pos.0 += y;
-            new_current_pcs.pos.1 += x;
-            if let Ok(_) = self.main_board.spawn_piece(&new_current_pcs) {
+            let (Some(row), Some(col)) = (
+                new_current_pcs.pos.0.checked_add(*y),
+                new_current_pcs.pos.1.checked_add(*x),
+            ) else {
+                continue;
+            };
+            new_current_pcs.pos = (row, col);
+            if self.spawn_current(&new_current_pcs).is_ok() {
                 self.current_pcs = Some(new_current_pcs);
                 let _is_blocked_up = false;
                 self.is_t_spin = true;
@@ -754,8 +1103,22 @@ impl GameState {
         }
         let mut new = self.clone();
         new.last_action = action;
+        if new.goal_reached(event_time) {
+            // the action that comes too late only ends the game
+            new.game_over = true;
+            new.put_replay_event(&GameReplayEvent { action }, event_time);
+            new.clear_ghost();
+            return Ok(new);
+        }
         new.refill_nextpcs(event_time);
 
+        // the replay keeps the key that was pressed
+        let input = action;
+        let action = if new.replay.rules.modifiers.mirror {
+            action.mirrored()
+        } else {
+            action
+        };
         match action {
             TetAction::HardDrop => {
                 new.try_harddrop(event_time)?;
@@ -779,9 +1142,31 @@ impl GameState {
                 new.try_rotate(RotDirection::Right)?;
             }
             TetAction::Nothing => {}
+            TetAction::SonicDrop => {
+                if new.replay.rules.version < 2 {
+                    anyhow::bail!("sonic drop needs rules version 2");
+                }
+                new.try_sonicdrop()?;
+            }
+            TetAction::InitialRotateLeft => {
+                new.check_initial_action(action)?;
+                new.try_rotate(RotDirection::Left)?;
+            }
+            TetAction::InitialRotateRight => {
+                new.check_initial_action(action)?;
+                new.try_rotate(RotDirection::Right)?;
+            }
+            TetAction::InitialHold => {
+                new.check_initial_action(action)?;
+                new.try_hold(event_time)?;
+            }
+            TetAction::ReceiveGarbage(lines) => {
+                new.receive_garbage(lines)?;
+            }
         }
+        new.apply_instant_gravity();
         let ev = GameReplayEvent {
-            action,
+            action: input,
             // game_over: self.game_over,
         };
         new.put_replay_event(&ev, event_time);
@@ -792,7 +1177,7 @@ impl GameState {
         Ok(new)
     }
 
-    fn put_ghost(&mut self) {
+    pub(crate) fn put_ghost(&mut self) {
         let mut ghost_board = self.main_board.clone();
         let info = self.current_pcs.unwrap();
         ghost_board
@@ -833,7 +1218,7 @@ impl GameState {
         gameboard_empty
     }
 
-    fn clear_ghost(&mut self) {
+    pub(crate) fn clear_ghost(&mut self) {
         for y in 0..self.main_board.get_num_rows() {
             for x in 0..self.main_board.get_num_cols() {
                 let old_value = (&self.main_board.v)[y][x];
@@ -949,4 +1334,47 @@ pub mod tests {
             // assert_eq!(active_game, passive_game);
         }
     }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn line_goal_ends_the_game() {
+        let rules = GameRules {
+            goal: GameGoal::Lines(1),
+            ..GameRules::standard()
+        };
+        let mut state = GameState::new_with_rules(&[5; 32], 0, &rules);
+        for cell in state.main_board.v[0].iter_mut() {
+            *cell = CellValue::Garbage;
+        }
+        state.apply_action_if_works(TetAction::HardDrop, 1).unwrap();
+        assert_eq!(state.lines, 1);
+        assert!(state.game_over);
+        assert!(state.goal_reached(1));
+    }
+
+    #[test]
+    #[wasm_bindgen_test]
+    fn time_goal_ends_the_game() {
+        let rules = GameRules {
+            goal: GameGoal::TimeLimitMs(1000),
+            ..GameRules::standard()
+        };
+        let mut state = GameState::new_with_rules(&[5; 32], 0, &rules);
+        state
+            .apply_action_if_works(TetAction::MoveLeft, 999_000_000)
+            .unwrap();
+        assert!(!state.game_over);
+        state
+            .apply_action_if_works(TetAction::MoveLeft, 1_000_000_000)
+            .unwrap();
+        assert!(state.game_over);
+        assert_eq!(state.duration_ns(), 1_000_000_000);
+
+        // a replay ends at the same slice
+        let mut rebuilt = GameState::new_with_rules(&[5; 32], 0, &rules);
+        for slice in &state.replay.replay_slices {
+            rebuilt.accept_replay_slice(slice).unwrap();
+        }
+        assert_eq!(rebuilt, state);
+    }
 }
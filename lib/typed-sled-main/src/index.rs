@@ -0,0 +1,382 @@
+//! Secondary indexes on a [Tree].
+//!
+//! An index maps every row of a tree to any number of [IndexKey]s, which sort
+//! by their bytes. The entries live in a sled tree of their own and are written
+//! in the same transaction as the row, so an index never disagrees with its tree.
+//!
+//! # Example
+//! ```
+//! use typed_sled::index::IndexKey;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let db = sled::Config::new().temporary(true).open().unwrap();
+//!     let scores = typed_sled::Tree::<String, i64>::open(&db, "scores")
+//!         .with_index(&db, "scores_by_value", |_name, score| {
+//!             vec![IndexKey::new().i64(*score)]
+//!         });
+//!
+//!     scores.insert(&"a".to_owned(), &5)?;
+//!     scores.insert(&"b".to_owned(), &-3)?;
+//!     scores.insert(&"c".to_owned(), &2)?;
+//!     scores.remove(&"c".to_owned())?;
+//!
+//!     let mut names = vec![];
+//!     for entry in scores.index_iter("scores_by_value") {
+//!         let (_index_key, name, _score) = entry?;
+//!         names.push(name);
+//!     }
+//!     assert_eq!(names, vec!["b".to_owned(), "a".to_owned()]);
+//!     Ok(())
+//! }
+//! ```
+use core::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use sled::transaction::UnabortableTransactionError;
+
+use crate::{deserialize, serialize, KV};
+
+/// Bytes that sort like the values they were built from. Fields are
+/// fixed-width, so a key built from a few leading fields is a prefix of the
+/// keys that continue with more.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IndexKey(Vec<u8>);
+
+impl IndexKey {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Raw bytes, as they are. Only fixed-width ones keep the order of the
+    /// fields after them.
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn bool(self, x: bool) -> Self {
+        self.u8(x as u8)
+    }
+
+    pub fn u8(mut self, x: u8) -> Self {
+        self.0.push(x);
+        self
+    }
+
+    pub fn u32(self, x: u32) -> Self {
+        self.bytes(&x.to_be_bytes())
+    }
+
+    pub fn u64(self, x: u64) -> Self {
+        self.bytes(&x.to_be_bytes())
+    }
+
+    /// Big-endian with the sign bit flipped.
+    pub fn i64(self, x: i64) -> Self {
+        self.u64((x as u64) ^ (1 << 63))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Smallest key after every key that starts with this one, or `None` if
+    /// there is no such key.
+    pub fn prefix_end(&self) -> Option<IndexKey> {
+        let mut end = self.0.clone();
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return Some(IndexKey(end));
+            }
+        }
+        None
+    }
+}
+
+impl From<Vec<u8>> for IndexKey {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<IndexKey> for Vec<u8> {
+    fn from(key: IndexKey) -> Self {
+        key.0
+    }
+}
+
+type IndexKeysFn<K, V> = dyn Fn(&K, &V) -> Vec<IndexKey> + Send + Sync;
+
+/// One secondary index of a tree. Entries are keyed by the index key followed
+/// by the primary key, so rows with equal index keys don't collide, and hold
+/// the primary key.
+pub(crate) struct Index<K, V> {
+    pub(crate) id: String,
+    pub(crate) tree: sled::Tree,
+    keys: Arc<IndexKeysFn<K, V>>,
+    _key: PhantomData<fn() -> K>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<K, V> Clone for Index<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            tree: self.tree.clone(),
+            keys: self.keys.clone(),
+            _key: PhantomData,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Index<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Index").field("id", &self.id).finish()
+    }
+}
+
+impl<K, V> Index<K, V> {
+    pub(crate) fn open<F>(db: &sled::Db, id: &str, keys: F) -> Self
+    where
+        F: Fn(&K, &V) -> Vec<IndexKey> + Send + Sync + 'static,
+    {
+        Self {
+            id: id.to_owned(),
+            tree: db.open_tree(id).unwrap(),
+            keys: Arc::new(keys),
+            _key: PhantomData,
+            _value: PhantomData,
+        }
+    }
+
+    fn entries(&self, key: &K, value: &V) -> Vec<Vec<u8>>
+    where
+        K: KV,
+    {
+        let primary = serialize(key);
+        (self.keys)(key, value)
+            .into_iter()
+            .map(|index_key| [index_key.0, primary.clone()].concat())
+            .collect()
+    }
+
+    /// Moves the entries of `key` from what `old` had to what `new` has.
+    pub(crate) fn update(
+        &self,
+        tree: &sled::transaction::TransactionalTree,
+        key: &K,
+        old: Option<&V>,
+        new: Option<&V>,
+    ) -> Result<(), UnabortableTransactionError>
+    where
+        K: KV,
+    {
+        let old_entries = old.map(|v| self.entries(key, v)).unwrap_or_default();
+        let new_entries = new.map(|v| self.entries(key, v)).unwrap_or_default();
+        for entry in &old_entries {
+            if !new_entries.contains(entry) {
+                tree.remove(entry.as_slice())?;
+            }
+        }
+        for entry in new_entries {
+            if !old_entries.contains(&entry) {
+                tree.insert(entry, serialize(key))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the entries of every row, outside of any transaction.
+    pub(crate) fn fill(&self, rows: crate::Iter<K, V>) -> sled::Result<()>
+    where
+        K: KV,
+        V: KV,
+    {
+        for row in rows {
+            let (key, value) = row?;
+            for entry in self.entries(&key, &value) {
+                self.tree.insert(entry, serialize(&key))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rows of a tree in the order of one of its indexes, with the whole key of
+/// each index entry. That key works as a bound for the next range.
+pub struct IndexIter<K, V> {
+    entries: sled::Iter,
+    rows: sled::Tree,
+    _key: PhantomData<fn() -> K>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<K, V> IndexIter<K, V> {
+    pub(crate) fn new(entries: sled::Iter, rows: sled::Tree) -> Self {
+        Self {
+            entries,
+            rows,
+            _key: PhantomData,
+            _value: PhantomData,
+        }
+    }
+
+    /// Looks up the row of an entry. Rows removed since the entry was read
+    /// are skipped.
+    fn row(
+        &self,
+        entry: sled::Result<(sled::IVec, sled::IVec)>,
+    ) -> Option<sled::Result<(IndexKey, K, V)>>
+    where
+        K: KV,
+        V: KV,
+    {
+        let (index_key, primary) = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+        match self.rows.get(&primary) {
+            Ok(Some(value)) => Some(Ok((
+                IndexKey(index_key.to_vec()),
+                deserialize(&primary),
+                deserialize(&value),
+            ))),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl<K: KV, V: KV> Iterator for IndexIter<K, V> {
+    type Item = sled::Result<(IndexKey, K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = self.entries.next()?;
+            if let Some(row) = self.row(entry) {
+                return Some(row);
+            }
+        }
+    }
+}
+
+impl<K: KV, V: KV> DoubleEndedIterator for IndexIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = self.entries.next_back()?;
+            if let Some(row) = self.row(entry) {
+                return Some(row);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transactional;
+    use crate::Tree;
+
+    fn by_parity(db: &sled::Db) -> Tree<u32, i64> {
+        Tree::open(db, "numbers").with_index(db, "numbers_by_parity", |k, v| {
+            vec![IndexKey::new().bool(k % 2 == 0).i64(*v)]
+        })
+    }
+
+    fn keys(iter: impl Iterator<Item = sled::Result<(IndexKey, u32, i64)>>) -> Vec<u32> {
+        iter.map(|r| r.unwrap().1).collect()
+    }
+
+    #[test]
+    fn index_keys_sort_like_numbers() {
+        let numbers = [i64::MIN, -5, -1, 0, 1, 7, i64::MAX];
+        for pair in numbers.windows(2) {
+            assert!(IndexKey::new().i64(pair[0]) < IndexKey::new().i64(pair[1]));
+        }
+        let key = IndexKey::new().u8(1).u8(2).u8(255);
+        assert_eq!(key.prefix_end(), Some(IndexKey::new().u8(1).u8(3)));
+        assert_eq!(IndexKey::new().u8(255).prefix_end(), None);
+    }
+
+    #[test]
+    fn index_follows_inserts_and_removes() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = by_parity(&db);
+        for (k, v) in [(1, 10), (2, -4), (3, 7), (4, 30), (5, -1)] {
+            tree.insert(&k, &v).unwrap();
+        }
+        assert_eq!(
+            keys(tree.index_iter("numbers_by_parity")),
+            vec![5, 3, 1, 2, 4]
+        );
+
+        tree.insert(&1, &-100).unwrap();
+        tree.remove(&4).unwrap();
+        let odd = IndexKey::new().bool(false);
+        assert_eq!(
+            keys(tree.index_scan_prefix("numbers_by_parity", &odd)),
+            vec![1, 5, 3]
+        );
+        let even = IndexKey::new().bool(true);
+        assert_eq!(
+            keys(tree.index_scan_prefix("numbers_by_parity", &even).rev()),
+            vec![2]
+        );
+
+        // what a range stopped at is where the next one starts
+        let first = tree
+            .index_scan_prefix("numbers_by_parity", &odd)
+            .next()
+            .unwrap()
+            .unwrap();
+        let rest = tree.index_range(
+            "numbers_by_parity",
+            (
+                std::ops::Bound::Excluded(first.0),
+                std::ops::Bound::Excluded(odd.prefix_end().unwrap()),
+            ),
+        );
+        assert_eq!(keys(rest), vec![5, 3]);
+    }
+
+    #[test]
+    fn transactions_update_indexes() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = by_parity(&db);
+        let plain = Tree::<u32, u32>::open(&db, "plain");
+        tree.insert(&1, &1).unwrap();
+
+        (&tree, &plain)
+            .transaction(|(numbers, plain)| {
+                numbers.insert(&2, &5)?;
+                numbers.remove(&1)?;
+                plain.insert(&0, &0)?;
+                Ok::<(), sled::transaction::ConflictableTransactionError<()>>(())
+            })
+            .unwrap();
+        assert_eq!(keys(tree.index_iter("numbers_by_parity")), vec![2]);
+
+        let aborted = tree.transaction(|numbers| {
+            numbers.insert(&3, &3)?;
+            sled::transaction::abort::<(), ()>(())
+        });
+        assert!(aborted.is_err());
+        assert_eq!(keys(tree.index_iter("numbers_by_parity")), vec![2]);
+    }
+
+    #[test]
+    fn fill_empty_indexes_catches_up() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let plain = Tree::<u32, i64>::open(&db, "numbers");
+        plain.insert(&1, &3).unwrap();
+        plain.insert(&2, &1).unwrap();
+
+        let tree = by_parity(&db);
+        assert_eq!(keys(tree.index_iter("numbers_by_parity")), vec![]);
+        tree.fill_empty_indexes().unwrap();
+        assert_eq!(keys(tree.index_iter("numbers_by_parity")), vec![1, 2]);
+    }
+}
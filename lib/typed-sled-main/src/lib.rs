@@ -12,6 +12,8 @@
 //! * [custom_serde]: Create `Tree`s with custom (de)serialization. This for example makes
 //!                   lazy or zero-copy (de)serialization possible.
 //!
+//! Secondary indexes on a `Tree` are always available, see [index].
+//!
 //! # Example
 //! ```
 //! use serde::{Deserialize, Serialize};
@@ -35,11 +37,13 @@
 //! ```
 //! [sled]: https://docs.rs/sled/latest/sled/
 
+use index::{Index, IndexIter, IndexKey};
 pub use sled::{open, Config};
 use transaction::TransactionalTree;
 
 #[cfg(feature = "convert")]
 pub mod convert;
+pub mod index;
 #[cfg(feature = "key-generating")]
 pub mod key_generating;
 #[cfg(feature = "search")]
@@ -88,6 +92,7 @@ use std::marker::PhantomData;
 #[derive(Debug)]
 pub struct Tree<K, V> {
     inner: sled::Tree,
+    indexes: Vec<Index<K, V>>,
     _key: PhantomData<fn() -> K>,
     _value: PhantomData<fn() -> V>,
 }
@@ -98,6 +103,7 @@ impl<K, V> Clone for Tree<K, V> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            indexes: self.indexes.clone(),
             _key: PhantomData,
             _value: PhantomData,
         }
@@ -164,36 +170,127 @@ impl<K, V> Tree<K, V> {
     pub fn open<T: AsRef<str>>(db: &sled::Db, id: T) -> Self {
         Self {
             inner: db.open_tree(id.as_ref()).unwrap(),
+            indexes: vec![],
             _key: PhantomData,
             _value: PhantomData,
         }
     }
 
+    /// Adds a secondary index, kept in the tree `id` of the same db. `keys`
+    /// gives the index keys of a row; see [index] for an example.
+    ///
+    /// Rows written before the index existed are only in it after
+    /// [fill_empty_indexes][Tree::fill_empty_indexes].
+    pub fn with_index<F>(mut self, db: &sled::Db, id: &str, keys: F) -> Self
+    where
+        F: Fn(&K, &V) -> Vec<IndexKey> + Send + Sync + 'static,
+    {
+        self.indexes.push(Index::open(db, id, keys));
+        self
+    }
+
+    fn index(&self, id: &str) -> &Index<K, V> {
+        self.indexes
+            .iter()
+            .find(|index| index.id == id)
+            .unwrap_or_else(|| panic!("tree has no index {:?}", id))
+    }
+
+    /// Rows in the order of the index `id`.
+    pub fn index_iter(&self, id: &str) -> IndexIter<K, V> {
+        IndexIter::new(self.index(id).tree.iter(), self.inner.clone())
+    }
+
+    /// Rows whose index keys fall in `range`.
+    pub fn index_range<R: RangeBounds<IndexKey>>(&self, id: &str, range: R) -> IndexIter<K, V> {
+        let start: Bound<Vec<u8>> = map_bound(range.start_bound(), |k| k.as_bytes().to_vec());
+        let end: Bound<Vec<u8>> = map_bound(range.end_bound(), |k| k.as_bytes().to_vec());
+        IndexIter::new(self.index(id).tree.range((start, end)), self.inner.clone())
+    }
+
+    /// Rows whose index keys start with `prefix`.
+    pub fn index_scan_prefix(&self, id: &str, prefix: &IndexKey) -> IndexIter<K, V> {
+        IndexIter::new(
+            self.index(id).tree.scan_prefix(prefix.as_bytes()),
+            self.inner.clone(),
+        )
+    }
+
+    /// Indexes every row again for each index that has no entries yet. Not
+    /// atomic, meant for when an index is added to a tree that has rows.
+    pub fn fill_empty_indexes(&self) -> Result<()>
+    where
+        K: KV,
+        V: KV,
+    {
+        for index in &self.indexes {
+            if index.tree.is_empty() {
+                index.fill(self.iter())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The sled trees a transaction on this tree spans: the tree itself,
+    /// then one per index.
+    pub(crate) fn sled_trees(&self) -> impl Iterator<Item = &sled::Tree> {
+        std::iter::once(&self.inner).chain(self.indexes.iter().map(|index| &index.tree))
+    }
+
+    /// The typed view of this tree in a transaction over `trees`, starting
+    /// where [sled_trees][Tree::sled_trees] of this tree were put.
+    pub(crate) fn transactional<'a>(
+        &'a self,
+        trees: &'a [sled::transaction::TransactionalTree],
+    ) -> TransactionalTree<'a, K, V> {
+        TransactionalTree::new(&trees[0], &self.indexes, &trees[1..=self.indexes.len()])
+    }
+
+    fn assert_no_indexes(&self, operation: &str) {
+        assert!(
+            self.indexes.is_empty(),
+            "{} does not keep indexes, use insert, remove or a transaction",
+            operation
+        );
+    }
+
     /// Insert a key to a new value, returning the last value if it was set.
     pub fn insert(&self, key: &K, value: &V) -> Result<Option<V>>
     where
         K: KV,
         V: KV,
     {
+        if !self.indexes.is_empty() {
+            return self
+                .transaction(|tree| Ok(tree.insert(key, value)?))
+                .map_err(storage_error);
+        }
         self.inner
             .insert(serialize(key), serialize(value))
             .map(|opt| opt.map(|old_value| deserialize(&old_value)))
     }
 
-    /// Perform a multi-key serializable transaction.
+    /// Perform a multi-key serializable transaction. Writes through the
+    /// transactional tree keep the indexes.
     pub fn transaction<F, A, E>(&self, f: F) -> TransactionResult<A, E>
     where
         F: Fn(&TransactionalTree<K, V>) -> ConflictableTransactionResult<A, E>,
     {
-        self.inner.transaction(|sled_transactional_tree| {
-            f(&TransactionalTree::new(sled_transactional_tree))
+        use sled::Transactional;
+
+        let trees: Vec<_> = self.sled_trees().collect();
+        trees.as_slice().transaction(|sled_transactional_trees| {
+            f(&self.transactional(sled_transactional_trees))
         })
     }
 
     /// Create a new batched update that can be atomically applied.
     ///
     /// It is possible to apply a Batch in a transaction as well, which is the way you can apply a Batch to multiple Trees atomically.
+    ///
+    /// Panics if the tree has indexes.
     pub fn apply_batch(&self, batch: Batch<K, V>) -> Result<()> {
+        self.assert_no_indexes("apply_batch");
         self.inner.apply_batch(batch.inner)
     }
 
@@ -237,6 +334,11 @@ impl<K, V> Tree<K, V> {
         K: KV,
         V: KV,
     {
+        if !self.indexes.is_empty() {
+            return self
+                .transaction(|tree| Ok(tree.remove(key)?))
+                .map_err(storage_error);
+        }
         self.inner
             .remove(serialize(key))
             .map(|opt| opt.map(|v| deserialize(&v)))
@@ -247,6 +349,8 @@ impl<K, V> Tree<K, V> {
     /// It returns Ok(Ok(())) if operation finishes successfully.
     ///
     /// If it fails it returns: - Ok(Err(CompareAndSwapError(current, proposed))) if operation failed to setup a new value. CompareAndSwapError contains current and proposed values. - Err(Error::Unsupported) if the database is opened in read-only mode.
+    ///
+    /// Panics if the tree has indexes.
     pub fn compare_and_swap(
         &self,
         key: &K,
@@ -257,6 +361,7 @@ impl<K, V> Tree<K, V> {
         K: KV,
         V: KV,
     {
+        self.assert_no_indexes("compare_and_swap");
         self.inner
             .compare_and_swap(
                 serialize(key),
@@ -272,6 +377,8 @@ impl<K, V> Tree<K, V> {
     }
 
     /// Fetch the value, apply a function to it and return the result.
+    ///
+    /// Panics if the tree has indexes.
     // not sure if implemented correctly (different trait bound for F)
     pub fn update_and_fetch<F>(&self, key: &K, mut f: F) -> Result<Option<V>>
     where
@@ -279,6 +386,7 @@ impl<K, V> Tree<K, V> {
         V: KV,
         F: FnMut(Option<V>) -> Option<V>,
     {
+        self.assert_no_indexes("update_and_fetch");
         self.inner
             .update_and_fetch(serialize(&key), |opt_value| {
                 f(opt_value.map(|v| deserialize(v))).map(|v| serialize(&v))
@@ -287,6 +395,8 @@ impl<K, V> Tree<K, V> {
     }
 
     /// Fetch the value, apply a function to it and return the previous value.
+    ///
+    /// Panics if the tree has indexes.
     // not sure if implemented correctly (different trait bound for F)
     pub fn fetch_and_update<F>(&self, key: &K, mut f: F) -> Result<Option<V>>
     where
@@ -294,6 +404,7 @@ impl<K, V> Tree<K, V> {
         V: KV,
         F: FnMut(Option<V>) -> Option<V>,
     {
+        self.assert_no_indexes("fetch_and_update");
         self.inner
             .fetch_and_update(serialize(key), |opt_value| {
                 f(opt_value.map(|v| deserialize(v))).map(|v| serialize(&v))
@@ -412,11 +523,14 @@ impl<K, V> Tree<K, V> {
     /// Merge operators are shared by all instances of a particular
     /// `Tree`. Different merge operators may be set on different
     /// `Tree`s.
+    ///
+    /// Panics if the tree has indexes.
     pub fn merge(&self, key: &K, value: &V) -> Result<Option<V>>
     where
         K: KV,
         V: KV,
     {
+        self.assert_no_indexes("merge");
         self.inner
             .merge(serialize(key), serialize(value))
             .map(|res| res.map(|old_v| deserialize(&old_v)))
@@ -529,22 +643,28 @@ impl<K, V> Tree<K, V> {
     }
 
     /// Atomically removes the maximum item in the `Tree` instance.
+    ///
+    /// Panics if the tree has indexes.
     pub fn pop_max(&self) -> Result<Option<(K, V)>>
     where
         K: KV,
         V: KV,
     {
+        self.assert_no_indexes("pop_max");
         self.inner
             .pop_max()
             .map(|res| res.map(|(k, v)| (deserialize(&k), deserialize(&v))))
     }
 
     /// Atomically removes the minimum item in the `Tree` instance.
+    ///
+    /// Panics if the tree has indexes.
     pub fn pop_min(&self) -> Result<Option<(K, V)>>
     where
         K: KV,
         V: KV,
     {
+        self.assert_no_indexes("pop_min");
         self.inner
             .pop_min()
             .map(|res| res.map(|(k, v)| (deserialize(&k), deserialize(&v))))
@@ -564,6 +684,9 @@ impl<K, V> Tree<K, V> {
     ///
     /// Note that this is not atomic.
     pub fn clear(&self) -> Result<()> {
+        for index in &self.indexes {
+            index.tree.clear()?;
+        }
         self.inner.clear()
     }
 
@@ -809,6 +932,22 @@ impl<K, V> Event<K, V> {
     }
 }
 
+fn map_bound<T, U>(bound: Bound<&T>, f: impl FnOnce(&T) -> U) -> Bound<U> {
+    match bound {
+        Bound::Included(x) => Bound::Included(f(x)),
+        Bound::Excluded(x) => Bound::Excluded(f(x)),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Single-tree transactions that never abort only fail on storage errors.
+fn storage_error(e: sled::transaction::TransactionError<()>) -> sled::Error {
+    match e {
+        sled::transaction::TransactionError::Storage(e) => e,
+        sled::transaction::TransactionError::Abort(()) => unreachable!("never aborted"),
+    }
+}
+
 /// The function which is used to deserialize all keys and values.
 pub fn deserialize<'a, T>(bytes: &'a [u8]) -> T
 where
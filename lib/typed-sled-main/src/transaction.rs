@@ -1,24 +1,46 @@
 use std::marker::PhantomData;
 
-use sled::transaction::{ConflictableTransactionResult, TransactionResult};
+use sled::transaction::{ConflictableTransactionResult, TransactionError, TransactionResult};
 
+use crate::index::Index;
 use crate::{deserialize, serialize, Batch, Tree, KV};
 
 pub struct TransactionalTree<'a, K, V> {
     inner: &'a sled::transaction::TransactionalTree,
+    indexes: Vec<(Index<K, V>, &'a sled::transaction::TransactionalTree)>,
     _key: PhantomData<fn() -> K>,
     _value: PhantomData<fn() -> V>,
 }
 
 impl<'a, K, V> TransactionalTree<'a, K, V> {
-    pub(crate) fn new(sled: &'a sled::transaction::TransactionalTree) -> Self {
+    pub(crate) fn new(
+        sled: &'a sled::transaction::TransactionalTree,
+        indexes: &[Index<K, V>],
+        index_trees: &'a [sled::transaction::TransactionalTree],
+    ) -> Self {
         Self {
             inner: sled,
+            indexes: indexes.iter().cloned().zip(index_trees).collect(),
             _key: PhantomData,
             _value: PhantomData,
         }
     }
 
+    fn update_indexes(
+        &self,
+        key: &K,
+        old: Option<&V>,
+        new: Option<&V>,
+    ) -> std::result::Result<(), sled::transaction::UnabortableTransactionError>
+    where
+        K: KV,
+    {
+        for (index, tree) in &self.indexes {
+            index.update(tree, key, old, new)?;
+        }
+        Ok(())
+    }
+
     pub fn insert(
         &self,
         key: &K,
@@ -28,9 +50,12 @@ impl<'a, K, V> TransactionalTree<'a, K, V> {
         K: KV,
         V: KV,
     {
-        self.inner
-            .insert(serialize(key), serialize(value))
-            .map(|opt| opt.map(|v| deserialize(&v)))
+        let old = self
+            .inner
+            .insert(serialize(key), serialize(value))?
+            .map(|v| deserialize(&v));
+        self.update_indexes(key, old.as_ref(), Some(value))?;
+        Ok(old)
     }
 
     pub fn remove(
@@ -41,9 +66,9 @@ impl<'a, K, V> TransactionalTree<'a, K, V> {
         K: KV,
         V: KV,
     {
-        self.inner
-            .remove(serialize(key))
-            .map(|opt| opt.map(|v| deserialize(&v)))
+        let old = self.inner.remove(serialize(key))?.map(|v| deserialize(&v));
+        self.update_indexes(key, old.as_ref(), None)?;
+        Ok(old)
     }
 
     pub fn get(
@@ -59,10 +84,12 @@ impl<'a, K, V> TransactionalTree<'a, K, V> {
             .map(|opt| opt.map(|v| deserialize(&v)))
     }
 
+    /// Panics if the tree has indexes.
     pub fn apply_batch(
         &self,
         batch: &Batch<K, V>,
     ) -> std::result::Result<(), sled::transaction::UnabortableTransactionError> {
+        assert!(self.indexes.is_empty(), "apply_batch does not keep indexes");
         self.inner.apply_batch(&batch.inner)
     }
 
@@ -75,6 +102,17 @@ impl<'a, K, V> TransactionalTree<'a, K, V> {
     }
 }
 
+/// Turns the result of a transaction into a plain one, for error types that
+/// storage errors convert into. Aborting with such an error then reads like
+/// returning it.
+pub fn flatten<A, E: From<sled::Error>>(result: TransactionResult<A, E>) -> Result<A, E> {
+    match result {
+        Ok(a) => Ok(a),
+        Err(TransactionError::Abort(e)) => Err(e),
+        Err(TransactionError::Storage(e)) => Err(e.into()),
+    }
+}
+
 pub trait Transactional<E = ()> {
     type View<'a>;
 
@@ -96,9 +134,16 @@ macro_rules! impl_transactional {
           {
               use sled::Transactional;
 
-              ($(&self.$i.inner),+).transaction(|trees| {
+              // every tree with its indexes right after it
+              let mut trees: Vec<&sled::Tree> = vec![];
+              let mut starts = vec![];
+              $(
+                  starts.push(trees.len());
+                  trees.extend(self.$i.sled_trees());
+              )+
+              trees.as_slice().transaction(|trees| {
                   f((
-                      $(TransactionalTree::new(&trees.$i)),+
+                      $(self.$i.transactional(&trees[starts[$i]..])),+
                   ))
               })
           }
@@ -149,3 +194,39 @@ fn test_multiple_tree_transaction() {
     assert_eq!(tree0.get(&0), Ok(Some(0)));
     assert_eq!(tree1.get(&0), Ok(Some(0)));
 }
+
+#[test]
+fn test_aborted_transaction_writes_nothing() {
+    #[derive(Debug, PartialEq)]
+    enum Error {
+        Taken,
+        Storage,
+    }
+    impl From<sled::Error> for Error {
+        fn from(_: sled::Error) -> Self {
+            Error::Storage
+        }
+    }
+
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let names = Tree::<u32, String>::open(&db, "names");
+    let counts = Tree::<u32, u32>::open(&db, "counts");
+    counts.insert(&0, &1).unwrap();
+
+    let claim = |expected: u32| {
+        flatten((&names, &counts).transaction(
+            |(names, counts)| -> ConflictableTransactionResult<(), Error> {
+                if counts.get(&0)? != Some(expected) {
+                    return sled::transaction::abort(Error::Taken);
+                }
+                names.insert(&expected, &"x".to_owned())?;
+                counts.insert(&0, &(expected + 1))?;
+                Ok(())
+            },
+        ))
+    };
+    assert_eq!(claim(1), Ok(()));
+    assert_eq!(claim(1), Err(Error::Taken));
+    assert_eq!(names.len(), 1);
+    assert_eq!(counts.get(&0), Ok(Some(2)));
+}
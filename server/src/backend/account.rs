@@ -0,0 +1,271 @@
+//! Registered accounts on top of guest identities.
+//!
+//! An account owns the guest identity it was registered from, so everything
+//! played before carries over. Logging in from another device switches that
+//! session to the same identity, and what its guest played moves over too;
+//! logging out gets a fresh guest.
+//!
+//! Every password check costs an argon2 hash, so failed logins are limited
+//! per peer address. A username that keeps failing only slows down, so
+//! nobody can lock someone else out of their account.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backend::guest_merge::{check_guest_merge, merge_guest};
+use crate::database::tables::*;
+use argon2::password_hash::{
+    PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
+use game::api::user::{Credentials, GuestInfo};
+use once_cell::sync::Lazy;
+use rand::Rng;
+use sled::transaction::{abort, ConflictableTransactionError};
+use typed_sled::transaction::{flatten, Transactional};
+
+/// Failed logins allowed in `LOGIN_WINDOW` for each peer address.
+pub const MAX_LOGIN_FAILURES: usize = 5;
+const LOGIN_WINDOW: Duration = Duration::from_secs(5 * 60);
+/// Wait after the first failed login for a username, doubled for each
+/// one after it.
+const LOGIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_LOGIN_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Recent failed logins, oldest first.
+#[derive(Debug, Default)]
+pub struct LoginFailures(VecDeque<Instant>);
+
+impl LoginFailures {
+    fn forget_old(&mut self, now: Instant) {
+        while self
+            .0
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > LOGIN_WINDOW)
+        {
+            self.0.pop_front();
+        }
+    }
+
+    pub fn check(&mut self, now: Instant) -> anyhow::Result<()> {
+        self.forget_old(now);
+        if self.0.len() >= MAX_LOGIN_FAILURES {
+            anyhow::bail!("too many failed logins, try again later");
+        }
+        Ok(())
+    }
+
+    /// Whether the wait after the last failure is over.
+    pub fn check_backoff(&mut self, now: Instant) -> anyhow::Result<()> {
+        self.forget_old(now);
+        let Some(last) = self.0.back() else {
+            return Ok(());
+        };
+        let doublings = (self.0.len() - 1).min(16) as u32;
+        let wait = (LOGIN_BACKOFF * 2u32.pow(doublings)).min(MAX_LOGIN_BACKOFF);
+        let waited = now.duration_since(*last);
+        if waited < wait {
+            anyhow::bail!(
+                "too many failed logins, try again in {}s",
+                (wait - waited).as_secs() + 1
+            );
+        }
+        Ok(())
+    }
+
+    pub fn add(&mut self, now: Instant) {
+        self.0.push_back(now);
+    }
+}
+
+/// Entries only exist for recent failures; they are dropped on the next
+/// failure once they are old, or on a successful login.
+#[derive(Debug)]
+struct FailuresBy<K>(Mutex<HashMap<K, LoginFailures>>);
+
+impl<K: std::hash::Hash + Eq> FailuresBy<K> {
+    fn new() -> Self {
+        Self(Mutex::new(HashMap::new()))
+    }
+
+    fn check(
+        &self,
+        key: &K,
+        check: impl FnOnce(&mut LoginFailures) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        match self.0.lock().unwrap().get_mut(key) {
+            Some(failures) => check(failures),
+            None => Ok(()),
+        }
+    }
+
+    fn add(&self, key: K, now: Instant) {
+        let mut all = self.0.lock().unwrap();
+        all.entry(key).or_default().add(now);
+        all.retain(|_, f| {
+            f.forget_old(now);
+            !f.0.is_empty()
+        });
+    }
+
+    fn forget(&self, key: &K) {
+        self.0.lock().unwrap().remove(key);
+    }
+}
+
+/// By the address of the peer, whichever connection it uses.
+static PEER_LOGIN_FAILURES: Lazy<FailuresBy<IpAddr>> = Lazy::new(FailuresBy::new);
+
+/// By `Credentials::username_key`.
+static USERNAME_LOGIN_FAILURES: Lazy<FailuresBy<String>> = Lazy::new(FailuresBy::new);
+
+fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::encode_b64(&rand::thread_rng().gen::<[u8; 16]>())
+        .map_err(|e| anyhow::anyhow!("cannot make salt: {e}"))?;
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("cannot hash password: {e}"))?;
+    Ok(hash.to_string())
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+pub fn register(
+    credentials: Credentials,
+    current_user_id: GuestInfo,
+) -> anyhow::Result<GuestInfo> {
+    credentials.validate()?;
+    let user_id = current_user_id.user_id;
+    let username_key = credentials.username_key();
+    let hash = hash_password(&credentials.password)?;
+    flatten((&*ACCOUNT_DB, &*USER_ACCOUNT_DB).transaction(
+        |(accounts, user_accounts)| {
+            if accounts.get(&username_key)?.is_some() {
+                return abort(anyhow::anyhow!("username is taken"));
+            }
+            if user_accounts.get(&user_id)?.is_some() {
+                return abort(anyhow::anyhow!("already registered"));
+            }
+            accounts.insert(&username_key, &(user_id, hash.clone()))?;
+            user_accounts.insert(&user_id, &credentials.username)?;
+            Ok::<(), ConflictableTransactionError<anyhow::Error>>(())
+        },
+    ))?;
+    log::info!("user {user_id} registered as {}", credentials.username);
+    Ok(GuestInfo {
+        username: Some(credentials.username),
+        ..current_user_id
+    })
+}
+
+/// `peer` is the address the login came from.
+pub fn login(
+    credentials: Credentials,
+    current_user_id: GuestInfo,
+    peer: IpAddr,
+) -> anyhow::Result<GuestInfo> {
+    let now = Instant::now();
+    let username_key = credentials.username_key();
+    PEER_LOGIN_FAILURES.check(&peer, |f| f.check(now))?;
+    USERNAME_LOGIN_FAILURES.check(&username_key, |f| f.check_backoff(now))?;
+
+    let account = ACCOUNT_DB.get(&username_key)?;
+    let verified = match &account {
+        Some((_, hash)) => verify_password(&credentials.password, hash),
+        None => false,
+    };
+    let Some((user_id, _)) = account.filter(|_| verified) else {
+        PEER_LOGIN_FAILURES.add(peer, now);
+        USERNAME_LOGIN_FAILURES.add(username_key, now);
+        anyhow::bail!("wrong username or password");
+    };
+    USERNAME_LOGIN_FAILURES.forget(&username_key);
+
+    let guest = current_user_id.user_id;
+    let is_guest = current_user_id.username.is_none()
+        && guest != user_id
+        && USER_ACCOUNT_DB.get(&guest)?.is_none();
+    if is_guest {
+        check_guest_merge(guest)?;
+        merge_guest(guest, user_id)?;
+    }
+    let username = USER_ACCOUNT_DB.get(&user_id)?;
+    log::info!("user {guest} logged in as {user_id}");
+    Ok(GuestInfo {
+        user_id,
+        username,
+        ..current_user_id
+    })
+}
+
+pub fn logout(_: (), current_user_id: GuestInfo) -> anyhow::Result<GuestInfo> {
+    if current_user_id.username.is_none() {
+        anyhow::bail!("not logged in");
+    }
+    Ok(GuestInfo::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_verify_against_their_hash() {
+        let hash = hash_password("hunter22").unwrap();
+        assert!(verify_password("hunter22", &hash));
+        assert!(!verify_password("hunter23", &hash));
+        assert!(!verify_password("hunter22", "not a hash"));
+        // salted
+        assert_ne!(hash, hash_password("hunter22").unwrap());
+    }
+
+    #[test]
+    fn failed_logins_are_limited_for_a_while() {
+        let start = Instant::now();
+        let mut failures = LoginFailures::default();
+        for _ in 0..MAX_LOGIN_FAILURES {
+            failures.check(start).unwrap();
+            failures.add(start);
+        }
+        assert!(failures.check(start).is_err());
+        assert!(failures.check(start + LOGIN_WINDOW / 2).is_err());
+        failures.check(start + LOGIN_WINDOW * 2).unwrap();
+        assert!(failures.0.is_empty());
+    }
+
+    #[test]
+    fn failed_logins_for_a_username_back_off() {
+        let start = Instant::now();
+        let mut failures = LoginFailures::default();
+        failures.check_backoff(start).unwrap();
+        failures.add(start);
+        assert!(failures.check_backoff(start).is_err());
+        failures.check_backoff(start + LOGIN_BACKOFF).unwrap();
+        failures.add(start + LOGIN_BACKOFF);
+        assert!(failures.check_backoff(start + LOGIN_BACKOFF * 2).is_err());
+        failures.check_backoff(start + LOGIN_BACKOFF * 3).unwrap();
+        for _ in 0..20 {
+            failures.add(start);
+        }
+        failures.check_backoff(start + MAX_LOGIN_BACKOFF).unwrap();
+    }
+
+    #[test]
+    fn successful_logins_leave_no_entry() {
+        let failures = FailuresBy::<u32>::new();
+        let now = Instant::now();
+        failures.check(&1, |f| f.check(now)).unwrap();
+        assert!(failures.0.lock().unwrap().is_empty());
+        failures.add(1, now);
+        failures.forget(&1);
+        assert!(failures.0.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+//! Direct challenges from one player to another.
+//!
+//! Open challenges only live in memory, like the matchmaking queues; they last
+//! a minute at most. The target is told over their websocket and answers with
+//! `AnswerChallenge`; accepting starts a series with the challenger's settings.
+
+use crate::backend::presence::{is_online, notify_user, UserNotification};
+use crate::backend::series::start_series;
+use crate::database::tables::get_display_name;
+use game::api::challenge::{
+    Challenge, ChallengeEvent, CHALLENGE_COOLDOWN_NS, MAX_OPEN_CHALLENGES,
+};
+use game::api::room::RoomSettings;
+use game::api::user::GuestInfo;
+use game::timestamp::get_timestamp_now_nano;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How often expired challenges are cleared out.
+const EXPIRY_TICK: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct Challenges {
+    open: HashMap<uuid::Uuid, Challenge>,
+    /// When each challenger last challenged each target.
+    last_sent: HashMap<(uuid::Uuid, uuid::Uuid), i64>,
+}
+
+impl Challenges {
+    fn add(&mut self, id: uuid::Uuid, challenge: Challenge) -> anyhow::Result<()> {
+        let pair = (challenge.from, challenge.to);
+        if self.open.values().any(|c| (c.from, c.to) == pair) {
+            anyhow::bail!("you already challenged this player");
+        }
+        if let Some(last) = self.last_sent.get(&pair) {
+            if challenge.created - last < CHALLENGE_COOLDOWN_NS {
+                anyhow::bail!("wait a bit before challenging this player again");
+            }
+        }
+        let open_count = self.open.values().filter(|c| c.from == pair.0).count();
+        if open_count >= MAX_OPEN_CHALLENGES {
+            anyhow::bail!("too many open challenges");
+        }
+        self.last_sent.insert(pair, challenge.created);
+        self.open.insert(id, challenge);
+        Ok(())
+    }
+
+    /// Takes out challenge `id` if it was sent to `user_id` and is still open.
+    fn take(
+        &mut self,
+        id: &uuid::Uuid,
+        user_id: &uuid::Uuid,
+        now: i64,
+    ) -> anyhow::Result<Challenge> {
+        match self.open.get(id) {
+            Some(c) if c.to != *user_id => anyhow::bail!("challenge not found"),
+            // left for `expire` to tell both players
+            Some(c) if c.expires() <= now => anyhow::bail!("challenge expired"),
+            Some(_) => Ok(self.open.remove(id).unwrap()),
+            None => anyhow::bail!("challenge not found"),
+        }
+    }
+
+    fn expire(&mut self, now: i64) -> Vec<(uuid::Uuid, Challenge)> {
+        let expired: Vec<_> = self
+            .open
+            .iter()
+            .filter(|(_, c)| c.expires() <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        self.last_sent
+            .retain(|_, sent| now - *sent < CHALLENGE_COOLDOWN_NS);
+        expired
+            .into_iter()
+            .filter_map(|id| self.open.remove(&id).map(|c| (id, c)))
+            .collect()
+    }
+}
+
+static CHALLENGES: Lazy<Mutex<Challenges>> =
+    Lazy::new(|| Mutex::new(Challenges::default()));
+
+fn notify_challenge(to: &uuid::Uuid, id: uuid::Uuid, event: ChallengeEvent) {
+    notify_user(to, UserNotification::Challenge(id, event));
+}
+
+pub fn send_challenge(
+    (to, settings): (uuid::Uuid, RoomSettings),
+    current_user: GuestInfo,
+) -> anyhow::Result<uuid::Uuid> {
+    let from = current_user.user_id;
+    if to == from {
+        anyhow::bail!("cannot challenge yourself");
+    }
+    settings.validate()?;
+    let match_type = &settings.match_type;
+    if match_type.min_players() > 2 || match_type.max_players() < 2 {
+        anyhow::bail!("{match_type:?} is not a two player match");
+    }
+    if !is_online(&to) {
+        anyhow::bail!("player is not online");
+    }
+
+    let challenge = Challenge {
+        from,
+        from_name: get_display_name(&from),
+        to,
+        settings,
+        created: get_timestamp_now_nano(),
+    };
+    let id = uuid::Uuid::new_v4();
+    CHALLENGES.lock().unwrap().add(id, challenge.clone())?;
+    notify_challenge(&to, id, ChallengeEvent::Received(Box::new(challenge)));
+    Ok(id)
+}
+
+/// Returns the new match when accepting.
+pub fn answer_challenge(
+    (id, accept): (uuid::Uuid, bool),
+    current_user: GuestInfo,
+) -> anyhow::Result<Option<uuid::Uuid>> {
+    let challenge = CHALLENGES.lock().unwrap().take(
+        &id,
+        &current_user.user_id,
+        get_timestamp_now_nano(),
+    )?;
+    if !accept {
+        notify_challenge(&challenge.from, id, ChallengeEvent::Declined);
+        return Ok(None);
+    }
+
+    let title = format!(
+        "Challenge: {} vs. {}",
+        challenge.from_name,
+        get_display_name(&challenge.to)
+    );
+    let users = vec![challenge.from, challenge.to];
+    let (match_id, _) = start_series(users, title, &challenge.settings)?;
+    for user_id in [challenge.from, challenge.to] {
+        notify_challenge(&user_id, id, ChallengeEvent::Accepted(match_id));
+    }
+    Ok(Some(match_id))
+}
+
+pub async fn run_challenge_expiry() {
+    loop {
+        tokio::time::sleep(EXPIRY_TICK).await;
+        let expired = CHALLENGES.lock().unwrap().expire(get_timestamp_now_nano());
+        for (id, challenge) in expired {
+            for user_id in [challenge.from, challenge.to] {
+                notify_challenge(&user_id, id, ChallengeEvent::Expired);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::api::challenge::CHALLENGE_TTL_NS;
+
+    fn challenge(from: uuid::Uuid, to: uuid::Uuid, created: i64) -> Challenge {
+        Challenge {
+            from,
+            from_name: "a".to_string(),
+            to,
+            settings: RoomSettings::default(),
+            created,
+        }
+    }
+
+    #[test]
+    fn challenges_are_limited_and_expire() {
+        let (a, b) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let mut challenges = Challenges::default();
+        let first = uuid::Uuid::new_v4();
+        challenges.add(first, challenge(a, b, 0)).unwrap();
+
+        // one open challenge per pair, then the cooldown
+        assert!(challenges
+            .add(uuid::Uuid::new_v4(), challenge(a, b, 1))
+            .is_err());
+        assert!(challenges.take(&first, &a, 1).is_err());
+        assert_eq!(challenges.take(&first, &b, 1).unwrap().from, a);
+        assert!(challenges.take(&first, &b, 1).is_err());
+        assert!(challenges
+            .add(uuid::Uuid::new_v4(), challenge(a, b, 2))
+            .is_err());
+        challenges
+            .add(uuid::Uuid::new_v4(), challenge(a, b, CHALLENGE_COOLDOWN_NS))
+            .unwrap();
+
+        // only so many open at once
+        for _ in 1..MAX_OPEN_CHALLENGES {
+            let to = uuid::Uuid::new_v4();
+            challenges
+                .add(uuid::Uuid::new_v4(), challenge(a, to, 0))
+                .unwrap();
+        }
+        let to = uuid::Uuid::new_v4();
+        assert!(challenges
+            .add(uuid::Uuid::new_v4(), challenge(a, to, 0))
+            .is_err());
+
+        let now = CHALLENGE_TTL_NS;
+        assert_eq!(challenges.expire(now).len(), MAX_OPEN_CHALLENGES - 1);
+        assert_eq!(challenges.open.len(), 1);
+        assert_eq!(challenges.expire(now + CHALLENGE_COOLDOWN_NS).len(), 1);
+        assert!(challenges.open.is_empty() && challenges.last_sent.is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+//! Hands everything a guest played over to the account it logs in to.
+//!
+//! Without this, a guest that logs in leaves its games, matches and profile
+//! behind on an identity nobody uses again. The games and matches move one
+//! at a time, each in a transaction of its own. `GUEST_MERGE_DB` keeps the
+//! merge until all of it is done, so one cut short by a restart is finished
+//! at startup.
+
+use std::collections::HashSet;
+use std::ops::Bound;
+
+use crate::backend::presence::all_ids_of;
+use crate::backend::stats::rebuild_user_stats;
+use crate::database::index::IndexGroup;
+use crate::database::tables::*;
+use game::api::game_match::{GameMatchType, UserAndMatchId};
+use game::api::game_replay::{GameId, GameSegmentId};
+use sled::transaction::ConflictableTransactionError;
+use typed_sled::index::IndexKey;
+use typed_sled::transaction::{flatten, Transactional};
+
+/// Whether the games of `guest` can move now. A match in progress addresses
+/// its games by id, so a guest in one keeps them until it is over.
+pub fn check_guest_merge(guest: uuid::Uuid) -> anyhow::Result<()> {
+    for item in MATCH_PLAYER_DB.range(GameId::get_range_for_user(&guest)) {
+        let (_, player) = item?;
+        if player.result.is_none() && !player.out {
+            anyhow::bail!("finish your match before logging in");
+        }
+    }
+    Ok(())
+}
+
+/// Moves the games, matches, follows and profile of `guest` to `account`.
+/// The profile of the account wins over the guest's.
+pub fn merge_guest(guest: uuid::Uuid, account: uuid::Uuid) -> anyhow::Result<()> {
+    let matches = matches_of(guest)?;
+    if matches.iter().any(|m| m.1.contains(&account)) {
+        // both games of such a match would end up under one id
+        log::warn!("guest {guest} played against {account}, not merging");
+        return Ok(());
+    }
+    GUEST_MERGE_DB.insert(&guest, &account)?;
+
+    let mut games = 0;
+    for item in GAME_SEGMENT_COUNT_DB.range(GameId::get_range_for_user(&guest)) {
+        let (game_id, _) = item?;
+        move_game(&game_id, account)?;
+        games += 1;
+    }
+    for (match_id, _) in &matches {
+        move_match(match_id, guest, account)?;
+    }
+    move_ratings(guest, account)?;
+    for item in WORLD_RECORD_DB.iter() {
+        let (mode, mut record) = item?;
+        if record.game_id.user_id == guest {
+            record.game_id.user_id = account;
+            WORLD_RECORD_DB.insert(&mode, &record)?;
+        }
+    }
+    move_follows(guest, account)?;
+    move_profile(guest, account)?;
+    rebuild_user_stats(guest)?;
+    rebuild_user_stats(account)?;
+
+    GUEST_MERGE_DB.remove(&guest)?;
+    log::info!(
+        "merged guest {guest} into {account}: {games} games, {} matches",
+        matches.len()
+    );
+    Ok(())
+}
+
+/// Merges that were cut short. Only runs at startup.
+pub fn finish_guest_merges() -> anyhow::Result<()> {
+    for item in GUEST_MERGE_DB.iter() {
+        let (guest, account) = item?;
+        merge_guest(guest, account)?;
+    }
+    Ok(())
+}
+
+/// Ids and players of every match `user_id` played.
+fn matches_of(
+    user_id: uuid::Uuid,
+) -> anyhow::Result<Vec<(uuid::Uuid, Vec<uuid::Uuid>)>> {
+    let prefix = IndexGroup::User(user_id).key();
+    let mut matches = vec![];
+    let mut seen = HashSet::new();
+    for item in GAME_MATCH_DB.index_scan_prefix(MATCHES_BY_START_TIME, &prefix) {
+        let (_, match_id, match_info) = item?;
+        if seen.insert(match_id) {
+            matches.push((match_id, match_info.users));
+        }
+    }
+    Ok(matches)
+}
+
+fn replace_user(users: &mut [uuid::Uuid], from: uuid::Uuid, to: uuid::Uuid) {
+    for user in users.iter_mut().filter(|u| **u == from) {
+        *user = to;
+    }
+}
+
+/// Every row of one game, under the same seed and start time.
+fn move_game(old: &GameId, account: uuid::Uuid) -> anyhow::Result<()> {
+    let new = GameId {
+        user_id: account,
+        ..*old
+    };
+    flatten(
+        (
+            &*GAME_SEGMENT_COUNT_DB,
+            &*GAME_SEGMENT_DB,
+            &*GAME_IS_IN_PROGRESS_DB,
+            &*GAME_FULL_DB,
+            &*GAME_RULES_DB,
+            &*GAME_SUMMARY_DB,
+            &*MATCH_PLAYER_DB,
+        )
+            .transaction(
+                |(
+                    count_db,
+                    segment_db,
+                    in_progress_db,
+                    full_db,
+                    rules_db,
+                    summary_db,
+                    player_db,
+                )| {
+                    // moved already
+                    let Some(count) = count_db.remove(old)? else {
+                        return Ok(());
+                    };
+                    count_db.insert(&new, &count)?;
+                    for segment_id in 0..count {
+                        let from = GameSegmentId {
+                            game_id: *old,
+                            segment_id,
+                        };
+                        if let Some(segment) = segment_db.remove(&from)? {
+                            let to = GameSegmentId {
+                                game_id: new,
+                                segment_id,
+                            };
+                            segment_db.insert(&to, &segment)?;
+                        }
+                    }
+                    if let Some(v) = in_progress_db.remove(old)? {
+                        in_progress_db.insert(&new, &v)?;
+                    }
+                    if let Some(v) = full_db.remove(old)? {
+                        full_db.insert(&new, &v)?;
+                    }
+                    if let Some(v) = rules_db.remove(old)? {
+                        rules_db.insert(&new, &v)?;
+                    }
+                    if let Some(mut v) = summary_db.remove(old)? {
+                        v.game_id = new;
+                        summary_db.insert(&new, &v)?;
+                    }
+                    if let Some(v) = player_db.remove(old)? {
+                        player_db.insert(&new, &v)?;
+                    }
+                    Ok::<(), ConflictableTransactionError<anyhow::Error>>(())
+                },
+            ),
+    )
+}
+
+/// The players, result, history and rating changes of one match, and the
+/// series it is part of. The match's games move with the other games.
+fn move_match(
+    match_id: &uuid::Uuid,
+    guest: uuid::Uuid,
+    account: uuid::Uuid,
+) -> anyhow::Result<()> {
+    let series_id = SERIES_FOR_MATCH_DB.get(match_id)?;
+    let old_key = UserAndMatchId {
+        user_id: guest,
+        match_id: *match_id,
+    };
+    let new_key = UserAndMatchId {
+        user_id: account,
+        match_id: *match_id,
+    };
+    flatten(
+        (
+            &*GAME_MATCH_DB,
+            &*GAME_MATCH_RESULT_DB,
+            &*GAME_MATCHES_FOR_USER_DB,
+            &*RATING_HISTORY_DB,
+            &*SERIES_DB,
+        )
+            .transaction(
+                |(match_db, result_db, history_db, rating_db, series_db)| {
+                    if let Some(mut match_info) = match_db.get(match_id)? {
+                        replace_user(&mut match_info.users, guest, account);
+                        match_db.insert(match_id, &match_info)?;
+                    }
+                    if let Some(mut result) = result_db.get(match_id)? {
+                        replace_user(&mut result.winners, guest, account);
+                        for (user, _) in result.podium.iter_mut() {
+                            replace_user(std::slice::from_mut(user), guest, account);
+                        }
+                        result_db.insert(match_id, &result)?;
+                    }
+                    if let Some(result) = history_db.remove(&old_key)? {
+                        history_db.insert(&new_key, &result)?;
+                    }
+                    if let Some(change) = rating_db.remove(&old_key)? {
+                        rating_db.insert(&new_key, &change)?;
+                    }
+                    if let Some(series_id) = &series_id {
+                        if let Some(mut series) = series_db.get(series_id)? {
+                            replace_user(&mut series.users, guest, account);
+                            replace_user(&mut series.rematch, guest, account);
+                            series_db.insert(series_id, &series)?;
+                        }
+                    }
+                    Ok::<(), ConflictableTransactionError<anyhow::Error>>(())
+                },
+            ),
+    )
+}
+
+/// The account keeps its own rating where it has one.
+fn move_ratings(guest: uuid::Uuid, account: uuid::Uuid) -> anyhow::Result<()> {
+    let mut ratings = vec![];
+    for item in RATING_DB.range(ratings_of(guest)) {
+        let ((_, match_type), rating) = item?;
+        ratings.push((match_type, rating));
+    }
+    for (match_type, rating) in ratings {
+        RATING_DB.remove(&(guest, match_type.clone()))?;
+        if !RATING_DB.contains_key(&(account, match_type.clone()))? {
+            RATING_DB.insert(&(account, match_type), &rating)?;
+        }
+    }
+    Ok(())
+}
+
+type RatingKey = (uuid::Uuid, GameMatchType);
+
+/// Every rating key of the user: `_1v1` is the lowest match type, and the
+/// next user id starts right after the last one.
+fn ratings_of(user_id: uuid::Uuid) -> (Bound<RatingKey>, Bound<RatingKey>) {
+    let start = Bound::Included((user_id, GameMatchType::_1v1));
+    match user_id.as_u128().checked_add(1) {
+        Some(next) => (
+            start,
+            Bound::Excluded((uuid::Uuid::from_u128(next), GameMatchType::_1v1)),
+        ),
+        None => (start, Bound::Unbounded),
+    }
+}
+
+/// Both ways; nobody ends up following themselves.
+fn move_follows(guest: uuid::Uuid, account: uuid::Uuid) -> anyhow::Result<()> {
+    let mut follows = vec![];
+    for item in FOLLOW_DB.range(all_ids_of(&guest)) {
+        follows.push(item?);
+    }
+    let followed = IndexKey::new().bytes(guest.as_bytes());
+    for item in FOLLOW_DB.index_scan_prefix(FOLLOWERS, &followed) {
+        let (_, key, since) = item?;
+        follows.push((key, since));
+    }
+    for ((follower, followed), since) in follows {
+        FOLLOW_DB.remove(&(follower, followed))?;
+        let moved = |u| if u == guest { account } else { u };
+        let (follower, followed) = (moved(follower), moved(followed));
+        if follower != followed && !FOLLOW_DB.contains_key(&(follower, followed))? {
+            FOLLOW_DB.insert(&(follower, followed), &since)?;
+        }
+    }
+    Ok(())
+}
+
+/// The guest's display name is freed, unless the account has no profile and
+/// takes the guest's.
+fn move_profile(guest: uuid::Uuid, account: uuid::Uuid) -> anyhow::Result<()> {
+    flatten(
+        (&*USER_PROFILE_DB, &*DISPLAY_NAME_DB).transaction(|(profiles, names)| {
+            let Some(profile) = profiles.remove(&guest)? else {
+                return Ok(());
+            };
+            let name_key = game::api::user::display_name_key(&profile.display_name);
+            if profiles.get(&account)?.is_some() {
+                if names.get(&name_key)? == Some(guest) {
+                    names.remove(&name_key)?;
+                }
+            } else {
+                profiles.insert(&account, &profile)?;
+                names.insert(&name_key, &account)?;
+            }
+            Ok::<(), ConflictableTransactionError<anyhow::Error>>(())
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratings_of_covers_only_that_user() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = typed_sled::Tree::<RatingKey, u32>::open(&db, "test_ratings");
+        let users = [
+            uuid::Uuid::from_bytes([1; 16]),
+            uuid::Uuid::from_bytes([2; 16]),
+            uuid::Uuid::from_bytes([3; 16]),
+        ];
+        let match_types = [
+            GameMatchType::_1v1,
+            GameMatchType::ManVsCar("bot".to_string()),
+            GameMatchType::BattleRoyale,
+        ];
+        for user_id in users {
+            for match_type in match_types.clone() {
+                tree.insert(&(user_id, match_type), &1).unwrap();
+            }
+        }
+
+        let keys: Vec<_> = tree
+            .range(ratings_of(users[1]))
+            .map(|item| item.unwrap().0)
+            .collect();
+        assert_eq!(
+            keys,
+            match_types
+                .map(|match_type| (users[1], match_type))
+                .to_vec()
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Game summaries, leaderboards and world records.
+//!
+//! A summary is written when a game is over. The server replays the game
+//! from its stored segments first; only games that replay to the same
+//! result count as verified, and only verified games get ranked.
+
+use std::collections::HashSet;
+use std::ops::Bound;
+
+use crate::database::index::IndexGroup;
+use crate::database::tables::*;
+use game::api::game_replay::GameId;
+use game::api::leaderboard::{GameMode, GameSummary, LeaderboardView};
+use game::api::user::GuestInfo;
+use game::tet::{GameReplaySegment, GameState, TetAction};
+use game::timestamp::get_timestamp_now_nano;
+use once_cell::sync::Lazy;
+use tokio::sync::broadcast;
+use typed_sled::index::IndexKey;
+use typed_sled::Tree;
+
+pub const LEADERBOARD_SIZE: usize = 20;
+
+/// Every read-modify-write of `WORLD_RECORD_DB` happens under this lock.
+static WORLD_RECORD_LOCK: Lazy<std::sync::Mutex<()>> =
+    Lazy::new(|| std::sync::Mutex::new(()));
+
+/// New world records, for every open connection.
+pub static WORLD_RECORDS: Lazy<broadcast::Sender<GameSummary>> =
+    Lazy::new(|| broadcast::channel(16).0);
+
+/// The summary of a game that ends in `state` after `segments`.
+pub fn game_summary(
+    game_id: &GameId,
+    state: &GameState,
+    segments: &[GameReplaySegment],
+) -> anyhow::Result<GameSummary> {
+    let versus = MATCH_PLAYER_DB.get(game_id)?.is_some();
+    let verified = replays_to(segments, state, versus);
+    let mode = GameMode::of_game(&state.replay.rules, versus);
+    Ok(GameSummary::new(
+        *game_id,
+        mode,
+        state,
+        verified,
+        get_timestamp_now_nano(),
+    ))
+}
+
+/// Keeps `summary` as the world record of its mode if it beats the old one.
+/// Runs once the summary is written.
+pub fn check_world_record(summary: &GameSummary) -> anyhow::Result<()> {
+    let mode = summary.mode;
+    if !mode.is_ranked(summary) {
+        return Ok(());
+    }
+    let _lock = WORLD_RECORD_LOCK.lock().unwrap();
+    let record = WORLD_RECORD_DB.get(&mode)?;
+    if record.is_none_or(|r| mode.compare(summary, &r).is_lt()) {
+        log::info!("new {} world record: {:?}", mode.name(), summary);
+        WORLD_RECORD_DB.insert(&mode, summary)?;
+        // nobody listening is fine
+        let _ = WORLD_RECORDS.send(summary.clone());
+    }
+    Ok(())
+}
+
+/// Whether replaying `segments` from scratch gives back every slice as it
+/// was stored and ends in `state`. Only `versus` games may take in garbage.
+fn replays_to(segments: &[GameReplaySegment], state: &GameState, versus: bool) -> bool {
+    let Some(GameReplaySegment::Init(replay)) = segments.first() else {
+        return false;
+    };
+    let mut rebuilt =
+        GameState::new_with_rules(&replay.init_seed, replay.start_time, &replay.rules);
+    for segment in &segments[1..] {
+        let GameReplaySegment::Update(slice) = segment else {
+            continue;
+        };
+        if !versus && matches!(slice.event.action, TetAction::ReceiveGarbage(_)) {
+            return false;
+        }
+        if rebuilt.accept_replay_slice(slice).is_err()
+            || rebuilt.replay.replay_slices.last() != Some(slice)
+        {
+            return false;
+        }
+    }
+    rebuilt.game_over && rebuilt == *state
+}
+
+/// Keys of `summary` in the `LEADERBOARDS` index on `GAME_SUMMARY_DB`: by
+/// mode, then for everyone and for its player, then by rank. Only ranked
+/// games have any.
+pub fn leaderboard_keys(game_id: &GameId, summary: &GameSummary) -> Vec<IndexKey> {
+    let Some((by_mode, by_time)) = summary.mode.rank(summary) else {
+        return vec![];
+    };
+    let rank = IndexKey::new().i64(by_mode).i64(by_time);
+    IndexGroup::all_and_user(game_id.user_id, rank)
+        .into_iter()
+        .map(|key| leaderboard_prefix(summary.mode).bytes(key.as_bytes()))
+        .collect()
+}
+
+fn leaderboard_prefix(mode: GameMode) -> IndexKey {
+    IndexKey::new().u8(mode as u8)
+}
+
+/// The best games of `group` on the leaderboard of `mode`, best first.
+fn best_games(
+    tree: &Tree<GameId, GameSummary>,
+    mode: GameMode,
+    group: IndexGroup,
+) -> impl Iterator<Item = anyhow::Result<GameSummary>> + '_ {
+    let prefix = leaderboard_prefix(mode).bytes(group.key().as_bytes());
+    let end = match prefix.prefix_end() {
+        Some(end) => Bound::Excluded(end),
+        None => Bound::Unbounded,
+    };
+    tree.index_range(LEADERBOARDS, (Bound::Included(prefix), end))
+        .rev()
+        .map(|entry| Ok(entry?.2))
+}
+
+fn leaderboard(
+    tree: &Tree<GameId, GameSummary>,
+    mode: GameMode,
+    view: LeaderboardView,
+) -> anyhow::Result<Vec<GameSummary>> {
+    let group = match view {
+        LeaderboardView::Player(user_id) => IndexGroup::User(user_id),
+        _ => IndexGroup::All,
+    };
+    // a player's first game down the board is their best one
+    let mut seen = HashSet::new();
+    let mut board = vec![];
+    for summary in best_games(tree, mode, group) {
+        let summary = summary?;
+        if view == LeaderboardView::PersonalBests
+            && !seen.insert(summary.game_id.user_id)
+        {
+            continue;
+        }
+        board.push(summary);
+        if board.len() == LEADERBOARD_SIZE {
+            break;
+        }
+    }
+    Ok(board)
+}
+
+pub fn get_leaderboard(
+    arg: (GameMode, LeaderboardView),
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<Vec<GameSummary>> {
+    let (mode, view) = arg;
+    leaderboard(&GAME_SUMMARY_DB, mode, view)
+}
+
+pub fn get_world_record(
+    mode: GameMode,
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<Option<GameSummary>> {
+    Ok(WORLD_RECORD_DB.get(&mode)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_exact_replays_are_verified() {
+        let rules = GameMode::Blitz.rules().unwrap();
+        let mut state = GameState::new_with_rules(&[7; 32], 0, &rules);
+        let mut segments = vec![GameReplaySegment::Init(state.replay.clone())];
+        for (i, action) in [TetAction::MoveLeft, TetAction::HardDrop, TetAction::Hold]
+            .into_iter()
+            .enumerate()
+        {
+            state.apply_action_if_works(action, i as i64 + 1).unwrap();
+            let slice = state.replay.replay_slices.last().unwrap().clone();
+            segments.push(GameReplaySegment::Update(slice));
+        }
+        // not over yet
+        assert!(!replays_to(&segments, &state, false));
+
+        state
+            .apply_action_if_works(TetAction::HardDrop, 200_000_000_000)
+            .unwrap();
+        assert!(state.game_over);
+        let slice = state.replay.replay_slices.last().unwrap().clone();
+        segments.push(GameReplaySegment::Update(slice));
+        segments.push(GameReplaySegment::GameOver);
+        assert!(replays_to(&segments, &state, false));
+
+        let mut tampered = state.clone();
+        tampered.score += 1000;
+        assert!(!replays_to(&segments, &tampered, false));
+    }
+
+    #[test]
+    fn garbage_only_verifies_in_matches() {
+        let rules = GameMode::Blitz.rules().unwrap();
+        let mut state = GameState::new_with_rules(&[7; 32], 0, &rules);
+        let mut segments = vec![GameReplaySegment::Init(state.replay.clone())];
+        for (i, action) in [TetAction::ReceiveGarbage(2), TetAction::HardDrop]
+            .into_iter()
+            .enumerate()
+        {
+            state.apply_action_if_works(action, i as i64 + 1).unwrap();
+            let slice = state.replay.replay_slices.last().unwrap().clone();
+            segments.push(GameReplaySegment::Update(slice));
+        }
+        state
+            .apply_action_if_works(TetAction::HardDrop, 200_000_000_000)
+            .unwrap();
+        let slice = state.replay.replay_slices.last().unwrap().clone();
+        segments.push(GameReplaySegment::Update(slice));
+        assert!(state.game_over);
+        assert!(replays_to(&segments, &state, true));
+        assert!(!replays_to(&segments, &state, false));
+    }
+
+    #[test]
+    fn leaderboards_read_the_best_games_first() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = Tree::<GameId, GameSummary>::open(&db, "test_summaries").with_index(
+            &db,
+            LEADERBOARDS,
+            leaderboard_keys,
+        );
+        let (a, b) = (
+            uuid::Uuid::from_bytes([1; 16]),
+            uuid::Uuid::from_bytes([2; 16]),
+        );
+        let rules = GameMode::Sprint40.rules().unwrap();
+        let state = GameState::new_with_rules(&[7; 32], 0, &rules);
+        for (i, (user_id, duration_ns, verified)) in
+            [(a, 50, true), (b, 30, true), (a, 40, true), (b, 10, false)]
+                .into_iter()
+                .enumerate()
+        {
+            let game_id = GameId {
+                user_id,
+                init_seed: [i as u8; 32],
+                start_time: i as i64,
+            };
+            let mut summary = GameSummary::new(
+                game_id,
+                GameMode::Sprint40,
+                &state,
+                verified,
+                i as i64,
+            );
+            summary.duration_ns = duration_ns;
+            summary.goal_reached = true;
+            tree.insert(&game_id, &summary).unwrap();
+        }
+        let times = |view| -> Vec<i64> {
+            leaderboard(&tree, GameMode::Sprint40, view)
+                .unwrap()
+                .iter()
+                .map(|s| s.duration_ns)
+                .collect()
+        };
+        assert_eq!(times(LeaderboardView::AllGames), vec![30, 40, 50]);
+        assert_eq!(times(LeaderboardView::PersonalBests), vec![30, 40]);
+        assert_eq!(times(LeaderboardView::Player(a)), vec![40, 50]);
+        assert!(
+            leaderboard(&tree, GameMode::Blitz, LeaderboardView::AllGames)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
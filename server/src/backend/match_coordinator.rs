@@ -0,0 +1,790 @@
+//! Runs a versus match on the server: routes the attack of each board to its
+//! opponents and knocks out boards that top out, until one team is left.
+//!
+//! Teammates pool their attack: the garbage multiplier applies to the team's
+//! total, and each player's share goes where that player's targeting points.
+//!
+//! Garbage reaches a board as `TetAction::ReceiveGarbage` slices in its own
+//! replay. The player's client adds them when the server says so, and
+//! `check_match_segment` refuses replays that skip, change or sit on them.
+
+use crate::backend::rating::record_match_result;
+use crate::backend::series::{record_series_win, SERIES_LOCK};
+use crate::backend::stats::add_match_stats;
+use crate::database::tables::*;
+use anyhow::Context;
+use game::api::game_match::{
+    GameMatch, GameMatchResult, GarbageBatch, GarbageSettings, GarbageTargeting,
+    MatchPlayerState, UserAndMatchResult,
+};
+use game::api::game_replay::GameId;
+use game::api::user::GuestInfo;
+use game::tet::{GameReplaySegment, GameState, TetAction};
+use game::timestamp::get_timestamp_now_nano;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use sled::transaction::ConflictableTransactionError;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use typed_sled::transaction::{flatten, Transactional};
+
+/// How long a player may keep placing pieces before taking in garbage.
+pub const GARBAGE_GRACE_NS: i64 = 5_000_000_000;
+/// A player that sends nothing for this long forfeits.
+const IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(90);
+
+/// Every read-modify-write of a match's `MATCH_PLAYER_DB` rows happens under
+/// its lock, see `match_lock`.
+static MATCH_LOCKS: Lazy<Mutex<HashMap<uuid::Uuid, Weak<Mutex<()>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The lock of one match. It lives as long as someone holds or waits for it,
+/// so finished matches leave nothing behind.
+fn match_lock(match_id: uuid::Uuid) -> Arc<Mutex<()>> {
+    let mut locks = MATCH_LOCKS.lock().unwrap();
+    if let Some(lock) = locks.get(&match_id).and_then(Weak::upgrade) {
+        return lock;
+    }
+    locks.retain(|_, lock| lock.strong_count() > 0);
+    let lock = Arc::new(Mutex::new(()));
+    locks.insert(match_id, Arc::downgrade(&lock));
+    lock
+}
+
+pub fn match_game_ids(match_info: &GameMatch) -> Vec<GameId> {
+    match_info
+        .users
+        .iter()
+        .map(|user_id| GameId {
+            user_id: *user_id,
+            init_seed: match_info.seed,
+            start_time: match_info.time,
+        })
+        .collect()
+}
+
+/// Raw attack a team has pooled, and the garbage lines it turned into.
+#[derive(Debug, Clone, Default)]
+struct TeamPool {
+    attack: u32,
+    sent: u32,
+}
+
+/// What the coordinator of a match keeps between events.
+struct MatchRun {
+    match_id: uuid::Uuid,
+    games: Vec<GameId>,
+    garbage: GarbageSettings,
+    pools: Vec<TeamPool>,
+}
+
+impl MatchRun {
+    /// Pools as they stand after what `players` have routed so far.
+    fn new(
+        match_id: uuid::Uuid,
+        games: Vec<GameId>,
+        garbage: GarbageSettings,
+        players: &[MatchPlayerState],
+    ) -> Self {
+        let teams = players
+            .iter()
+            .map(|p| p.team as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let mut pools = vec![TeamPool::default(); teams];
+        for p in players {
+            pools[p.team as usize].attack += p.routed;
+        }
+        for pool in pools.iter_mut() {
+            pool.sent = garbage.lines_sent(pool.attack);
+        }
+        Self {
+            match_id,
+            games,
+            garbage,
+            pools,
+        }
+    }
+
+    /// Runs `f` under the match lock, on a blocking thread: the database
+    /// calls in there must not stall the runtime.
+    async fn locked<T: Send + 'static>(
+        mut self,
+        f: impl FnOnce(&mut Self) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<(Self, T)> {
+        tokio::task::spawn_blocking(move || {
+            let lock = match_lock(self.match_id);
+            let _lock = lock.lock().unwrap();
+            let out = f(&mut self)?;
+            Ok((self, out))
+        })
+        .await?
+    }
+}
+
+/// Coordinator state of each player of a new match, to be stored together
+/// with the match; see `spawn_match_coordinator`.
+pub fn new_match_players(
+    match_id: uuid::Uuid,
+    match_info: &GameMatch,
+) -> anyhow::Result<Vec<(GameId, MatchPlayerState)>> {
+    let games = match_game_ids(match_info);
+    if games.len() < 2 {
+        anyhow::bail!("a versus match needs two games or more");
+    }
+    let players = games
+        .into_iter()
+        .enumerate()
+        .map(|(i, game_id)| {
+            let player = MatchPlayerState {
+                match_id,
+                team: match_info.team_of(i),
+                targeting: GarbageTargeting::default(),
+                target: None,
+                last_attacker: None,
+                kos: 0,
+                incoming: vec![],
+                applied: 0,
+                routed: 0,
+                out: false,
+                placement: None,
+                result: None,
+            };
+            (game_id, player)
+        })
+        .collect();
+    Ok(players)
+}
+
+/// Starts routing garbage, once the match and `players` are stored.
+pub fn spawn_match_coordinator(
+    match_id: uuid::Uuid,
+    players: Vec<(GameId, MatchPlayerState)>,
+    garbage: GarbageSettings,
+) {
+    let (games, players): (Vec<_>, Vec<_>) = players.into_iter().unzip();
+    spawn_match(MatchRun::new(match_id, games, garbage, &players), &players);
+}
+
+fn spawn_match(run: MatchRun, players: &[MatchPlayerState]) {
+    let match_id = run.match_id;
+    let out = players.iter().map(|p| p.out).collect();
+    tokio::spawn(async move {
+        if let Err(e) = run_match(run, out).await {
+            log::warn!("match {match_id} coordinator stopped: {e:?}");
+        }
+    });
+}
+
+/// Picks up the matches that were still running when the server stopped.
+/// A match without coordinator state to resume from is closed instead: its
+/// games can no longer get garbage, so nobody would ever win it.
+pub fn resume_match_coordinators() -> anyhow::Result<()> {
+    for item in GAME_MATCH_IS_IN_PROGRESS_DB.iter() {
+        let (match_id, in_progress) = item?;
+        if !in_progress {
+            continue;
+        }
+        let match_info = GAME_MATCH_DB.get(&match_id)?.context("match not found")?;
+        let games = match_game_ids(&match_info);
+        let players: Option<Vec<_>> = games
+            .iter()
+            .map(|g| MATCH_PLAYER_DB.get(g))
+            .collect::<Result<_, _>>()?;
+        match (MATCH_GARBAGE_DB.get(&match_id)?, players) {
+            (Some(garbage), Some(players)) if games.len() >= 2 => {
+                log::info!("resuming match {match_id}");
+                spawn_match(
+                    MatchRun::new(match_id, games, garbage, &players),
+                    &players,
+                );
+            }
+            _ => {
+                log::info!("closing match {match_id}, it cannot be resumed");
+                for game_id in &games {
+                    GAME_IS_IN_PROGRESS_DB.insert(game_id, &false)?;
+                }
+                GAME_MATCH_IS_IN_PROGRESS_DB.insert(&match_id, &false)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `out` marks the players already knocked out.
+async fn run_match(mut run: MatchRun, mut out: Vec<bool>) -> anyhow::Result<()> {
+    let match_id = run.match_id;
+    let games = run.games.clone();
+    let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+    let watchers: Vec<_> = games
+        .iter()
+        .enumerate()
+        .map(|(i, game_id)| {
+            let mut watch = GAME_FULL_DB.watch_prefix2(game_id);
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                while let Some(event) = (&mut watch).await {
+                    if tx.send((i, event)).await.is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let mut last_seen = vec![tokio::time::Instant::now(); games.len()];
+    let result = loop {
+        let idle = (0..games.len())
+            .filter(|i| !out[*i])
+            .min_by_key(|i| last_seen[*i])
+            .context("no player left")?;
+        let (player, event) = tokio::select! {
+            e = rx.recv() => e.context("game state watch closed")?,
+            _ = tokio::time::sleep_until(last_seen[idle] + IDLE_TIMEOUT) => {
+                log::info!("match {match_id}: player {idle} idle, forfeit");
+                out[idle] = true;
+                let (next, over) = run.locked(move |r| knock_out(&r.games, idle)).await?;
+                run = next;
+                if over {
+                    break Ok(());
+                }
+                continue;
+            }
+        };
+        last_seen[player] = tokio::time::Instant::now();
+        match event {
+            typed_sled::Event::Insert { value, .. } => {
+                if value.game_over {
+                    out[player] = true;
+                }
+                let (next, over) = run
+                    .locked(move |r| on_game_state(r, player, &value))
+                    .await?;
+                run = next;
+                if over {
+                    log::info!("match {match_id} over");
+                    break Ok(());
+                }
+            }
+            typed_sled::Event::Remove { .. } => {}
+        }
+    };
+    for watcher in watchers {
+        watcher.abort();
+    }
+    result
+}
+
+/// Call with the match lock held. Routes new attack to the player's targets.
+/// Returns true once the match is over.
+fn on_game_state(
+    run: &mut MatchRun,
+    player: usize,
+    state: &GameState,
+) -> anyhow::Result<bool> {
+    let games = &run.games;
+    let mut players = load_players(games)?;
+    if players[player].result.is_some() {
+        return Ok(true);
+    }
+    if players[player].out {
+        return Ok(false);
+    }
+    if state.game_over {
+        return knock_out(games, player);
+    }
+    let me = &mut players[player];
+    let attack = state.garbage_sent.saturating_sub(me.routed);
+    if attack == 0 {
+        return Ok(false);
+    }
+    me.routed = state.garbage_sent;
+    let pool = &mut run.pools[me.team as usize];
+    pool.attack += attack;
+    let lines = run
+        .garbage
+        .lines_sent(pool.attack)
+        .saturating_sub(pool.sent);
+    pool.sent += lines;
+
+    let users: Vec<_> = games.iter().map(|g| g.user_id).collect();
+    let roll = rand::thread_rng().gen();
+    let targets = pick_targets(player, &users, &players, roll);
+    if lines > 0 {
+        let now = get_timestamp_now_nano();
+        for &t in &targets {
+            players[t].incoming.push(GarbageBatch {
+                lines: lines.min(u8::MAX as u32) as u8,
+                sent_at: now,
+            });
+            players[t].last_attacker = Some(games[player].user_id);
+            MATCH_PLAYER_DB.insert(&games[t], &players[t])?;
+        }
+    }
+    let me = &mut players[player];
+    me.target = targets.first().map(|t| games[*t].user_id);
+    MATCH_PLAYER_DB.insert(&games[player], me)?;
+    Ok(false)
+}
+
+fn load_players(games: &[GameId]) -> anyhow::Result<Vec<MatchPlayerState>> {
+    games
+        .iter()
+        .map(|g| MATCH_PLAYER_DB.get(g)?.context("no match player"))
+        .collect()
+}
+
+/// Opponents that get the attack of `attacker`. `roll` picks the random one.
+fn pick_targets(
+    attacker: usize,
+    users: &[uuid::Uuid],
+    players: &[MatchPlayerState],
+    roll: usize,
+) -> Vec<usize> {
+    let me = &players[attacker];
+    let opponents: Vec<usize> = (0..players.len())
+        .filter(|j| !players[*j].out && players[*j].team != me.team)
+        .collect();
+    if opponents.is_empty() {
+        return vec![];
+    }
+    let random = vec![opponents[roll % opponents.len()]];
+    let most = |key: fn(&MatchPlayerState) -> u32| {
+        let best = opponents.iter().copied().max_by_key(|j| key(&players[*j]));
+        match best {
+            Some(j) if key(&players[j]) > 0 => vec![j],
+            _ => random.clone(),
+        }
+    };
+    match me.targeting {
+        GarbageTargeting::Random => random,
+        GarbageTargeting::Attackers => {
+            let attackers: Vec<_> = opponents
+                .iter()
+                .copied()
+                .filter(|j| players[*j].target == Some(users[attacker]))
+                .collect();
+            if attackers.is_empty() {
+                random
+            } else {
+                attackers
+            }
+        }
+        GarbageTargeting::KOs => most(|p| p.pending_lines()),
+        GarbageTargeting::Badges => most(|p| p.kos),
+    }
+}
+
+fn teams_left(players: &[MatchPlayerState]) -> usize {
+    let mut teams: Vec<_> = players.iter().filter(|p| !p.out).map(|p| p.team).collect();
+    teams.sort_unstable();
+    teams.dedup();
+    teams.len()
+}
+
+/// Takes `player` out: the last attacker gets the KO, and a team with nobody
+/// left gets its podium position. Returns true if one team is left.
+fn mark_out(
+    players: &mut [MatchPlayerState],
+    users: &[uuid::Uuid],
+    player: usize,
+) -> bool {
+    let teams_before = teams_left(players);
+    players[player].out = true;
+    if let Some(by) = players[player].last_attacker {
+        if let Some(k) = users.iter().position(|u| *u == by) {
+            if !players[k].out {
+                players[k].kos += 1;
+            }
+        }
+    }
+    let team = players[player].team;
+    if players.iter().all(|p| p.team != team || p.out) {
+        for p in players.iter_mut().filter(|p| p.team == team) {
+            p.placement = Some(teams_before as u32);
+        }
+    }
+    if teams_left(players) > 1 {
+        return false;
+    }
+    for p in players.iter_mut().filter(|p| p.placement.is_none()) {
+        p.placement = Some(1);
+    }
+    true
+}
+
+/// Call with the match lock held. Returns true if that ended the match.
+fn knock_out(games: &[GameId], player: usize) -> anyhow::Result<bool> {
+    let mut players = load_players(games)?;
+    if players[player].out {
+        return Ok(players[player].result.is_some());
+    }
+    let users: Vec<_> = games.iter().map(|g| g.user_id).collect();
+    let over = mark_out(&mut players, &users, player);
+    GAME_IS_IN_PROGRESS_DB.insert(&games[player], &false)?;
+    if over {
+        finish_match(games, &mut players)?;
+    } else {
+        for (game_id, p) in games.iter().zip(players.iter()) {
+            MATCH_PLAYER_DB.insert(game_id, p)?;
+        }
+    }
+    Ok(over)
+}
+
+/// Call with the match lock held, once every player has a placement. All
+/// of it is written in one transaction, so a match is either over with its
+/// ratings, stats and series counted, or still running.
+fn finish_match(
+    games: &[GameId],
+    players: &mut [MatchPlayerState],
+) -> anyhow::Result<()> {
+    let end_time = get_timestamp_now_nano();
+    let podium: Vec<_> = games
+        .iter()
+        .zip(players.iter())
+        .map(|(g, p)| (g.user_id, p.placement.unwrap_or(1)))
+        .collect();
+    let result = GameMatchResult {
+        winners: podium
+            .iter()
+            .filter(|(_, pos)| *pos == 1)
+            .map(|(u, _)| *u)
+            .collect(),
+        podium: podium.clone(),
+        end_time,
+    };
+    let mut results = vec![];
+    for (game_id, (_, position)) in games.iter().zip(&podium) {
+        let state = GAME_FULL_DB.get(game_id)?;
+        let user_result = player_result(*position, state.as_ref(), end_time);
+        results.push((game_id.user_id, user_result));
+    }
+    let finished: Vec<_> = players
+        .iter()
+        .map(|p| MatchPlayerState {
+            result: Some(result.clone()),
+            ..p.clone()
+        })
+        .collect();
+    let match_id = players.first().context("never happens")?.match_id;
+    let match_info = GAME_MATCH_DB.get(&match_id)?.context("match not found")?;
+    let lines_sent: u32 = results.iter().map(|r| r.1.lines_sent).sum();
+    let series_id = SERIES_FOR_MATCH_DB.get(&match_id)?;
+
+    let _series_lock = SERIES_LOCK.lock().unwrap();
+    flatten(
+        (
+            &*MATCH_PLAYER_DB,
+            &*GAME_IS_IN_PROGRESS_DB,
+            &*GAME_MATCHES_FOR_USER_DB,
+            &*RATING_DB,
+            &*RATING_HISTORY_DB,
+            &*USER_STATS_DB,
+            &*USER_DAILY_PACE_DB,
+            &*MATCH_LINES_SENT_DB,
+            &*SERIES_DB,
+            &*GAME_MATCH_RESULT_DB,
+            &*GAME_MATCH_IS_IN_PROGRESS_DB,
+        )
+            .transaction(
+                |(
+                    player_db,
+                    game_in_progress_db,
+                    match_results_db,
+                    rating_db,
+                    rating_history_db,
+                    stats_db,
+                    pace_db,
+                    lines_sent_db,
+                    series_db,
+                    result_db,
+                    match_in_progress_db,
+                )| {
+                    for (game_id, player) in games.iter().zip(&finished) {
+                        player_db.insert(game_id, player)?;
+                        game_in_progress_db.insert(game_id, &false)?;
+                    }
+                    record_match_result(
+                        &match_results_db,
+                        &rating_db,
+                        &rating_history_db,
+                        match_id,
+                        &match_info.match_type,
+                        &results,
+                        end_time,
+                    )?;
+                    add_match_stats(&stats_db, &pace_db, &results, match_info.time)?;
+                    lines_sent_db.insert(&match_id, &lines_sent)?;
+                    if let Some(series_id) = &series_id {
+                        record_series_win(&series_db, series_id, &result.winners)?;
+                    }
+                    result_db.insert(&match_id, &result)?;
+                    match_in_progress_db.insert(&match_id, &false)?;
+                    Ok::<(), ConflictableTransactionError<anyhow::Error>>(())
+                },
+            ),
+    )?;
+    players.clone_from_slice(&finished);
+    Ok(())
+}
+
+/// What goes in the player's match history. `state` is missing if the player
+/// never placed a piece.
+fn player_result(
+    podium_position: u32,
+    state: Option<&GameState>,
+    end_time: i64,
+) -> UserAndMatchResult {
+    UserAndMatchResult {
+        is_win: podium_position == 1,
+        podium_position,
+        score: state.map_or(0, |s| s.score),
+        lines_sent: state.map_or(0, |s| s.garbage_sent),
+        lines_received: state.map_or(0, |s| s.garbage_received),
+        pieces: state.map_or(0, |s| s.current_id),
+        end_time,
+    }
+}
+
+pub fn set_garbage_targeting(
+    arg: (uuid::Uuid, GarbageTargeting),
+    current_user_id: GuestInfo,
+) -> anyhow::Result<()> {
+    let (match_id, targeting) = arg;
+    let match_info = GAME_MATCH_DB.get(&match_id)?.context("match not found")?;
+    let game_id = match_game_ids(&match_info)
+        .into_iter()
+        .find(|g| g.user_id == current_user_id.user_id)
+        .context("not a player of this match")?;
+    let lock = match_lock(match_id);
+    let _lock = lock.lock().unwrap();
+    let mut me = MATCH_PLAYER_DB.get(&game_id)?.context("no match player")?;
+    me.targeting = targeting;
+    MATCH_PLAYER_DB.insert(&game_id, &me)?;
+    Ok(())
+}
+
+/// Checks a segment against the garbage sent to its game; games outside a
+/// match get none. Returns true if the segment takes in a batch; call
+/// `mark_garbage_applied` once it is stored.
+pub fn check_match_segment(
+    game_id: &GameId,
+    segment: &GameReplaySegment,
+) -> anyhow::Result<bool> {
+    let GameReplaySegment::Update(slice) = segment else {
+        return Ok(false);
+    };
+    let me = MATCH_PLAYER_DB.get(game_id)?;
+    check_action(me.as_ref(), slice.event.action, get_timestamp_now_nano())
+}
+
+fn check_action(
+    me: Option<&MatchPlayerState>,
+    action: TetAction,
+    now: i64,
+) -> anyhow::Result<bool> {
+    let Some(me) = me else {
+        if let TetAction::ReceiveGarbage(_) = action {
+            anyhow::bail!("only match games take in garbage");
+        }
+        return Ok(false);
+    };
+    if me.result.is_some() || me.out {
+        anyhow::bail!("match is over");
+    }
+    let pending = me.incoming.get(me.applied as usize);
+    if let TetAction::ReceiveGarbage(lines) = action {
+        let batch = pending.context("no garbage was sent")?;
+        if batch.lines != lines {
+            anyhow::bail!("got {lines} garbage lines, sent {}", batch.lines);
+        }
+        return Ok(true);
+    }
+    if let Some(batch) = pending {
+        if now - batch.sent_at > GARBAGE_GRACE_NS {
+            anyhow::bail!("incoming garbage was not taken in");
+        }
+    }
+    Ok(false)
+}
+
+pub fn mark_garbage_applied(game_id: &GameId) -> anyhow::Result<()> {
+    let me = MATCH_PLAYER_DB.get(game_id)?.context("no match player")?;
+    let lock = match_lock(me.match_id);
+    let _lock = lock.lock().unwrap();
+    let mut me = MATCH_PLAYER_DB.get(game_id)?.context("no match player")?;
+    me.applied += 1;
+    MATCH_PLAYER_DB.insert(game_id, &me)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(team: u32) -> MatchPlayerState {
+        MatchPlayerState {
+            match_id: uuid::Uuid::nil(),
+            team,
+            targeting: GarbageTargeting::Random,
+            target: None,
+            last_attacker: None,
+            kos: 0,
+            incoming: vec![],
+            applied: 0,
+            routed: 0,
+            out: false,
+            placement: None,
+            result: None,
+        }
+    }
+
+    #[test]
+    fn garbage_must_be_taken_in_order() {
+        let game_id = GameId {
+            user_id: uuid::Uuid::new_v4(),
+            init_seed: [1; 32],
+            start_time: 0,
+        };
+        let mut me = player(0);
+        me.incoming.push(GarbageBatch {
+            lines: 2,
+            sent_at: 0,
+        });
+        assert!(check_action(Some(&me), TetAction::ReceiveGarbage(3), 0).is_err());
+        assert!(!check_action(Some(&me), TetAction::HardDrop, 0).unwrap());
+        assert!(check_action(Some(&me), TetAction::ReceiveGarbage(2), 0).unwrap());
+        assert!(
+            check_action(Some(&me), TetAction::HardDrop, 2 * GARBAGE_GRACE_NS).is_err()
+        );
+
+        me.applied = 1;
+        assert!(check_action(Some(&me), TetAction::ReceiveGarbage(2), 0).is_err());
+        assert!(
+            !check_action(Some(&me), TetAction::HardDrop, 2 * GARBAGE_GRACE_NS)
+                .unwrap()
+        );
+
+        me.result = Some(GameMatchResult {
+            winners: vec![game_id.user_id],
+            podium: vec![(game_id.user_id, 1)],
+            end_time: 0,
+        });
+        assert!(check_action(Some(&me), TetAction::HardDrop, 0).is_err());
+
+        me.result = None;
+        me.out = true;
+        assert!(check_action(Some(&me), TetAction::HardDrop, 0).is_err());
+
+        // solo games
+        assert!(check_action(None, TetAction::ReceiveGarbage(2), 0).is_err());
+        assert!(!check_action(None, TetAction::HardDrop, 0).unwrap());
+    }
+
+    #[test]
+    fn results_keep_board_stats() {
+        let mut state = GameState::new(&[3; 32], 0);
+        state
+            .apply_action_if_works(TetAction::ReceiveGarbage(2), 1)
+            .unwrap();
+        state.apply_action_if_works(TetAction::HardDrop, 2).unwrap();
+
+        let lost = player_result(2, Some(&state), 10);
+        assert!(!lost.is_win);
+        assert_eq!(lost.podium_position, 2);
+        assert_eq!(lost.lines_received, 2);
+        assert_eq!(lost.pieces, state.current_id);
+        assert_eq!(lost.end_time, 10);
+
+        let won = player_result(1, None, 10);
+        assert!(won.is_win);
+        assert_eq!(won.podium_position, 1);
+        assert_eq!(won.pieces, 0);
+    }
+
+    #[test]
+    fn targeting_skips_teammates_and_knocked_out() {
+        let users: Vec<_> = (0..5).map(|_| uuid::Uuid::new_v4()).collect();
+        let mut players: Vec<_> = (0..5).map(|i| player(i as u32)).collect();
+        players[1].team = 0;
+        players[4].out = true;
+        for roll in 0..10 {
+            let targets = pick_targets(0, &users, &players, roll);
+            assert_eq!(targets.len(), 1);
+            assert!([2, 3].contains(&targets[0]));
+        }
+
+        players[0].targeting = GarbageTargeting::Attackers;
+        players[2].target = Some(users[0]);
+        players[3].target = Some(users[0]);
+        assert_eq!(pick_targets(0, &users, &players, 0), vec![2, 3]);
+
+        players[0].targeting = GarbageTargeting::KOs;
+        players[3].incoming.push(GarbageBatch {
+            lines: 4,
+            sent_at: 0,
+        });
+        assert_eq!(pick_targets(0, &users, &players, 0), vec![3]);
+        players[3].applied = 1;
+        assert_eq!(pick_targets(0, &users, &players, 0).len(), 1);
+
+        players[0].targeting = GarbageTargeting::Badges;
+        players[2].kos = 2;
+        assert_eq!(pick_targets(0, &users, &players, 1), vec![2]);
+    }
+
+    #[test]
+    fn resumed_pools_count_routed_attack() {
+        let mut players: Vec<_> = [0, 0, 1].into_iter().map(player).collect();
+        players[0].routed = 3;
+        players[1].routed = 2;
+        players[2].routed = 1;
+        let garbage = GarbageSettings {
+            enabled: true,
+            multiplier_percent: 50,
+        };
+        let run = MatchRun::new(uuid::Uuid::nil(), vec![], garbage, &players);
+        let pools: Vec<_> = run.pools.iter().map(|p| (p.attack, p.sent)).collect();
+        assert_eq!(pools, vec![(5, 2), (1, 0)]);
+    }
+
+    #[test]
+    fn match_locks_are_per_match() {
+        let a = uuid::Uuid::new_v4();
+        let held = match_lock(a);
+        let _held = held.lock().unwrap();
+        assert!(Arc::ptr_eq(&held, &match_lock(a)));
+        assert!(match_lock(uuid::Uuid::new_v4()).try_lock().is_ok());
+    }
+
+    #[test]
+    fn players_are_placed_in_knock_out_order() {
+        let users: Vec<_> = (0..4).map(|_| uuid::Uuid::new_v4()).collect();
+        let mut players: Vec<_> = (0..4).map(|i| player(i as u32)).collect();
+        players[2].last_attacker = Some(users[3]);
+
+        assert!(!mark_out(&mut players, &users, 2));
+        assert_eq!(players[2].placement, Some(4));
+        assert_eq!(players[3].kos, 1);
+        assert!(!mark_out(&mut players, &users, 0));
+        assert_eq!(players[0].placement, Some(3));
+        assert!(mark_out(&mut players, &users, 3));
+        assert_eq!(players[3].placement, Some(2));
+        assert_eq!(players[1].placement, Some(1));
+    }
+
+    #[test]
+    fn teams_are_placed_together() {
+        let users: Vec<_> = (0..4).map(|_| uuid::Uuid::new_v4()).collect();
+        let mut players: Vec<_> = [0, 0, 1, 1].into_iter().map(player).collect();
+
+        assert!(!mark_out(&mut players, &users, 0));
+        assert_eq!(players[0].placement, None);
+        assert!(!mark_out(&mut players, &users, 2));
+        assert!(mark_out(&mut players, &users, 1));
+        let placements: Vec<_> = players.iter().map(|p| p.placement).collect();
+        assert_eq!(placements, vec![Some(2), Some(2), Some(1), Some(1)]);
+    }
+}
@@ -0,0 +1,351 @@
+//! Matchmaking queues, one per match type.
+//!
+//! Joining returns at once; the queue reports back on the player's connection
+//! with `MatchmakingStatus` notifications. Players are grouped when their
+//! ratings are close enough, and the allowed distance grows the longer they
+//! wait. Entries go away when the connection that made them closes.
+
+use crate::backend::match_coordinator::{new_match_players, spawn_match_coordinator};
+use crate::backend::rating::get_user_rating;
+use crate::backend::series::start_series;
+use crate::database::tables::*;
+use anyhow::Context;
+use game::api::game_match::{
+    GameMatch, GameMatchType, MatchPlayerState, MatchmakingStatus,
+};
+use game::api::game_replay::GameId;
+use game::api::room::RoomSettings;
+use game::api::user::GuestInfo;
+use game::timestamp::get_timestamp_now_nano;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use sled::transaction::ConflictableTransactionError;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::Sender;
+use typed_sled::transaction::{flatten, Transactional};
+
+pub const BASE_RATING_BAND: f64 = 100.0;
+pub const RATING_BAND_PER_SEC: f64 = 10.0;
+pub const MAX_RATING_BAND: f64 = 1000.0;
+/// How often waiting players are grouped and told how it goes.
+const MATCHMAKING_TICK: Duration = Duration::from_secs(1);
+/// Battle royales from the queue wait for this many players.
+pub const BATTLE_ROYALE_QUEUE_PLAYERS: usize = 10;
+
+pub type MatchmakingSender = Sender<MatchmakingStatus>;
+
+struct QueueEntry {
+    player_id: uuid::Uuid,
+    rating: f64,
+    joined_at: Instant,
+    notify: MatchmakingSender,
+}
+
+static QUEUES: Lazy<std::sync::Mutex<HashMap<GameMatchType, Vec<QueueEntry>>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
+/// Players in one match of this type, or `None` if it has no matchmaking.
+pub fn players_per_match(match_type: &GameMatchType) -> Option<usize> {
+    match match_type {
+        GameMatchType::BattleRoyale => Some(BATTLE_ROYALE_QUEUE_PLAYERS),
+        _ => match_type.team_size().map(|_| match_type.max_players()),
+    }
+}
+
+pub fn rating_band(waited: Duration) -> f64 {
+    (BASE_RATING_BAND + RATING_BAND_PER_SEC * waited.as_secs_f64()).min(MAX_RATING_BAND)
+}
+
+/// Groups `(rating, band)` entries by `size`, oldest first. Players fit
+/// together if each one's band reaches every other one.
+fn find_groups(entries: &[(f64, f64)], size: usize) -> Vec<Vec<usize>> {
+    let mut taken = vec![false; entries.len()];
+    let mut groups = vec![];
+    for i in 0..entries.len() {
+        if taken[i] {
+            continue;
+        }
+        let mut group = vec![i];
+        for j in i + 1..entries.len() {
+            if group.len() == size {
+                break;
+            }
+            let (rating, band) = entries[j];
+            let fits = group.iter().all(|k| {
+                let (other_rating, other_band) = entries[*k];
+                (rating - other_rating).abs() <= band.min(other_band)
+            });
+            if !taken[j] && fits {
+                group.push(j);
+            }
+        }
+        if group.len() == size {
+            for k in &group {
+                taken[*k] = true;
+            }
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+pub fn start_match(
+    match_type: GameMatchType,
+    current_user_id: GuestInfo,
+    notify: MatchmakingSender,
+) -> anyhow::Result<()> {
+    players_per_match(&match_type)
+        .with_context(|| format!("no matchmaking for {match_type:?}"))?;
+    let player_id = current_user_id.user_id;
+    let rating =
+        get_user_rating(player_id, match_type.clone(), get_timestamp_now_nano())?
+            .rating;
+    {
+        let mut queues = QUEUES.lock().unwrap();
+        remove_closed_entries(&mut queues);
+        if queues.values().flatten().any(|e| e.player_id == player_id) {
+            anyhow::bail!("another game is already in matchmaking!");
+        }
+        queues
+            .entry(match_type.clone())
+            .or_default()
+            .push(QueueEntry {
+                player_id,
+                rating,
+                joined_at: Instant::now(),
+                notify,
+            });
+    }
+    group_queue(&match_type);
+    Ok(())
+}
+
+pub fn cancel_matchmaking(
+    _: (),
+    current_user_id: GuestInfo,
+    notify: MatchmakingSender,
+) -> anyhow::Result<bool> {
+    let mut queues = QUEUES.lock().unwrap();
+    let mut removed = false;
+    for queue in queues.values_mut() {
+        queue.retain(|e| {
+            let mine = e.player_id == current_user_id.user_id
+                && e.notify.same_channel(&notify);
+            removed |= mine;
+            !mine
+        });
+    }
+    if removed {
+        let _ = notify.try_send(MatchmakingStatus::Cancelled);
+    }
+    Ok(removed)
+}
+
+/// Drops whatever the closed connection left in the queues.
+pub fn on_connection_closed(notify: &MatchmakingSender) {
+    let mut queues = QUEUES.lock().unwrap();
+    for queue in queues.values_mut() {
+        queue.retain(|e| !e.notify.same_channel(notify));
+    }
+}
+
+fn remove_closed_entries(queues: &mut HashMap<GameMatchType, Vec<QueueEntry>>) {
+    for queue in queues.values_mut() {
+        queue.retain(|e| !e.notify.is_closed());
+    }
+}
+
+pub async fn run_matchmaker() {
+    loop {
+        tokio::time::sleep(MATCHMAKING_TICK).await;
+        let match_types: Vec<_> = QUEUES.lock().unwrap().keys().cloned().collect();
+        for match_type in match_types {
+            // starting a match writes to the database
+            let grouped = tokio::task::spawn_blocking(move || group_queue(&match_type));
+            if let Err(e) = grouped.await {
+                log::warn!("matchmaking failed: {e:?}");
+            }
+        }
+
+        let mut queues = QUEUES.lock().unwrap();
+        remove_closed_entries(&mut queues);
+        for (match_type, queue) in queues.iter() {
+            for entry in queue {
+                let waited = entry.joined_at.elapsed();
+                let _ = entry.notify.try_send(MatchmakingStatus::Waiting {
+                    match_type: match_type.clone(),
+                    waited_secs: waited.as_secs() as u32,
+                    players_waiting: queue.len() as u32,
+                    rating_band: rating_band(waited) as u32,
+                });
+            }
+        }
+    }
+}
+
+fn group_queue(match_type: &GameMatchType) {
+    let Some(size) = players_per_match(match_type) else {
+        return;
+    };
+    let matched: Vec<Vec<QueueEntry>> = {
+        let mut queues = QUEUES.lock().unwrap();
+        let Some(queue) = queues.get_mut(match_type) else {
+            return;
+        };
+        queue.retain(|e| !e.notify.is_closed());
+        let entries: Vec<_> = queue
+            .iter()
+            .map(|e| (e.rating, rating_band(e.joined_at.elapsed())))
+            .collect();
+        let groups = find_groups(&entries, size);
+        let mut picked: Vec<_> = groups.iter().flatten().copied().collect();
+        picked.sort_unstable_by(|a, b| b.cmp(a));
+        let mut removed: HashMap<usize, QueueEntry> =
+            picked.into_iter().map(|i| (i, queue.remove(i))).collect();
+        groups
+            .into_iter()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .map(|i| removed.remove(&i).unwrap())
+                    .collect()
+            })
+            .collect()
+    };
+
+    for players in matched {
+        let users = players.iter().map(|p| p.player_id).collect();
+        let title = match match_type {
+            GameMatchType::_1v1 => format!(
+                "1v1 {} vs. {}",
+                get_display_name(&players[0].player_id),
+                get_display_name(&players[1].player_id)
+            ),
+            _ => format!("{match_type:?} of {} players", players.len()),
+        };
+        let settings = RoomSettings {
+            match_type: match_type.clone(),
+            ..Default::default()
+        };
+        match start_series(users, title, &settings) {
+            Ok((match_id, match_info)) => {
+                for player in &players {
+                    let found = MatchmakingStatus::Found(match_id, match_info.clone());
+                    let _ = player.notify.try_send(found);
+                }
+            }
+            Err(e) => {
+                log::warn!("cannot create {match_type:?} match: {e:?}");
+                for player in &players {
+                    let _ = player.notify.try_send(MatchmakingStatus::Cancelled);
+                }
+            }
+        }
+    }
+}
+
+/// Stores a new match with a game per player and starts its coordinator.
+pub fn create_match(
+    match_type: &GameMatchType,
+    users: Vec<uuid::Uuid>,
+    title: String,
+    settings: &RoomSettings,
+) -> anyhow::Result<(uuid::Uuid, GameMatch)> {
+    let new_match = GameMatch {
+        seed: rand::thread_rng().gen(),
+        time: get_timestamp_now_nano(),
+        users,
+        title,
+        match_type: match_type.clone(),
+    };
+    let new_match_id = uuid::Uuid::new_v4();
+    let players = new_match_players(new_match_id, &new_match)?;
+    create_db_match_entry(&new_match_id, &new_match, settings, &players)?;
+    spawn_match_coordinator(new_match_id, players, settings.garbage.clone());
+    Ok((new_match_id, new_match))
+}
+
+/// The match, the games of its players and the state its coordinator starts
+/// from, in one transaction: a match is never stored without a coordinator.
+fn create_db_match_entry(
+    match_id: &uuid::Uuid,
+    match_info: &GameMatch,
+    settings: &RoomSettings,
+    players: &[(GameId, MatchPlayerState)],
+) -> anyhow::Result<()> {
+    flatten(
+        (
+            &*GAME_MATCH_DB,
+            &*GAME_RULES_DB,
+            &*GAME_IS_IN_PROGRESS_DB,
+            &*GAME_SEGMENT_COUNT_DB,
+            &*MATCH_PLAYER_DB,
+            &*MATCH_GARBAGE_DB,
+            &*GAME_MATCH_IS_IN_PROGRESS_DB,
+        )
+            .transaction(
+                |(
+                    match_db,
+                    rules_db,
+                    in_progress_db,
+                    count_db,
+                    player_db,
+                    garbage_db,
+                    match_in_progress_db,
+                )| {
+                    match_db.insert(match_id, match_info)?;
+                    for (game_id, player) in players {
+                        rules_db.insert(game_id, &settings.rules)?;
+                        in_progress_db.insert(game_id, &true)?;
+                        count_db.insert(game_id, &0)?;
+                        player_db.insert(game_id, player)?;
+                    }
+                    garbage_db.insert(match_id, &settings.garbage)?;
+                    match_in_progress_db.insert(match_id, &true)?;
+                    Ok::<(), ConflictableTransactionError<anyhow::Error>>(())
+                },
+            ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bands_widen_with_time() {
+        assert_eq!(rating_band(Duration::ZERO), BASE_RATING_BAND);
+        assert!(rating_band(Duration::from_secs(10)) > BASE_RATING_BAND);
+        assert_eq!(rating_band(Duration::from_secs(100_000)), MAX_RATING_BAND);
+    }
+
+    #[test]
+    fn groups_respect_every_band() {
+        // the oldest player is far from everyone but the last one
+        let entries = [
+            (1500.0, 100.0),
+            (2000.0, 100.0),
+            (1450.0, 100.0),
+            (1950.0, 50.0),
+        ];
+        assert_eq!(find_groups(&entries, 2), vec![vec![0, 2], vec![1, 3]]);
+
+        // one band is wide, the other is not
+        assert!(find_groups(&[(1500.0, 1000.0), (1800.0, 100.0)], 2).is_empty());
+        assert_eq!(
+            find_groups(&[(1500.0, 400.0), (1800.0, 300.0)], 2),
+            vec![vec![0, 1]]
+        );
+
+        // the third player fits the first, but not the second
+        let entries = [
+            (1500.0, 200.0),
+            (1650.0, 200.0),
+            (1350.0, 200.0),
+            (1600.0, 200.0),
+        ];
+        assert_eq!(find_groups(&entries, 3), vec![vec![0, 1, 3]]);
+        assert!(find_groups(&entries[..3], 3).is_empty());
+    }
+}
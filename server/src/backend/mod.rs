@@ -1,5 +1,17 @@
+pub mod account;
+pub mod challenge;
+pub mod guest_merge;
+pub mod leaderboard;
+pub mod match_coordinator;
+pub mod matchmaking;
+pub mod presence;
+pub mod rating;
+pub mod render;
+pub mod room;
+pub mod series;
 pub mod server_fn;
 pub mod server_info;
 pub mod server_main;
 pub mod session;
+pub mod stats;
 pub mod websocket;
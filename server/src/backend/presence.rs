@@ -0,0 +1,308 @@
+//! Live connections of every user, the presence they add up to, and follows.
+//!
+//! Each websocket holds a [Connection] for as long as it lives. Dropping it
+//! is the only way out of the registry, so presence clears however the
+//! socket ends, including when its tasks are aborted. Changes are pushed to
+//! friends, who are users that follow each other.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::database::tables::*;
+use game::api::challenge::ChallengeEvent;
+use game::api::game_replay::GameId;
+use game::api::presence::{FollowInfo, Presence};
+use game::api::user::GuestInfo;
+use game::timestamp::get_timestamp_now_nano;
+use once_cell::sync::Lazy;
+use typed_sled::index::IndexKey;
+
+/// Pushed to every connection of one user.
+#[derive(Debug, Clone)]
+pub enum UserNotification {
+    Presence(uuid::Uuid, Presence),
+    Challenge(uuid::Uuid, ChallengeEvent),
+}
+
+pub type UserSender = tokio::sync::mpsc::Sender<UserNotification>;
+
+#[derive(Default)]
+struct UserConnections {
+    senders: HashMap<u64, UserSender>,
+    playing: Option<GameId>,
+}
+
+#[derive(Default)]
+struct Registry {
+    users: HashMap<uuid::Uuid, UserConnections>,
+}
+
+impl Registry {
+    fn presence(&self, user_id: &uuid::Uuid) -> Presence {
+        match self.users.get(user_id) {
+            None => Presence::Offline,
+            Some(UserConnections {
+                playing: Some(game_id),
+                ..
+            }) => Presence::Playing(*game_id),
+            Some(_) => Presence::Online,
+        }
+    }
+
+    /// The methods below return the user's new presence if it changed.
+    fn connect(
+        &mut self,
+        user_id: uuid::Uuid,
+        id: u64,
+        sender: UserSender,
+    ) -> Option<Presence> {
+        let before = self.presence(&user_id);
+        self.users
+            .entry(user_id)
+            .or_default()
+            .senders
+            .insert(id, sender);
+        self.changed(&user_id, before)
+    }
+
+    fn disconnect(&mut self, user_id: &uuid::Uuid, id: u64) -> Option<Presence> {
+        let before = self.presence(user_id);
+        if let Some(user) = self.users.get_mut(user_id) {
+            user.senders.remove(&id);
+            if user.senders.is_empty() {
+                self.users.remove(user_id);
+            }
+        }
+        self.changed(user_id, before)
+    }
+
+    /// Only for users that are online.
+    fn set_playing(
+        &mut self,
+        user_id: &uuid::Uuid,
+        playing: Option<GameId>,
+    ) -> Option<Presence> {
+        let before = self.presence(user_id);
+        if let Some(user) = self.users.get_mut(user_id) {
+            user.playing = playing;
+        }
+        self.changed(user_id, before)
+    }
+
+    fn changed(&self, user_id: &uuid::Uuid, before: Presence) -> Option<Presence> {
+        let after = self.presence(user_id);
+        (after != before).then_some(after)
+    }
+
+    fn sender(&self, user_id: &uuid::Uuid, id: u64) -> Option<UserSender> {
+        self.users.get(user_id)?.senders.get(&id).cloned()
+    }
+
+    fn senders(&self, user_id: &uuid::Uuid) -> Vec<UserSender> {
+        self.users
+            .get(user_id)
+            .map(|u| u.senders.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+static REGISTRY: Lazy<std::sync::Mutex<Registry>> = Lazy::new(Default::default);
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// One live websocket of a user; what is pushed to them goes to `sender`.
+pub struct Connection {
+    id: u64,
+    user_id: uuid::Uuid,
+}
+
+impl Connection {
+    pub fn open(user_id: uuid::Uuid, sender: UserSender) -> Self {
+        let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        let changed = REGISTRY.lock().unwrap().connect(user_id, id, sender);
+        if let Some(presence) = changed {
+            tell_friends(&user_id, presence);
+        }
+        Self { id, user_id }
+    }
+
+    /// After logging in or out, the connection counts for someone else.
+    pub fn switch_user(&mut self, user_id: uuid::Uuid) {
+        if user_id == self.user_id {
+            return;
+        }
+        let (left, joined) = {
+            let mut registry = REGISTRY.lock().unwrap();
+            let Some(sender) = registry.sender(&self.user_id, self.id) else {
+                return;
+            };
+            let left = registry.disconnect(&self.user_id, self.id);
+            (left, registry.connect(user_id, self.id, sender))
+        };
+        if let Some(presence) = left {
+            tell_friends(&self.user_id, presence);
+        }
+        self.user_id = user_id;
+        if let Some(presence) = joined {
+            tell_friends(&user_id, presence);
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let changed = REGISTRY.lock().unwrap().disconnect(&self.user_id, self.id);
+        if let Some(presence) = changed {
+            tell_friends(&self.user_id, presence);
+        }
+    }
+}
+
+/// Called as games of `user_id` start and end.
+pub fn set_playing(user_id: &uuid::Uuid, playing: Option<GameId>) {
+    let changed = REGISTRY.lock().unwrap().set_playing(user_id, playing);
+    if let Some(presence) = changed {
+        tell_friends(user_id, presence);
+    }
+}
+
+pub fn is_online(user_id: &uuid::Uuid) -> bool {
+    REGISTRY.lock().unwrap().presence(user_id) != Presence::Offline
+}
+
+/// To every connection of `to`, if any.
+pub fn notify_user(to: &uuid::Uuid, notification: UserNotification) {
+    for sender in REGISTRY.lock().unwrap().senders(to) {
+        // a full channel only misses this one update
+        let _ = sender.try_send(notification.clone());
+    }
+}
+
+fn send_presence(to: &uuid::Uuid, about: &uuid::Uuid, presence: Presence) {
+    notify_user(to, UserNotification::Presence(*about, presence));
+}
+
+fn tell_friends(user_id: &uuid::Uuid, presence: Presence) {
+    match get_friends(user_id) {
+        Ok(friends) => {
+            for friend in friends {
+                send_presence(&friend, user_id, presence);
+            }
+        }
+        Err(e) => log::warn!("cannot tell friends of {user_id} about presence: {e:?}"),
+    }
+}
+
+pub fn all_ids_of(
+    user_id: &uuid::Uuid,
+) -> std::ops::RangeInclusive<(uuid::Uuid, uuid::Uuid)> {
+    (*user_id, uuid::Uuid::nil())..=(*user_id, uuid::Uuid::from_bytes([u8::MAX; 16]))
+}
+
+fn follows(follower: &uuid::Uuid, followed: &uuid::Uuid) -> anyhow::Result<bool> {
+    Ok(FOLLOW_DB.contains_key(&(*follower, *followed))?)
+}
+
+fn get_friends(user_id: &uuid::Uuid) -> anyhow::Result<Vec<uuid::Uuid>> {
+    let mut friends = vec![];
+    let prefix = IndexKey::new().bytes(user_id.as_bytes());
+    for item in FOLLOW_DB.index_scan_prefix(FOLLOWERS, &prefix) {
+        let (_, (follower, _), _) = item?;
+        if follows(user_id, &follower)? {
+            friends.push(follower);
+        }
+    }
+    Ok(friends)
+}
+
+/// After a follow changes, friends see each other and others don't.
+fn share_presence(a: &uuid::Uuid, b: &uuid::Uuid) -> anyhow::Result<()> {
+    let friends = follows(a, b)? && follows(b, a)?;
+    for (to, about) in [(a, b), (b, a)] {
+        let presence = match friends {
+            true => REGISTRY.lock().unwrap().presence(about),
+            false => Presence::Offline,
+        };
+        send_presence(to, about, presence);
+    }
+    Ok(())
+}
+
+pub fn follow(user_id: uuid::Uuid, _current_user_id: GuestInfo) -> anyhow::Result<()> {
+    let me = _current_user_id.user_id;
+    if user_id == me {
+        anyhow::bail!("cannot follow yourself");
+    }
+    if USER_PROFILE_DB.get(&user_id)?.is_none() {
+        anyhow::bail!("user not found");
+    }
+    FOLLOW_DB.insert(&(me, user_id), &get_timestamp_now_nano())?;
+    share_presence(&me, &user_id)
+}
+
+pub fn unfollow(
+    user_id: uuid::Uuid,
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<()> {
+    let me = _current_user_id.user_id;
+    FOLLOW_DB.remove(&(me, user_id))?;
+    share_presence(&me, &user_id)
+}
+
+pub fn get_following(
+    _: (),
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<Vec<FollowInfo>> {
+    let me = _current_user_id.user_id;
+    let mut following = vec![];
+    for item in FOLLOW_DB.range(all_ids_of(&me)) {
+        let ((_, user_id), _) = item?;
+        let follows_back = follows(&user_id, &me)?;
+        following.push(FollowInfo {
+            user_id,
+            display_name: get_display_name(&user_id),
+            follows_back,
+            presence: follows_back.then(|| REGISTRY.lock().unwrap().presence(&user_id)),
+        });
+    }
+    following.sort_by(|a, b| {
+        (!a.follows_back, &a.display_name).cmp(&(!b.follows_back, &b.display_name))
+    });
+    Ok(following)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presence_follows_connections() {
+        let mut registry = Registry::default();
+        let user = uuid::Uuid::from_bytes([1; 16]);
+        let (sender, _receiver) = tokio::sync::mpsc::channel(1);
+        let game_id = GameId {
+            user_id: user,
+            init_seed: [0; 32],
+            start_time: 0,
+        };
+
+        assert_eq!(registry.set_playing(&user, Some(game_id)), None);
+        assert_eq!(
+            registry.connect(user, 1, sender.clone()),
+            Some(Presence::Online)
+        );
+        assert_eq!(registry.connect(user, 2, sender.clone()), None);
+        assert_eq!(
+            registry.set_playing(&user, Some(game_id)),
+            Some(Presence::Playing(game_id))
+        );
+        assert_eq!(registry.set_playing(&user, Some(game_id)), None);
+        assert_eq!(registry.disconnect(&user, 1), None);
+        assert_eq!(registry.senders(&user).len(), 1);
+
+        // the last connection takes the game along
+        assert_eq!(registry.disconnect(&user, 2), Some(Presence::Offline));
+        assert_eq!(registry.connect(user, 3, sender), Some(Presence::Online));
+        assert_eq!(registry.disconnect(&user, 3), Some(Presence::Offline));
+        assert!(registry.users.is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+//! Skill ratings, one per user and match type. They change together with the
+//! match result, in one transaction, and every change is kept as history.
+
+use crate::database::tables::*;
+use game::api::game_match::{GameMatchType, UserAndMatchId, UserAndMatchResult};
+use game::rating::{Rating, RatingChange};
+use sled::transaction::UnabortableTransactionError;
+use typed_sled::transaction::TransactionalTree;
+
+/// Rates everyone in a finished match. Each player wins against everyone
+/// with a lower podium position and draws with equal ones.
+pub fn rate_players(before: &[Rating], podium: &[u32], now: i64) -> Vec<Rating> {
+    (0..before.len())
+        .map(|i| {
+            let results: Vec<_> = (0..before.len())
+                .filter(|j| *j != i)
+                .map(|j| {
+                    let score = match podium[i].cmp(&podium[j]) {
+                        std::cmp::Ordering::Less => 1.0,
+                        std::cmp::Ordering::Equal => 0.5,
+                        std::cmp::Ordering::Greater => 0.0,
+                    };
+                    (before[j].decayed(now), score)
+                })
+                .collect();
+            before[i].update(&results, now)
+        })
+        .collect()
+}
+
+/// Writes the players' match results and their new ratings, as part of the
+/// transaction that finishes the match.
+pub fn record_match_result(
+    results_db: &TransactionalTree<UserAndMatchId, UserAndMatchResult>,
+    rating_db: &TransactionalTree<(uuid::Uuid, GameMatchType), Rating>,
+    history_db: &TransactionalTree<UserAndMatchId, RatingChange>,
+    match_id: uuid::Uuid,
+    match_type: &GameMatchType,
+    results: &[(uuid::Uuid, UserAndMatchResult)],
+    now: i64,
+) -> Result<(), UnabortableTransactionError> {
+    let mut before = vec![];
+    for (user_id, _) in results {
+        let rating = rating_db.get(&(*user_id, match_type.clone()))?;
+        before.push(rating.unwrap_or_default());
+    }
+    let podium: Vec<_> = results.iter().map(|r| r.1.podium_position).collect();
+    let after = rate_players(&before, &podium, now);
+
+    for (i, (user_id, result)) in results.iter().enumerate() {
+        let key = UserAndMatchId {
+            user_id: *user_id,
+            match_id,
+        };
+        results_db.insert(&key, result)?;
+        rating_db.insert(&(*user_id, match_type.clone()), &after[i])?;
+        let change = RatingChange {
+            match_type: match_type.clone(),
+            time: now,
+            before: before[i],
+            after: after[i],
+        };
+        history_db.insert(&key, &change)?;
+    }
+    Ok(())
+}
+
+pub fn get_user_rating(
+    user_id: uuid::Uuid,
+    match_type: GameMatchType,
+    now: i64,
+) -> anyhow::Result<Rating> {
+    let rating = RATING_DB.get(&(user_id, match_type))?.unwrap_or_default();
+    Ok(rating.decayed(now))
+}
+
+/// Rating changes of a user in one match type, oldest first.
+pub fn get_user_rating_history(
+    user_id: uuid::Uuid,
+    match_type: GameMatchType,
+) -> anyhow::Result<Vec<(uuid::Uuid, RatingChange)>> {
+    let mut v = vec![];
+    for x in RATING_HISTORY_DB.range(UserAndMatchId::get_range_for_user(&user_id)) {
+        let (key, change) = x?;
+        if change.match_type == match_type {
+            v.push((key.match_id, change));
+        }
+    }
+    v.sort_by_key(|x| x.1.time);
+    Ok(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn winner_gains_what_loser_drops() {
+        let before = [Rating::default(), Rating::default()];
+        let after = rate_players(&before, &[2, 1], 5);
+        assert!(after[1].rating > 1500.0);
+        assert!((after[0].rating - 1500.0 + after[1].rating - 1500.0).abs() < 0.001);
+        assert_eq!(after[0].last_played, 5);
+        assert_eq!(after[1].games, 1);
+    }
+
+    #[test]
+    fn podium_ranks_everyone() {
+        let before = [Rating::default(); 3];
+        let after = rate_players(&before, &[3, 1, 2], 0);
+        assert!(after[1].rating > after[2].rating);
+        assert!(after[2].rating > after[0].rating);
+        assert_eq!(after[2].games, 2);
+    }
+}
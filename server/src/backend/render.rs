@@ -0,0 +1,415 @@
+//! Draws game states to PNG images and animated GIFs, without a browser.
+//!
+//! Every frame shows hold, main board and next queue side by side, with the
+//! piece colors from the client's `style.rs`. Frames are drawn straight into
+//! a palette, so both formats are written as indexed images; custom pieces
+//! bring their own colors, which each frame appends to the fixed ones.
+
+use anyhow::Context;
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use game::{
+    api::game_replay::GameId,
+    rules::LockedVisibility,
+    tet::{BoardMatrix, CellValue, GameReplaySegment, GameState, Tet},
+};
+
+use super::server_fn::load_game_segments;
+use crate::database::tables::{GAME_FULL_DB, GAME_MATCH_DB};
+
+const VISIBLE_ROWS: usize = 20;
+const SIDE_COLS: usize = 4;
+const MAIN_COLS: usize = 10;
+/// hold, gap, main board, gap, next
+const FRAME_COLS: usize = SIDE_COLS + 1 + MAIN_COLS + 1 + SIDE_COLS;
+const DEFAULT_CELL_PX: usize = 16;
+
+const BACKGROUND: u8 = 0;
+const EMPTY: u8 = 1;
+const GHOST: u8 = 2;
+const GARBAGE: u8 = 3;
+const GRID: u8 = 4;
+/// Custom piece colors follow at `CUSTOM_BASE`.
+const PALETTE: [[u8; 3]; 12] = [
+    [0x80, 0x80, 0x80], // background: gray
+    [0x00, 0x00, 0x00], // empty: black
+    [0x55, 0x55, 0x55], // ghost: #555
+    [0xb0, 0xb0, 0xb0], // garbage
+    [0x40, 0x40, 0x40], // grid lines
+    [0x74, 0xC2, 0x1D], // S
+    [0xFF, 0x4A, 0x58], // Z
+    [0xDA, 0x5D, 0xB2], // T
+    [0xFF, 0xC1, 0x25], // O
+    [0x21, 0xB6, 0xF8], // I
+    [0x41, 0x69, 0xE7], // J
+    [0xFF, 0x87, 0x20], // L
+];
+
+/// Palette index of a standard piece; custom pieces go through
+/// `Frame::custom_color`.
+fn tet_color(tet: Tet) -> u8 {
+    match tet {
+        Tet::S => 5,
+        Tet::Z => 6,
+        Tet::T => 7,
+        Tet::O => 8,
+        Tet::I => 9,
+        Tet::J => 10,
+        Tet::L => 11,
+        Tet::Custom(_) => GARBAGE,
+    }
+}
+
+const CUSTOM_BASE: u8 = PALETTE.len() as u8;
+
+/// Palette indices, row-major, top row first.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    /// Colors of custom pieces, at `CUSTOM_BASE` onwards in the palette.
+    pub custom: Vec<[u8; 3]>,
+}
+
+impl Frame {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![BACKGROUND; width * height],
+            custom: vec![],
+        }
+    }
+
+    fn palette_bytes(&self) -> Vec<u8> {
+        PALETTE
+            .iter()
+            .chain(&self.custom)
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Palette index for a custom piece color, added on first use. Past the
+    /// 256 colors a palette holds, pieces are drawn like garbage.
+    fn custom_color(&mut self, rgb: [u8; 3]) -> u8 {
+        if let Some(i) = self.custom.iter().position(|c| *c == rgb) {
+            return CUSTOM_BASE + i as u8;
+        }
+        if CUSTOM_BASE as usize + self.custom.len() > u8::MAX as usize {
+            return GARBAGE;
+        }
+        self.custom.push(rgb);
+        CUSTOM_BASE + (self.custom.len() - 1) as u8
+    }
+
+    fn cell_color(&mut self, cell: CellValue) -> u8 {
+        match cell {
+            CellValue::Piece(Tet::Custom(p)) => self.custom_color(p.color),
+            CellValue::Piece(tet) => tet_color(tet),
+            CellValue::Garbage => GARBAGE,
+            CellValue::Empty => EMPTY,
+            CellValue::Ghost => GHOST,
+        }
+    }
+
+    fn fill_cell(&mut self, col: usize, row: usize, cell_px: usize, color: u8) {
+        for dy in 0..cell_px {
+            for dx in 0..cell_px {
+                let edge = dx == 0 || dy == 0;
+                let px = if edge && cell_px > 2 { GRID } else { color };
+                let (x, y) = (col * cell_px + dx, row * cell_px + dy);
+                self.pixels[y * self.width + x] = px;
+            }
+        }
+    }
+
+    fn draw_board<const R: usize, const C: usize>(
+        &mut self,
+        board: &BoardMatrix<R, C>,
+        left_col: usize,
+        rows: usize,
+        cell_px: usize,
+    ) {
+        for y in 0..rows.min(R) {
+            for x in 0..C {
+                let row = rows - 1 - y;
+                let color = self.cell_color(board.v[y][x]);
+                self.fill_cell(left_col + x, row, cell_px, color);
+            }
+        }
+    }
+
+    /// Frames are placed left to right, top aligned.
+    pub fn side_by_side(frames: &[Frame]) -> Self {
+        let width = frames.iter().map(|f| f.width).sum();
+        let height = frames.iter().map(|f| f.height).max().unwrap_or(0);
+        let mut out = Self::new(width, height);
+        let mut left = 0;
+        for f in frames {
+            let custom: Vec<u8> =
+                f.custom.iter().map(|c| out.custom_color(*c)).collect();
+            for y in 0..f.height {
+                for x in 0..f.width {
+                    let px = f.pixels[y * f.width + x];
+                    out.pixels[y * width + left + x] = match px.checked_sub(CUSTOM_BASE)
+                    {
+                        Some(i) => custom[i as usize],
+                        None => px,
+                    };
+                }
+            }
+            left += f.width;
+        }
+        out
+    }
+}
+
+/// The main board as the player saw it. Fading cells count as hidden: they
+/// are gone long before anyone looks at a thumbnail.
+fn visible_board(state: &GameState) -> BoardMatrix {
+    let mut board = state.get_display_board();
+    if state.replay.rules.modifiers.locked_pieces == LockedVisibility::Visible {
+        return board;
+    }
+    let current = state.current_piece_cells();
+    for (y, row) in board.v.iter_mut().enumerate() {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let locked = matches!(cell, CellValue::Piece(_) | CellValue::Garbage);
+            if locked && !current.contains(&(y, x)) {
+                *cell = CellValue::Empty;
+            }
+        }
+    }
+    board
+}
+
+pub fn render_state(state: &GameState, cell_px: usize) -> Frame {
+    let mut frame = Frame::new(FRAME_COLS * cell_px, VISIBLE_ROWS * cell_px);
+    // in set order, so every frame of a game gets the same palette
+    for tet in state.replay.rules.pieces.tets() {
+        if let Tet::Custom(p) = tet {
+            frame.custom_color(p.color);
+        }
+    }
+    let hold = state.get_hold_board();
+    frame.draw_board(&hold, 0, hold.get_num_rows(), cell_px);
+    frame.draw_board(&visible_board(state), SIDE_COLS + 1, VISIBLE_ROWS, cell_px);
+    let next = state.get_next_board();
+    frame.draw_board(
+        &next,
+        SIDE_COLS + 1 + MAIN_COLS + 1,
+        next.get_num_rows(),
+        cell_px,
+    );
+    frame
+}
+
+pub fn encode_png(frame: &Frame) -> anyhow::Result<Vec<u8>> {
+    let mut out = vec![];
+    let mut encoder =
+        png::Encoder::new(&mut out, frame.width as u32, frame.height as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(frame.palette_bytes());
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&frame.pixels)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// `delay_ms` is rounded to the 10ms steps that GIF supports.
+pub fn encode_gif(frames: &[Frame], delay_ms: u32) -> anyhow::Result<Vec<u8>> {
+    let first = frames.first().context("no frames to encode")?;
+    let mut out = vec![];
+    {
+        let mut encoder = gif::Encoder::new(
+            &mut out,
+            first.width as u16,
+            first.height as u16,
+            &first.palette_bytes(),
+        )?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        for f in frames {
+            let mut gif_frame = gif::Frame::from_indexed_pixels(
+                f.width as u16,
+                f.height as u16,
+                f.pixels.clone(),
+                None,
+            );
+            if f.custom != first.custom {
+                gif_frame.palette = Some(f.palette_bytes());
+            }
+            gif_frame.delay = (delay_ms / 10).max(1) as u16;
+            encoder.write_frame(&gif_frame)?;
+        }
+    }
+    Ok(out)
+}
+
+/// Same simulation the replay page runs: one state per segment.
+pub fn replay_states(segments: &[GameReplaySegment]) -> anyhow::Result<Vec<GameState>> {
+    let mut current_state = match segments.first() {
+        Some(GameReplaySegment::Init(replay)) => GameState::new_with_rules(
+            &replay.init_seed,
+            replay.start_time,
+            &replay.rules,
+        ),
+        _ => anyhow::bail!("got no init segment"),
+    };
+    let mut all_states = vec![current_state.clone()];
+    for segment in &segments[1..] {
+        match segment {
+            GameReplaySegment::Init(_) => anyhow::bail!("got two init segments"),
+            GameReplaySegment::Update(slice) => {
+                current_state.accept_replay_slice(slice)?;
+            }
+            GameReplaySegment::GameOver => {
+                current_state.game_over = true;
+            }
+        }
+        all_states.push(current_state.clone());
+    }
+    Ok(all_states)
+}
+
+/// Final board of a game, as stored after its last segment.
+pub fn game_thumbnail_png(game_id: &GameId) -> anyhow::Result<Vec<u8>> {
+    let state = GAME_FULL_DB.get(game_id)?.context("game not found")?;
+    encode_png(&render_state(&state, DEFAULT_CELL_PX / 2))
+}
+
+/// Final boards of all players of a match, side by side.
+pub fn match_thumbnail_png(match_id: &uuid::Uuid) -> anyhow::Result<Vec<u8>> {
+    let game_match = GAME_MATCH_DB.get(match_id)?.context("match not found")?;
+    let mut frames = vec![];
+    for user_id in game_match.users {
+        let game_id = GameId {
+            user_id,
+            init_seed: game_match.seed,
+            start_time: game_match.time,
+        };
+        let state = GAME_FULL_DB.get(&game_id)?.unwrap_or_else(GameState::empty);
+        frames.push(render_state(&state, DEFAULT_CELL_PX / 2));
+    }
+    encode_png(&Frame::side_by_side(&frames))
+}
+
+fn png_response(png: anyhow::Result<Vec<u8>>) -> Response {
+    match png {
+        Ok(bytes) => ([(header::CONTENT_TYPE, "image/png")], bytes).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, format!("{e:#}")).into_response(),
+    }
+}
+
+pub async fn game_thumbnail_handler(Path(game_url): Path<String>) -> Response {
+    png_response(GameId::from_url(game_url).and_then(|id| game_thumbnail_png(&id)))
+}
+
+pub async fn match_thumbnail_handler(Path(match_id): Path<uuid::Uuid>) -> Response {
+    png_response(match_thumbnail_png(&match_id))
+}
+
+const RENDER_USAGE: &str = "usage: server render <game-url | replay.json> <out.gif | out.png | out-dir> [delay-ms]";
+
+/// Command line entry point: `server render <game> <output> [delay-ms]`.
+///
+/// The game is either the hex id from a `/view-game/` url, read from the
+/// server database, or a json file holding a list of replay segments. Output
+/// ending in `.gif` is an animation, `.png` is the final frame and anything
+/// else is a directory that receives one png per frame.
+pub fn render_command(args: &[String]) -> anyhow::Result<()> {
+    let source = args.first().context(RENDER_USAGE)?;
+    let output = args.get(1).context(RENDER_USAGE)?;
+    let delay_ms: u32 = match args.get(2) {
+        Some(d) => d.parse().context("bad delay-ms")?,
+        None => 100,
+    };
+
+    let segments = if source.ends_with(".json") {
+        let text = std::fs::read_to_string(source)?;
+        serde_json::from_str(&text)
+            .context("replay file must be a json list of segments")?
+    } else {
+        crate::database::migrate::migrate_tables()?;
+        load_game_segments(&GameId::from_url(source.clone())?)?
+    };
+    let states = replay_states(&segments)?;
+    let frames: Vec<_> = states
+        .iter()
+        .map(|s| render_state(s, DEFAULT_CELL_PX))
+        .collect();
+
+    if output.ends_with(".gif") {
+        std::fs::write(output, encode_gif(&frames, delay_ms)?)?;
+    } else if output.ends_with(".png") {
+        let last = frames.last().context("no frames")?;
+        std::fs::write(output, encode_png(last)?)?;
+    } else {
+        std::fs::create_dir_all(output)?;
+        for (i, frame) in frames.iter().enumerate() {
+            std::fs::write(format!("{output}/frame_{i:05}.png"), encode_png(frame)?)?;
+        }
+    }
+    log::info!("rendered {} frames to {output}", frames.len());
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn render_places_cells_in_palette() {
+        let state = GameState::from_ascii(
+            "
+            hold: I
+            next: SZ
+            current: T
+            #.........
+            ",
+        )
+        .unwrap();
+        let frame = render_state(&state, 1);
+        assert_eq!(frame.width, FRAME_COLS);
+        assert_eq!(frame.height, VISIBLE_ROWS);
+        let px = |x: usize, y: usize| frame.pixels[y * frame.width + x];
+        assert_eq!(px(SIDE_COLS + 1, VISIBLE_ROWS - 1), GARBAGE);
+        assert_eq!(px(SIDE_COLS + 2, VISIBLE_ROWS - 1), EMPTY);
+        assert_eq!(px(SIDE_COLS, 0), BACKGROUND);
+        assert!(frame.pixels.contains(&tet_color(Tet::I)));
+        assert!(frame.pixels.contains(&tet_color(Tet::Z)));
+
+        let png = encode_png(&frame).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        let gif = encode_gif(&[frame.clone(), frame], 50).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+    }
+
+    #[test]
+    pub fn render_gives_custom_pieces_their_colors() {
+        let mut rules = game::rules::GameRules::standard();
+        rules.pieces = game::pieces::PieceSet::pentominoes();
+        let state = GameState::new_with_rules(&[3; 32], 0, &rules);
+        let frame = render_state(&state, 1);
+        assert_eq!(frame.custom.len(), 12);
+        let Some(Tet::Custom(next)) = state.next_pcs.front().cloned() else {
+            panic!("no custom piece up next");
+        };
+        let index = frame.custom.iter().position(|c| *c == next.color).unwrap();
+        assert!(frame.pixels.contains(&(CUSTOM_BASE + index as u8)));
+
+        // the other side has its colors in another order
+        let mut other = Frame::new(1, 1);
+        other.custom_color([1, 2, 3]);
+        other.pixels[0] = other.custom_color(next.color);
+        let both = Frame::side_by_side(&[frame.clone(), other]);
+        let last = both.pixels[both.width - 1];
+        assert_eq!(both.custom[(last - CUSTOM_BASE) as usize], next.color);
+        encode_gif(&[frame, both], 50).unwrap();
+    }
+}
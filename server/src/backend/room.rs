@@ -0,0 +1,258 @@
+//! Private rooms: a host shares a short code, players ready up, and the room
+//! starts a series of matches with the host's rules once a countdown is over.
+
+use crate::backend::series::start_series;
+use crate::database::tables::*;
+use anyhow::Context;
+use game::api::room::{
+    RoomInfo, RoomPlayer, RoomSettings, ROOM_CODE_CHARS, ROOM_CODE_LEN,
+    ROOM_COUNTDOWN_NS,
+};
+use game::api::user::GuestInfo;
+use game::timestamp::get_timestamp_now_nano;
+use once_cell::sync::Lazy;
+use rand::Rng;
+
+/// Every read-modify-write of `ROOM_DB` happens under this lock.
+static ROOM_LOCK: Lazy<std::sync::Mutex<()>> = Lazy::new(|| std::sync::Mutex::new(()));
+
+fn new_room_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..ROOM_CODE_LEN)
+        .map(|_| ROOM_CODE_CHARS[rng.gen_range(0..ROOM_CODE_CHARS.len())] as char)
+        .collect()
+}
+
+pub fn create_room(
+    settings: RoomSettings,
+    current_user_id: GuestInfo,
+) -> anyhow::Result<RoomInfo> {
+    settings.validate()?;
+    let _lock = ROOM_LOCK.lock().unwrap();
+    let mut code = new_room_code();
+    while ROOM_DB.get(&code)?.is_some() {
+        code = new_room_code();
+    }
+    let room = RoomInfo {
+        code: code.clone(),
+        host: current_user_id.user_id,
+        settings,
+        players: vec![RoomPlayer {
+            user_id: current_user_id.user_id,
+            ready: false,
+        }],
+        spectators: vec![],
+        matches: vec![],
+        created: get_timestamp_now_nano(),
+        starts_at: None,
+    };
+    ROOM_DB.insert(&code, &room)?;
+    Ok(room)
+}
+
+pub fn get_room(code: String, _current_user_id: GuestInfo) -> anyhow::Result<RoomInfo> {
+    ROOM_DB.get(&code)?.context("room not found")
+}
+
+/// Joins as a player if `as_player` and a seat is free, as spectator
+/// otherwise. Joining again keeps the seat.
+pub fn join_room(
+    arg: (String, bool),
+    current_user_id: GuestInfo,
+) -> anyhow::Result<RoomInfo> {
+    let (code, as_player) = arg;
+    update_room(&code, |room| {
+        add_member(room, current_user_id.user_id, as_player);
+        Ok(())
+    })
+}
+
+pub fn leave_room(
+    code: String,
+    current_user_id: GuestInfo,
+) -> anyhow::Result<RoomInfo> {
+    update_room(&code, |room| {
+        remove_member(room, &current_user_id.user_id);
+        Ok(())
+    })
+}
+
+pub fn set_room_settings(
+    arg: (String, RoomSettings),
+    current_user_id: GuestInfo,
+) -> anyhow::Result<RoomInfo> {
+    let (code, settings) = arg;
+    settings.validate()?;
+    update_room(&code, |room| {
+        if room.host != current_user_id.user_id {
+            anyhow::bail!("only the host can change the rules");
+        }
+        room.settings = settings.clone();
+        // everyone has to agree to the new rules
+        for player in room.players.iter_mut() {
+            player.ready = false;
+        }
+        Ok(())
+    })
+}
+
+/// Once enough seats are taken and everyone is ready, the countdown to the
+/// room's next match starts.
+pub fn set_room_ready(
+    arg: (String, bool),
+    current_user_id: GuestInfo,
+) -> anyhow::Result<RoomInfo> {
+    let (code, ready) = arg;
+    update_room(&code, |room| {
+        let player = room
+            .players
+            .iter_mut()
+            .find(|p| p.user_id == current_user_id.user_id)
+            .context("only players can ready up")?;
+        player.ready = ready;
+        if !room.all_ready() {
+            return Ok(());
+        }
+        check_last_match_over(room)?;
+        let now = get_timestamp_now_nano();
+        // a countdown from before a restart never ends
+        if room.starts_at.is_none_or(|t| t < now) {
+            let starts_at = now + ROOM_COUNTDOWN_NS;
+            room.starts_at = Some(starts_at);
+            spawn_countdown(room.code.clone(), starts_at);
+        }
+        Ok(())
+    })
+}
+
+fn check_last_match_over(room: &RoomInfo) -> anyhow::Result<()> {
+    if let Some(last) = room.matches.last() {
+        if GAME_MATCH_IS_IN_PROGRESS_DB.get(last)?.unwrap_or(false) {
+            anyhow::bail!("the last match is not over yet");
+        }
+    }
+    Ok(())
+}
+
+fn spawn_countdown(code: String, starts_at: i64) {
+    let wait = (starts_at - get_timestamp_now_nano()).max(0) as u64;
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_nanos(wait)).await;
+        let started =
+            tokio::task::spawn_blocking(move || start_room_match(&code, starts_at))
+                .await;
+        if let Err(e) = started.map_err(anyhow::Error::from).and_then(|r| r) {
+            log::warn!("room countdown failed: {e:?}");
+        }
+    });
+}
+
+/// Starts the match the countdown ending at `starts_at` was for, unless
+/// someone left or stopped being ready in the meantime.
+fn start_room_match(code: &str, starts_at: i64) -> anyhow::Result<()> {
+    update_room(code, |room| {
+        if room.starts_at != Some(starts_at) {
+            return Ok(());
+        }
+        room.starts_at = None;
+        check_last_match_over(room)?;
+        let users = room.players.iter().map(|p| p.user_id).collect();
+        let title = format!("room {}", room.code);
+        let (match_id, _) = start_series(users, title, &room.settings)?;
+        room.matches.push(match_id);
+        for player in room.players.iter_mut() {
+            player.ready = false;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+fn update_room(
+    code: &str,
+    f: impl FnOnce(&mut RoomInfo) -> anyhow::Result<()>,
+) -> anyhow::Result<RoomInfo> {
+    let _lock = ROOM_LOCK.lock().unwrap();
+    let code = code.to_string();
+    let mut room = ROOM_DB.get(&code)?.context("room not found")?;
+    f(&mut room)?;
+    if !room.all_ready() {
+        room.starts_at = None;
+    }
+    ROOM_DB.insert(&code, &room)?;
+    Ok(room)
+}
+
+fn add_member(room: &mut RoomInfo, user_id: uuid::Uuid, as_player: bool) {
+    if room.is_player(&user_id) {
+        return;
+    }
+    if as_player && room.players.len() < room.seats() {
+        room.spectators.retain(|s| *s != user_id);
+        room.players.push(RoomPlayer {
+            user_id,
+            ready: false,
+        });
+    } else if !room.spectators.contains(&user_id) {
+        room.spectators.push(user_id);
+    }
+}
+
+fn remove_member(room: &mut RoomInfo, user_id: &uuid::Uuid) {
+    room.players.retain(|p| p.user_id != *user_id);
+    room.spectators.retain(|s| s != user_id);
+    if room.host == *user_id {
+        if let Some(next) = room.players.first() {
+            room.host = next.user_id;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(host: uuid::Uuid) -> RoomInfo {
+        RoomInfo {
+            code: new_room_code(),
+            host,
+            settings: RoomSettings::default(),
+            players: vec![RoomPlayer {
+                user_id: host,
+                ready: false,
+            }],
+            spectators: vec![],
+            matches: vec![],
+            created: 0,
+            starts_at: None,
+        }
+    }
+
+    #[test]
+    fn codes_use_the_room_alphabet() {
+        let code = new_room_code();
+        assert_eq!(code.len(), ROOM_CODE_LEN);
+        assert!(code.bytes().all(|c| ROOM_CODE_CHARS.contains(&c)));
+    }
+
+    #[test]
+    fn extra_players_spectate() {
+        let [a, b, c] = [(); 3].map(|_| uuid::Uuid::new_v4());
+        let mut r = room(a);
+        add_member(&mut r, b, true);
+        add_member(&mut r, c, true);
+        assert_eq!(r.players.len(), r.seats());
+        assert_eq!(r.spectators, vec![c]);
+
+        // rejoining after a reconnect changes nothing
+        add_member(&mut r, b, false);
+        assert!(r.is_player(&b));
+        assert_eq!(r.spectators, vec![c]);
+
+        remove_member(&mut r, &a);
+        assert_eq!(r.host, b);
+        add_member(&mut r, c, true);
+        assert!(r.is_player(&c));
+        assert!(r.spectators.is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+//! Series: the same players keep playing matches until one of them has won
+//! `first_to`. After each match the next one starts once every player has
+//! accepted the rematch; a rematch after a decided series starts a new one.
+
+use crate::backend::matchmaking::create_match;
+use crate::database::tables::*;
+use anyhow::Context;
+use game::api::game_match::{GameMatch, GameSeries};
+use game::api::room::RoomSettings;
+use game::api::user::GuestInfo;
+use once_cell::sync::Lazy;
+use sled::transaction::UnabortableTransactionError;
+use typed_sled::transaction::TransactionalTree;
+
+/// Every read-modify-write of `SERIES_DB` happens under this lock.
+pub static SERIES_LOCK: Lazy<std::sync::Mutex<()>> =
+    Lazy::new(|| std::sync::Mutex::new(()));
+
+/// What a rematch request leads to.
+#[derive(Debug, PartialEq, Eq)]
+enum RematchStep {
+    Wait,
+    NextMatch,
+    NewSeries,
+}
+
+/// Starts a series and its first match.
+pub fn start_series(
+    users: Vec<uuid::Uuid>,
+    title: String,
+    settings: &RoomSettings,
+) -> anyhow::Result<(uuid::Uuid, GameMatch)> {
+    let _lock = SERIES_LOCK.lock().unwrap();
+    let (_, match_id, match_info) = new_series(users, title, settings)?;
+    Ok((match_id, match_info))
+}
+
+/// Call with `SERIES_LOCK` held.
+fn new_series(
+    users: Vec<uuid::Uuid>,
+    title: String,
+    settings: &RoomSettings,
+) -> anyhow::Result<(uuid::Uuid, uuid::Uuid, GameMatch)> {
+    let series_id = uuid::Uuid::new_v4();
+    let mut series = GameSeries::new(title, users, settings.clone());
+    let (match_id, match_info) = next_match(&series_id, &mut series)?;
+    SERIES_DB.insert(&series_id, &series)?;
+    Ok((series_id, match_id, match_info))
+}
+
+/// Call with `SERIES_LOCK` held; the caller saves `series`.
+fn next_match(
+    series_id: &uuid::Uuid,
+    series: &mut GameSeries,
+) -> anyhow::Result<(uuid::Uuid, GameMatch)> {
+    let title = match series.matches.len() {
+        0 => series.title.clone(),
+        n => format!("{} (game {})", series.title, n + 1),
+    };
+    let (match_id, match_info) = create_match(
+        &series.settings.match_type,
+        series.users.clone(),
+        title,
+        &series.settings,
+    )?;
+    SERIES_FOR_MATCH_DB.insert(&match_id, series_id)?;
+    series.matches.push(match_id);
+    Ok((match_id, match_info))
+}
+
+/// Counts the win of a finished match towards its series, as part of the
+/// transaction that finishes the match. Call with `SERIES_LOCK` held.
+pub fn record_series_win(
+    series_db: &TransactionalTree<uuid::Uuid, GameSeries>,
+    series_id: &uuid::Uuid,
+    winners: &[uuid::Uuid],
+) -> Result<(), UnabortableTransactionError> {
+    let Some(mut series) = series_db.get(series_id)? else {
+        return Ok(());
+    };
+    for winner in winners {
+        add_win(&mut series, winner);
+    }
+    series_db.insert(series_id, &series)?;
+    Ok(())
+}
+
+pub fn get_match_series(
+    match_id: uuid::Uuid,
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<Option<(uuid::Uuid, GameSeries)>> {
+    let Some(series_id) = SERIES_FOR_MATCH_DB.get(&match_id)? else {
+        return Ok(None);
+    };
+    let series = SERIES_DB.get(&series_id)?.context("series not found")?;
+    Ok(Some((series_id, series)))
+}
+
+pub fn accept_rematch(
+    match_id: uuid::Uuid,
+    current_user_id: GuestInfo,
+) -> anyhow::Result<GameSeries> {
+    let _lock = SERIES_LOCK.lock().unwrap();
+    let series_id = SERIES_FOR_MATCH_DB
+        .get(&match_id)?
+        .context("match is not part of a series")?;
+    let mut series = SERIES_DB.get(&series_id)?.context("series not found")?;
+    if !series.users.contains(&current_user_id.user_id) {
+        anyhow::bail!("only players can ask for a rematch");
+    }
+    // the rematch of this match already started
+    if series.matches.last() != Some(&match_id) || series.rematch_match.is_some() {
+        return Ok(series);
+    }
+    if GAME_MATCH_IS_IN_PROGRESS_DB
+        .get(&match_id)?
+        .unwrap_or(false)
+    {
+        anyhow::bail!("the match is not over yet");
+    }
+    match add_rematch(&mut series, current_user_id.user_id) {
+        RematchStep::Wait => {}
+        RematchStep::NextMatch => {
+            next_match(&series_id, &mut series)?;
+        }
+        RematchStep::NewSeries => {
+            let (_, next_id, _) = new_series(
+                series.users.clone(),
+                series.title.clone(),
+                &series.settings,
+            )?;
+            series.rematch_match = Some(next_id);
+        }
+    }
+    SERIES_DB.insert(&series_id, &series)?;
+    Ok(series)
+}
+
+fn add_win(series: &mut GameSeries, winner: &uuid::Uuid) {
+    if let Some(i) = series.users.iter().position(|u| u == winner) {
+        series.wins[i] += 1;
+    }
+}
+
+fn add_rematch(series: &mut GameSeries, user_id: uuid::Uuid) -> RematchStep {
+    if !series.rematch.contains(&user_id) {
+        series.rematch.push(user_id);
+    }
+    if series.users.iter().any(|u| !series.rematch.contains(u)) {
+        return RematchStep::Wait;
+    }
+    series.rematch.clear();
+    if series.winner().is_some() {
+        RematchStep::NewSeries
+    } else {
+        RematchStep::NextMatch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_ends_at_first_to() {
+        let [a, b] = [(); 2].map(|_| uuid::Uuid::new_v4());
+        let mut series =
+            GameSeries::new("s".to_string(), vec![a, b], RoomSettings::default());
+        assert_eq!(series.first_to, 2);
+
+        add_win(&mut series, &a);
+        assert_eq!(add_rematch(&mut series, b), RematchStep::Wait);
+        // asking twice does not count for the other player
+        assert_eq!(add_rematch(&mut series, b), RematchStep::Wait);
+        assert_eq!(add_rematch(&mut series, a), RematchStep::NextMatch);
+        assert!(series.rematch.is_empty());
+
+        add_win(&mut series, &b);
+        assert_eq!(series.winner(), None);
+        add_win(&mut series, &b);
+        assert_eq!(series.winner(), Some(b));
+        assert_eq!((series.wins_of(&a), series.wins_of(&b)), (1, 2));
+
+        assert_eq!(add_rematch(&mut series, a), RematchStep::Wait);
+        assert_eq!(add_rematch(&mut series, b), RematchStep::NewSeries);
+    }
+}
@@ -1,19 +1,41 @@
+use crate::backend::match_coordinator::*;
+use crate::backend::rating::*;
+use crate::backend::leaderboard::{check_world_record, game_summary};
+use crate::backend::presence::set_playing;
 use crate::backend::server_info::GIT_VERSION;
+use crate::backend::stats::add_game_stats;
+use crate::database::index::{page, IndexGroup};
 use crate::database::tables::*;
 
 use anyhow::Context;
+use game::api::game_match::CoopGameInfo;
 use game::api::game_match::GameMatch;
+use game::api::game_match::GameMatchResult;
 use game::api::game_match::GameMatchType;
+use game::api::game_match::UserAndMatchId;
+use game::api::game_match::UserAndMatchResult;
 use game::api::game_replay::GameId;
 use game::api::game_replay::GameSegmentId;
+use game::api::page::Page;
+use game::api::page::PageRequest;
 use game::api::user::GuestInfo;
 use game::api::user::UserProfile;
+use game::api::user::{display_name_key, ProfileUpdate};
 use game::api::websocket::GameSegmentCountReply;
 use game::api::websocket::GetMatchListArg;
+use game::coop::CoopReplaySlice;
+use game::coop::CoopState;
+use game::rating::Rating;
+use game::rating::RatingChange;
+use game::rules::GameRules;
 use game::tet::GameReplaySegment;
 use game::tet::GameState;
+use game::tet::TetAction;
+use game::tet::UndoMode;
 use game::timestamp::get_timestamp_now_nano;
 use rand::Rng;
+use sled::transaction::{abort, ConflictableTransactionError};
+use typed_sled::transaction::{flatten, Transactional};
 
 pub fn get_profile(
     user_id: uuid::Uuid,
@@ -23,20 +45,55 @@ pub fn get_profile(
     get_user_profile(&user_id)
 }
 
+pub fn update_profile(
+    update: ProfileUpdate,
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<UserProfile> {
+    update.validate()?;
+    let user_id = _current_user_id.user_id;
+    let new_key = display_name_key(&update.display_name);
+    flatten((&*USER_PROFILE_DB, &*DISPLAY_NAME_DB).transaction(
+        |(profiles, names)| {
+            let Some(old) = profiles.get(&user_id)? else {
+                return abort(anyhow::anyhow!("user profile not found"));
+            };
+            if names.get(&new_key)?.is_some_and(|owner| owner != user_id) {
+                return abort(anyhow::anyhow!("display name is taken"));
+            }
+            let old_key = display_name_key(&old.display_name);
+            if old_key != new_key && names.get(&old_key)? == Some(user_id) {
+                names.remove(&old_key)?;
+            }
+            names.insert(&new_key, &user_id)?;
+            let new = UserProfile {
+                display_name: update.display_name.clone(),
+                avatar: update.avatar,
+                country: update.country.clone(),
+                bio: update.bio.clone(),
+                joined: old.joined,
+            };
+            profiles.insert(&user_id, &new)?;
+            Ok(new)
+        },
+    ))
+}
+
 pub fn git_version(_: (), _current_user_id: GuestInfo) -> anyhow::Result<String> {
     Ok(GIT_VERSION.clone())
 }
 
 pub fn create_new_game_id(
-    _: (),
+    rules: GameRules,
     _current_user_id: GuestInfo,
 ) -> anyhow::Result<GameId> {
+    rules.validate()?;
+    let mut old_games = vec![];
     for existing_game in GAME_IS_IN_PROGRESS_DB
         .range(GameId::get_range_for_user(&_current_user_id.user_id))
     {
         let (old_game_id, is_in_progress) = existing_game?;
         if is_in_progress {
-            GAME_IS_IN_PROGRESS_DB.insert(&old_game_id, &false)?;
+            old_games.push(old_game_id);
         }
     }
 
@@ -47,11 +104,29 @@ pub fn create_new_game_id(
         start_time: get_timestamp_now_nano(),
     };
 
-    GAME_IS_IN_PROGRESS_DB.insert(&g, &true)?;
-    GAME_SEGMENT_COUNT_DB.insert(&g, &0)?;
+    flatten(
+        (&*GAME_RULES_DB, &*GAME_IS_IN_PROGRESS_DB, &*GAME_SEGMENT_COUNT_DB).transaction(
+            |(rules_db, in_progress_db, count_db)| {
+                for old_game_id in &old_games {
+                    in_progress_db.insert(old_game_id, &false)?;
+                }
+                rules_db.insert(&g, &rules)?;
+                in_progress_db.insert(&g, &true)?;
+                count_db.insert(&g, &0)?;
+                Ok::<(), ConflictableTransactionError<anyhow::Error>>(())
+            },
+        ),
+    )?;
     Ok(g)
 }
 
+pub fn get_game_rules(
+    game_id: GameId,
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<GameRules> {
+    GAME_RULES_DB.get(&game_id)?.context("game rules not found")
+}
+
 pub fn append_game_segment(
     (id, segment_json): (GameId, String),
     _current_user_id: GuestInfo,
@@ -91,10 +166,17 @@ pub fn append_game_segment(
     };
 
     match &new_segment {
-        GameReplaySegment::Init(_) => {
+        GameReplaySegment::Init(replay) => {
             if existing_segment_count != 0 {
                 anyhow::bail!("only 1st segment should be init");
             }
+            if replay.undo_mode != UndoMode::Disabled {
+                anyhow::bail!("recorded games cannot use undo");
+            }
+            let rules = GAME_RULES_DB.get(&id)?.context("game rules not found")?;
+            if replay.rules != rules {
+                anyhow::bail!("init segment rules differ from the game's rules");
+            }
         }
         GameReplaySegment::Update(update_seg) => {
             let last_segment = last_segment.context("last segment not found")?;
@@ -117,36 +199,101 @@ pub fn append_game_segment(
             }
         }
         GameReplaySegment::GameOver => {
+            if let Some(GameReplaySegment::GameOver) = last_segment {
+                anyhow::bail!("game is already over");
+            }
             log::info!("append segment game over");
         }
     };
+    let takes_garbage = check_match_segment(&id, &new_segment)?;
+    let is_game_over = matches!(new_segment, GameReplaySegment::GameOver);
     let game_in_progress = match &new_segment {
         GameReplaySegment::Init(_) => true,
         GameReplaySegment::Update(_) => true,
         GameReplaySegment::GameOver => false,
     };
-    GAME_IS_IN_PROGRESS_DB.insert(&id, &game_in_progress)?;
-    GAME_SEGMENT_DB.insert(&new_segment_id, &new_segment)?;
-    GAME_SEGMENT_COUNT_DB.insert(&id, &(existing_segment_count + 1))?;
 
-    let new_game_state = match new_segment {
-        GameReplaySegment::Init(replay) => {
-            GameState::new(&replay.init_seed, replay.start_time)
-        }
+    let new_game_state = match &new_segment {
+        GameReplaySegment::Init(replay) => GameState::new_with_rules(
+            &replay.init_seed,
+            replay.start_time,
+            &replay.rules,
+        ),
         GameReplaySegment::Update(slice) => {
             let mut last_state = last_state.context("no last state found")?;
-            last_state.accept_replay_slice(&slice)?;
+            last_state.accept_replay_slice(slice)?;
             last_state
         }
         GameReplaySegment::GameOver => {
             let last_state = last_state.context("no last state found")?;
             if !last_state.game_over {
+                log::trace!(
+                    "game {:?} not over on server:\n{}",
+                    id,
+                    last_state.to_ascii()
+                );
                 anyhow::bail!("got game over but reconstructed state is not game over")
             }
+            log::trace!("game {:?} over:\n{}", id, last_state.to_ascii());
             last_state
         }
     };
-    GAME_FULL_DB.insert(&id, &new_game_state)?;
+    // the summary goes in with the game over segment; the segments it
+    // replays are the ones the count check below vouches for
+    let summary = if is_game_over {
+        let mut segments = load_game_segments(&id)?;
+        segments.push(new_segment.clone());
+        Some(game_summary(&id, &new_game_state, &segments)?)
+    } else {
+        None
+    };
+
+    // all of it or nothing, and only on top of the segment count checked
+    // above - a concurrent append of the same segment loses
+    flatten(
+        (
+            &*GAME_IS_IN_PROGRESS_DB,
+            &*GAME_SEGMENT_DB,
+            &*GAME_SEGMENT_COUNT_DB,
+            &*GAME_FULL_DB,
+            &*GAME_SUMMARY_DB,
+            &*USER_STATS_DB,
+            &*USER_DAILY_PACE_DB,
+        )
+            .transaction(
+                |(
+                    in_progress_db,
+                    segment_db,
+                    count_db,
+                    full_db,
+                    summary_db,
+                    stats_db,
+                    pace_db,
+                )| {
+                    if count_db.get(&id)? != Some(existing_segment_count) {
+                        return abort(anyhow::anyhow!(
+                            "another segment was appended first"
+                        ));
+                    }
+                    in_progress_db.insert(&id, &game_in_progress)?;
+                    segment_db.insert(&new_segment_id, &new_segment)?;
+                    count_db.insert(&id, &(existing_segment_count + 1))?;
+                    full_db.insert(&id, &new_game_state)?;
+                    if let Some(summary) = &summary {
+                        summary_db.insert(&id, summary)?;
+                        add_game_stats(&stats_db, &pace_db, summary)?;
+                    }
+                    Ok(())
+                },
+            ),
+    )?;
+    set_playing(&id.user_id, game_in_progress.then_some(id));
+    if takes_garbage {
+        mark_garbage_applied(&id)?;
+    }
+    if let Some(summary) = &summary {
+        check_world_record(summary)?;
+    }
 
     Ok(())
 }
@@ -162,11 +309,12 @@ pub fn get_all_segments_for_game(
     game_id: GameId,
     _current_user_id: GuestInfo,
 ) -> anyhow::Result<Vec<GameReplaySegment>> {
+    load_game_segments(&game_id)
+}
+
+pub fn load_game_segments(game_id: &GameId) -> anyhow::Result<Vec<GameReplaySegment>> {
     let mut r = vec![];
-    for item in GAME_SEGMENT_DB
-        .range(GameSegmentId::get_range_for_game(&game_id))
-        .into_iter()
-    {
+    for item in GAME_SEGMENT_DB.range(GameSegmentId::get_range_for_game(game_id)) {
         let (_segment_id, replay_segment) = item?;
         r.push(replay_segment);
     }
@@ -192,60 +340,37 @@ pub fn get_segment_count(
     })
 }
 use game::api::websocket::GetAllGamesArg;
-const PAGE_SIZE: usize = 9;
 
 pub fn get_all_games(
-    arg: GetAllGamesArg,
+    arg: (GetAllGamesArg, PageRequest),
     _current_user_id: GuestInfo,
-) -> anyhow::Result<Vec<(GameId, GameSegmentCountReply)>> {
-    let load_all_games = || -> anyhow::Result<_> {
-        let mut v = vec![];
-        for game_id in GAME_IS_IN_PROGRESS_DB.iter().keys() {
-            let game_id = game_id?;
-            let r = get_segment_count(game_id, _current_user_id.clone())?;
-            v.push((game_id, r));
-        }
-        Ok(v)
+) -> anyhow::Result<Page<(GameId, GameSegmentCountReply)>> {
+    let (list_type, req) = arg;
+    let me = _current_user_id.user_id;
+    let best = |group| -> anyhow::Result<_> {
+        let p = page(&GAME_SUMMARY_DB, GAMES_BY_SCORE, &group, &req)?;
+        Ok((p.items.into_iter().map(|x| x.0).collect::<Vec<_>>(), p.next))
     };
-    let load_games_for_user = |user: &uuid::Uuid| -> anyhow::Result<_> {
-        let mut v = vec![];
-        for game_id in GAME_IS_IN_PROGRESS_DB
-            .range(GameId::get_range_for_user(user))
-            .keys()
-        {
-            let game_id = game_id?;
-            let r = get_segment_count(game_id, _current_user_id.clone())?;
-            v.push((game_id, r));
-        }
-        Ok(v)
-    };
-    let sort_best = |mut v: Vec<(_, GameSegmentCountReply)>| -> anyhow::Result<_> {
-        v.sort_by_key(|x| -(x.1.segment_count as i32));
-        Ok(v)
-    };
-    let sort_recent = |mut v: Vec<(GameId, _)>| -> anyhow::Result<_> {
-        v.sort_by_key(|x| -((x.0.start_time / 100000) as i32));
-        Ok(v)
+    let recent = |group| -> anyhow::Result<_> {
+        let p = page(&GAME_RULES_DB, GAMES_BY_START_TIME, &group, &req)?;
+        Ok((p.items.into_iter().map(|x| x.0).collect::<Vec<_>>(), p.next))
     };
-
-    let mut v = match arg {
-        GetAllGamesArg::BestGames => sort_best(load_all_games()?)?,
-        GetAllGamesArg::RecentGames => sort_recent(load_all_games()?)?,
-        GetAllGamesArg::MyBestGames => {
-            sort_best(load_games_for_user(&_current_user_id.user_id)?)?
-        }
-        GetAllGamesArg::MyRecentGames => {
-            sort_recent(load_games_for_user(&_current_user_id.user_id)?)?
-        }
-        GetAllGamesArg::BestGamesForPlayer(player_id) => {
-            sort_best(load_games_for_user(&player_id)?)?
-        }
+    let (game_ids, next) = match list_type {
+        GetAllGamesArg::BestGames => best(IndexGroup::All)?,
+        GetAllGamesArg::RecentGames => recent(IndexGroup::All)?,
+        GetAllGamesArg::MyBestGames => best(IndexGroup::User(me))?,
+        GetAllGamesArg::MyRecentGames => recent(IndexGroup::User(me))?,
+        GetAllGamesArg::BestGamesForPlayer(player_id) => best(IndexGroup::User(player_id))?,
         GetAllGamesArg::RecentGamesForPlayer(player_id) => {
-            sort_recent(load_games_for_user(&player_id)?)?
+            recent(IndexGroup::User(player_id))?
         }
     };
-    v.truncate(PAGE_SIZE);
-    Ok(v)
+    let mut items = vec![];
+    for game_id in game_ids {
+        let r = get_segment_count(game_id, _current_user_id.clone())?;
+        items.push((game_id, r));
+    }
+    Ok(Page { items, next })
 }
 
 #[allow(unused_variables)]
@@ -265,7 +390,7 @@ pub fn get_gustom_game(
     arg: String,
     _current_user_id: GuestInfo,
 ) -> anyhow::Result<GameState> {
-    Ok(CUSTOM_GAME_BOARD_DB.get(&arg)?.context("not found")?)
+    CUSTOM_GAME_BOARD_DB.get(&arg)?.context("not found")
 }
 
 pub fn update_custom_game(
@@ -280,122 +405,186 @@ pub fn random_word2(_: (), _current_user_id: GuestInfo) -> anyhow::Result<String
     Ok(random_word())
 }
 
-pub struct MatchMakingItem {
-    channel: tokio::sync::mpsc::Sender<(uuid::Uuid, GameMatch)>,
-    player_id: uuid::Uuid,
+pub fn get_match_list(
+    arg: (GetMatchListArg, PageRequest),
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<Page<(uuid::Uuid, GameMatch)>> {
+    let (list_type, req) = arg;
+    let me = _current_user_id.user_id;
+    // best matches overall are the ones with the most lines sent, by all
+    // players; a user's best are their wins first, then the most lines sent
+    let best = || -> anyhow::Result<_> {
+        let p = page(&MATCH_LINES_SENT_DB, MATCHES_BY_LINES_SENT, &IndexGroup::All, &req)?;
+        Ok((p.items.into_iter().map(|x| x.0).collect::<Vec<_>>(), p.next))
+    };
+    let best_for_user = |user_id| -> anyhow::Result<_> {
+        let group = IndexGroup::User(user_id);
+        let p = page(&GAME_MATCHES_FOR_USER_DB, USER_MATCHES_BY_RESULT, &group, &req)?;
+        Ok((p.items.into_iter().map(|x| x.0.match_id).collect(), p.next))
+    };
+    let recent = |group| -> anyhow::Result<_> {
+        let p = page(&GAME_MATCH_DB, MATCHES_BY_START_TIME, &group, &req)?;
+        Ok((p.items.into_iter().map(|x| x.0).collect(), p.next))
+    };
+    let (match_ids, next): (Vec<uuid::Uuid>, _) = match list_type {
+        GetMatchListArg::BestGames => best()?,
+        GetMatchListArg::RecentGames => recent(IndexGroup::All)?,
+        GetMatchListArg::MyBestGames => best_for_user(me)?,
+        GetMatchListArg::MyRecentGames => recent(IndexGroup::User(me))?,
+        GetMatchListArg::BestGamesForPlayer(player_id) => best_for_user(player_id)?,
+        GetMatchListArg::RecentGamesForPlayer(player_id) => {
+            recent(IndexGroup::User(player_id))?
+        }
+    };
+    let mut items = vec![];
+    for match_id in match_ids {
+        // entries can outlive their match after a table change
+        if let Some(_match) = GAME_MATCH_DB.get(&match_id)? {
+            items.push((match_id, _match));
+        }
+    }
+    Ok(Page { items, next })
 }
 
-pub static MATCH_MAKING_QUEUE: Lazy<MatchMakingQueue> =
-    Lazy::new(|| MatchMakingQueue {
-        v: Arc::new(Mutex::new(HashMap::new())),
-    });
+/// Finished matches of a user with their result, most recent first.
+fn load_match_history(
+    user: &uuid::Uuid,
+) -> anyhow::Result<Vec<(uuid::Uuid, GameMatch, UserAndMatchResult)>> {
+    let mut v = vec![];
+    for x in GAME_MATCHES_FOR_USER_DB.range(UserAndMatchId::get_range_for_user(user)) {
+        let (key, result) = x?;
+        // results can outlive their match after a table change
+        let Some(_match) = GAME_MATCH_DB.get(&key.match_id)? else {
+            continue;
+        };
+        v.push((key.match_id, _match, result));
+    }
+    v.sort_by_key(|x| std::cmp::Reverse(x.2.end_time));
+    Ok(v)
+}
 
-use once_cell::sync::Lazy;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-pub struct MatchMakingQueue {
-    v: Arc<Mutex<HashMap<uuid::Uuid, MatchMakingItem>>>,
+pub fn get_rating(
+    arg: (uuid::Uuid, GameMatchType),
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<Rating> {
+    get_user_rating(arg.0, arg.1, get_timestamp_now_nano())
 }
 
-pub async fn start_match(
-    _: GameMatchType,
+pub fn get_rating_history(
+    arg: (uuid::Uuid, GameMatchType),
     _current_user_id: GuestInfo,
-) -> anyhow::Result<(uuid::Uuid, GameMatch)> {
-    // Ok(uuid::Uuid::nil(), GameMatch)
+) -> anyhow::Result<Vec<(uuid::Uuid, RatingChange)>> {
+    get_user_rating_history(arg.0, arg.1)
+}
 
-    let mut _waiting_for_match: Option<_> = None;
-    let mut _got_new_match: Option<_> = None;
-    {
-        let mut q = MATCH_MAKING_QUEUE.v.lock().await;
-        if q.is_empty() {
-                // creezi chan, te bagi in el
-                let (tx, rx) = tokio::sync::mpsc::channel(1);
-                let player_id =  _current_user_id.user_id;
-                let new_item = MatchMakingItem {
-                    channel: tx,
-                    player_id ,
-                };
-
-                _waiting_for_match = Some(rx);
-                q.insert(player_id, new_item);
-        }else {
-            if q.contains_key(&_current_user_id.user_id) {
-                anyhow::bail!("another game is already in matchmaking!");
-            } else {
-                let k = *q.keys().next().unwrap();
-                let other_player = q.remove(&k).unwrap();
-                let new_match = GameMatch {
-                    seed: (&mut rand::thread_rng()).gen(),
-                    time: get_timestamp_now_nano(),
-                    users: vec![other_player.player_id, _current_user_id.user_id],
-                    title: format!(
-                        "1v1 {} vs. {}",
-                        other_player.player_id, _current_user_id.user_id
-                    ),
-                };
-                let new_match_id = uuid::Uuid::new_v4();
-                GAME_MATCH_DB.insert(&new_match_id, &new_match)?;
-                other_player
-                    .channel
-                    .send((new_match_id, new_match.clone()))
-                    .await?;
-                _got_new_match = Some((new_match_id, new_match));
-            }
-        }
-    }
-    if let Some(mut waiting_rx) = _waiting_for_match {
-        if let Some(match_info) = waiting_rx.recv().await {
-            create_db_match_entry(&match_info.1)?;
-            Ok(match_info)
-        } else {
-            anyhow::bail!("cannot read from channel");
-        }
-    } else {
-        let r = _got_new_match.context("never happens")?;
+pub fn get_match_history(
+    user_id: uuid::Uuid,
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<Vec<(uuid::Uuid, GameMatch, UserAndMatchResult)>> {
+    load_match_history(&user_id)
+}
 
-        create_db_match_entry(&r.1)?;
+pub fn get_match_info(
+    match_id: uuid::Uuid,
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<GameMatch> {
+    GAME_MATCH_DB.get(&match_id)?.context(".not found")
+}
 
-        Ok(r)
-    }
+pub fn get_match_result(
+    match_id: uuid::Uuid,
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<Option<GameMatchResult>> {
+    Ok(GAME_MATCH_RESULT_DB.get(&match_id)?)
 }
 
-fn create_db_match_entry(match_info: &GameMatch) -> anyhow::Result<()> {
-    let gameinfo_0 = GameId {
-        user_id: match_info.users[0],
-        init_seed: match_info.seed,
-        start_time: match_info.time,
-    };
-    let gameinfo_1 = GameId {
-        user_id: match_info.users[1],
-        init_seed: match_info.seed,
-        start_time: match_info.time,
+pub fn create_coop_game(
+    rules: GameRules,
+    current_user_id: GuestInfo,
+) -> anyhow::Result<uuid::Uuid> {
+    CoopState::check_rules(&rules)?;
+    let seed = rand::thread_rng().gen();
+    let game = CoopGameInfo {
+        players: vec![current_user_id.user_id],
+        state: CoopState::new(&seed, get_timestamp_now_nano(), &rules),
     };
+    let game_id = uuid::Uuid::new_v4();
+    COOP_GAME_DB.insert(&game_id, &game)?;
+    Ok(game_id)
+}
 
-    GAME_IS_IN_PROGRESS_DB.insert(&gameinfo_0, &true)?;
-    GAME_SEGMENT_COUNT_DB.insert(&gameinfo_0, &0)?;
-
-    GAME_IS_IN_PROGRESS_DB.insert(&gameinfo_1, &true)?;
-    GAME_SEGMENT_COUNT_DB.insert(&gameinfo_1, &0)?;
+pub fn join_coop_game(
+    game_id: uuid::Uuid,
+    current_user_id: GuestInfo,
+) -> anyhow::Result<(Option<u8>, CoopGameInfo)> {
+    let user_id = current_user_id.user_id;
+    let (seat, mut game) = flatten(COOP_GAME_DB.transaction(|games| {
+        let Some(mut game) = games.get(&game_id)? else {
+            return abort(anyhow::anyhow!("co-op game not found"));
+        };
+        if let Some(seat) = game.players.iter().position(|p| *p == user_id) {
+            return Ok((Some(seat as u8), game));
+        }
+        if game.players.len() >= game.state.players.len() {
+            return Ok((None, game));
+        }
+        game.players.push(user_id);
+        games.insert(&game_id, &game)?;
+        Ok((Some(game.players.len() as u8 - 1), game))
+    }))?;
+    // joining players replay the game from its first slice
+    game.state.replay.replay_slices = coop_slices(game_id, 0)?;
+    Ok((seat, game))
+}
 
-    Ok(())
+/// Stores the new slice on its own and the state with only that slice, so
+/// an action costs the same however long the game is.
+pub fn append_coop_action(
+    arg: (uuid::Uuid, TetAction, i64),
+    current_user_id: GuestInfo,
+) -> anyhow::Result<CoopReplaySlice> {
+    let (game_id, action, event_time) = arg;
+    flatten(
+        (&*COOP_GAME_DB, &*COOP_SLICE_DB).transaction(|(games, slices)| {
+            let Some(mut game) = games.get(&game_id)? else {
+                return abort(anyhow::anyhow!("co-op game not found"));
+            };
+            let Some(seat) = game
+                .players
+                .iter()
+                .position(|p| *p == current_user_id.user_id)
+            else {
+                return abort(anyhow::anyhow!("you are not playing this co-op game"));
+            };
+            if let Err(e) = game.state.apply_action_if_works(seat, action, event_time) {
+                return abort(e);
+            }
+            game.state.forget_old_slices();
+            let Some(slice) = game.state.replay.replay_slices.last().cloned() else {
+                return abort(anyhow::anyhow!("never happens"));
+            };
+            slices.insert(&(game_id, slice.idx), &slice)?;
+            games.insert(&game_id, &game)?;
+            Ok(slice)
+        }),
+    )
 }
 
-pub fn get_match_list(
-    _: GetMatchListArg,
-    _current_user_id: GuestInfo,
-) -> anyhow::Result<Vec<(uuid::Uuid, GameMatch)>> {
-    let mut v = vec![];
-    for x in GAME_MATCH_DB.iter() {
-        let (uuid, _match) = x?;
-        v.push((uuid, _match));
+fn coop_slices(game_id: uuid::Uuid, from: u32) -> anyhow::Result<Vec<CoopReplaySlice>> {
+    let mut slices = vec![];
+    for item in COOP_SLICE_DB.range((game_id, from)..=(game_id, u32::MAX)) {
+        slices.push(item?.1);
     }
-    Ok(v)
+    Ok(slices)
 }
 
-pub fn get_match_info(
-    match_id: uuid::Uuid,
+pub fn get_coop_slices(
+    arg: (uuid::Uuid, u32),
     _current_user_id: GuestInfo,
-) -> anyhow::Result<GameMatch> {
-    GAME_MATCH_DB.get(&match_id)?.context(".not found")
+) -> anyhow::Result<Vec<CoopReplaySlice>> {
+    let (game_id, from) = arg;
+    if !COOP_GAME_DB.contains_key(&game_id)? {
+        anyhow::bail!("co-op game not found");
+    }
+    coop_slices(game_id, from)
 }
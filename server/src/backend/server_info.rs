@@ -49,4 +49,4 @@ fn get_timestamp_hash() -> u64 {
         % MAGIC_NUMBER as u128) as u64
 }
 
-pub static GIT_VERSION: Lazy<String> = Lazy::new(|| _fetch_git_version());
+pub static GIT_VERSION: Lazy<String> = Lazy::new(_fetch_git_version);
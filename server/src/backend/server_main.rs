@@ -39,6 +39,14 @@ pub async fn server_main() {
         // this should include a get() handler if you have any GetUrl-based server fns
         // .route("/api/*fn_name", post(leptos_axum::handle_server_fns))
         .route("/api/ws", get(crate::backend::websocket::ws_handler))
+        .route(
+            "/api/thumbnail/game/:game_url",
+            get(crate::backend::render::game_thumbnail_handler),
+        )
+        .route(
+            "/api/thumbnail/match/:match_id",
+            get(crate::backend::render::match_thumbnail_handler),
+        )
         // .fallback(file_or_index_handler)
         // .with_state(leptos_options)
         .layer(
@@ -47,6 +55,15 @@ pub async fn server_main() {
         )
         .layer(super::session::make_session_layer());
 
+    crate::database::migrate::migrate_tables().expect("couldn't migrate tables");
+    crate::database::tables::fill_empty_indexes().expect("couldn't fill indexes");
+    crate::backend::guest_merge::finish_guest_merges().expect("couldn't merge guests");
+    crate::backend::stats::fill_empty_stats().expect("couldn't fill stats");
+    crate::backend::match_coordinator::resume_match_coordinators()
+        .expect("couldn't resume matches");
+    tokio::spawn(crate::backend::matchmaking::run_matchmaker());
+    tokio::spawn(crate::backend::challenge::run_challenge_expiry());
+
     // run our app with hyper
     // `axum::Server` is a re-export of `hyper::Server`
     let listener = tokio::net::TcpListener::bind(&addr)
@@ -7,7 +7,7 @@ use tower_sessions::{cookie::time::Duration, Session, SessionManagerLayer};
 use tower_sessions_sled_store::SledStore;
 
 pub struct Guest {
-    _session: Session,
+    session: Session,
     pub guest_data: GuestInfo,
 }
 
@@ -19,6 +19,18 @@ impl Guest {
     //     Self::update_session(&self.session, &self.guest_data).await
     // }
 
+    /// Makes this session, and the connections it opens from now on, use
+    /// another identity. The session is saved right away, since websocket
+    /// connections outlive the response that would save it.
+    pub async fn switch_to(&mut self, guest_data: GuestInfo) -> anyhow::Result<()> {
+        self.session
+            .insert(Self::GUEST_DATA_KEY, &guest_data)
+            .await?;
+        self.session.save().await?;
+        self.guest_data = guest_data;
+        Ok(())
+    }
+
     async fn update_session(session: &Session, guest_data: &GuestInfo) {
         session
             .insert(Self::GUEST_DATA_KEY, guest_data.clone())
@@ -37,9 +49,9 @@ where
         req: &mut Parts,
         state: &S,
     ) -> Result<Self, Self::Rejection> {
-        let _session = Session::from_request_parts(req, state).await?;
+        let session = Session::from_request_parts(req, state).await?;
 
-        let mut guest_data: GuestInfo = _session
+        let mut guest_data: GuestInfo = session
             .get(Self::GUEST_DATA_KEY)
             .await
             .unwrap()
@@ -52,10 +64,10 @@ where
             guest_data.user_id,
             guest_data.pageviews
         );
-        Self::update_session(&_session, &guest_data).await;
+        Self::update_session(&session, &guest_data).await;
 
         Ok(Self {
-            _session,
+            session,
             guest_data,
         })
     }
@@ -66,8 +78,7 @@ pub fn make_session_layer() -> SessionManagerLayer<SledStore> {
 
     let sled = sled::open(format!("{SERVER_DATA_PATH}/sessions.sled")).unwrap();
     let session_store = SledStore::new(sled.open_tree("sessions").unwrap());
-    let session_layer = SessionManagerLayer::new(session_store)
+    SessionManagerLayer::new(session_store)
         .with_secure(false)
-        .with_expiry(tower_sessions::Expiry::OnInactivity(Duration::days(666)));
-    session_layer
+        .with_expiry(tower_sessions::Expiry::OnInactivity(Duration::days(666)))
 }
@@ -0,0 +1,130 @@
+//! Per-user totals. Every finished game and match is added to them once, as
+//! it ends, so reading them never scans games.
+
+use crate::database::tables::*;
+use game::api::game_match::{UserAndMatchId, UserAndMatchResult};
+use game::api::game_replay::GameId;
+use game::api::leaderboard::GameSummary;
+use game::api::stats::{day_of, PaceStats, UserStats};
+use game::api::user::GuestInfo;
+use sled::transaction::{ConflictableTransactionError, UnabortableTransactionError};
+use typed_sled::transaction::{flatten, Transactional, TransactionalTree};
+
+/// Adds a game that just got its summary.
+pub fn record_game_stats(summary: &GameSummary) -> anyhow::Result<()> {
+    flatten((&*USER_STATS_DB, &*USER_DAILY_PACE_DB).transaction(
+        |(stats_db, pace_db)| {
+            add_game_stats(&stats_db, &pace_db, summary)?;
+            Ok::<(), ConflictableTransactionError<anyhow::Error>>(())
+        },
+    ))
+}
+
+/// `record_game_stats` within a transaction of the caller's.
+pub fn add_game_stats(
+    stats_db: &TransactionalTree<uuid::Uuid, UserStats>,
+    pace_db: &TransactionalTree<(uuid::Uuid, i64), PaceStats>,
+    summary: &GameSummary,
+) -> Result<(), UnabortableTransactionError> {
+    let user_id = summary.game_id.user_id;
+    let mut stats = stats_db.get(&user_id)?.unwrap_or_default();
+    stats.add_game(summary);
+    stats_db.insert(&user_id, &stats)?;
+    let day = (user_id, day_of(summary.end_time));
+    let mut pace = pace_db.get(&day)?.unwrap_or_default();
+    pace.add_game(summary);
+    pace_db.insert(&day, &pace)?;
+    Ok(())
+}
+
+/// Adds the results of a match that started at `start_time`.
+pub fn record_match_stats(
+    results: &[(uuid::Uuid, UserAndMatchResult)],
+    start_time: i64,
+) -> anyhow::Result<()> {
+    flatten((&*USER_STATS_DB, &*USER_DAILY_PACE_DB).transaction(
+        |(stats_db, pace_db)| {
+            add_match_stats(&stats_db, &pace_db, results, start_time)?;
+            Ok::<(), ConflictableTransactionError<anyhow::Error>>(())
+        },
+    ))
+}
+
+/// `record_match_stats` within a transaction of the caller's.
+pub fn add_match_stats(
+    stats_db: &TransactionalTree<uuid::Uuid, UserStats>,
+    pace_db: &TransactionalTree<(uuid::Uuid, i64), PaceStats>,
+    results: &[(uuid::Uuid, UserAndMatchResult)],
+    start_time: i64,
+) -> Result<(), UnabortableTransactionError> {
+    for (user_id, result) in results {
+        let duration_ns = result.end_time - start_time;
+        let mut stats = stats_db.get(user_id)?.unwrap_or_default();
+        stats.add_match(result, duration_ns);
+        stats_db.insert(user_id, &stats)?;
+        let day = (*user_id, day_of(result.end_time));
+        let mut pace = pace_db.get(&day)?.unwrap_or_default();
+        pace.add_match(result, duration_ns);
+        pace_db.insert(&day, &pace)?;
+    }
+    Ok(())
+}
+
+/// Adds up every game and match from before stats were kept. Only runs at
+/// startup, and only while there are no stats at all.
+pub fn fill_empty_stats() -> anyhow::Result<()> {
+    if !USER_STATS_DB.is_empty() {
+        return Ok(());
+    }
+    for item in GAME_SUMMARY_DB.iter() {
+        let (_, summary) = item?;
+        record_game_stats(&summary)?;
+    }
+    for item in GAME_MATCHES_FOR_USER_DB.iter() {
+        let (key, result) = item?;
+        let Some(match_info) = GAME_MATCH_DB.get(&key.match_id)? else {
+            continue;
+        };
+        record_match_stats(&[(key.user_id, result)], match_info.time)?;
+    }
+    Ok(())
+}
+
+/// Counts the games and matches of `user_id` again from scratch, after they
+/// moved over from a guest.
+pub fn rebuild_user_stats(user_id: uuid::Uuid) -> anyhow::Result<()> {
+    USER_STATS_DB.remove(&user_id)?;
+    for item in USER_DAILY_PACE_DB.range((user_id, 0)..=(user_id, i64::MAX)) {
+        let (day, _) = item?;
+        USER_DAILY_PACE_DB.remove(&day)?;
+    }
+    for item in GAME_SUMMARY_DB.range(GameId::get_range_for_user(&user_id)) {
+        let (_, summary) = item?;
+        record_game_stats(&summary)?;
+    }
+    for item in
+        GAME_MATCHES_FOR_USER_DB.range(UserAndMatchId::get_range_for_user(&user_id))
+    {
+        let (key, result) = item?;
+        let Some(match_info) = GAME_MATCH_DB.get(&key.match_id)? else {
+            continue;
+        };
+        record_match_stats(&[(user_id, result)], match_info.time)?;
+    }
+    Ok(())
+}
+
+pub fn get_user_stats(
+    user_id: uuid::Uuid,
+    _current_user_id: GuestInfo,
+) -> anyhow::Result<(UserStats, Vec<(i64, PaceStats)>)> {
+    let stats = USER_STATS_DB.get(&user_id)?.unwrap_or_default();
+    let mut days = vec![];
+    for item in USER_DAILY_PACE_DB.range((user_id, 0)..=(user_id, i64::MAX)) {
+        let ((_, day), pace) = item?;
+        days.push((day, pace));
+    }
+    // keys don't sort by day
+    days.sort_by_key(|d| d.0);
+    Ok((stats, days))
+}
@@ -8,6 +8,7 @@ use axum_extra::TypedHeader;
 use futures::Future;
 use game::{
     api::{
+        game_match::MatchPlayerState,
         game_replay::{GameId, GameSegmentId},
         websocket::{
             APIMethod, SubscribeGamePlzArgument, WebsocketAPIMessageRaw,
@@ -30,31 +31,33 @@ use axum_extra::headers;
 
 use crate::database::tables::get_or_create_user_profile;
 
+use super::matchmaking::MatchmakingSender;
+use super::presence::{Connection, UserNotification};
 use super::session::Guest;
 //allows to split the websocket stream into separate TX and RX branches
 // use futures::{sink::SinkExt, stream::StreamExt};
 
 // use crate::server::api::user::{GuestInfo, UserProfile};
 
-/// The handler for the HTTP request (this gets called when the HTTP GET lands at the start
-/// of websocket negotiation). After this completes, the actual switching from HTTP to
-/// websocket protocol will occur.
-/// This is the last point where we can extract TCP/IP metadata such as IP address of the client
-/// as well as things from HTTP headers such as user-agent of the browser etc.
-
-fn convert_subscribed_message_to_bytes(
-    vect: <game::api::websocket::SubscribedGameUpdateNotification as game::api::websocket::APIMethod>::Req,
+/// A notification the server sends on its own, outside of any request.
+fn notification_bytes<T: serde::Serialize>(
+    msg: &T,
+    _type: WebsocketAPIMessageType,
 ) -> anyhow::Result<Vec<u8>> {
-    let data_bytes = bincode::serialize(&vect)?;
     let msg = WebsocketAPIMessageRaw {
         id: 0,
         is_req: true,
-        _type: WebsocketAPIMessageType::SubscribedGameUpdateNotification,
-        data: data_bytes,
+        _type,
+        data: bincode::serialize(msg)?,
     };
     Ok(bincode::serialize(&msg)?)
 }
 
+/// The handler for the HTTP request (this gets called when the HTTP GET lands at the start
+/// of websocket negotiation). After this completes, the actual switching from HTTP to
+/// websocket protocol will occur.
+/// This is the last point where we can extract TCP/IP metadata such as IP address of the client
+/// as well as things from HTTP headers such as user-agent of the browser etc.
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
@@ -72,8 +75,7 @@ pub async fn ws_handler(
 }
 
 /// Actual websocket statemachine (one will be spawned per connection)
-async fn handle_socket(socket: WebSocket, who: SocketAddr, guest: Guest) {
-    let user_info = (&guest).guest_data.clone();
+async fn handle_socket(socket: WebSocket, who: SocketAddr, mut guest: Guest) {
     use futures::{sink::SinkExt, stream::StreamExt};
     let (mut sender, mut receiver) = socket.split();
     let (response_tx, mut response_rx) = tokio::sync::mpsc::channel(32);
@@ -81,7 +83,18 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, guest: Guest) {
 
     let (subscribe_game_sender, mut subscribe_game_recv) =
         tokio::sync::mpsc::channel(16);
-    let mut subscribed_games = SubscribedGamesState::new(subscribe_game_sender);
+    let (subscribe_match_sender, mut subscribe_match_recv) =
+        tokio::sync::mpsc::channel(16);
+    let (matchmaking_sender, mut matchmaking_recv) = tokio::sync::mpsc::channel(16);
+    let mut world_record_recv = crate::backend::leaderboard::WORLD_RECORDS.subscribe();
+    let (user_sender, mut user_recv) = tokio::sync::mpsc::channel(16);
+    let mut subscribed_games = SubscribedGamesState::new(
+        subscribe_game_sender,
+        subscribe_match_sender,
+        matchmaking_sender.clone(),
+        Connection::open(guest.guest_data.user_id, user_sender),
+        who.ip(),
+    );
 
     let mut send_task = tokio::spawn(async move {
         let mut cnt: usize = 0;
@@ -98,7 +111,7 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, guest: Guest) {
                 }
                 msg = subscribe_game_recv.recv() => {
                     if let Some(msg) = msg {
-                        if let Ok(b) = convert_subscribed_message_to_bytes(msg) {
+                        if let Ok(b) = notification_bytes(&msg, WebsocketAPIMessageType::SubscribedGameUpdateNotification) {
                             cnt += 1;
                             log::info!("SUBSCRIBE NOTIFICATION: SubscribedGameUpdateNotification  {} bytes", b.len());
                             if let Err(e) = sender.send(Message::Binary(b)).await {
@@ -108,6 +121,58 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, guest: Guest) {
                         }
                     }
                 }
+                msg = subscribe_match_recv.recv() => {
+                    if let Some(msg) = msg {
+                        if let Ok(b) = notification_bytes(&msg, WebsocketAPIMessageType::MatchPlayerUpdateNotification) {
+                            cnt += 1;
+                            if let Err(e) = sender.send(Message::Binary(b)).await {
+                                log::warn!("could not send message becaue: {e}");
+                                break;
+                            }
+                        }
+                    }
+                }
+                msg = matchmaking_recv.recv() => {
+                    if let Some(msg) = msg {
+                        if let Ok(b) = notification_bytes(&msg, WebsocketAPIMessageType::MatchmakingStatusNotification) {
+                            cnt += 1;
+                            if let Err(e) = sender.send(Message::Binary(b)).await {
+                                log::warn!("could not send message becaue: {e}");
+                                break;
+                            }
+                        }
+                    }
+                }
+                msg = world_record_recv.recv() => {
+                    if let Ok(msg) = msg {
+                        if let Ok(b) = notification_bytes(&msg, WebsocketAPIMessageType::WorldRecordNotification) {
+                            cnt += 1;
+                            if let Err(e) = sender.send(Message::Binary(b)).await {
+                                log::warn!("could not send message becaue: {e}");
+                                break;
+                            }
+                        }
+                    }
+                }
+                msg = user_recv.recv() => {
+                    if let Some(msg) = msg {
+                        let b = match msg {
+                            UserNotification::Presence(user_id, presence) => {
+                                notification_bytes(&(user_id, presence), WebsocketAPIMessageType::PresenceNotification)
+                            }
+                            UserNotification::Challenge(id, event) => {
+                                notification_bytes(&(id, event), WebsocketAPIMessageType::ChallengeNotification)
+                            }
+                        };
+                        if let Ok(b) = b {
+                            cnt += 1;
+                            if let Err(e) = sender.send(Message::Binary(b)).await {
+                                log::warn!("could not send message becaue: {e}");
+                                break;
+                            }
+                        }
+                    }
+                }
             }
         }
 
@@ -155,12 +220,8 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, guest: Guest) {
         while let Some(b) = request_rx.recv().await {
             cnt += 1;
 
-            let b = match websocket_handle_request(
-                b,
-                (&user_info).clone(),
-                &mut subscribed_games,
-            )
-            .await
+            let b = match websocket_handle_request(b, &mut guest, &mut subscribed_games)
+                .await
             {
                 Ok(b) => b,
                 Err(e) => {
@@ -207,6 +268,8 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, guest: Guest) {
         }
     }
 
+    crate::backend::matchmaking::on_connection_closed(&matchmaking_sender);
+
     // returning from the handler closes the websocket connection
     log::info!("Websocket context {who} destroyed");
 }
@@ -216,19 +279,75 @@ struct SingleSubscribedGameState {
 }
 pub struct SubscribedGamesState {
     games_info: HashMap<GameId, SingleSubscribedGameState>,
+    matches_info: HashMap<GameId, SingleSubscribedGameState>,
     pub reply_callback:
         tokio::sync::mpsc::Sender<Vec<(GameSegmentId, GameReplaySegment)>>,
+    pub match_callback: tokio::sync::mpsc::Sender<(GameId, MatchPlayerState)>,
+    pub matchmaking_callback: MatchmakingSender,
+    /// Keeps the user online until the connection is gone.
+    pub presence: Connection,
+    /// Where the connection comes from.
+    pub peer: std::net::IpAddr,
 }
 
 impl SubscribedGamesState {
     pub fn new(
         sender: tokio::sync::mpsc::Sender<Vec<(GameSegmentId, GameReplaySegment)>>,
+        match_sender: tokio::sync::mpsc::Sender<(GameId, MatchPlayerState)>,
+        matchmaking_sender: MatchmakingSender,
+        presence: Connection,
+        peer: std::net::IpAddr,
     ) -> Self {
         Self {
             games_info: HashMap::<_, _>::new(),
+            matches_info: HashMap::<_, _>::new(),
             reply_callback: sender,
+            match_callback: match_sender,
+            matchmaking_callback: matchmaking_sender,
+            presence,
+            peer,
         }
     }
+
+    /// Sends the match state of `game_id` now and after every change.
+    pub async fn start_match_streaming(
+        &mut self,
+        game_id: &GameId,
+    ) -> anyhow::Result<()> {
+        use crate::database::tables::MATCH_PLAYER_DB;
+        let game_id = *game_id;
+        let Some(current) = MATCH_PLAYER_DB.get(&game_id)? else {
+            log::info!("game {:?} is not in a match", game_id);
+            return Ok(());
+        };
+        self.match_callback.send((game_id, current)).await?;
+
+        let mut subscriber = MATCH_PLAYER_DB.watch_prefix2(&game_id);
+        let match_callback = self.match_callback.clone();
+        let new_thread = tokio::task::spawn(async move {
+            while let Some(event) = (&mut subscriber).await {
+                if let typed_sled::Event::Insert { key, value } = event {
+                    if let Err(e) = match_callback.send((key, value)).await {
+                        log::error!(
+                            "error sending match update for {:?}: {:?}",
+                            game_id,
+                            e
+                        );
+                        break;
+                    }
+                }
+            }
+        });
+        if let Some(old) = self.matches_info.insert(
+            game_id,
+            SingleSubscribedGameState {
+                join_handle: new_thread,
+            },
+        ) {
+            old.join_handle.abort();
+        }
+        Ok(())
+    }
     pub async fn accept_message(
         &mut self,
         msg: &SubscribeGamePlzArgument,
@@ -252,7 +371,7 @@ impl SubscribedGamesState {
     }
     pub async fn start_streaming(&mut self, msg: &GameId) -> anyhow::Result<()> {
         use crate::database::tables::GAME_SEGMENT_DB;
-        let game_id = msg.clone();
+        let game_id = *msg;
         let mut existing_segments = vec![];
         for item in GAME_SEGMENT_DB.range(GameSegmentId::get_range_for_game(&game_id)) {
             existing_segments.push(item?);
@@ -268,8 +387,6 @@ impl SubscribedGamesState {
         log::info!("Start streaming for game{:?}", game_id);
         let reply_callback = self.reply_callback.clone();
         let new_thread = tokio::task::spawn(async move {
-            let game_id = game_id.clone();
-
             // log::info!("{:?}: Spectate found existing segments {}", game_id, existing_segments.len());
             let mut subscriber = subscriber;
             loop {
@@ -318,17 +435,25 @@ impl SubscribedGamesState {
 use game::api::user::GuestInfo;
 pub async fn websocket_handle_request(
     b: Vec<u8>,
-    user_id: GuestInfo,
+    guest: &mut Guest,
     subscribe_games: &mut SubscribedGamesState,
 ) -> anyhow::Result<Vec<u8>> {
+    use crate::backend::account::*;
+    use crate::backend::challenge::*;
+    use crate::backend::leaderboard::*;
+    use crate::backend::match_coordinator::set_garbage_targeting;
+    use crate::backend::presence::*;
+    use crate::backend::room::*;
+    use crate::backend::series::*;
     use crate::backend::server_fn::*;
     use game::api::websocket::*;
+    let user_id = guest.guest_data.clone();
     let user_id2 = user_id.clone();
     get_or_create_user_profile(&user_id2.user_id).unwrap();
 
     let msg: WebsocketAPIMessageRaw = bincode::deserialize(&b)
         .context("bincode deserialize fail for WebsocketAPIMessageRaw")?;
-    let msg_type = msg._type.clone();
+    let msg_type = msg._type;
     log::info!(
         "handling request {:?} for userID {:?}",
         msg_type,
@@ -342,6 +467,9 @@ pub async fn websocket_handle_request(
         WebsocketAPIMessageType::GetProfile => {
             specific_sync_request::<GetProfile>(msg, user_id, get_profile).await
         }
+        WebsocketAPIMessageType::UpdateProfile => {
+            specific_sync_request::<UpdateProfile>(msg, user_id, update_profile).await
+        }
         WebsocketAPIMessageType::GitVersion => {
             specific_sync_request::<GitVersion>(msg, user_id, git_version).await
         }
@@ -400,17 +528,32 @@ pub async fn websocket_handle_request(
             let request: <game::api::websocket::SubscribeGamePlz as APIMethod>::Req =
                 bincode::deserialize(&msg.data).context("bincode never fail")?;
 
-            let response = subscribe_games.accept_message(&request).await?;
+            subscribe_games.accept_message(&request).await?;
 
             Ok(WebsocketAPIMessageRaw {
                 id: msg.id,
                 _type: msg._type,
                 is_req: false,
-                data: bincode::serialize(&response).context("bincode never fail")?,
+                data: bincode::serialize(&()).context("bincode never fail")?,
             })
         }
         WebsocketAPIMessageType::StartMatch => {
-            specific_async_request::<StartMatch, _, _>(msg, user_id, start_match).await
+            use crate::backend::matchmaking::start_match;
+            let notify = subscribe_games.matchmaking_callback.clone();
+            specific_sync_request::<StartMatch>(msg, user_id, move |arg, user| {
+                start_match(arg, user, notify.clone())
+            })
+            .await
+        }
+        WebsocketAPIMessageType::CancelMatchmaking => {
+            use crate::backend::matchmaking::cancel_matchmaking;
+            let notify = subscribe_games.matchmaking_callback.clone();
+            specific_sync_request::<CancelMatchmaking>(
+                msg,
+                user_id,
+                move |arg, user| cancel_matchmaking(arg, user, notify.clone()),
+            )
+            .await
         }
         WebsocketAPIMessageType::GetMatchList => {
             specific_sync_request::<GetMatchList>(msg, user_id, get_match_list).await
@@ -421,6 +564,140 @@ pub async fn websocket_handle_request(
         WebsocketAPIMessageType::GetMatchInfo => {
             specific_sync_request::<GetMatchInfo>(msg, user_id, get_match_info).await
         }
+        WebsocketAPIMessageType::GetGameRules => {
+            specific_sync_request::<GetGameRules>(msg, user_id, get_game_rules).await
+        }
+        WebsocketAPIMessageType::CreateCoopGame => {
+            specific_sync_request::<CreateCoopGame>(msg, user_id, create_coop_game)
+                .await
+        }
+        WebsocketAPIMessageType::JoinCoopGame => {
+            specific_sync_request::<JoinCoopGame>(msg, user_id, join_coop_game).await
+        }
+        WebsocketAPIMessageType::AppendCoopAction => {
+            specific_sync_request::<AppendCoopAction>(msg, user_id, append_coop_action)
+                .await
+        }
+        WebsocketAPIMessageType::GetCoopSlices => {
+            specific_sync_request::<GetCoopSlices>(msg, user_id, get_coop_slices).await
+        }
+        WebsocketAPIMessageType::SubscribeMatchPlz => {
+            let request: <SubscribeMatchPlz as APIMethod>::Req =
+                bincode::deserialize(&msg.data).context("bincode never fail")?;
+
+            subscribe_games.start_match_streaming(&request).await?;
+
+            Ok(WebsocketAPIMessageRaw {
+                id: msg.id,
+                _type: msg._type,
+                is_req: false,
+                data: bincode::serialize(&()).context("bincode never fail")?,
+            })
+        }
+        WebsocketAPIMessageType::CreateRoom => {
+            specific_sync_request::<CreateRoom>(msg, user_id, create_room).await
+        }
+        WebsocketAPIMessageType::GetRoom => {
+            specific_sync_request::<GetRoom>(msg, user_id, get_room).await
+        }
+        WebsocketAPIMessageType::JoinRoom => {
+            specific_sync_request::<JoinRoom>(msg, user_id, join_room).await
+        }
+        WebsocketAPIMessageType::LeaveRoom => {
+            specific_sync_request::<LeaveRoom>(msg, user_id, leave_room).await
+        }
+        WebsocketAPIMessageType::SetRoomSettings => {
+            specific_sync_request::<SetRoomSettings>(msg, user_id, set_room_settings)
+                .await
+        }
+        WebsocketAPIMessageType::SetRoomReady => {
+            specific_sync_request::<SetRoomReady>(msg, user_id, set_room_ready).await
+        }
+        WebsocketAPIMessageType::GetMatchSeries => {
+            specific_sync_request::<GetMatchSeries>(msg, user_id, get_match_series)
+                .await
+        }
+        WebsocketAPIMessageType::AcceptRematch => {
+            specific_sync_request::<AcceptRematch>(msg, user_id, accept_rematch).await
+        }
+        WebsocketAPIMessageType::SetGarbageTargeting => {
+            specific_sync_request::<SetGarbageTargeting>(
+                msg,
+                user_id,
+                set_garbage_targeting,
+            )
+            .await
+        }
+        WebsocketAPIMessageType::GetLeaderboard => {
+            specific_sync_request::<GetLeaderboard>(msg, user_id, get_leaderboard).await
+        }
+        WebsocketAPIMessageType::GetWorldRecord => {
+            specific_sync_request::<GetWorldRecord>(msg, user_id, get_world_record)
+                .await
+        }
+        WebsocketAPIMessageType::MatchPlayerUpdateNotification
+        | WebsocketAPIMessageType::MatchmakingStatusNotification
+        | WebsocketAPIMessageType::WorldRecordNotification
+        | WebsocketAPIMessageType::PresenceNotification
+        | WebsocketAPIMessageType::ChallengeNotification => {
+            anyhow::bail!("Unsupported message from client: {:?}", msg._type);
+        }
+        WebsocketAPIMessageType::GetMatchResult => {
+            specific_sync_request::<GetMatchResult>(msg, user_id, get_match_result)
+                .await
+        }
+        WebsocketAPIMessageType::GetMatchHistory => {
+            specific_sync_request::<GetMatchHistory>(msg, user_id, get_match_history)
+                .await
+        }
+        WebsocketAPIMessageType::GetRating => {
+            specific_sync_request::<GetRating>(msg, user_id, get_rating).await
+        }
+        WebsocketAPIMessageType::GetRatingHistory => {
+            specific_sync_request::<GetRatingHistory>(msg, user_id, get_rating_history)
+                .await
+        }
+        WebsocketAPIMessageType::GetUserStats => {
+            use crate::backend::stats::get_user_stats;
+            specific_sync_request::<GetUserStats>(msg, user_id, get_user_stats).await
+        }
+        WebsocketAPIMessageType::Register => {
+            switch_identity_request::<Register>(msg, guest, register).await
+        }
+        WebsocketAPIMessageType::Login => {
+            let peer = subscribe_games.peer;
+            let r = switch_identity_request::<Login>(msg, guest, move |c, g| {
+                login(c, g, peer)
+            })
+            .await;
+            subscribe_games
+                .presence
+                .switch_user(guest.guest_data.user_id);
+            r
+        }
+        WebsocketAPIMessageType::Logout => {
+            let r = switch_identity_request::<Logout>(msg, guest, logout).await;
+            subscribe_games
+                .presence
+                .switch_user(guest.guest_data.user_id);
+            r
+        }
+        WebsocketAPIMessageType::Follow => {
+            specific_sync_request::<Follow>(msg, user_id, follow).await
+        }
+        WebsocketAPIMessageType::Unfollow => {
+            specific_sync_request::<Unfollow>(msg, user_id, unfollow).await
+        }
+        WebsocketAPIMessageType::GetFollowing => {
+            specific_sync_request::<GetFollowing>(msg, user_id, get_following).await
+        }
+        WebsocketAPIMessageType::SendChallenge => {
+            specific_sync_request::<SendChallenge>(msg, user_id, send_challenge).await
+        }
+        WebsocketAPIMessageType::AnswerChallenge => {
+            specific_sync_request::<AnswerChallenge>(msg, user_id, answer_challenge)
+                .await
+        }
     }
     .context(format!("specific handler {:?}", msg_type))?;
 
@@ -429,7 +706,7 @@ pub async fn websocket_handle_request(
         msg_type,
         user_id2.user_id
     );
-    Ok(bincode::serialize(&r).context("bincode never fail")?)
+    bincode::serialize(&r).context("bincode never fail")
 }
 use anyhow::Context;
 pub async fn specific_sync_request<T: APIMethod>(
@@ -463,6 +740,27 @@ pub async fn specific_sync_request<T: APIMethod>(
         data: bincode::serialize(&response).context("bincode never fail")?,
     })
 }
+/// For methods that reply with who the connection is from now on: the new
+/// identity goes into the session and is used for the requests after it.
+pub async fn switch_identity_request<T: APIMethod<Resp = GuestInfo>>(
+    request_msg: WebsocketAPIMessageRaw,
+    guest: &mut Guest,
+    callback: impl Fn(T::Req, GuestInfo) -> anyhow::Result<GuestInfo>
+        + std::marker::Sync
+        + std::marker::Send
+        + 'static,
+) -> anyhow::Result<WebsocketAPIMessageRaw> {
+    let guest_info = guest.guest_data.clone();
+    specific_async_request::<T, _, _>(request_msg, guest_info, |req, info| async move {
+        let new_info = tokio::task::spawn_blocking(move || callback(req, info))
+            .await
+            .context("tokio never fail")??;
+        guest.switch_to(new_info.clone()).await?;
+        Ok(new_info)
+    })
+    .await
+}
+
 //   (impl Future<>)+ std::marker::Sync+ std::marker::Send+ 'static,
 pub async fn specific_async_request<T, F, Fut>(
     request_msg: WebsocketAPIMessageRaw,
@@ -0,0 +1,127 @@
+//! Listings over the secondary indexes of the tables. Index keys start with
+//! the group they are listed in, so a page is one range read backwards from
+//! the end of its group.
+
+use std::ops::Bound;
+
+use game::api::page::{Page, PageCursor, PageRequest};
+use typed_sled::index::IndexKey;
+use typed_sled::{Tree, KV};
+
+/// Entries of an index are listed either for everyone or per user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexGroup {
+    All,
+    User(uuid::Uuid),
+}
+
+impl IndexGroup {
+    pub fn key(&self) -> IndexKey {
+        match self {
+            IndexGroup::All => IndexKey::new().u8(0),
+            IndexGroup::User(user_id) => {
+                IndexKey::new().u8(1).bytes(user_id.as_bytes())
+            }
+        }
+    }
+
+    /// `order` both for everyone and for `user_id`.
+    pub fn all_and_user(user_id: uuid::Uuid, order: IndexKey) -> Vec<IndexKey> {
+        [IndexGroup::All, IndexGroup::User(user_id)]
+            .iter()
+            .map(|group| group.key().bytes(order.as_bytes()))
+            .collect()
+    }
+}
+
+/// One page of a group of the index `index` on `tree`, highest key first.
+pub fn page<K: KV, V: KV>(
+    tree: &Tree<K, V>,
+    index: &str,
+    group: &IndexGroup,
+    req: &PageRequest,
+) -> anyhow::Result<Page<(K, V)>> {
+    let prefix = group.key();
+    let end = match &req.cursor {
+        Some(PageCursor(cursor)) => {
+            anyhow::ensure!(cursor.starts_with(prefix.as_bytes()), "bad page cursor");
+            Bound::Excluded(IndexKey::from(cursor.clone()))
+        }
+        None => match prefix.prefix_end() {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        },
+    };
+    let limit = req.clamped_limit();
+    let mut items = vec![];
+    let mut last_key = None;
+    for entry in tree
+        .index_range(index, (Bound::Included(prefix), end))
+        .rev()
+    {
+        let (index_key, key, value) = entry?;
+        if items.len() == limit {
+            return Ok(Page {
+                items,
+                next: last_key.map(|k: IndexKey| PageCursor(k.into())),
+            });
+        }
+        items.push((key, value));
+        last_key = Some(index_key);
+    }
+    Ok(Page { items, next: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_follow_the_cursor() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let user = uuid::Uuid::from_bytes([255; 16]);
+        let tree = Tree::<u32, i64>::open(&db, "test_rows").with_index(
+            &db,
+            "test_rows_by_value",
+            move |k, v| {
+                let order = IndexKey::new().i64(*v);
+                if k % 2 == 0 {
+                    IndexGroup::all_and_user(user, order)
+                } else {
+                    vec![IndexGroup::All.key().bytes(order.as_bytes())]
+                }
+            },
+        );
+        for i in 0..5u32 {
+            tree.insert(&i, &-(i as i64)).unwrap();
+        }
+        let page_of = |group, req| -> anyhow::Result<(Vec<u32>, Option<PageCursor>)> {
+            let page = page(&tree, "test_rows_by_value", &group, &req)?;
+            Ok((page.items.into_iter().map(|x| x.0).collect(), page.next))
+        };
+
+        let (first, next) = page_of(IndexGroup::All, PageRequest::first(2)).unwrap();
+        assert_eq!(first, vec![0, 1]);
+        let (second, next) =
+            page_of(IndexGroup::All, PageRequest::after(next.unwrap(), 2)).unwrap();
+        assert_eq!(second, vec![2, 3]);
+        let (last, next) =
+            page_of(IndexGroup::All, PageRequest::after(next.unwrap(), 2)).unwrap();
+        assert_eq!(last, vec![4]);
+        assert_eq!(next, None);
+
+        let (mine, next) =
+            page_of(IndexGroup::User(user), PageRequest::first(9)).unwrap();
+        assert_eq!(mine, vec![0, 2, 4]);
+        assert_eq!(next, None);
+
+        tree.remove(&0).unwrap();
+        let (mine, next) =
+            page_of(IndexGroup::User(user), PageRequest::first(1)).unwrap();
+        assert_eq!(mine, vec![2]);
+        // someone else's cursor
+        assert!(
+            page_of(IndexGroup::All, PageRequest::after(next.unwrap(), 1)).is_err()
+        );
+    }
+}
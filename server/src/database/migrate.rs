@@ -0,0 +1,264 @@
+//! Brings rows written by older versions of the server to the current
+//! layout, in place, so tables keep their names when a stored type changes.
+//!
+//! Each step takes the tables from one schema version to the next and runs
+//! once, at startup, before anything reads them. Steps skip rows that already
+//! decode as the current layout, so an interrupted step can run again.
+
+use std::collections::VecDeque;
+
+use anyhow::Context;
+use bincode::Options;
+use game::api::game_match::{GameMatch, GameMatchType};
+use game::random::GameSeed;
+use game::rules::GameRules;
+use game::tet::{
+    BoardMatrix, CurrentPcsInfo, GameReplay, GameReplaySegment, GameReplaySlice,
+    GameState, HoldPcsInfo, Tet, TetAction, UndoMode,
+};
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::tables::*;
+
+/// Schema version the tables are at, under `SCHEMA_KEY`.
+static SCHEMA_DB: Lazy<typed_sled::Tree<String, u32>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "schema_v1"));
+
+const SCHEMA_KEY: &str = "tables";
+
+/// Bump together with a new step in `migrate_tables`.
+pub const SCHEMA_VERSION: u32 = 2;
+
+pub fn migrate_tables() -> anyhow::Result<()> {
+    let key = SCHEMA_KEY.to_string();
+    let mut version = SCHEMA_DB.get(&key)?.unwrap_or(0);
+    while version < SCHEMA_VERSION {
+        log::info!("migrating tables from schema version {version}");
+        match version {
+            0 => add_legacy_game_rules()?,
+            1 => add_legacy_match_types()?,
+            _ => unreachable!(),
+        }
+        version += 1;
+        SCHEMA_DB.insert(&key, &version)?;
+    }
+    Ok(())
+}
+
+/// Rules of games recorded before there were rules: the engine played them
+/// the way version 1 of the standard rules does.
+fn legacy_rules() -> GameRules {
+    GameRules {
+        version: 1,
+        ..GameRules::standard()
+    }
+}
+
+/// Same encoding `typed_sled` uses, except that leftover bytes fail: an old
+/// row must not pass for a shorter new one.
+fn decode_exact<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+        .ok()
+}
+
+#[derive(Deserialize)]
+struct LegacyGameReplay {
+    init_seed: GameSeed,
+    start_time: i64,
+    replay_slices: Vec<GameReplaySlice>,
+}
+
+impl LegacyGameReplay {
+    fn upgrade(self) -> GameReplay {
+        GameReplay {
+            init_seed: self.init_seed,
+            start_time: self.start_time,
+            replay_slices: self.replay_slices,
+            undo_mode: UndoMode::Disabled,
+            rules: legacy_rules(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+enum LegacyGameReplaySegment {
+    Init(LegacyGameReplay),
+    Update(GameReplaySlice),
+    GameOver,
+}
+
+impl LegacyGameReplaySegment {
+    fn upgrade(self) -> GameReplaySegment {
+        match self {
+            Self::Init(replay) => GameReplaySegment::Init(replay.upgrade()),
+            Self::Update(slice) => GameReplaySegment::Update(slice),
+            Self::GameOver => GameReplaySegment::GameOver,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyGameState {
+    score: i64,
+    is_t_spin: bool,
+    have_combo: bool,
+    main_board: BoardMatrix,
+    last_action: TetAction,
+    next_pcs: VecDeque<Tet>,
+    current_pcs: Option<CurrentPcsInfo>,
+    current_id: u32,
+    hold_pcps: Option<HoldPcsInfo>,
+    game_over: bool,
+    replay: LegacyGameReplay,
+    seed: GameSeed,
+    init_seed: GameSeed,
+    start_time: i64,
+}
+
+impl LegacyGameState {
+    fn upgrade(self) -> GameState {
+        GameState {
+            score: self.score,
+            is_t_spin: self.is_t_spin,
+            have_combo: self.have_combo,
+            main_board: self.main_board,
+            last_action: self.last_action,
+            next_pcs: self.next_pcs,
+            current_pcs: self.current_pcs,
+            current_id: self.current_id,
+            hold_pcps: self.hold_pcps,
+            game_over: self.game_over,
+            garbage_sent: 0,
+            garbage_received: 0,
+            // not counted back then
+            lines: 0,
+            replay: self.replay.upgrade(),
+            seed: self.seed,
+            init_seed: self.init_seed,
+            start_time: self.start_time,
+        }
+    }
+}
+
+/// Rewrites every row of `tree` that only decodes as `Legacy`. Goes through
+/// the raw tree: the typed one would decode the old value on insert.
+fn upgrade_rows<K, V, Legacy>(
+    tree: &typed_sled::Tree<K, V>,
+    upgrade: impl Fn(Legacy) -> V,
+) -> anyhow::Result<usize>
+where
+    V: DeserializeOwned + serde::Serialize,
+    Legacy: DeserializeOwned,
+{
+    let raw = TABLES_DB.open_tree(tree.name())?;
+    let mut upgraded = 0;
+    for item in raw.iter() {
+        let (key, value) = item?;
+        if decode_exact::<V>(&value).is_some() {
+            continue;
+        }
+        let legacy: Legacy = decode_exact(&value).with_context(|| {
+            format!(
+                "row in {} decodes as neither layout",
+                String::from_utf8_lossy(&tree.name())
+            )
+        })?;
+        raw.insert(key, bincode::serialize(&upgrade(legacy))?)?;
+        upgraded += 1;
+    }
+    Ok(upgraded)
+}
+
+/// Games from before rules were recorded get the version 1 rules in their
+/// `Init` segment, their states and `GAME_RULES_DB`.
+fn add_legacy_game_rules() -> anyhow::Result<()> {
+    let segments = upgrade_rows(&GAME_SEGMENT_DB, LegacyGameReplaySegment::upgrade)?;
+    let states = upgrade_rows(&GAME_FULL_DB, LegacyGameState::upgrade)?;
+    let boards = upgrade_rows(&CUSTOM_GAME_BOARD_DB, LegacyGameState::upgrade)?;
+
+    let rules = legacy_rules();
+    let mut games = 0;
+    for item in GAME_SEGMENT_COUNT_DB.iter() {
+        let (game_id, _) = item?;
+        if !GAME_RULES_DB.contains_key(&game_id)? {
+            GAME_RULES_DB.insert(&game_id, &rules)?;
+            games += 1;
+        }
+    }
+    log::info!(
+        "{games} games got legacy rules: upgraded {segments} segments, \
+         {states} states and {boards} custom boards"
+    );
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct LegacyGameMatch {
+    seed: GameSeed,
+    time: i64,
+    users: Vec<uuid::Uuid>,
+    title: String,
+}
+
+impl LegacyGameMatch {
+    /// Every match was 1v1 before there were match types.
+    fn upgrade(self) -> GameMatch {
+        GameMatch {
+            seed: self.seed,
+            time: self.time,
+            users: self.users,
+            title: self.title,
+            match_type: GameMatchType::_1v1,
+        }
+    }
+}
+
+/// Matches from before match types get `_1v1`. The index keys only use the
+/// time and players, which stay the same.
+fn add_legacy_match_types() -> anyhow::Result<()> {
+    let matches = upgrade_rows(&GAME_MATCH_DB, LegacyGameMatch::upgrade)?;
+    log::info!("upgraded {matches} matches");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct OldReplay {
+        init_seed: GameSeed,
+        start_time: i64,
+        replay_slices: Vec<GameReplaySlice>,
+    }
+
+    #[derive(Serialize)]
+    enum OldSegment {
+        Init(OldReplay),
+    }
+
+    #[test]
+    fn old_init_segments_get_legacy_rules() {
+        let state = GameState::new_with_rules(&[4; 32], 7, &legacy_rules());
+        let old = bincode::serialize(&OldSegment::Init(OldReplay {
+            init_seed: [4; 32],
+            start_time: 7,
+            replay_slices: vec![],
+        }))
+        .unwrap();
+        assert!(decode_exact::<GameReplaySegment>(&old).is_none());
+        let legacy: LegacyGameReplaySegment = decode_exact(&old).unwrap();
+        assert_eq!(legacy.upgrade(), GameReplaySegment::Init(state.replay.clone()));
+
+        // new rows are left alone
+        let new = bincode::serialize(&GameReplaySegment::Init(state.replay)).unwrap();
+        assert!(decode_exact::<GameReplaySegment>(&new).is_some());
+        assert!(decode_exact::<LegacyGameReplaySegment>(&new).is_none());
+    }
+}
@@ -1,2 +1,4 @@
 pub mod config;
+pub mod index;
+pub mod migrate;
 pub mod tables;
@@ -1,26 +1,49 @@
 use game::{
     api::{
-        game_match::{GameMatch, UserAndMatchId, UserAndMatchResult},
+        game_match::{
+            CoopGameInfo, GameMatch, GameMatchResult, GameMatchType, GameSeries,
+            GarbageSettings, MatchPlayerState, UserAndMatchId, UserAndMatchResult,
+        },
         game_replay::{GameId, GameSegmentId},
+        leaderboard::{GameMode, GameSummary},
+        room::RoomInfo,
+        stats::{PaceStats, UserStats},
     },
+    coop::CoopReplaySlice,
+    rating::{Rating, RatingChange},
+    rules::GameRules,
     tet::{GameReplaySegment, GameState},
 };
 
 use super::config::SERVER_DATA_PATH;
+use super::index::IndexGroup;
 use anyhow::Context;
+use typed_sled::index::IndexKey;
 
 use once_cell::sync::Lazy;
 
 pub static TABLES_DB: Lazy<sled::Db> =
     Lazy::new(|| sled::open(format!("{SERVER_DATA_PATH}/tables.sled")).unwrap());
 
-use game::api::user::UserProfile;
+use game::api::user::{display_name_key, Avatar, UserProfile};
+use game::timestamp::get_timestamp_now_nano;
+use rand::Rng;
+use sled::transaction::ConflictableTransactionError;
+use typed_sled::transaction::{flatten, Transactional};
 
 pub static USER_PROFILE_DB: Lazy<typed_sled::Tree<uuid::Uuid, UserProfile>> =
     Lazy::new(|| {
-        typed_sled::Tree::<uuid::Uuid, UserProfile>::open(&TABLES_DB, "user_profile_v1")
+        typed_sled::Tree::<uuid::Uuid, UserProfile>::open(&TABLES_DB, "user_profile_v2")
     });
 
+/// Profiles from before avatars and bios, which only had a display name.
+static USER_PROFILE_V1_DB: Lazy<typed_sled::Tree<uuid::Uuid, String>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "user_profile_v1"));
+
+/// Owner of every display name in use, by `display_name_key`.
+pub static DISPLAY_NAME_DB: Lazy<typed_sled::Tree<String, uuid::Uuid>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "display_name_v1"));
+
 pub static GAME_IS_IN_PROGRESS_DB: Lazy<typed_sled::Tree<GameId, bool>> =
     Lazy::new(|| {
         typed_sled::Tree::<GameId, bool>::open(&TABLES_DB, "game_is_in_progress_v1")
@@ -47,32 +70,99 @@ pub static CUSTOM_GAME_BOARD_DB: Lazy<typed_sled::Tree<String, GameState>> =
 pub static GAME_FULL_DB: Lazy<typed_sled::Tree<GameId, GameState>> =
     Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "game_full_v2"));
 
+/// Index of `GAME_RULES_DB`, which has a row for every game there is.
+pub const GAMES_BY_START_TIME: &str = "games_by_start_time_v1";
+
+pub static GAME_RULES_DB: Lazy<typed_sled::Tree<GameId, GameRules>> =
+    Lazy::new(|| {
+        typed_sled::Tree::<GameId, GameRules>::open(&TABLES_DB, "game_rules_v1")
+            .with_index(&TABLES_DB, GAMES_BY_START_TIME, |game_id, _| {
+                let order = IndexKey::new().i64(game_id.start_time);
+                IndexGroup::all_and_user(game_id.user_id, order)
+            })
+    });
+
 pub fn get_user_profile(uuid: &uuid::Uuid) -> anyhow::Result<UserProfile> {
-    Ok(USER_PROFILE_DB
+    USER_PROFILE_DB
         .get(uuid)
         .context("operation failed")?
-        .context("user profile not found")?)
+        .context("user profile not found")
 }
 
 pub fn random_word() -> String {
     random_word::gen(random_word::Lang::De).to_string()
 }
 
+/// Makes a profile with the name it had before profiles were editable, or a
+/// random word, and a number after it if the name is taken.
 pub fn get_or_create_user_profile(uuid: &uuid::Uuid) -> anyhow::Result<UserProfile> {
     if let Ok(u) = get_user_profile(uuid) {
         return Ok(u);
     }
-    let new: UserProfile = UserProfile {
-        display_name: random_word(),
+    let base_name = match USER_PROFILE_V1_DB.get(uuid)? {
+        Some(old_name) => old_name,
+        None => random_word(),
     };
-    USER_PROFILE_DB.insert(uuid, &new).context("cannot write")?;
-    Ok(new)
+    for attempt in 0..10 {
+        let display_name = match attempt {
+            0 => base_name.clone(),
+            _ => format!("{base_name}{}", rand::thread_rng().gen_range(10..10000)),
+        };
+        let new = UserProfile {
+            display_name,
+            avatar: Avatar::default(),
+            country: None,
+            bio: String::new(),
+            joined: get_timestamp_now_nano(),
+        };
+        let name_key = display_name_key(&new.display_name);
+        let created = flatten((&*USER_PROFILE_DB, &*DISPLAY_NAME_DB).transaction(
+            |(profiles, names)| {
+                if let Some(existing) = profiles.get(uuid)? {
+                    return Ok(Some(existing));
+                }
+                if names.get(&name_key)?.is_some() {
+                    return Ok(None);
+                }
+                profiles.insert(uuid, &new)?;
+                names.insert(&name_key, uuid)?;
+                Ok::<_, ConflictableTransactionError<anyhow::Error>>(Some(new.clone()))
+            },
+        ))?;
+        if let Some(profile) = created {
+            return Ok(profile);
+        }
+    }
+    anyhow::bail!("cannot find a free display name")
+}
+
+/// Display name for titles and the like; the id if there is no profile.
+pub fn get_display_name(uuid: &uuid::Uuid) -> String {
+    match get_user_profile(uuid) {
+        Ok(profile) => profile.display_name,
+        Err(_) => uuid.to_string(),
+    }
 }
 
 // ===
 
+pub const MATCHES_BY_START_TIME: &str = "matches_by_start_time_v1";
+/// A user's finished matches, wins first, then by lines sent.
+pub const USER_MATCHES_BY_RESULT: &str = "user_matches_by_result_v1";
+pub const MATCHES_BY_LINES_SENT: &str = "matches_by_lines_sent_v1";
+
 pub static GAME_MATCH_DB: Lazy<typed_sled::Tree<uuid::Uuid, GameMatch>> =
-    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "game_match_v2"));
+    Lazy::new(|| {
+        typed_sled::Tree::<uuid::Uuid, GameMatch>::open(&TABLES_DB, "game_match_v2")
+            .with_index(&TABLES_DB, MATCHES_BY_START_TIME, |_, match_info| {
+                let order = IndexKey::new().i64(match_info.time);
+                let mut keys = vec![IndexGroup::All.key().bytes(order.as_bytes())];
+                for user_id in &match_info.users {
+                    keys.push(IndexGroup::User(*user_id).key().bytes(order.as_bytes()));
+                }
+                keys
+            })
+    });
 
 pub static GAME_MATCH_IS_IN_PROGRESS_DB: Lazy<typed_sled::Tree<uuid::Uuid, bool>> =
     Lazy::new(|| {
@@ -82,5 +172,128 @@ pub static GAME_MATCH_IS_IN_PROGRESS_DB: Lazy<typed_sled::Tree<uuid::Uuid, bool>
 pub static GAME_MATCHES_FOR_USER_DB: Lazy<
     typed_sled::Tree<UserAndMatchId, UserAndMatchResult>,
 > = Lazy::new(|| {
-    typed_sled::Tree::<_, _>::open(&TABLES_DB, "GAME_MATCHES_FOR_USER_DB_v1")
+    typed_sled::Tree::<UserAndMatchId, UserAndMatchResult>::open(
+        &TABLES_DB,
+        "GAME_MATCHES_FOR_USER_DB_v1",
+    )
+    .with_index(&TABLES_DB, USER_MATCHES_BY_RESULT, |key, result| {
+        vec![IndexGroup::User(key.user_id)
+            .key()
+            .bool(result.is_win)
+            .u32(result.lines_sent)]
+    })
 });
+
+pub static COOP_GAME_DB: Lazy<typed_sled::Tree<uuid::Uuid, CoopGameInfo>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "coop_game_v1"));
+
+/// Every slice of a co-op game, by (game, index). The state in
+/// `COOP_GAME_DB` only keeps the last one.
+pub static COOP_SLICE_DB: Lazy<typed_sled::Tree<(uuid::Uuid, u32), CoopReplaySlice>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "coop_slice_v1"));
+
+pub static GAME_MATCH_RESULT_DB: Lazy<typed_sled::Tree<uuid::Uuid, GameMatchResult>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "game_match_result_v1"));
+
+/// Garbage and result of each game that is part of a versus match.
+pub static MATCH_PLAYER_DB: Lazy<typed_sled::Tree<GameId, MatchPlayerState>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "match_player_v1"));
+
+/// Garbage settings of each versus match, for resuming its coordinator.
+pub static MATCH_GARBAGE_DB: Lazy<typed_sled::Tree<uuid::Uuid, GarbageSettings>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "match_garbage_v1"));
+
+pub static RATING_DB: Lazy<typed_sled::Tree<(uuid::Uuid, GameMatchType), Rating>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "rating_v1"));
+
+pub static RATING_HISTORY_DB: Lazy<typed_sled::Tree<UserAndMatchId, RatingChange>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "rating_history_v1"));
+
+/// Private rooms by their code.
+pub static ROOM_DB: Lazy<typed_sled::Tree<String, RoomInfo>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "room_v1"));
+
+pub static SERIES_DB: Lazy<typed_sled::Tree<uuid::Uuid, GameSeries>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "game_series_v1"));
+
+/// Series of each match that is part of one.
+pub static SERIES_FOR_MATCH_DB: Lazy<typed_sled::Tree<uuid::Uuid, uuid::Uuid>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "series_for_match_v1"));
+
+pub const GAMES_BY_SCORE: &str = "games_by_score_v2";
+pub const LEADERBOARDS: &str = "leaderboards_v1";
+
+/// Written when a game is over.
+pub static GAME_SUMMARY_DB: Lazy<typed_sled::Tree<GameId, GameSummary>> =
+    Lazy::new(|| {
+        typed_sled::Tree::<GameId, GameSummary>::open(&TABLES_DB, "game_summary_v1")
+            .with_index(&TABLES_DB, GAMES_BY_SCORE, |game_id, summary| {
+                IndexGroup::all_and_user(
+                    game_id.user_id,
+                    IndexKey::new().i64(summary.score),
+                )
+            })
+            .with_index(&TABLES_DB, LEADERBOARDS, |game_id, summary| {
+                crate::backend::leaderboard::leaderboard_keys(game_id, summary)
+            })
+    });
+
+/// Best ranked game of each mode.
+pub static WORLD_RECORD_DB: Lazy<typed_sled::Tree<GameMode, GameSummary>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "world_record_v1"));
+
+/// Lines sent by all players of a finished match together.
+pub static MATCH_LINES_SENT_DB: Lazy<typed_sled::Tree<uuid::Uuid, u32>> =
+    Lazy::new(|| {
+        typed_sled::Tree::<_, _>::open(&TABLES_DB, "match_lines_sent_v1").with_index(
+            &TABLES_DB,
+            MATCHES_BY_LINES_SENT,
+            |_, lines| vec![IndexGroup::All.key().u32(*lines)],
+        )
+    });
+
+/// Registered accounts by lowercase username: the identity they own and
+/// the argon2 hash of their password.
+pub static ACCOUNT_DB: Lazy<typed_sled::Tree<String, (uuid::Uuid, String)>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "account_v1"));
+
+/// Guests whose games are being handed to the account they logged in to,
+/// see `backend::guest_merge`.
+pub static GUEST_MERGE_DB: Lazy<typed_sled::Tree<uuid::Uuid, uuid::Uuid>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "guest_merge_v1"));
+
+/// Username of every identity that has an account, as it was registered.
+pub static USER_ACCOUNT_DB: Lazy<typed_sled::Tree<uuid::Uuid, String>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "user_account_v1"));
+
+/// Running totals of each user, see `backend::stats`.
+pub static USER_STATS_DB: Lazy<typed_sled::Tree<uuid::Uuid, UserStats>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "user_stats_v1"));
+
+/// Pace of each user on each day, by day since the epoch.
+pub static USER_DAILY_PACE_DB: Lazy<typed_sled::Tree<(uuid::Uuid, i64), PaceStats>> =
+    Lazy::new(|| typed_sled::Tree::<_, _>::open(&TABLES_DB, "user_daily_pace_v1"));
+
+/// Index of `FOLLOW_DB` by who is followed.
+pub const FOLLOWERS: &str = "followers_v1";
+
+/// Who follows whom, as (follower, followed), with when it started.
+pub static FOLLOW_DB: Lazy<typed_sled::Tree<(uuid::Uuid, uuid::Uuid), i64>> =
+    Lazy::new(|| {
+        typed_sled::Tree::<(uuid::Uuid, uuid::Uuid), i64>::open(&TABLES_DB, "follow_v1")
+            .with_index(&TABLES_DB, FOLLOWERS, |(_, followed), _| {
+                vec![IndexKey::new().bytes(followed.as_bytes())]
+            })
+    });
+
+/// Fills indexes that are new next to existing rows. Scans everything, so it
+/// only runs at startup.
+pub fn fill_empty_indexes() -> anyhow::Result<()> {
+    GAME_RULES_DB.fill_empty_indexes()?;
+    GAME_SUMMARY_DB.fill_empty_indexes()?;
+    GAME_MATCH_DB.fill_empty_indexes()?;
+    GAME_MATCHES_FOR_USER_DB.fill_empty_indexes()?;
+    MATCH_LINES_SENT_DB.fill_empty_indexes()?;
+    FOLLOW_DB.fill_empty_indexes()?;
+    Ok(())
+}
@@ -7,6 +7,14 @@ pub mod database;
 #[tokio::main]
 pub async fn main() {
     use crate::backend::server_main::server_main;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(|a| a.as_str()) == Some("render") {
+        if let Err(e) = crate::backend::render::render_command(&args[1..]) {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
     server_main().await
 }
 